@@ -0,0 +1,77 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use ecosim::orderbook::OrderBookMarket;
+use ecosim::{EntityId, Market, OrderType, Owner, Quantity, TestMarket};
+
+// The clearing of the markets at growing numbers of orders, half buying and half selling in
+// three prestige tiers, and the shapes that keep the distribution busy: a single huge order
+// against many small ones, where every round fills only some of the small ones.
+
+const ORDERS: [usize; 3] = [10, 1_000, 100_000];
+
+// Quantities from 1 to 100, the same at every run
+fn register(market: &mut dyn Market, orders: usize) {
+    for i in 0..orders {
+        let otype = if i % 2 == 0 { OrderType::Buy } else { OrderType::Sell };
+        let quantity = 1 + (i as Quantity * 7_919) % 100;
+        market.register_limit_order(Owner::Entity(EntityId(i)), otype, quantity, (i % 3) as f64, 0.9 + (i % 5) as f64 * 0.05).unwrap();
+    }
+}
+
+fn build(name: &str) -> Box<dyn Market> {
+    match name {
+        "order_book" => Box::new(OrderBookMarket::new(0, 1, 1, 1.)),
+        _ => Box::new(TestMarket::new(0, 1, 1.)),
+    }
+}
+
+fn run_trade(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run_trade");
+    group.sample_size(10);
+    for orders in ORDERS {
+        group.throughput(Throughput::Elements(orders as u64));
+        for name in ["test", "order_book"] {
+            group.bench_with_input(BenchmarkId::new(name, orders), &orders, |b, orders| {
+                b.iter_batched(|| {
+                    let mut market = build(name);
+                    register(market.as_mut(), *orders);
+                    market
+                }, |mut market| market.run_trade(), BatchSize::LargeInput);
+            });
+        }
+    }
+    group.finish();
+}
+
+fn distribute(c: &mut Criterion) {
+    let mut group = c.benchmark_group("distribute");
+    group.sample_size(10);
+    for orders in ORDERS {
+        group.throughput(Throughput::Elements(orders as u64));
+        // Growing buys, the smallest are filled first and the rest shares again
+        group.bench_with_input(BenchmarkId::new("huge_seller", orders), &orders, |b, orders| {
+            b.iter_batched(|| {
+                let mut market = TestMarket::new(0, 1, 1.);
+                for i in 0..*orders {
+                    market.register_order(Owner::Entity(EntityId(i)), OrderType::Buy, 1 + i as Quantity, 0.).unwrap();
+                }
+                market.register_order(Owner::Route, OrderType::Sell, (*orders * *orders / 4) as Quantity, 0.).unwrap();
+                market
+            }, |mut market| market.run_trade(), BatchSize::LargeInput);
+        });
+        // A remainder to give out a unit at a time
+        group.bench_with_input(BenchmarkId::new("remainder", orders), &orders, |b, orders| {
+            b.iter_batched(|| {
+                let mut market = TestMarket::new(0, 1, 1.);
+                for i in 0..*orders {
+                    market.register_order(Owner::Entity(EntityId(i)), OrderType::Sell, 10, 0.).unwrap();
+                }
+                market.register_order(Owner::Route, OrderType::Buy, (*orders * 10 - 1) as Quantity, 0.).unwrap();
+                market
+            }, |mut market| market.run_trade(), BatchSize::LargeInput);
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run_trade, distribute);
+criterion_main!(benches);
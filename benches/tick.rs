@@ -0,0 +1,23 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ecosim::bench::synthetic_scenario;
+use ecosim::Scenario;
+
+// A whole tick of the synthetic world of `ecosim bench`, half RGOs and half pops, on all the
+// threads. The world keeps running from one iteration to the next, as it would in a long run.
+
+const WORLDS: [(usize, usize); 3] = [(100, 10), (10_000, 1_000), (100_000, 1_000)];
+
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    group.sample_size(10);
+    for (entities, markets) in WORLDS {
+        let scenario = Scenario::from_toml(&synthetic_scenario(entities, markets, 1_000_000)).unwrap();
+        let mut sim = scenario.build().unwrap();
+        group.throughput(Throughput::Elements(entities as u64));
+        group.bench_function(format!("{entities}_entities_{markets}_markets"), |b| b.iter(|| sim.step()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
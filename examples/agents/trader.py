@@ -0,0 +1,19 @@
+#!/usr/bin/env python3
+# An external agent for ecosim, see src/external.rs for the protocol. It buys a good when the
+# price is below its average and sells it when above, a few units at a time.
+import json
+import sys
+
+LOT = 20
+
+for line in sys.stdin:
+    observation = json.loads(line)
+    orders = []
+    for good in observation["goods"]:
+        mean = good["mean_price"] or good["price"]
+        quantity = max(LOT, good["lot"])
+        if good["price"] <= mean and observation["money"] > good["price"] * quantity:
+            orders.append({"good": good["good"], "side": "Buy", "quantity": quantity})
+        elif good["price"] > mean and good["stock"] > 0:
+            orders.append({"good": good["good"], "side": "Sell", "quantity": min(quantity, good["stock"])})
+    print(json.dumps({"seq": observation["seq"], "orders": orders}), flush=True)
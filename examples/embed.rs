@@ -0,0 +1,75 @@
+// The simulation embedded in another program: the wheat_bread scenario with an entity of our
+// own, a granary buying Grain every tick, stepped by hand reading the prices.
+
+use ecosim::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use ecosim::{EcoEntity, EntityId, GoodUid, Market, MarketMetadata, OrderResult, OrderType, Owner, Quantity, Scenario};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Granary {
+    good_uid: GoodUid,
+    per_tick: Quantity,
+    stock: Quantity,
+    money_balance: f64,
+    money_flows: MoneyFlows,
+}
+
+#[typetag::serde]
+impl EcoEntity for Granary {
+    fn produce_and_consume(&mut self) -> f64 {
+        0.
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        (vec![self.good_uid], vec![])
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        let Some(market) = markets.iter_mut().find(|x| x.good_uid() == self.good_uid) else {
+            return;
+        };
+        // Without the money for a lot there's nothing to buy
+        if let Ok(affordable) = market.affordable_order(self.money_balance) {
+            let _ = market.register_order(Owner::Entity(id), OrderType::Buy, self.per_tick.min(affordable), 0.);
+        }
+    }
+
+    fn settle_order(&mut self, _good_uid: GoodUid, result: OrderResult) {
+        self.stock += result.traded_quantity;
+        self.money_balance -= result.total_cost;
+        self.money_flows.record(FlowKind::Trade, -result.total_cost);
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        vec![(self.good_uid, self.stock)]
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let scenario = Scenario::from_toml(include_str!("../scenarios/wheat_bread.toml"))?;
+    let mut sim = scenario.build()?;
+    let grain = sim.goods.uid_of("Grain").ok_or("no Grain")?;
+    let granary = sim.add_entity("Granary", 0, Box::new(Granary {
+        good_uid: grain,
+        per_tick: 50,
+        stock: 0,
+        money_balance: 1000.,
+        money_flows: MoneyFlows::default(),
+    }));
+    for _ in 0..10 {
+        sim.step();
+        let (_, market) = sim.markets().find(|(_, x)| x.good_uid() == grain).ok_or("no Grain market")?;
+        let granary = &sim.entities[granary];
+        println!("tick {:>2}: Grain at {:.2}, the granary has {:.2}$ and {:?}",
+                 sim.tick, market.price_per_unit(), granary.money_balance(), granary.inventory());
+    }
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::engine::Simulation;
+use crate::ledger::{FlowKind, MoneyFlow};
+use crate::recorder::Recorder;
+use crate::EntityId;
+
+// Financial statements of some entities, closed every tick and recorded as
+// `{name}/balance/...` and `{name}/income/...`. The inventory is valued at the price of the
+// markets of the region of the entity, a good without a market there is worth nothing. The cost
+// of the goods sold is what was spent on the inputs, the wages and the variable costs less the
+// growth of the value of the inventory, so the output not sold yet is not a loss. The loans and
+// the deposits move money between the lines of the balance sheet, only their interests go in
+// the income statement, and the dividends paid and the shares bought are not expenses.
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BalanceSheet {
+    pub cash: f64,
+    pub deposits: f64,
+    pub inventory: f64,
+    // Owed to the bank
+    pub liabilities: f64,
+}
+
+impl BalanceSheet {
+    // The position of the entity now, at the prices of now
+    pub fn of(sim: &Simulation, id: EntityId) -> BalanceSheet {
+        let entity = sim.entities[id].as_ref();
+        let region = &sim.regions[sim.entities.region(id)];
+        let inventory = entity.inventory().into_iter()
+            .filter_map(|(good_uid, quantity)| region.market(good_uid)
+                .map(|x| sim.goods.to_units(good_uid, quantity) * x.price_per_unit()))
+            .sum();
+        let account = sim.bank.as_ref().and_then(|x| x.accounts.iter().find(|x| x.entity == id));
+        BalanceSheet {
+            cash: entity.money_balance(),
+            deposits: account.map_or(0., |x| x.deposit),
+            inventory,
+            liabilities: account.map_or(0., |x| x.loan),
+        }
+    }
+
+    pub fn equity(&self) -> f64 {
+        self.cash + self.deposits + self.inventory - self.liabilities
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct IncomeStatement {
+    // Goods and labor sold
+    pub revenue: f64,
+    pub cogs: f64,
+    // Fixed costs, storage and prospecting
+    pub fixed_costs: f64,
+    // Taxes, penalties, interests, dividends received and money created, net
+    pub other: f64,
+}
+
+impl IncomeStatement {
+    // The statement of the flows of a tick between the two balance sheets
+    pub fn new(flows: &[MoneyFlow], opening: &BalanceSheet, closing: &BalanceSheet) -> IncomeStatement {
+        let mut statement = IncomeStatement::default();
+        let mut direct_costs = 0.;
+        let (mut borrowed, mut withdrawn) = (0., 0.);
+        for flow in flows.iter() {
+            match flow.kind {
+                FlowKind::Trade | FlowKind::Wages | FlowKind::Frozen if flow.amount > 0. => statement.revenue += flow.amount,
+                FlowKind::Trade | FlowKind::Wages | FlowKind::Frozen | FlowKind::VariableCost | FlowKind::Transport =>
+                    direct_costs -= flow.amount,
+                FlowKind::FixedCost | FlowKind::Storage | FlowKind::Prospecting => statement.fixed_costs -= flow.amount,
+                FlowKind::Tax | FlowKind::Penalty | FlowKind::Monetary => statement.other += flow.amount,
+                FlowKind::Dividend if flow.amount > 0. => statement.other += flow.amount,
+                FlowKind::Loan => borrowed += flow.amount,
+                FlowKind::Deposit => withdrawn += flow.amount,
+                FlowKind::Dividend | FlowKind::Shares | FlowKind::Exchange => {}
+            }
+        }
+        statement.cogs = direct_costs - (closing.inventory - opening.inventory);
+        // What the balances at the bank grew more than the money moved, a written off loan is a gain
+        statement.other += closing.deposits - opening.deposits + withdrawn;
+        statement.other -= closing.liabilities - opening.liabilities - borrowed;
+        statement
+    }
+
+    pub fn net_profit(&self) -> f64 {
+        self.revenue - self.cogs - self.fixed_costs + self.other
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Books {
+    opening: BalanceSheet,
+    flows: Vec<MoneyFlow>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Accounts {
+    // By name of the entity
+    books: BTreeMap<String, Books>,
+}
+
+impl Accounts {
+    pub fn new(entities: &[String]) -> Accounts {
+        Accounts { books: entities.iter().map(|x| (x.clone(), Books::default())).collect() }
+    }
+
+    pub fn keeps(&self, name: &str) -> bool {
+        self.books.contains_key(name)
+    }
+
+    // At the start of the tick
+    pub fn open(&mut self, name: &str, opening: BalanceSheet) {
+        if let Some(books) = self.books.get_mut(name) {
+            *books = Books { opening, flows: vec![] };
+        }
+    }
+
+    pub fn add_flows(&mut self, name: &str, flows: &[MoneyFlow]) {
+        if let Some(books) = self.books.get_mut(name) {
+            books.flows.extend_from_slice(flows);
+        }
+    }
+
+    // At the end of the tick, with the flows of the tick
+    pub fn close(&mut self, name: &str, closing: BalanceSheet, recorder: &mut Recorder) {
+        let Some(books) = self.books.get_mut(name) else {
+            return;
+        };
+        let statement = IncomeStatement::new(&books.flows, &books.opening, &closing);
+        recorder.record(&format!("{name}/balance/cash"), closing.cash);
+        recorder.record(&format!("{name}/balance/deposits"), closing.deposits);
+        recorder.record(&format!("{name}/balance/inventory"), closing.inventory);
+        recorder.record(&format!("{name}/balance/liabilities"), closing.liabilities);
+        recorder.record(&format!("{name}/balance/equity"), closing.equity());
+        recorder.record(&format!("{name}/income/revenue"), statement.revenue);
+        recorder.record(&format!("{name}/income/cogs"), statement.cogs);
+        recorder.record(&format!("{name}/income/fixed_costs"), statement.fixed_costs);
+        recorder.record(&format!("{name}/income/other"), statement.other);
+        recorder.record(&format!("{name}/income/net_profit"), statement.net_profit());
+        books.flows.clear();
+    }
+
+    // Income statement of the whole run after the burn-in, from the recorded series
+    pub fn print_report(&self, recorder: &Recorder, burn_in: u64) {
+        let start = recorder.ticks().iter().position(|x| *x >= burn_in).unwrap_or(recorder.ticks().len());
+        let total = |name: &str| -> f64 {
+            recorder.series(name).map_or(0., |x| x[start..].iter().filter(|v| !v.is_nan()).sum())
+        };
+        for name in self.books.keys() {
+            println!("Income of {name}: revenue {:.2}, cogs {:.2}, fixed costs {:.2}, other {:.2}, net profit {:.2}",
+                     total(&format!("{name}/income/revenue")), total(&format!("{name}/income/cogs")),
+                     total(&format!("{name}/income/fixed_costs")), total(&format!("{name}/income/other")),
+                     total(&format!("{name}/income/net_profit")));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scenario;
+
+    #[test]
+    fn the_unsold_output_is_not_a_cost() {
+        let flows = [
+            MoneyFlow { kind: FlowKind::Trade, amount: -60. },
+            MoneyFlow { kind: FlowKind::Wages, amount: -20. },
+            MoneyFlow { kind: FlowKind::Trade, amount: 50. },
+            MoneyFlow { kind: FlowKind::FixedCost, amount: -10. },
+            MoneyFlow { kind: FlowKind::Loan, amount: 30. },
+        ];
+        let opening = BalanceSheet { cash: 100., inventory: 40., ..Default::default() };
+        let closing = BalanceSheet { cash: 90., inventory: 70., liabilities: 31., ..Default::default() };
+        let statement = IncomeStatement::new(&flows, &opening, &closing);
+        assert_eq!(statement, IncomeStatement { revenue: 50., cogs: 50., fixed_costs: 10., other: -1. });
+        assert_eq!(statement.net_profit(), closing.equity() - opening.equity());
+    }
+
+    #[test]
+    fn the_profit_is_the_growth_of_the_equity() {
+        let scenario = Scenario::from_toml(include_str!("../scenarios/bank.toml")).unwrap();
+        let mut sim = scenario.build().unwrap();
+        let names = sim.entities.names().to_vec();
+        sim.accounts = Some(Accounts::new(&names));
+        for _ in 0..10 {
+            sim.step();
+        }
+        for name in names.iter() {
+            let equity = sim.recorder.series(&format!("{name}/balance/equity")).unwrap();
+            let profit = sim.recorder.series(&format!("{name}/income/net_profit")).unwrap();
+            for tick in 1..equity.len() {
+                assert!((equity[tick] - equity[tick - 1] - profit[tick]).abs() < 1e-6 * equity[tick].abs().max(1.),
+                        "{name} at tick {tick}");
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use crate::scenario::EntityConfig;
+
+// A library of ready made entities, see archetypes/library.toml. An entity of a scenario with
+// `archetype = "mill"` starts from the archetype and its fields override the ones of the
+// archetype: the tables field by field, the lists and the values as a whole. The name, and the
+// region if any, are given by the scenario.
+
+const LIBRARY: &str = include_str!("../archetypes/library.toml");
+
+pub fn library() -> BTreeMap<String, Map<String, Value>> {
+    toml::from_str(LIBRARY).expect("The archetype library is not valid")
+}
+
+pub fn names() -> Vec<String> {
+    library().into_keys().collect()
+}
+
+// The archetype named in `entity` with the other fields of `entity` over it
+pub fn instantiate(mut entity: Map<String, Value>) -> Result<Map<String, Value>, String> {
+    let Some(Value::String(name)) = entity.remove("archetype") else {
+        return Err("`archetype` is not the name of an archetype".to_owned());
+    };
+    let Some(mut fields) = library().remove(&name) else {
+        return Err(format!("unknown archetype `{name}`, expected one of {}", names().join(", ")));
+    };
+    for (field, value) in entity {
+        merge(fields.entry(field).or_insert(Value::Null), value);
+    }
+    Ok(fields)
+}
+
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (field, value) in value {
+                merge(base.entry(field).or_insert(Value::Null), value);
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+// The entities of a scenario, the ones with an archetype instantiated
+pub(crate) fn deserialize_entities<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<EntityConfig>, D::Error> {
+    Vec::<Value>::deserialize(deserializer)?.into_iter().map(|entity| {
+        let name = entity.get("name").and_then(|x| x.as_str()).unwrap_or("?").to_owned();
+        let entity = match entity {
+            Value::Object(fields) if fields.contains_key("archetype") =>
+                Value::Object(instantiate(fields).map_err(|e| D::Error::custom(format!("entity `{name}`: {e}")))?),
+            entity => entity,
+        };
+        EntityConfig::deserialize(entity).map_err(|e| D::Error::custom(format!("entity `{name}`: {e}")))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_merge_into_the_archetype() {
+        for name in names() {
+            let entity = serde_json::json!({ "archetype": name, "name": "X" });
+            assert!(EntityConfig::deserialize(Value::Object(instantiate(entity.as_object().unwrap().clone()).unwrap())).is_ok());
+        }
+        let entity = serde_json::json!({ "archetype": "urban_pop", "name": "X", "labor": { "per_tick": 10 } });
+        let fields = instantiate(entity.as_object().unwrap().clone()).unwrap();
+        assert_eq!(fields["labor"], serde_json::json!({ "good": "Labor", "per_tick": 10 }));
+        assert_eq!(fields["kind"], "pop");
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::registry::EntityRegistry;
+use crate::{EcoEntity, EntityId};
+
+// A bank keeping the accounts of some entities. Before the production every account holder is
+// brought back to the cash it wants on hand: the money above it repays the loan and then goes
+// on deposit, the money missing is withdrawn from the deposit and then borrowed up to the credit
+// limit. The interests are added to the deposits and to the loans, so money only moves when a
+// holder deposits, withdraws, borrows or repays. The bank lends and returns only the cash it
+// holds. The loan of a bankrupt holder is repaid with what it has left and the rest is lost.
+// With a CentralBank both rates are over its policy rate.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub entity: EntityId,
+    // Money kept on hand
+    pub cash: f64,
+    pub credit_limit: f64,
+    pub deposit: f64,
+    pub loan: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bank {
+    money_balance: f64,
+    money_flows: MoneyFlows,
+    // Interest per tick, over the policy rate
+    deposit_rate: f64,
+    loan_rate: f64,
+    #[serde(default)]
+    policy_rate: f64,
+    pub accounts: Vec<Account>,
+    // Loans lost to the bankruptcies
+    written_off: f64,
+}
+
+impl Bank {
+    pub fn new(money_balance: f64, deposit_rate: f64, loan_rate: f64) -> Bank {
+        Bank {
+            money_balance,
+            money_flows: MoneyFlows::default(),
+            deposit_rate,
+            loan_rate,
+            policy_rate: 0.,
+            accounts: vec![],
+            written_off: 0.,
+        }
+    }
+
+    pub fn open_account(&mut self, entity: EntityId, cash: f64, credit_limit: f64) {
+        self.accounts.push(Account { entity, cash, credit_limit, deposit: 0., loan: 0. });
+    }
+
+    pub fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    pub fn deposits(&self) -> f64 {
+        self.accounts.iter().map(|x| x.deposit).sum()
+    }
+
+    pub fn loans(&self) -> f64 {
+        self.accounts.iter().map(|x| x.loan).sum()
+    }
+
+    pub fn written_off(&self) -> f64 {
+        self.written_off
+    }
+
+    pub fn set_policy_rate(&mut self, rate: f64) {
+        self.policy_rate = rate;
+    }
+
+    pub fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    // Move `amount` to the entity, negative when the entity pays the bank
+    fn transfer(&mut self, entity: &mut dyn EcoEntity, kind: FlowKind, amount: f64) -> bool {
+        if amount == 0. || !entity.transfer(kind, amount) {
+            return false;
+        }
+        self.money_balance -= amount;
+        self.money_flows.record(kind, -amount);
+        true
+    }
+
+    pub fn settle(&mut self, entities: &mut EntityRegistry) {
+        let mut accounts = std::mem::take(&mut self.accounts);
+        for account in accounts.iter_mut() {
+            account.deposit *= 1. + self.policy_rate + self.deposit_rate;
+            account.loan *= 1. + self.policy_rate + self.loan_rate;
+            let entity = entities[account.entity].as_mut();
+            let money = entity.money_balance();
+            let bankrupt = entity.state_fields().into_iter().any(|x| x.0 == "bankrupt" && x.1 > 0.);
+            if bankrupt {
+                let repaid = money.min(account.loan).max(0.);
+                if self.transfer(entity, FlowKind::Loan, -repaid) {
+                    account.loan -= repaid;
+                }
+                self.written_off += account.loan;
+                account.loan = 0.;
+                account.credit_limit = 0.;
+            } else if money > account.cash {
+                let repaid = (money - account.cash).min(account.loan);
+                if self.transfer(entity, FlowKind::Loan, -repaid) {
+                    account.loan -= repaid;
+                }
+                let deposited = money - account.cash - repaid;
+                if self.transfer(entity, FlowKind::Deposit, -deposited) {
+                    account.deposit += deposited;
+                }
+            } else {
+                let withdrawn = (account.cash - money).min(account.deposit).min(self.money_balance.max(0.));
+                if self.transfer(entity, FlowKind::Deposit, withdrawn) {
+                    account.deposit -= withdrawn;
+                }
+                let borrowed = (account.cash - money - withdrawn)
+                    .min(account.credit_limit - account.loan)
+                    .min(self.money_balance)
+                    .max(0.);
+                if self.transfer(entity, FlowKind::Loan, borrowed) {
+                    account.loan += borrowed;
+                }
+            }
+        }
+        self.accounts = accounts;
+    }
+}
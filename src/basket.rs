@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use crate::market::{guarded_trade, validate_order};
+use crate::{goods, GoodUid, Market, MarketError, OrderType, Owner, Price, Quantity};
+
+// A basket is an all-or-nothing group of orders on different markets: after the trade either
+// every leg filled completely at an acceptable price or all the legs are cancelled.
+// Cancelled legs stay in their market as empty orders, so the owner is settled as usual and
+// simply finds nothing traded.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasketLeg {
+    pub good_uid: GoodUid,
+    pub otype: OrderType,
+    pub quantity: Quantity,
+    // Worst acceptable price per unit
+    pub limit_price: Price,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasketOrder {
+    pub legs: Vec<BasketLeg>,
+    pub prestige: f64,
+}
+
+pub type BasketId = usize;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PlacedBasket {
+    order: BasketOrder,
+    uuids: Vec<Uuid>,
+    cancelled: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BasketBook {
+    baskets: Vec<PlacedBasket>,
+}
+
+fn find_market(markets: &mut [Box<dyn Market>], good_uid: GoodUid) -> Option<&mut Box<dyn Market>> {
+    markets.iter_mut().find(|x| x.good_uid() == good_uid)
+}
+
+impl BasketBook {
+    // Register all the legs of the basket. Returns None, registering nothing, if a leg has no
+    // market or is not a valid order. The uuids of the legs are in the same order of the legs.
+    pub fn place(&mut self, owner: Owner, markets: &mut [Box<dyn Market>], order: BasketOrder) -> Option<(BasketId, Vec<Uuid>)> {
+        if order.legs.iter().any(|leg| !markets.iter().any(|x| x.good_uid() == leg.good_uid)
+            || validate_order(leg.quantity, Some(leg.limit_price)).is_err()) {
+            return None;
+        }
+        let mut uuids = vec![];
+        for leg in order.legs.iter() {
+            let market = find_market(markets, leg.good_uid).unwrap();
+            uuids.push(market.register_limit_order(owner, leg.otype, leg.quantity, order.prestige, leg.limit_price)
+                .expect("The legs were validated"));
+        }
+        self.baskets.push(PlacedBasket { order, uuids: uuids.clone(), cancelled: false });
+        Some((self.baskets.len() - 1, uuids))
+    }
+
+    fn leg_is_satisfied(market: &mut Box<dyn Market>, leg: &BasketLeg, uuid: &Uuid) -> bool {
+        let Some(result) = market.peek_order_result(uuid) else {
+            return false;
+        };
+        if result.traded_quantity != leg.quantity {
+            return false;
+        }
+        let max_cost = goods::to_units(leg.quantity, market.unit_scale()) * leg.limit_price;
+        match leg.otype {
+            OrderType::Buy => result.total_cost <= max_cost,
+            OrderType::Sell => result.total_cost >= max_cost,
+        }
+    }
+
+    // Called after the markets ran the trade. Cancels every basket with a leg not satisfied and
+    // runs again the trade of the markets touched by the cancellation, until all the remaining
+    // baskets are executed. Every round cancels at least one basket so this terminates.
+    // Returns the markets that failed, they are halted and their baskets cancelled.
+    pub fn settle(&mut self, markets: &mut [Box<dyn Market>]) -> Vec<(GoodUid, MarketError)> {
+        let mut failures = vec![];
+        loop {
+            let mut affected = HashSet::<GoodUid>::new();
+            for basket in self.baskets.iter_mut().filter(|x| !x.cancelled) {
+                let satisfied = basket.order.legs.iter().zip(basket.uuids.iter()).all(|(leg, uuid)| {
+                    BasketBook::leg_is_satisfied(find_market(markets, leg.good_uid).unwrap(), leg, uuid)
+                });
+                if satisfied {
+                    continue;
+                }
+                for (leg, uuid) in basket.order.legs.iter().zip(basket.uuids.iter()) {
+                    find_market(markets, leg.good_uid).unwrap().cancel_order(uuid);
+                    affected.insert(leg.good_uid);
+                }
+                basket.cancelled = true;
+            }
+            if affected.is_empty() {
+                return failures;
+            }
+            for market in markets.iter_mut().filter(|x| affected.contains(&x.good_uid())) {
+                if let Err(e) = guarded_trade(market.as_mut()) {
+                    failures.push((market.good_uid(), e));
+                }
+            }
+        }
+    }
+
+    pub fn clear_state(&mut self) {
+        self.baskets.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::TestMarket;
+    use crate::EntityId;
+
+    fn markets() -> Vec<Box<dyn Market>> {
+        vec![Box::new(TestMarket::new(0, 1, 1.)), Box::new(TestMarket::new(1, 1, 1.))]
+    }
+
+    fn basket() -> BasketOrder {
+        BasketOrder {
+            legs: vec![
+                BasketLeg { good_uid: 0, otype: OrderType::Buy, quantity: 10, limit_price: 1. },
+                BasketLeg { good_uid: 1, otype: OrderType::Buy, quantity: 10, limit_price: 1. },
+            ],
+            prestige: 0.,
+        }
+    }
+
+    fn traded(markets: &[Box<dyn Market>], good_uid: GoodUid, uuid: &Uuid) -> Quantity {
+        markets[good_uid].peek_order_result(uuid).unwrap().traded_quantity
+    }
+
+    #[test]
+    fn a_basket_with_every_leg_filled_is_executed() {
+        let mut markets = markets();
+        let mut book = BasketBook::default();
+        let (_, uuids) = book.place(Owner::Entity(EntityId(0)), &mut markets, basket()).unwrap();
+        for market in markets.iter_mut() {
+            market.register_order(Owner::Entity(EntityId(1)), OrderType::Sell, 10, 0.).unwrap();
+            market.run_trade().unwrap();
+        }
+        assert!(book.settle(&mut markets).is_empty());
+        assert_eq!(traded(&markets, 0, &uuids[0]), 10);
+        assert_eq!(traded(&markets, 1, &uuids[1]), 10);
+    }
+
+    #[test]
+    fn a_basket_with_a_leg_short_is_cancelled() {
+        let mut markets = markets();
+        let mut book = BasketBook::default();
+        let (_, uuids) = book.place(Owner::Entity(EntityId(0)), &mut markets, basket()).unwrap();
+        // The first good is shared with another buyer, the basket gets only half of it
+        let other = markets[0].register_order(Owner::Entity(EntityId(1)), OrderType::Buy, 10, 0.).unwrap();
+        markets[0].register_order(Owner::Entity(EntityId(2)), OrderType::Sell, 10, 0.).unwrap();
+        let seller = markets[1].register_order(Owner::Entity(EntityId(2)), OrderType::Sell, 10, 0.).unwrap();
+        for market in markets.iter_mut() {
+            market.run_trade().unwrap();
+        }
+        assert_eq!(traded(&markets, 0, &uuids[0]), 5);
+        assert_eq!(traded(&markets, 1, &uuids[1]), 10);
+        assert!(book.settle(&mut markets).is_empty());
+        // The market traded again without the basket, the other buyer gets all the goods and
+        // the seller of the filled leg keeps its goods
+        assert_eq!(traded(&markets, 0, &uuids[0]), 0);
+        assert_eq!(traded(&markets, 0, &other), 10);
+        assert_eq!(traded(&markets, 1, &uuids[1]), 0);
+        assert_eq!(traded(&markets, 1, &seller), 0);
+    }
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::time::{Duration, Instant};
+use crate::scenario::Scenario;
+
+// Timing of the simulation on a large synthetic world: every good has its market, half of the
+// entities are RGOs extracting a good and half are pops buying two goods. The same world runs
+// on a single thread and on all of them, the results must be the same.
+
+// The scenario of the synthetic world, in TOML
+pub fn synthetic_scenario(entities: usize, markets: usize, ticks: u64) -> String {
+    let mut text = format!("[simulation]\nticks = {ticks}\n");
+    for good in 0..markets {
+        let _ = write!(text, "\n[[goods]]\nname = \"G{good}\"\n");
+    }
+    for good in 0..markets {
+        let _ = write!(text, "\n[[markets]]\nkind = \"test\"\ngood = \"G{good}\"\nprice = {}\n", 1. + (good % 7) as f64);
+    }
+    for i in 0..entities {
+        let good = (i / 2) % markets;
+        if i % 2 == 0 {
+            let _ = write!(text, "\n[[entities]]\nkind = \"rgo\"\nname = \"RGO{i}\"\ngood = \"G{good}\"\nquantity = 100\n\
+                                  target_quantity = 50\nmax_production_rate = 50\nfixed_cost = 1.0\nmoney_balance = 10000.0\n");
+        } else {
+            let other = (good + 1) % markets;
+            let _ = write!(text, "\n[[entities]]\nkind = \"pop\"\nname = \"Pop{i}\"\nmoney_balance = 10000.0\n");
+            for good in [good, other] {
+                let _ = write!(text, "\n[[entities.goods]]\ngood = \"G{good}\"\ninventory = 20\ndesired = 40\nconsumed = 20\n");
+            }
+        }
+    }
+    text
+}
+
+pub struct BenchReport {
+    pub entities: usize,
+    pub markets: usize,
+    pub ticks: u64,
+    pub threads: usize,
+    pub sequential: Duration,
+    pub parallel: Duration,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_tick = |x: Duration| x.as_secs_f64() * 1000. / self.ticks.max(1) as f64;
+        writeln!(f, "{} entities, {} markets, {} ticks", self.entities, self.markets, self.ticks)?;
+        writeln!(f, "  sequential: {:>10.2} ms/tick", per_tick(self.sequential))?;
+        writeln!(f, "  parallel:   {:>10.2} ms/tick on {} threads", per_tick(self.parallel), self.threads)?;
+        writeln!(f, "  speedup:    {:>10.2}x", self.sequential.as_secs_f64() / self.parallel.as_secs_f64())
+    }
+}
+
+// Run the world for `ticks` ticks in a pool of `threads` threads, the time of the steps only
+fn time_run(scenario: &Scenario, threads: usize) -> Result<(Duration, Vec<f64>), Box<dyn Error>> {
+    let mut sim = scenario.build()?;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()?;
+    let start = Instant::now();
+    pool.install(|| {
+        while sim.tick < scenario.simulation.ticks {
+            sim.step();
+        }
+    });
+    let elapsed = start.elapsed();
+    let prices = sim.markets().map(|(_, x)| x.price_per_unit()).chain(sim.entities.iter().map(|x| x.2.money_balance())).collect();
+    Ok((elapsed, prices))
+}
+
+pub fn bench(entities: usize, markets: usize, ticks: u64) -> Result<BenchReport, Box<dyn Error>> {
+    let scenario = Scenario::from_toml(&synthetic_scenario(entities, markets.max(1), ticks))?;
+    let threads = rayon::current_num_threads();
+    let (sequential, expected) = time_run(&scenario, 1)?;
+    let (parallel, result) = time_run(&scenario, threads)?;
+    if expected != result {
+        return Err("the parallel run differs from the sequential one".into());
+    }
+    Ok(BenchReport { entities, markets, ticks, threads, sequential, parallel })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_run_matches_sequential() {
+        let scenario = Scenario::from_toml(&synthetic_scenario(200, 20, 3)).unwrap();
+        let (_, expected) = time_run(&scenario, 1).unwrap();
+        let (_, result) = time_run(&scenario, 4).unwrap();
+        assert_eq!(expected, result);
+    }
+}
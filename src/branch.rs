@@ -0,0 +1,202 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use serde::de::DeserializeOwned;
+use ciborium::Value;
+use serde::Serialize;
+use crate::checkpoint::{Checkpoint, CheckpointError};
+use crate::engine::Simulation;
+
+// Counterfactual analysis: a checkpoint is forked in many branches, every one with its own
+// parameter overrides, and the branches are run side by side from the same state. The first
+// branch is always the baseline, without overrides.
+
+#[derive(Debug)]
+pub enum BranchError {
+    Syntax(String),
+    UnknownTarget(String),
+    UnknownField(String, String),
+    Value(String, String),
+    Checkpoint(CheckpointError),
+}
+
+impl fmt::Display for BranchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BranchError::Syntax(x) => write!(f, "cannot parse branch `{x}`, expected NAME:TARGET.FIELD=VALUE,..."),
+            BranchError::UnknownTarget(x) => write!(f, "no entity, market or route named `{x}`"),
+            BranchError::UnknownField(target, field) => write!(f, "`{target}` has no field `{field}`"),
+            BranchError::Value(field, e) => write!(f, "invalid value for `{field}`: {e}"),
+            BranchError::Checkpoint(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BranchError {}
+
+// A field of an entity, of a market (`market/{label}`) or of a route (`route/{name}`) set to a
+// value, written as in the checkpoints: quantities are in base units
+#[derive(Debug, Clone)]
+pub struct Override {
+    pub target: String,
+    pub field: String,
+    // Parsed when applied, as the type of the field. Other than numbers, booleans and strings
+    // the values are written in JSON.
+    pub value: String,
+}
+
+impl FromStr for Override {
+    type Err = BranchError;
+
+    fn from_str(s: &str) -> Result<Override, BranchError> {
+        let (path, value) = s.split_once('=').ok_or_else(|| BranchError::Syntax(s.to_owned()))?;
+        let (target, field) = path.rsplit_once('.').ok_or_else(|| BranchError::Syntax(s.to_owned()))?;
+        Ok(Override { target: target.to_owned(), field: field.to_owned(), value: value.to_owned() })
+    }
+}
+
+impl Override {
+    // The value is parsed as the type of the field it replaces
+    fn parse_like(&self, old: &Value) -> Result<Value, BranchError> {
+        let error = |e: String| BranchError::Value(self.field.clone(), e);
+        Ok(match old {
+            Value::Float(_) => Value::Float(self.value.parse().map_err(|_| error("expected a number".to_owned()))?),
+            Value::Integer(_) => Value::Integer(self.value.parse::<u64>().map_err(|_| error("expected an integer".to_owned()))?.into()),
+            Value::Bool(_) => Value::Bool(self.value.parse().map_err(|_| error("expected true or false".to_owned()))?),
+            Value::Text(_) => Value::Text(self.value.clone()),
+            _ => {
+                let json: serde_json::Value = serde_json::from_str(&self.value).map_err(|e| error(e.to_string()))?;
+                Value::serialized(&json).map_err(|e| error(e.to_string()))?
+            }
+        })
+    }
+
+    // Through CBOR like the checkpoints, JSON would turn the numeric keys of the maps into strings
+    fn patch<T: Serialize + DeserializeOwned>(&self, object: &mut T) -> Result<(), BranchError> {
+        let error = |e: ciborium::value::Error| BranchError::Value(self.field.clone(), e.to_string());
+        let mut value = Value::serialized(&*object).map_err(error)?;
+        let field = value.as_map_mut().and_then(|x| x.iter_mut().find(|(k, _)| k.as_text() == Some(&self.field)));
+        match field {
+            Some((_, x)) => *x = self.parse_like(x)?,
+            None => return Err(BranchError::UnknownField(self.target.clone(), self.field.clone())),
+        }
+        *object = value.deserialized().map_err(error)?;
+        Ok(())
+    }
+
+    pub fn apply(&self, sim: &mut Simulation) -> Result<(), BranchError> {
+        if let Some(id) = sim.entities.find(&self.target) {
+            return self.patch(&mut sim.entities[id]);
+        }
+        if let Some(route) = sim.routes.iter_mut().find(|x| self.target == format!("route/{}", x.name)) {
+            return self.patch(route);
+        }
+        let labels: Vec<String> = sim.markets().map(|(region, x)| sim.market_label(region, x.good_uid())).collect();
+        let markets = sim.regions.iter_mut().flat_map(|x| x.markets.iter_mut());
+        match labels.iter().zip(markets).find(|(label, _)| self.target == format!("market/{label}")) {
+            Some((_, market)) => self.patch(market),
+            None => Err(BranchError::UnknownTarget(self.target.clone())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub name: String,
+    pub overrides: Vec<Override>,
+}
+
+// NAME:TARGET.FIELD=VALUE,TARGET.FIELD=VALUE
+impl FromStr for Branch {
+    type Err = BranchError;
+
+    fn from_str(s: &str) -> Result<Branch, BranchError> {
+        let (name, overrides) = s.split_once(':').ok_or_else(|| BranchError::Syntax(s.to_owned()))?;
+        let overrides = overrides.split(',').map(Override::from_str).collect::<Result<Vec<_>, _>>()?;
+        Ok(Branch { name: name.to_owned(), overrides })
+    }
+}
+
+// Trajectories of the branches compared metric by metric
+pub struct BranchReport {
+    pub from_tick: u64,
+    pub to_tick: u64,
+    pub branches: Vec<String>,
+    // Name of the metric and value in every branch, in the same order of the branches
+    pub metrics: Vec<(String, Vec<f64>)>,
+}
+
+// Metrics of a branch: the final total money and standard of living, and the average of every
+// market series over the ticks of the branch
+fn metrics(sim: &Simulation, from_tick: u64) -> Vec<(String, f64)> {
+    let mut metrics = vec![
+        ("total money".to_owned(), sim.total_money()),
+        ("standard of living".to_owned(), sim.average_standard_of_living().unwrap_or(f64::NAN)),
+    ];
+    let start = sim.recorder.ticks().iter().position(|x| *x >= from_tick).unwrap_or(sim.recorder.ticks().len());
+    for name in sim.recorder.names().iter().filter(|x| x.starts_with("market/")) {
+        let series: Vec<f64> = sim.recorder.series(name).unwrap()[start..].iter().copied()
+            .filter(|x| !x.is_nan()).collect();
+        let mean = series.iter().sum::<f64>() / series.len() as f64;
+        metrics.push((format!("mean {name}"), mean));
+    }
+    metrics
+}
+
+// Runs every branch from the checkpoint to `ticks`. The simulations are returned with the
+// report, in the order of the branches.
+pub fn run_branches(checkpoint: &Path, branches: Vec<Branch>, ticks: Option<u64>)
+                    -> Result<(BranchReport, Vec<Simulation>), BranchError> {
+    let branches: Vec<Branch> = std::iter::once(Branch { name: "base".to_owned(), overrides: vec![] })
+        .chain(branches).collect();
+    let mut sims = vec![];
+    let mut rows: Vec<Vec<(String, f64)>> = vec![];
+    let mut from_tick = 0;
+    let mut to_tick = 0;
+    for branch in branches.iter() {
+        let Checkpoint { ticks: saved_ticks, mut sim, .. } = Checkpoint::load(checkpoint).map_err(BranchError::Checkpoint)?;
+        for o in branch.overrides.iter() {
+            o.apply(&mut sim)?;
+        }
+        from_tick = sim.tick;
+        to_tick = ticks.unwrap_or(saved_ticks);
+        while sim.tick < to_tick {
+            sim.step();
+        }
+        rows.push(metrics(&sim, from_tick));
+        sims.push(sim);
+    }
+    // Every branch records the same series, unless an override changes the markets
+    let metrics = rows[0].iter()
+        .map(|(name, _)| (name.clone(), rows.iter()
+            .map(|x| x.iter().find(|m| &m.0 == name).map_or(f64::NAN, |m| m.1))
+            .collect()))
+        .collect();
+    let report = BranchReport { from_tick, to_tick, branches: branches.into_iter().map(|x| x.name).collect(), metrics };
+    Ok((report, sims))
+}
+
+impl fmt::Display for BranchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} branches from tick {} to tick {}, differences from {}",
+                 self.branches.len(), self.from_tick, self.to_tick, self.branches[0])?;
+        let width = self.metrics.iter().map(|x| x.0.len()).max().unwrap_or(0);
+        write!(f, "{:width$}", "")?;
+        for name in self.branches.iter() {
+            write!(f, " {name:>14}")?;
+        }
+        writeln!(f)?;
+        for (name, values) in self.metrics.iter() {
+            write!(f, "{name:width$}")?;
+            for (i, value) in values.iter().enumerate() {
+                // The branches are compared to the baseline
+                match i {
+                    0 => write!(f, " {value:>14.2}")?,
+                    _ => write!(f, " {:>+14.2}", value - values[0])?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use crate::trace::DecisionLog;
+use crate::{goods, EntityId, GoodUid, Market, OrderResult, OrderType, Owner, Quantity};
+
+// Building of a producer. Every level lets the producer use `input_per_level` base units of
+// input per tick and costs `fixed_cost_per_level` more every tick. A new level is built with
+// `capital_per_level` base units of the capital good, bought on its market with the money set
+// aside for it: when a profitable window makes the producer want to grow past its building, the
+// fraction `reinvest` of the margin of the window is set aside. The money set aside is not
+// spent elsewhere, what is left when the level is built goes back to the producer.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Building {
+    capital_good_uid: GoodUid,
+    level: u32,
+    input_per_level: Quantity,
+    capital_per_level: Quantity,
+    fixed_cost_per_level: f64,
+    reinvest: f64,
+    // Capital bought for the next level
+    capital: Quantity,
+    // Set aside for the capital and not spent yet
+    budget: f64,
+}
+
+impl Building {
+    pub fn new(capital_good_uid: GoodUid, level: u32, input_per_level: Quantity, capital_per_level: Quantity,
+               fixed_cost_per_level: f64, reinvest: f64) -> Building {
+        Building {
+            capital_good_uid,
+            level,
+            input_per_level,
+            capital_per_level,
+            fixed_cost_per_level,
+            reinvest,
+            capital: 0,
+            budget: 0.,
+        }
+    }
+
+    pub fn capital_good_uid(&self) -> GoodUid {
+        self.capital_good_uid
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn capital(&self) -> Quantity {
+        self.capital
+    }
+
+    pub fn budget(&self) -> f64 {
+        self.budget
+    }
+
+    // Max input used per tick
+    pub fn capacity(&self) -> Quantity {
+        self.level as Quantity * self.input_per_level
+    }
+
+    pub fn fixed_cost(&self) -> f64 {
+        self.level as f64 * self.fixed_cost_per_level
+    }
+
+    // The producer wants to use `target` base units of input per tick after a window with
+    // `margin`, it sets money aside for a new level if the building is too small
+    pub fn plan(&mut self, target: Quantity, unit_scale: Quantity, margin: f64, log: &mut DecisionLog) {
+        if target <= self.capacity() || margin <= 0. {
+            return;
+        }
+        self.budget += margin * self.reinvest;
+        log.record("reinvest", Some(self.capital_good_uid), vec![
+            ("margin", margin),
+            ("capacity", goods::to_units(self.capacity(), unit_scale)),
+            ("target", goods::to_units(target, unit_scale)),
+            ("budget", self.budget),
+        ], self.capital_per_level - self.capital);
+    }
+
+    // Buy the capital missing for the next level with the money set aside, spending at most
+    // `money`
+    pub fn invest(&mut self, id: EntityId, markets: &mut [Box<dyn Market>], money: f64, prestige: f64, log: &mut DecisionLog) {
+        if self.budget <= 0. {
+            return;
+        }
+        let Some(market) = markets.iter_mut().find(|x| x.good_uid() == self.capital_good_uid) else {
+            return;
+        };
+        let missing = self.capital_per_level.saturating_sub(self.capital);
+        let required = missing.min(market.affordable_order(self.budget.min(money)).unwrap_or(0));
+        log.record("invest", Some(self.capital_good_uid), vec![
+            ("price", market.price_per_unit()),
+            ("budget", self.budget),
+            ("money_available", money),
+        ], required);
+        let limit_price = market.price_per_unit();
+        let _ = market.register_limit_order(Owner::Entity(id), OrderType::Buy, required, prestige, limit_price);
+    }
+
+    // Collect the capital bought, returns its cost or None if the order wasn't for capital
+    pub fn settle(&mut self, good_uid: GoodUid, result: &OrderResult, log: &mut DecisionLog) -> Option<f64> {
+        if good_uid != self.capital_good_uid || result.ordertype != OrderType::Buy {
+            return None;
+        }
+        self.capital += result.traded_quantity;
+        self.budget = (self.budget - result.total_cost).max(0.);
+        if self.capital >= self.capital_per_level {
+            self.capital -= self.capital_per_level;
+            self.level += 1;
+            self.budget = 0.;
+            log.record("upgrade", Some(good_uid), vec![("level", self.level as f64)], self.capital_per_level);
+        }
+        Some(result.total_cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestMarket;
+
+    #[test]
+    fn profit_past_the_capacity_builds_a_level() {
+        let mut building = Building::new(0, 1, 100, 10, 5., 0.5);
+        let mut log = DecisionLog::default();
+        // Within the building nothing is set aside
+        building.plan(100, 1, 1000., &mut log);
+        assert_eq!(building.budget(), 0.);
+        building.plan(150, 1, 1000., &mut log);
+        assert_eq!(building.budget(), 500.);
+        let mut markets: Vec<Box<dyn Market>> = vec![Box::new(TestMarket::new(0, 1, 20.))];
+        building.invest(EntityId(0), &mut markets, 10_000., 0., &mut log);
+        assert_eq!(markets[0].registered_orders()[0].1.quantity, 10);
+        assert_eq!(building.settle(0, &OrderResult::new(OrderType::Buy, 10, 200.), &mut log), Some(200.));
+        assert_eq!((building.level(), building.capacity(), building.fixed_cost(), building.budget()), (2, 200, 10., 0.));
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use crate::goods;
+use crate::ledger::FlowKind;
+use crate::registry::EntityRegistry;
+use crate::{EntityId, GoodUid, Price, Quantity};
+
+// The central bank of the world. After the trade of every tick it measures the prices with an
+// index, the cost of a fixed basket of goods at the prices of their markets over its cost at the
+// first tick, 100. The inflation is the mean change of the index per tick in the last `window`
+// ticks. From the inflation it sets:
+//  the policy rate, `neutral_rate + rate_response * (inflation - target)`, never negative. The
+//  Bank lends and pays the deposits at this rate plus its own rates.
+//  the base money created at the start of the next tick, `growth - growth_response * (inflation
+//  - target)` of the money supply, never negative, shared equally by the recipients.
+// The base money has no counterpart, like the money of the MonetaryAuthority.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonetaryPolicy {
+    // Inflation per tick
+    pub target: f64,
+    pub neutral_rate: f64,
+    pub rate_response: f64,
+    // Base money created per tick, relative to the money supply
+    pub growth: f64,
+    pub growth_response: f64,
+    pub window: usize,
+}
+
+impl Default for MonetaryPolicy {
+    fn default() -> Self {
+        MonetaryPolicy { target: 0., neutral_rate: 0., rate_response: 0., growth: 0., growth_response: 0., window: 5 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CentralBank {
+    policy: MonetaryPolicy,
+    // Base units of every good of the basket
+    basket: Vec<(GoodUid, Quantity, Quantity)>,
+    recipients: Vec<EntityId>,
+    // Of the basket at the first tick
+    base_cost: Option<f64>,
+    // The last `window` + 1 values of the index
+    index: VecDeque<f64>,
+    rate: f64,
+}
+
+impl CentralBank {
+    // The basket is of goods, their quantities and the unit scales of their goods
+    pub fn new(policy: MonetaryPolicy, basket: Vec<(GoodUid, Quantity, Quantity)>, recipients: Vec<EntityId>) -> CentralBank {
+        let rate = policy.neutral_rate.max(0.);
+        CentralBank { policy, basket, recipients, base_cost: None, index: VecDeque::new(), rate }
+    }
+
+    pub fn policy_rate(&self) -> f64 {
+        self.rate
+    }
+
+    // 100 until the prices are observed
+    pub fn price_index(&self) -> f64 {
+        self.index.back().copied().unwrap_or(100.)
+    }
+
+    pub fn inflation(&self) -> f64 {
+        match (self.index.front(), self.index.back()) {
+            (Some(first), Some(last)) if self.index.len() > 1 && *first > 0. => {
+                (last / first).powf(1. / (self.index.len() - 1) as f64) - 1.
+            }
+            _ => 0.,
+        }
+    }
+
+    fn gap(&self) -> f64 {
+        self.inflation() - self.policy.target
+    }
+
+    // Measure the prices of the tick, `price` gives the price of a good, None without a market
+    pub fn observe(&mut self, price: impl Fn(GoodUid) -> Option<Price>) {
+        let cost: f64 = self.basket.iter()
+            .filter_map(|(good_uid, quantity, unit_scale)| Some(price(*good_uid)? * goods::to_units(*quantity, *unit_scale)))
+            .sum();
+        let base_cost = *self.base_cost.get_or_insert(cost);
+        let index = match base_cost > 0. {
+            true => cost / base_cost * 100.,
+            false => 100.,
+        };
+        self.index.push_back(index);
+        if self.index.len() > self.policy.window.max(1) + 1 {
+            self.index.pop_front();
+        }
+        self.rate = (self.policy.neutral_rate + self.policy.rate_response * self.gap()).max(0.);
+    }
+
+    // Create the base money of the tick for a money supply of `supply`, returns the money created
+    pub fn inject(&self, entities: &mut EntityRegistry, supply: f64) -> f64 {
+        if self.recipients.is_empty() {
+            return 0.;
+        }
+        let growth = (self.policy.growth - self.policy.growth_response * self.gap()).max(0.);
+        let share = supply * growth / self.recipients.len() as f64;
+        let mut created = 0.;
+        for id in self.recipients.iter() {
+            if share > 0. && entities[*id].transfer(FlowKind::Monetary, share) {
+                created += share;
+            }
+        }
+        created
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_follows_the_inflation_of_the_basket() {
+        let policy = MonetaryPolicy { target: 0.01, neutral_rate: 0.02, rate_response: 1.5, growth: 0., growth_response: 0., window: 2 };
+        let mut bank = CentralBank::new(policy, vec![(0, 10, 1), (1, 100, 10)], vec![]);
+        // 10 * 1 + 10 * 4 = 50 at the first tick
+        bank.observe(|good| Some([1., 4.][good]));
+        assert_eq!((bank.price_index(), bank.inflation()), (100., 0.));
+        assert!((bank.policy_rate() - 0.005).abs() < 1e-12);
+        bank.observe(|good| Some([1.5, 4.5][good]));
+        bank.observe(|good| Some([1.5, 4.5][good]));
+        // 60 / 50 in two ticks
+        assert_eq!(bank.price_index(), 120.);
+        assert!((bank.inflation() - (1.2f64.sqrt() - 1.)).abs() < 1e-12);
+        assert!((bank.policy_rate() - (0.02 + 1.5 * (bank.inflation() - 0.01))).abs() < 1e-12);
+        // Only the last two ticks count
+        bank.observe(|good| Some([1.5, 4.5][good]));
+        assert_eq!(bank.inflation(), 0.);
+    }
+}
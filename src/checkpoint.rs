@@ -0,0 +1,105 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::engine::Simulation;
+
+// Savegame of a run: the whole state of the simulation between two ticks, random streams and
+// recorded series included, and the ticks the run has to reach. Written in CBOR.
+
+// Bumped when the saved state changes in an incompatible way
+const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Encode(String),
+    Decode(String),
+    Version(u32),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "cannot access checkpoint: {e}"),
+            CheckpointError::Encode(e) => write!(f, "cannot write checkpoint: {e}"),
+            CheckpointError::Decode(e) => write!(f, "cannot read checkpoint: {e}"),
+            CheckpointError::Version(v) => write!(f, "checkpoint version {v} is not supported, expected {VERSION}"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    version: u32,
+    pub ticks: u64,
+    pub sim: Simulation,
+}
+
+impl Checkpoint {
+    pub fn save(sim: &Simulation, ticks: u64, path: &Path) -> Result<(), CheckpointError> {
+        #[derive(Serialize)]
+        struct CheckpointRef<'a> {
+            version: u32,
+            ticks: u64,
+            sim: &'a Simulation,
+        }
+        // Written aside and renamed, a crash while saving doesn't ruin the previous checkpoint
+        let tmp = path.with_extension("tmp");
+        let mut out = BufWriter::new(File::create(&tmp).map_err(CheckpointError::Io)?);
+        ciborium::ser::into_writer(&CheckpointRef { version: VERSION, ticks, sim }, &mut out)
+            .map_err(|e| CheckpointError::Encode(e.to_string()))?;
+        out.flush().map_err(CheckpointError::Io)?;
+        drop(out);
+        std::fs::rename(&tmp, path).map_err(CheckpointError::Io)
+    }
+
+    pub fn load(path: &Path) -> Result<Checkpoint, CheckpointError> {
+        let input = BufReader::new(File::open(path).map_err(CheckpointError::Io)?);
+        let checkpoint: Checkpoint = ciborium::de::from_reader(input)
+            .map_err(|e| CheckpointError::Decode(e.to_string()))?;
+        if checkpoint.version != VERSION {
+            return Err(CheckpointError::Version(checkpoint.version));
+        }
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scenario;
+
+    #[test]
+    fn a_resumed_run_records_the_same_series() {
+        // The random demand spikes of the shocks draw from the random streams saved in the checkpoint
+        let scenario = Scenario::from_toml(include_str!("../scenarios/shocks.toml")).unwrap();
+        let ticks = scenario.simulation.ticks;
+        let mut whole = scenario.build().unwrap();
+        while whole.tick < ticks {
+            whole.step();
+        }
+        let mut first = scenario.build().unwrap();
+        while first.tick < ticks / 2 {
+            first.step();
+        }
+        let path = std::env::temp_dir().join(format!("ecosim-checkpoint-{}.cbor", std::process::id()));
+        Checkpoint::save(&first, ticks, &path).unwrap();
+        let checkpoint = Checkpoint::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut resumed = checkpoint.unwrap().sim;
+        while resumed.tick < ticks {
+            resumed.step();
+        }
+        assert_eq!(resumed.recorder.ticks(), whole.recorder.ticks());
+        assert_eq!(resumed.recorder.names(), whole.recorder.names());
+        // Bit by bit, the series without a value yet are NaN
+        let bits = |x: Option<&[f64]>| x.unwrap().iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        for name in whole.recorder.names() {
+            assert_eq!(bits(resumed.recorder.series(name)), bits(whole.recorder.series(name)), "{name}");
+        }
+    }
+}
@@ -0,0 +1,479 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use ecosim::accounting::Accounts;
+use ecosim::archetype;
+use ecosim::bench;
+use ecosim::branch::{self, Branch};
+use ecosim::checkpoint::Checkpoint;
+use ecosim::compare;
+use ecosim::differential::{self, Mechanism};
+use ecosim::convergence::SteadyStateDetector;
+use ecosim::events::{JsonlSink, StdoutSink};
+use ecosim::experiment::{self, Manifest};
+use ecosim::freeze::FreezePlan;
+use ecosim::inspector::WorldSnapshot;
+use ecosim::plot::{self, PlotBuilder, PlotFormat};
+use ecosim::recorder::Recorder;
+use ecosim::rundir::{self, RunManifest};
+use ecosim::scenario::Scenario;
+use ecosim::sweep;
+use ecosim::trace::DecisionTrace;
+use ecosim::waste;
+
+const DEFAULT_SCENARIO: &str = include_str!("../scenarios/wheat_bread.toml");
+
+#[derive(Debug, Parser)]
+#[command(name = "ecosim", about = "Agent based simulation of a small economy")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Run a scenario and write series, plots and a manifest to a directory of the run")]
+    Run(Box<RunArgs>),
+    #[command(about = "Draw the plots of an exported run")]
+    Plot {
+        #[arg(long, help = "Series exported by `run`, in CSV")]
+        input: PathBuf,
+        #[arg(long, default_value = ".")]
+        out: PathBuf,
+        #[arg(long, default_value_t = 0, help = "Ticks left out of the plots")]
+        burn_in: u64,
+        #[arg(long, default_value = "png", help = "png or svg")]
+        format: PlotFormat,
+        #[arg(long = "series", value_name = "NAME",
+              help = "Draw only these series in out_series, a name or a prefix ending with `*`")]
+        series: Vec<String>,
+        #[arg(long, default_value = "", help = "Title of the chart of --series")]
+        title: String,
+    },
+    #[command(about = "Run a scenario with many seeds and report the frequency of every regime")]
+    Sweep {
+        #[arg(long)]
+        scenario: Option<PathBuf>,
+        #[arg(long, default_value_t = 100, help = "Number of runs")]
+        seeds: u64,
+        #[arg(long, default_value_t = 0)]
+        first_seed: u64,
+        #[arg(long, help = "Overrides the ticks of the scenario")]
+        ticks: Option<u64>,
+        #[arg(long, help = "Overrides the burn-in of the scenario")]
+        burn_in: Option<u64>,
+        #[arg(long, default_value_t = 0.1, help = "Change still considered steady")]
+        tolerance: f64,
+    },
+    #[command(about = "Fork a checkpoint in branches with different parameters and compare them")]
+    Branch {
+        #[arg(long)]
+        checkpoint: PathBuf,
+        #[arg(long, help = "Overrides the ticks of the checkpoint")]
+        ticks: Option<u64>,
+        #[arg(long = "branch", value_name = "NAME:TARGET.FIELD=VALUE,...",
+              help = "A branch and its overrides, the target is an entity, market/{label} or route/{name}")]
+        branches: Vec<Branch>,
+        #[arg(long, help = "Write the series of every branch to {out}/{branch}/series.csv")]
+        out: Option<PathBuf>,
+    },
+    #[command(about = "Run a scenario with different parameters and compare the runs")]
+    Compare {
+        #[arg(long, help = "Scenario in TOML or RON, the wheat_bread scenario if omitted")]
+        scenario: Option<PathBuf>,
+        #[arg(long, help = "Overrides the ticks of the scenario")]
+        ticks: Option<u64>,
+        #[arg(long = "variant", value_name = "NAME:TARGET.FIELD=VALUE,...",
+              help = "A variant and its overrides, the target is an entity, market/{label} or route/{name}")]
+        variants: Vec<Branch>,
+        #[arg(long, help = "Write the series of all the variants and their plots here")]
+        out: Option<PathBuf>,
+        #[arg(long, default_value = "png", help = "Format of the plots, png or svg")]
+        plot_format: PlotFormat,
+    },
+    #[command(about = "Check that a scenario can be loaded and built")]
+    Validate {
+        #[arg(long)]
+        scenario: PathBuf,
+    },
+    #[command(about = "Print the goods, markets and entities of a scenario and the parameters of their kinds, in JSON")]
+    Describe {
+        #[arg(long)]
+        scenario: PathBuf,
+    },
+    #[command(about = "Time the ticks of a large synthetic world, on one thread and on all of them")]
+    Bench {
+        #[arg(long, default_value_t = 10_000)]
+        entities: usize,
+        #[arg(long, default_value_t = 1_000)]
+        markets: usize,
+        #[arg(long, default_value_t = 10)]
+        ticks: u64,
+    },
+    #[command(about = "Run the same seeded orders through two market mechanisms and compare the outcomes")]
+    MarketDiff {
+        #[arg(long, default_value = "test", help = "test, pooled or order_book")]
+        a: Mechanism,
+        #[arg(long, default_value = "order_book", help = "test, pooled or order_book")]
+        b: Mechanism,
+        #[arg(long, default_value_t = 100)]
+        ticks: u64,
+        #[arg(long, default_value_t = 20, help = "Orders every tick")]
+        orders: usize,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        #[arg(long, default_value_t = 1.0, help = "Starting price of the markets")]
+        price: f64,
+    },
+    #[command(about = "List the archetypes of the entities, or show the fields of one")]
+    Archetypes {
+        name: Option<String>,
+    },
+    #[command(about = "Run the scenarios and seeds of an experiment manifest and index their outputs")]
+    Experiment {
+        #[arg(long, help = "Manifest in TOML, see experiments/seeds.toml")]
+        manifest: PathBuf,
+        #[arg(long, default_value_t = 1, help = "Runs done at the same time, every one in a process")]
+        jobs: usize,
+    },
+    #[cfg(feature = "tui")]
+    #[command(about = "Run a scenario in a live dashboard that can be paused and stepped")]
+    Tui {
+        #[arg(long, help = "Scenario in TOML or RON, the wheat_bread scenario if omitted")]
+        scenario: Option<PathBuf>,
+        #[arg(long, help = "Overrides the ticks of the scenario")]
+        ticks: Option<u64>,
+        #[arg(long, help = "Overrides the seed of the scenario")]
+        seed: Option<u64>,
+    },
+    #[cfg(feature = "web")]
+    #[command(about = "Run scenarios side by side and stream their series to a dashboard in the browser")]
+    Web {
+        #[arg(long = "scenario", value_name = "PATH",
+              help = "Scenario in TOML or RON, once for every run, the wheat_bread scenario if omitted")]
+        scenarios: Vec<PathBuf>,
+        #[arg(long, help = "Overrides the ticks of the scenarios")]
+        ticks: Option<u64>,
+        #[arg(long, help = "Overrides the seed of the scenarios")]
+        seed: Option<u64>,
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        #[arg(long, default_value_t = 0, help = "Milliseconds between two ticks of a run")]
+        delay: u64,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[arg(long, help = "Scenario in TOML or RON, the wheat_bread scenario if omitted")]
+    scenario: Option<PathBuf>,
+    #[arg(long, conflicts_with_all = ["scenario", "seed"], help = "Continue the run saved in this checkpoint")]
+    resume: Option<PathBuf>,
+    #[arg(long, help = "Save a checkpoint of the run here at the end of the run, relative to the run directory")]
+    save: Option<PathBuf>,
+    #[arg(long, requires = "save", help = "Also save the checkpoint every N ticks")]
+    save_every: Option<u64>,
+    #[arg(long, help = "Overrides the ticks of the scenario, or of the checkpoint")]
+    ticks: Option<u64>,
+    #[arg(long, help = "Overrides the seed of the scenario")]
+    seed: Option<u64>,
+    #[arg(long, help = "Overrides the burn-in of the scenario, ticks left out of the report and the plots")]
+    burn_in: Option<u64>,
+    #[arg(long, help = "Directory of series.csv, the plots, the trace and manifest.json, a new one in --runs if omitted")]
+    out: Option<PathBuf>,
+    #[arg(long, default_value = "runs", help = "Where the directories of the runs are created")]
+    runs: PathBuf,
+    #[arg(long, help = "Also export the series to this file, CSV or JSON from the extension")]
+    export: Option<PathBuf>,
+    #[arg(long, default_value = "png", help = "Format of the plots, png or svg")]
+    plot_format: PlotFormat,
+    #[arg(long, value_name = "FROM,TO", help = "Print the differences between the state at two ticks")]
+    diff: Option<String>,
+    #[arg(long, help = "Check the invariants after every stage of every tick")]
+    paranoid: bool,
+    #[arg(long, help = "Print a table row every tick")]
+    dashboard: bool,
+    #[arg(long, value_name = "TICKS", help = "Stop when the market series are flat for this many ticks")]
+    stop_when_steady: Option<u64>,
+    #[arg(long, default_value_t = 1e-3, help = "Relative change per tick still considered flat")]
+    steady_tolerance: f64,
+    #[arg(long, value_name = "ENTITY,...", value_delimiter = ',', help = "Trace the decisions of these entities")]
+    trace: Vec<String>,
+    #[arg(long, value_name = "ENTITY,...", value_delimiter = ',',
+          help = "Record the balance sheets and the income statements of these entities")]
+    statements: Vec<String>,
+    #[arg(long, value_name = "SINK", help = "Log the events of every tick, `-` to stdout or a JSONL file in the run directory")]
+    events: Vec<PathBuf>,
+    #[arg(long, value_name = "ENTITY,...", value_delimiter = ',', requires = "freeze_at",
+          help = "Freeze these entities, they keep their state and replay their orders")]
+    freeze: Vec<String>,
+    #[arg(long, value_name = "TICK", help = "First frozen tick")]
+    freeze_at: Option<u64>,
+    #[arg(long, value_name = "TICKS", default_value_t = 1, help = "Ticks before the freeze whose orders are replayed")]
+    freeze_record: u64,
+}
+
+fn load_scenario(path: &Option<PathBuf>) -> Result<Scenario, Box<dyn Error>> {
+    Ok(match path {
+        Some(path) => Scenario::load(path)?,
+        None => Scenario::from_toml(DEFAULT_SCENARIO)?,
+    })
+}
+
+pub fn run(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    let (mut sim, ticks, source) = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(path)?;
+            (checkpoint.sim, checkpoint.ticks, Some(path))
+        }
+        None => {
+            let mut scenario = load_scenario(&args.scenario)?;
+            if let Some(seed) = args.seed {
+                scenario.simulation.seed = seed;
+            }
+            (scenario.build()?, scenario.simulation.ticks, args.scenario.as_ref())
+        }
+    };
+    let ticks = args.ticks.unwrap_or(ticks);
+    let out = match &args.out {
+        Some(out) => {
+            fs::create_dir_all(out)?;
+            out.clone()
+        }
+        None => {
+            let name = source.and_then(|x| x.file_stem()).and_then(|x| x.to_str()).unwrap_or("wheat_bread");
+            rundir::create(&args.runs, name)?
+        }
+    };
+    let text = match source {
+        Some(path) => fs::read(path)?,
+        None => DEFAULT_SCENARIO.as_bytes().to_vec(),
+    };
+    RunManifest::new(source.map(|x| x.as_path()), &text, sim.rng_streams.seed(), ticks).write(&out)?;
+    let save = args.save.as_ref().map(|x| rundir::artifact(&out, x));
+    if let Some(burn_in) = args.burn_in {
+        sim.burn_in = burn_in;
+    }
+    if let Some(window) = args.stop_when_steady {
+        sim.steady_state = Some(SteadyStateDetector::new(vec![], args.steady_tolerance, window, true));
+    }
+    sim.paranoid = args.paranoid;
+    sim.dashboard = args.dashboard;
+    if !args.trace.is_empty() {
+        if let Some(name) = args.trace.iter().find(|x| !sim.entities.contains(x)) {
+            return Err(format!("--trace: unknown entity `{name}`").into());
+        }
+        sim.trace = Some(DecisionTrace::new(args.trace.clone()));
+    }
+    if !args.statements.is_empty() {
+        if let Some(name) = args.statements.iter().find(|x| !sim.entities.contains(x)) {
+            return Err(format!("--statements: unknown entity `{name}`").into());
+        }
+        sim.accounts = Some(Accounts::new(&args.statements));
+    }
+    for sink in args.events.iter() {
+        match sink.to_str() {
+            Some("-") => sim.events.add_sink(Box::new(StdoutSink)),
+            _ => sim.events.add_sink(Box::new(JsonlSink::create(&rundir::artifact(&out, sink))?)),
+        }
+    }
+    if let (false, Some(at)) = (args.freeze.is_empty(), args.freeze_at) {
+        if let Some(name) = args.freeze.iter().find(|x| !sim.entities.contains(x)) {
+            return Err(format!("--freeze: unknown entity `{name}`").into());
+        }
+        sim.freeze = Some(FreezePlan::new(args.freeze.clone(), at, args.freeze_record));
+    }
+    // Ticks whose starting state are compared at the end of the run
+    let diff_ticks = match &args.diff {
+        Some(spec) => {
+            let (from, to) = spec.split_once(',').ok_or("--diff requires FROM,TO ticks")?;
+            Some((from.parse::<u64>()?, to.parse::<u64>()?))
+        }
+        None => None,
+    };
+    let mut snapshots = Vec::<WorldSnapshot>::new();
+    while sim.tick < ticks {
+        if diff_ticks.is_some_and(|(from, to)| sim.tick == from || sim.tick == to) {
+            snapshots.push(WorldSnapshot::capture(&sim));
+        }
+        sim.step();
+        if sim.should_stop() {
+            break;
+        }
+        if let (Some(path), Some(every)) = (&save, args.save_every) {
+            if sim.tick % every.max(1) == 0 {
+                Checkpoint::save(&sim, ticks, path)?;
+            }
+        }
+    }
+    if let Some(path) = &save {
+        Checkpoint::save(&sim, ticks, path)?;
+    }
+    sim.events.flush()?;
+    if diff_ticks.is_some_and(|(from, to)| sim.tick == from || sim.tick == to) {
+        snapshots.push(WorldSnapshot::capture(&sim));
+    }
+    if let [from, to] = &snapshots[..] {
+        print!("{}", from.diff(to));
+    }
+    if let Some(since) = sim.steady_state.as_ref().and_then(|x| x.since()) {
+        println!("Steady state since tick {since}, run ended at tick {}", sim.tick);
+    }
+    sim.ledger.print_report(sim.burn_in);
+    waste::print_report(&sim.recorder, sim.burn_in);
+    if let Some(accounts) = &sim.accounts {
+        accounts.print_report(&sim.recorder, sim.burn_in);
+    }
+    sim.recorder.to_csv(&out.join("series.csv"))?;
+    if let Some(path) = &args.export {
+        sim.recorder.export(&rundir::artifact(&out, path))?;
+    }
+    if let Some(trace) = &sim.trace {
+        trace.export(&out.join("trace.jsonl"))?;
+    }
+    plot::plot_run(&sim.recorder, &out, sim.burn_in, args.plot_format)?;
+    println!("Run written to {}", out.display());
+    Ok(())
+}
+
+pub fn plot(input: PathBuf, out: PathBuf, burn_in: u64, format: PlotFormat, series: Vec<String>,
+            title: String) -> Result<(), Box<dyn Error>> {
+    let recorder = Recorder::from_csv(&input)?;
+    fs::create_dir_all(&out)?;
+    if series.is_empty() {
+        return plot::plot_run(&recorder, &out, burn_in, format);
+    }
+    let builder = series.iter().fold(PlotBuilder::new(&recorder), |builder, x| builder.series(x));
+    if builder.is_empty() {
+        return Err(format!("No series matching {}", series.join(", ")).into());
+    }
+    builder.title(&title).axes("tick", "").burn_in(burn_in).draw(&plot::plot_path(&out, "out_series", format))
+}
+
+pub fn sweep(scenario: Option<PathBuf>, seeds: u64, first_seed: u64, ticks: Option<u64>, burn_in: Option<u64>,
+             tolerance: f64) -> Result<(), Box<dyn Error>> {
+    let mut scenario = load_scenario(&scenario)?;
+    if let Some(ticks) = ticks {
+        scenario.simulation.ticks = ticks;
+    }
+    if let Some(burn_in) = burn_in {
+        scenario.simulation.burn_in = burn_in;
+    }
+    let report = sweep::sweep(&mut scenario, first_seed..first_seed + seeds, tolerance)?;
+    print!("{report}");
+    Ok(())
+}
+
+pub fn branch(checkpoint: PathBuf, ticks: Option<u64>, branches: Vec<Branch>, out: Option<PathBuf>)
+              -> Result<(), Box<dyn Error>> {
+    let (report, sims) = branch::run_branches(&checkpoint, branches, ticks)?;
+    print!("{report}");
+    if let Some(out) = out {
+        for (name, sim) in report.branches.iter().zip(sims.iter()) {
+            fs::create_dir_all(out.join(name))?;
+            sim.recorder.to_csv(&out.join(name).join("series.csv"))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn compare(scenario: Option<PathBuf>, ticks: Option<u64>, variants: Vec<Branch>, out: Option<PathBuf>,
+               plot_format: PlotFormat) -> Result<(), Box<dyn Error>> {
+    let scenario = load_scenario(&scenario)?;
+    let (report, sims) = compare::compare(&scenario, variants, ticks)?;
+    print!("{report}");
+    if let Some(out) = out {
+        fs::create_dir_all(&out)?;
+        let runs: Vec<(&str, &Recorder)> = report.variants.iter().map(|x| x.as_str())
+            .zip(sims.iter().map(|x| &x.recorder))
+            .collect();
+        let merged = compare::merge(&runs);
+        merged.to_csv(&out.join("series.csv"))?;
+        compare::plot_variants(&merged, &report.variants, &out, scenario.simulation.burn_in, plot_format)?;
+        println!("Comparison written to {}", out.display());
+    }
+    Ok(())
+}
+
+pub fn validate(scenario: PathBuf) -> Result<(), Box<dyn Error>> {
+    let scenario = Scenario::load(&scenario)?;
+    let sim = scenario.build()?;
+    println!("scenario ok: {} ticks, {} regions, {} markets, {} entities, {} routes",
+             scenario.simulation.ticks, sim.regions.len(), sim.markets().count(), sim.entities.len(), sim.routes.len());
+    Ok(())
+}
+
+pub fn describe(scenario: PathBuf) -> Result<(), Box<dyn Error>> {
+    let sim = Scenario::load(&scenario)?.build()?;
+    println!("{}", serde_json::to_string_pretty(&sim.describe())?);
+    Ok(())
+}
+
+pub fn bench(entities: usize, markets: usize, ticks: u64) -> Result<(), Box<dyn Error>> {
+    print!("{}", bench::bench(entities, markets, ticks)?);
+    Ok(())
+}
+
+pub fn market_diff(a: Mechanism, b: Mechanism, ticks: u64, orders: usize, seed: u64, price: f64)
+    -> Result<(), Box<dyn Error>> {
+    let flow = differential::order_flow(seed, ticks, orders, price);
+    let names = (format!("{a:?}"), format!("{b:?}"));
+    print!("{}", differential::compare(&flow, (&names.0, &names.1), a.build(price), b.build(price))?);
+    Ok(())
+}
+
+pub fn archetypes(name: Option<String>) -> Result<(), Box<dyn Error>> {
+    let mut library = archetype::library();
+    let Some(name) = name else {
+        for (name, fields) in library.iter() {
+            println!("{name:<12} {}", fields.get("kind").and_then(|x| x.as_str()).unwrap_or("?"));
+        }
+        return Ok(());
+    };
+    let fields = library.remove(&name).ok_or_else(|| format!("Unknown archetype {name}"))?;
+    print!("{}", toml::to_string(&fields)?);
+    Ok(())
+}
+
+pub fn experiment(manifest: PathBuf, jobs: usize) -> Result<(), Box<dyn Error>> {
+    let report = experiment::run(&Manifest::load(&manifest)?, jobs)?;
+    print!("{report}");
+    match report.outcomes.iter().filter(|x| !x.succeeded()).count() {
+        0 => Ok(()),
+        failed => Err(format!("{failed} runs failed").into()),
+    }
+}
+
+#[cfg(feature = "tui")]
+pub fn tui(scenario: Option<PathBuf>, ticks: Option<u64>, seed: Option<u64>) -> Result<(), Box<dyn Error>> {
+    let mut scenario = load_scenario(&scenario)?;
+    if let Some(seed) = seed {
+        scenario.simulation.seed = seed;
+    }
+    let sim = scenario.build()?;
+    crate::tui::run(sim, ticks.unwrap_or(scenario.simulation.ticks))
+}
+
+#[cfg(feature = "web")]
+pub fn web(scenarios: Vec<PathBuf>, ticks: Option<u64>, seed: Option<u64>, port: u16, delay: u64) -> Result<(), Box<dyn Error>> {
+    let paths = match scenarios.is_empty() {
+        true => vec![None],
+        false => scenarios.into_iter().map(Some).collect(),
+    };
+    let mut runs = vec![];
+    for (i, path) in paths.iter().enumerate() {
+        let mut scenario = load_scenario(path)?;
+        if let Some(seed) = seed {
+            scenario.simulation.seed = seed;
+        }
+        let stem = path.as_ref().and_then(|x| x.file_stem()).map_or("wheat_bread".into(), |x| x.to_string_lossy());
+        // The same scenario twice is told apart by its position
+        let name = match runs.iter().any(|(name, _, _)| *name == stem) {
+            true => format!("{stem}#{i}"),
+            false => stem.into_owned(),
+        };
+        runs.push((name, scenario.build()?, ticks.unwrap_or(scenario.simulation.ticks)));
+    }
+    crate::web::run(runs, port, std::time::Duration::from_millis(delay))
+}
@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use crate::branch::{Branch, BranchError};
+use crate::engine::Simulation;
+use crate::plot::{self, PlotBuilder, PlotFormat};
+use crate::recorder::Recorder;
+use crate::scenario::{Scenario, ScenarioError};
+
+// Comparison of parameterizations: the same scenario is run once for every variant and the
+// variants are compared by their state at the end of the run and by their series, drawn
+// overlaid. A variant is a set of overrides applied to the simulation before the first tick,
+// written like the overrides of a branch. The first variant is always the scenario as it is.
+
+#[derive(Debug)]
+pub enum CompareError {
+    Scenario(ScenarioError),
+    Override(String, BranchError),
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareError::Scenario(e) => write!(f, "{e}"),
+            CompareError::Override(variant, e) => write!(f, "variant `{variant}`: {e}"),
+        }
+    }
+}
+
+impl Error for CompareError {}
+
+pub struct CompareReport {
+    pub variants: Vec<String>,
+    // Ticks run by every variant, fewer than the scenario when a variant stopped
+    pub ticks: Vec<u64>,
+    // Name of the metric and value in every variant, in the same order of the variants
+    pub metrics: Vec<(String, Vec<f64>)>,
+}
+
+// State of a variant at the end of its run: the total money, the standard of living of the
+// pops, the bankrupt producers and the last price of every market
+fn end_state(sim: &Simulation) -> Vec<(String, f64)> {
+    let bankrupt = sim.entities.iter()
+        .filter(|(_, _, x)| x.state_fields().into_iter().any(|f| f.0 == "bankrupt" && f.1 > 0.))
+        .count();
+    let mut metrics = vec![
+        ("total money".to_owned(), sim.total_money()),
+        ("standard of living".to_owned(), sim.average_standard_of_living().unwrap_or(f64::NAN)),
+        ("bankruptcies".to_owned(), bankrupt as f64),
+    ];
+    for name in sim.recorder.names().iter().filter(|x| x.starts_with("market/") && x.ends_with("/price")) {
+        let last = sim.recorder.series(name).unwrap().iter().rev().copied().find(|x| !x.is_nan());
+        metrics.push((format!("final {name}"), last.unwrap_or(f64::NAN)));
+    }
+    metrics
+}
+
+// Runs the scenario with every variant for `ticks`, the ticks of the scenario if None. The
+// simulations are returned with the report, in the order of the variants.
+pub fn compare(scenario: &Scenario, variants: Vec<Branch>, ticks: Option<u64>)
+               -> Result<(CompareReport, Vec<Simulation>), CompareError> {
+    let variants: Vec<Branch> = std::iter::once(Branch { name: "base".to_owned(), overrides: vec![] })
+        .chain(variants).collect();
+    let ticks = ticks.unwrap_or(scenario.simulation.ticks);
+    let mut sims = vec![];
+    let mut rows: Vec<Vec<(String, f64)>> = vec![];
+    for variant in variants.iter() {
+        let mut sim = scenario.build().map_err(CompareError::Scenario)?;
+        for o in variant.overrides.iter() {
+            o.apply(&mut sim).map_err(|e| CompareError::Override(variant.name.clone(), e))?;
+        }
+        while sim.tick < ticks && !sim.should_stop() {
+            sim.step();
+        }
+        rows.push(end_state(&sim));
+        sims.push(sim);
+    }
+    // Every variant has the metrics of the base, unless an override changes the markets
+    let metrics = rows[0].iter()
+        .map(|(name, _)| (name.clone(), rows.iter()
+            .map(|x| x.iter().find(|m| &m.0 == name).map_or(f64::NAN, |m| m.1))
+            .collect()))
+        .collect();
+    let report = CompareReport {
+        variants: variants.into_iter().map(|x| x.name).collect(),
+        ticks: sims.iter().map(|x| x.tick).collect(),
+        metrics,
+    };
+    Ok((report, sims))
+}
+
+// The series of all the runs in one recorder, `{variant}/{series}`. A run shorter than the
+// longest one is padded with NaN.
+pub fn merge(runs: &[(&str, &Recorder)]) -> Recorder {
+    let mut merged = Recorder::default();
+    let Some(longest) = runs.iter().map(|x| x.1).max_by_key(|x| x.ticks().len()) else {
+        return merged;
+    };
+    for (row, tick) in longest.ticks().iter().enumerate() {
+        merged.begin_tick(*tick);
+        for (variant, recorder) in runs.iter() {
+            for name in recorder.names() {
+                if let Some(value) = recorder.series(name).unwrap().get(row) {
+                    merged.record(&format!("{variant}/{name}"), *value);
+                }
+            }
+        }
+    }
+    merged
+}
+
+// A chart for every price and traded quantity of a market and for the money of every entity,
+// with a line for every variant, written as compare_{series}
+pub fn plot_variants(merged: &Recorder, variants: &[String], out_dir: &Path, burn_in: u64, format: PlotFormat)
+                     -> Result<(), Box<dyn Error>> {
+    let Some(base) = variants.first() else {
+        return Ok(());
+    };
+    let compared = merged.names().iter()
+        .filter_map(|x| x.strip_prefix(base.as_str())?.strip_prefix('/'))
+        .filter(|x| match x.split('/').collect::<Vec<_>>()[..] {
+            ["market", .., "price" | "traded"] => true,
+            ["market" | "route", ..] => false,
+            [_, "money"] => true,
+            _ => false,
+        });
+    for series in compared {
+        PlotBuilder::new(merged)
+            .select(|name| variants.iter().find(|v| name.strip_prefix(v.as_str()) == Some(&format!("/{series}")))
+                .cloned())
+            .title(series)
+            .axes("tick", "")
+            .burn_in(burn_in)
+            .draw(&plot::plot_path(out_dir, &format!("compare_{}", series.replace('/', "_")), format))?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for CompareReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} variants, state at the end of the run", self.variants.len())?;
+        let width = self.metrics.iter().map(|x| x.0.len()).max().unwrap_or(0).max("ticks".len());
+        write!(f, "{:width$}", "")?;
+        for name in self.variants.iter() {
+            write!(f, " {name:>14}")?;
+        }
+        writeln!(f)?;
+        write!(f, "{:width$}", "ticks")?;
+        for ticks in self.ticks.iter() {
+            write!(f, " {ticks:>14}")?;
+        }
+        writeln!(f)?;
+        for (name, values) in self.metrics.iter() {
+            write!(f, "{name:width$}")?;
+            for value in values.iter() {
+                write!(f, " {value:>14.2}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_run_the_same_scenario_with_their_overrides() {
+        let scenario = Scenario::from_toml(include_str!("../scenarios/wheat_bread.toml")).unwrap();
+        let variant: Branch = "dear:market/Groceries.price_per_unit=12".parse().unwrap();
+        let (report, sims) = compare(&scenario, vec![variant], Some(5)).unwrap();
+        assert_eq!(report.variants, vec!["base", "dear"]);
+        assert_eq!(report.ticks, vec![5, 5]);
+        let price = &report.metrics.iter().find(|x| x.0 == "final market/Groceries/price").unwrap().1;
+        assert_eq!(price, &vec![10., 12.]);
+        let merged = merge(&[("base", &sims[0].recorder), ("dear", &sims[1].recorder)]);
+        assert_eq!(merged.series("dear/market/Groceries/price").unwrap(), &[12.; 5]);
+    }
+}
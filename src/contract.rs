@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::ledger::FlowKind;
+use crate::registry::EntityRegistry;
+use crate::{goods, EntityId, GoodUid, Market, OrderResult, OrderType, Price, Quantity};
+
+// Forward contracts: a seller delivers a quantity of a good every tick for some ticks, at a
+// price fixed when the contract is signed. The entities offer contracts during the tick and the
+// offers of a region are matched after the trade, the best prices first. A buy offer and a sell
+// offer match when the buyer pays at least what the seller asks, the contract is at the middle
+// price, for the smaller quantity and the shorter duration. The contracts deliver from the next
+// tick, before the production, and are settled like the trades of the markets.
+// A seller without the goods, or a buyer without the money, defaults on what is missing and pays
+// to the other side a penalty, a fraction of its value.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractOffer {
+    pub good_uid: GoodUid,
+    pub otype: OrderType,
+    // Per tick
+    pub quantity: Quantity,
+    // Max price per unit for a buyer, min price per unit for a seller
+    pub price: Price,
+    pub ticks: u64,
+    pub prestige: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contract {
+    pub good_uid: GoodUid,
+    pub buyer: EntityId,
+    pub seller: EntityId,
+    // Per tick
+    pub quantity: Quantity,
+    pub price: Price,
+    pub ticks_left: u64,
+    pub unit_scale: Quantity,
+}
+
+// Delivered and defaulted quantities of the contracts of a good in the tick
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct DeliveryReport {
+    pub delivered: Quantity,
+    pub defaulted: Quantity,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ContractBook {
+    // Fraction of the value of what is not delivered, or not paid, paid by who defaults
+    pub penalty: f64,
+    offers: Vec<(EntityId, ContractOffer)>,
+    contracts: Vec<Contract>,
+    report: BTreeMap<GoodUid, DeliveryReport>,
+}
+
+impl ContractBook {
+    pub fn offer(&mut self, id: EntityId, offer: ContractOffer) {
+        if offer.quantity > 0 && offer.ticks > 0 {
+            self.offers.push((id, offer));
+        }
+    }
+
+    // Quantity per tick of the contracts of `id` on a side of a good
+    pub fn committed(&self, id: EntityId, good_uid: GoodUid, otype: OrderType) -> Quantity {
+        self.contracts.iter()
+            .filter(|x| x.good_uid == good_uid)
+            .filter(|x| match otype {
+                OrderType::Buy => x.buyer == id,
+                OrderType::Sell => x.seller == id,
+            })
+            .map(|x| x.quantity)
+            .sum()
+    }
+
+    pub fn contracts(&self) -> &[Contract] {
+        &self.contracts
+    }
+
+    // Match the offers of the tick, the goods without a market in the region have no contracts
+    pub fn sign(&mut self, markets: &[Box<dyn Market>]) {
+        let mut offers = BTreeMap::<GoodUid, (Vec<(EntityId, ContractOffer)>, Vec<(EntityId, ContractOffer)>)>::new();
+        for (id, offer) in self.offers.drain(..) {
+            let sides = offers.entry(offer.good_uid).or_default();
+            match offer.otype {
+                OrderType::Buy => sides.0.push((id, offer)),
+                OrderType::Sell => sides.1.push((id, offer)),
+            }
+        }
+        for (good_uid, (mut buy, mut sell)) in offers {
+            let Some(market) = markets.iter().find(|x| x.good_uid() == good_uid) else {
+                continue;
+            };
+            // Price priority, then prestige priority, the sort is stable
+            buy.sort_by(|a, b| b.1.price.total_cmp(&a.1.price).then(b.1.prestige.total_cmp(&a.1.prestige)));
+            sell.sort_by(|a, b| a.1.price.total_cmp(&b.1.price).then(b.1.prestige.total_cmp(&a.1.prestige)));
+            let (mut i, mut j) = (0, 0);
+            while i < buy.len() && j < sell.len() && buy[i].1.price >= sell[j].1.price {
+                let quantity = buy[i].1.quantity.min(sell[j].1.quantity);
+                self.contracts.push(Contract {
+                    good_uid,
+                    buyer: buy[i].0,
+                    seller: sell[j].0,
+                    quantity,
+                    price: (buy[i].1.price + sell[j].1.price) / 2.,
+                    ticks_left: buy[i].1.ticks.min(sell[j].1.ticks),
+                    unit_scale: market.unit_scale(),
+                });
+                buy[i].1.quantity -= quantity;
+                sell[j].1.quantity -= quantity;
+                if buy[i].1.quantity == 0 { i += 1; }
+                if sell[j].1.quantity == 0 { j += 1; }
+            }
+        }
+    }
+
+    // Deliver the goods of every contract and pay for them, the penalties of the defaults too
+    pub fn deliver(&mut self, entities: &mut EntityRegistry) {
+        self.report.clear();
+        for contract in self.contracts.iter_mut() {
+            let good_uid = contract.good_uid;
+            let stock = entities[contract.seller].inventory().into_iter()
+                .find(|x| x.0 == good_uid).map_or(0, |x| x.1);
+            let available = contract.quantity.min(stock);
+            let affordable = (entities[contract.buyer].money_balance().max(0.) / contract.price
+                * contract.unit_scale as f64) as Quantity;
+            let delivered = available.min(affordable);
+            let value = |quantity: Quantity| goods::to_units(quantity, contract.unit_scale) * contract.price;
+            if delivered > 0 {
+                entities[contract.seller].settle_order(good_uid, OrderResult::new(OrderType::Sell, delivered, value(delivered)));
+                entities[contract.buyer].settle_order(good_uid, OrderResult::new(OrderType::Buy, delivered, value(delivered)));
+            }
+            // The seller defaults on the goods it doesn't have, the buyer on the ones it can't pay
+            let penalty = |quantity: Quantity| value(quantity) * self.penalty;
+            pay_penalty(entities, contract.seller, contract.buyer, penalty(contract.quantity - available));
+            pay_penalty(entities, contract.buyer, contract.seller, penalty(available - delivered));
+            let report = self.report.entry(good_uid).or_default();
+            report.delivered += delivered;
+            report.defaulted += contract.quantity - delivered;
+            contract.ticks_left -= 1;
+        }
+        self.contracts.retain(|x| x.ticks_left > 0);
+    }
+
+    // Deliveries of the last tick, by good
+    pub fn report(&self) -> &BTreeMap<GoodUid, DeliveryReport> {
+        &self.report
+    }
+
+    pub fn clear_state(&mut self) {
+        self.offers.clear();
+    }
+}
+
+// As much of `amount` as the payer has
+fn pay_penalty(entities: &mut EntityRegistry, payer: EntityId, payee: EntityId, amount: f64) {
+    let amount = amount.min(entities[payer].money_balance()).max(0.);
+    if amount == 0. || !entities[payer].transfer(FlowKind::Penalty, -amount) {
+        return;
+    }
+    if !entities[payee].transfer(FlowKind::Penalty, amount) {
+        entities[payer].transfer(FlowKind::Penalty, amount);
+    }
+}
+
+// How an entity offers forward contracts: `share` of what it produces, or uses, every tick for
+// `ticks` ticks, at `price` times the spot price. A share of 0 offers nothing.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct ContractPolicy {
+    pub ticks: u64,
+    pub share: f64,
+    pub price: f64,
+}
+
+impl ContractPolicy {
+    // Offer the part of the share of `per_tick` not covered yet by the contracts of `id`.
+    // Returns the quantity offered.
+    pub fn offer(&self, id: EntityId, market: &dyn Market, otype: OrderType, per_tick: Quantity, prestige: f64,
+                 contracts: &mut ContractBook) -> Quantity {
+        let target = (per_tick as f64 * self.share.clamp(0., 1.)) as Quantity;
+        let quantity = target.saturating_sub(contracts.committed(id, market.good_uid(), otype));
+        contracts.offer(id, ContractOffer {
+            good_uid: market.good_uid(),
+            otype,
+            quantity,
+            price: market.price_per_unit() * self.price,
+            ticks: self.ticks,
+            prestige,
+        });
+        quantity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestMarket;
+
+    fn offer(otype: OrderType, quantity: Quantity, price: Price, ticks: u64) -> ContractOffer {
+        ContractOffer { good_uid: 0, otype, quantity, price, ticks, prestige: 0. }
+    }
+
+    #[test]
+    fn best_prices_sign_first() {
+        let markets: Vec<Box<dyn Market>> = vec![Box::new(TestMarket::new(0, 1, 2.))];
+        let mut book = ContractBook::default();
+        book.offer(EntityId(0), offer(OrderType::Buy, 100, 2.2, 10));
+        book.offer(EntityId(1), offer(OrderType::Buy, 50, 3., 5));
+        book.offer(EntityId(2), offer(OrderType::Sell, 120, 2., 8));
+        book.offer(EntityId(3), offer(OrderType::Sell, 100, 2.5, 8));
+        book.sign(&markets);
+        let signed: Vec<_> = book.contracts().iter().map(|x| (x.buyer.0, x.seller.0, x.quantity, x.price, x.ticks_left)).collect();
+        assert_eq!(signed, vec![(1, 2, 50, 2.5, 5), (0, 2, 70, 2.1, 8)]);
+        assert_eq!(book.committed(EntityId(2), 0, OrderType::Sell), 120);
+        assert_eq!(book.committed(EntityId(3), 0, OrderType::Sell), 0);
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use crate::recorder::Recorder;
+
+// Detects when the run reached a steady state: every chosen series stayed within the tolerance
+// of its previous value for `window` consecutive ticks. The series are chosen by the prefix of
+// their name in the recorder, by default all the market series.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SteadyStateDetector {
+    metrics: Vec<String>,
+    // Relative change per tick still considered flat
+    tolerance: f64,
+    window: u64,
+    // Stop the run when the steady state is reached, otherwise it's only reported
+    pub stop: bool,
+    streak: u64,
+    since: Option<u64>,
+}
+
+impl SteadyStateDetector {
+    pub fn new(metrics: Vec<String>, tolerance: f64, window: u64, stop: bool) -> SteadyStateDetector {
+        let metrics = if metrics.is_empty() { vec!["market/".to_owned()] } else { metrics };
+        SteadyStateDetector { metrics, tolerance, window: window.max(1), stop, streak: 0, since: None }
+    }
+
+    // First tick of the current steady state
+    pub fn since(&self) -> Option<u64> {
+        self.since
+    }
+
+    fn is_flat(&self, recorder: &Recorder) -> bool {
+        let mut any = false;
+        for name in recorder.names().iter().filter(|x| self.metrics.iter().any(|m| x.starts_with(m.as_str()))) {
+            let series = recorder.series(name).unwrap();
+            let [.., before, now] = series else {
+                return false;
+            };
+            if before.is_nan() || now.is_nan() || (now - before).abs() > self.tolerance * before.abs().max(1.) {
+                return false;
+            }
+            any = true;
+        }
+        any
+    }
+
+    // Called once the recorder has the whole row of the tick
+    pub fn observe(&mut self, recorder: &Recorder, tick: u64) {
+        if self.is_flat(recorder) {
+            self.streak += 1;
+            if self.streak >= self.window && self.since.is_none() {
+                self.since = Some(tick + 1 - self.window);
+            }
+        } else {
+            self.streak = 0;
+            self.since = None;
+        }
+    }
+}
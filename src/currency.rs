@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::market::OrderError;
+use crate::Owner;
+
+// Every region pays in its own currency: the balances of its entities and the prices of its
+// markets are in it. Without currencies in the scenario all the regions share a single one.
+// Money goes from a currency to another only through the foreign exchange markets, where the
+// currencies trade against each other at a floating rate.
+
+pub type CurrencyId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: CurrencyId,
+}
+
+impl Money {
+    pub fn new(amount: f64, currency: CurrencyId) -> Money {
+        Money { amount, currency }
+    }
+}
+
+// Ticket of an exchange, valid until the state of the market is cleared
+pub type FxOrderId = usize;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FxOrder {
+    owner: Owner,
+    give: Money,
+    result: Option<FxResult>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FxResult {
+    pub given: Money,
+    pub received: Money,
+}
+
+// Exchange of `base` and `quote`, the rate is the quote a unit of base is worth. The orders give
+// an amount of one of the two and get the other. The orders giving base and the ones giving quote
+// are matched at the rate, the desk of the market fills the imbalance from its reserves as long
+// as they last and the orders left over are filled in part, all in the same proportion.
+// The rate follows the imbalance: it falls by `adjustment` when more base is given than taken,
+// it rises when less.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FxMarket {
+    pub base: CurrencyId,
+    pub quote: CurrencyId,
+    rate: f64,
+    adjustment: f64,
+    // Money of the desk, in base and in quote
+    reserves: (f64, f64),
+    money_flows: MoneyFlows,
+    orders: Vec<FxOrder>,
+    // Base exchanged in the last clearing
+    traded: f64,
+}
+
+impl FxMarket {
+    pub fn new(base: CurrencyId, quote: CurrencyId, rate: f64, adjustment: f64, reserves: (f64, f64)) -> FxMarket {
+        FxMarket {
+            base,
+            quote,
+            rate,
+            adjustment,
+            reserves,
+            money_flows: MoneyFlows::default(),
+            orders: vec![],
+            traded: 0.,
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn reserves(&self) -> (Money, Money) {
+        (Money::new(self.reserves.0, self.base), Money::new(self.reserves.1, self.quote))
+    }
+
+    pub fn traded(&self) -> f64 {
+        self.traded
+    }
+
+    pub fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    pub fn trades(&self, a: CurrencyId, b: CurrencyId) -> bool {
+        (a, b) == (self.base, self.quote) || (b, a) == (self.base, self.quote)
+    }
+
+    // Units of `to` a unit of `from` is worth, one of the two is base and the other quote
+    pub fn rate_of(&self, from: CurrencyId, to: CurrencyId) -> f64 {
+        debug_assert!(self.trades(from, to));
+        match from == self.base {
+            true => self.rate,
+            false => 1. / self.rate,
+        }
+    }
+
+    // Give `give` for the other currency of the market
+    pub fn register(&mut self, owner: Owner, give: Money) -> Result<FxOrderId, OrderError> {
+        assert!(give.currency == self.base || give.currency == self.quote, "The market doesn't trade the currency");
+        if give.amount.is_nan() || give.amount <= 0. {
+            return Err(OrderError::ZeroQuantity);
+        }
+        self.orders.push(FxOrder { owner, give, result: None });
+        Ok(self.orders.len() - 1)
+    }
+
+    pub fn clear(&mut self) {
+        if self.orders.is_empty() {
+            return;
+        }
+        let given = |currency: CurrencyId| -> f64 {
+            self.orders.iter().filter(|x| x.give.currency == currency).map(|x| x.give.amount).sum()
+        };
+        // In base, what is offered and what is asked
+        let offered = given(self.base);
+        let asked = given(self.quote) / self.rate;
+        // The desk takes the imbalance, up to its reserves of the currency it pays
+        let filled_offered = asked + (offered - asked).clamp(0., self.reserves.1 / self.rate);
+        let filled_asked = offered + (asked - offered).clamp(0., self.reserves.0);
+        let fill = |filled: f64, total: f64| if total > 0. { (filled / total).min(1.) } else { 0. };
+        let (fill_offered, fill_asked) = (fill(filled_offered, offered), fill(filled_asked, asked));
+        let (mut base, mut quote) = (0., 0.);
+        for order in self.orders.iter_mut() {
+            let result = match order.give.currency == self.base {
+                true => {
+                    let given = order.give.amount * fill_offered;
+                    base += given;
+                    quote -= given * self.rate;
+                    FxResult { given: Money::new(given, self.base), received: Money::new(given * self.rate, self.quote) }
+                }
+                false => {
+                    let given = order.give.amount * fill_asked;
+                    quote += given;
+                    base -= given / self.rate;
+                    FxResult { given: Money::new(given, self.quote), received: Money::new(given / self.rate, self.base) }
+                }
+            };
+            order.result = Some(result);
+        }
+        self.reserves.0 += base;
+        self.reserves.1 += quote;
+        self.money_flows.record(FlowKind::Exchange, base);
+        self.money_flows.record(FlowKind::Exchange, quote);
+        self.traded = (offered * fill_offered).max(asked * fill_asked);
+        if offered > asked {
+            self.rate *= 1. - self.adjustment;
+        } else if offered < asked {
+            self.rate *= 1. + self.adjustment;
+        }
+    }
+
+    pub fn retrieve_order_result(&self, id: FxOrderId) -> Option<FxResult> {
+        self.orders.get(id).and_then(|x| x.result)
+    }
+
+    // Owners of the orders registered since the state was cleared and what they give
+    pub fn registered_orders(&self) -> Vec<(Owner, Money)> {
+        self.orders.iter().map(|x| (x.owner, x.give)).collect()
+    }
+
+    pub fn clear_state(&mut self) {
+        self.orders.clear();
+        self.traded = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desk_fills_the_imbalance_and_the_rate_follows() {
+        let mut market = FxMarket::new(0, 1, 2., 0.1, (10., 100.));
+        let seller = market.register(Owner::Route, Money::new(50., 0)).unwrap();
+        let buyer = market.register(Owner::Route, Money::new(40., 1)).unwrap();
+        assert_eq!(market.register(Owner::Route, Money::new(0., 1)), Err(OrderError::ZeroQuantity));
+        market.clear();
+        // 50 base offered and 20 asked, the desk takes the other 30
+        let sold = market.retrieve_order_result(seller).unwrap();
+        assert_eq!((sold.given.amount, sold.received.amount), (50., 100.));
+        let bought = market.retrieve_order_result(buyer).unwrap();
+        assert_eq!((bought.given.amount, bought.received.amount), (40., 20.));
+        assert_eq!(market.reserves().0.amount + market.reserves().1.amount / 2., 10. + 50.);
+        assert_eq!(market.rate(), 1.8);
+    }
+
+    #[test]
+    fn the_total_money_is_in_the_home_currency() {
+        let scenario = crate::Scenario::from_toml(include_str!("../scenarios/two_currencies.toml")).unwrap();
+        let mut sim = scenario.build().unwrap();
+        for _ in 0..5 {
+            sim.step();
+        }
+        // The Marks count at the rate of the Crown, the first region's currency
+        let (crown, mark) = (sim.home_currency(), 1 - sim.home_currency());
+        let totals = sim.money_by_currency();
+        let total = totals[crown] + totals[mark] * sim.fx[0].rate_of(mark, crown);
+        assert!((sim.total_money() - total).abs() < 1e-9 * total);
+        assert!(sim.fx[0].rate() != 1.);
+    }
+}
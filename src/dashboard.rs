@@ -0,0 +1,41 @@
+use crate::engine::Simulation;
+
+// Compact table printed while the simulation runs, one row per tick with the price and the
+// traded volume of every market, the total money and the average standard of living of the pops.
+
+const WIDTH: usize = 12;
+
+// Titles of the market columns, a column is wide enough for its title
+fn market_columns(sim: &Simulation) -> Vec<(String, String)> {
+    sim.markets()
+        .map(|(region, market)| {
+            let label = sim.market_label(region, market.good_uid());
+            (format!("{label} price"), format!("{label} vol"))
+        })
+        .collect()
+}
+
+pub fn header(sim: &Simulation) -> String {
+    let mut line = format!("{:>6}", "tick");
+    for (price, volume) in market_columns(sim) {
+        line += &format!(" {price:>WIDTH$} {volume:>WIDTH$}");
+    }
+    line += &format!(" {:>WIDTH$} {:>WIDTH$}", "money", "avg SoL");
+    line
+}
+
+// Called after the trade, before the markets are cleared
+pub fn row(sim: &Simulation) -> String {
+    let mut line = format!("{:>6}", sim.tick);
+    for ((_, market), (price, volume)) in sim.markets().zip(market_columns(sim)) {
+        let traded = sim.goods.to_units(market.good_uid(), market.trade_report().traded);
+        line += &format!(" {:>w1$.2} {:>w2$.2}", market.price_per_unit(), traded,
+                         w1 = price.len().max(WIDTH), w2 = volume.len().max(WIDTH));
+    }
+    line += &format!(" {:>WIDTH$.2}", sim.total_money());
+    match sim.average_standard_of_living() {
+        Some(sol) => line += &format!(" {sol:>WIDTH$.2}"),
+        None => line += &format!(" {:>WIDTH$}", "-"),
+    }
+    line
+}
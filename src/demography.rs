@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use crate::Quantity;
+
+// Size of a pop and how it changes. A pop that consumed all its goods grows by `birth_rate`
+// every tick, a pop that consumed none of them shrinks by `death_rate`, in between the change
+// follows the standard of living gained in the tick. The consumption, the desired inventory
+// and the labor of a pop are per head, so a growing pop asks for more goods and earns more.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Demography {
+    population: u64,
+    birth_rate: f64,
+    death_rate: f64,
+    // Fraction of a head not yet born or dead
+    remainder: f64,
+}
+
+impl Default for Demography {
+    fn default() -> Self {
+        Demography::new(1, 0., 0.)
+    }
+}
+
+impl Demography {
+    pub fn new(population: u64, birth_rate: f64, death_rate: f64) -> Demography {
+        Demography { population, birth_rate, death_rate, remainder: 0. }
+    }
+
+    pub fn population(&self) -> u64 {
+        self.population
+    }
+
+    // The quantity of the whole pop
+    pub fn scale(&self, per_head: Quantity) -> Quantity {
+        per_head * self.population
+    }
+
+    // `satisfaction` goes from -1, nothing consumed, to 1, everything consumed.
+    // Returns the change of the population.
+    pub fn update(&mut self, satisfaction: f64) -> i64 {
+        let rate = match satisfaction >= 0. {
+            true => self.birth_rate * satisfaction,
+            false => self.death_rate * satisfaction,
+        };
+        self.remainder += self.population as f64 * rate;
+        let change = (self.remainder.trunc() as i64).max(-(self.population as i64));
+        self.remainder -= self.remainder.trunc();
+        self.population = self.population.saturating_add_signed(change);
+        change
+    }
+}
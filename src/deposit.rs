@@ -0,0 +1,126 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::goods;
+use crate::rng::SimRng;
+use crate::trace::DecisionLog;
+use crate::{GoodUid, Quantity};
+
+// Deposit an RGO extracts its good from. The efficiency of the extraction is the reserve left
+// over the size of the deposits found: a full deposit gives a unit of good for every unit of
+// production, a half empty one half a unit for the same labor and costs. The reserve regrows by
+// `regeneration` every tick, never past the size. When the efficiency falls below
+// `Prospecting::below` the RGO pays for an attempt to find a new deposit every tick, found with
+// a chance drawn from its random stream.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prospecting {
+    // Paid for every attempt
+    pub cost: f64,
+    // Chance that an attempt finds a deposit
+    pub chance: f64,
+    // Size of a deposit found, in base units
+    pub discovery: Quantity,
+    // Efficiency under which the attempts are made
+    pub below: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deposit {
+    // Left to extract
+    reserve: Quantity,
+    // Of all the deposits found
+    size: Quantity,
+    regeneration: Quantity,
+    prospecting: Option<Prospecting>,
+    // None until the RGO joins the simulation, nothing is found without it
+    rng: Option<SimRng>,
+}
+
+impl Deposit {
+    pub fn new(reserve: Quantity, regeneration: Quantity, prospecting: Option<Prospecting>) -> Deposit {
+        Deposit {
+            reserve,
+            size: reserve,
+            regeneration,
+            prospecting,
+            rng: None,
+        }
+    }
+
+    pub fn reserve(&self) -> Quantity {
+        self.reserve
+    }
+
+    pub fn size(&self) -> Quantity {
+        self.size
+    }
+
+    pub fn efficiency(&self) -> f64 {
+        match self.size {
+            0 => 0.,
+            size => self.reserve as f64 / size as f64,
+        }
+    }
+
+    pub fn attach_rng(&mut self, rng: SimRng) {
+        self.rng = Some(rng);
+    }
+
+    // Good extracted by `production` base units of production, taken from the reserve
+    pub fn extract(&mut self, production: Quantity) -> Quantity {
+        let extracted = ((production as f64 * self.efficiency()) as Quantity).min(self.reserve);
+        self.reserve -= extracted;
+        extracted
+    }
+
+    pub fn regenerate(&mut self) {
+        self.reserve = (self.reserve + self.regeneration).min(self.size);
+    }
+
+    // Looks for a new deposit if the efficiency is low and `money` pays for it, returns the cost
+    // of the attempt
+    pub fn prospect(&mut self, good_uid: GoodUid, money: f64, unit_scale: Quantity, log: &mut DecisionLog) -> f64 {
+        let Some(prospecting) = &self.prospecting else {
+            return 0.;
+        };
+        let efficiency = self.efficiency();
+        if efficiency >= prospecting.below || money < prospecting.cost {
+            return 0.;
+        }
+        let found = match self.rng.as_mut() {
+            Some(rng) => rng.gen_bool(prospecting.chance.clamp(0., 1.)),
+            None => false,
+        };
+        let discovery = if found { prospecting.discovery } else { 0 };
+        log.record("prospect", Some(good_uid), vec![
+            ("efficiency", efficiency),
+            ("reserve", goods::to_units(self.reserve, unit_scale)),
+            ("cost", prospecting.cost),
+        ], discovery);
+        let cost = prospecting.cost;
+        self.reserve += discovery;
+        self.size += discovery;
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use super::*;
+
+    #[test]
+    fn depleted_deposit_extracts_less_until_a_new_one_is_found() {
+        let prospecting = Prospecting { cost: 10., chance: 1., discovery: 100, below: 0.5 };
+        let mut deposit = Deposit::new(100, 0, Some(prospecting));
+        deposit.attach_rng(SimRng::seed_from_u64(0));
+        let mut log = DecisionLog::default();
+        assert_eq!(deposit.extract(40), 40);
+        assert_eq!(deposit.prospect(0, 100., 1, &mut log), 0.);
+        // 60 left of 100, the same production extracts 60% of it
+        assert_eq!(deposit.extract(50), 30);
+        assert_eq!(deposit.prospect(0, 5., 1, &mut log), 0.);
+        assert_eq!(deposit.prospect(0, 100., 1, &mut log), 10.);
+        assert_eq!((deposit.reserve(), deposit.size()), (130, 200));
+    }
+}
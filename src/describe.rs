@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use serde_json::Value;
+use crate::engine::Simulation;
+use crate::goods::Good;
+use crate::{EntityId, GoodUid, Price, Quantity};
+
+// Machine readable description of a built simulation: its goods, markets and entities, and the
+// parameters of every kind of market and entity with their JSON types, the fields of the kind
+// in the checkpoints. Frontends use it to build editors and autocomplete without knowing the
+// kinds in advance.
+
+#[derive(Debug, Serialize)]
+pub struct Description {
+    pub goods: Vec<GoodDescription>,
+    pub regions: Vec<String>,
+    pub markets: Vec<MarketDescription>,
+    pub entities: Vec<EntityDescription>,
+    // Parameters by kind, of the kinds in the simulation
+    pub market_kinds: BTreeMap<String, Parameters>,
+    pub entity_kinds: BTreeMap<String, Parameters>,
+}
+
+// JSON type of every parameter: boolean, integer, number, string, array, object or null
+pub type Parameters = BTreeMap<String, &'static str>;
+
+#[derive(Debug, Serialize)]
+pub struct GoodDescription {
+    pub uid: GoodUid,
+    #[serde(flatten)]
+    pub good: Good,
+    pub unit_scale: Quantity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarketDescription {
+    pub label: String,
+    pub region: String,
+    pub good: String,
+    pub kind: String,
+    pub price: Price,
+    pub lot_size: Quantity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityDescription {
+    pub id: EntityId,
+    pub name: String,
+    pub region: String,
+    pub kind: String,
+    // Goods of the markets it trades in
+    pub goods: Vec<String>,
+    pub money: f64,
+    // In units
+    pub inventory: BTreeMap<String, f64>,
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(x) if x.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// The kind and the parameters of a market or an entity, from its tagged serialization
+fn kind_of(value: Result<Value, serde_json::Error>) -> (String, Parameters) {
+    let Ok(Value::Object(mut fields)) = value else {
+        return ("?".to_owned(), Parameters::new());
+    };
+    let kind = match fields.remove("kind") {
+        Some(Value::String(kind)) => kind,
+        _ => "?".to_owned(),
+    };
+    (kind, fields.iter().map(|(name, value)| (name.clone(), json_type(value))).collect())
+}
+
+impl Simulation {
+    pub fn describe(&self) -> Description {
+        let mut market_kinds = BTreeMap::new();
+        let mut entity_kinds = BTreeMap::new();
+        let markets = self.markets().map(|(region, market)| {
+            let (kind, parameters) = kind_of(serde_json::to_value(market));
+            market_kinds.entry(kind.clone()).or_insert(parameters);
+            MarketDescription {
+                label: self.market_label(region, market.good_uid()),
+                region: self.regions[region].name.clone(),
+                good: self.goods.get_good_name(market.good_uid()),
+                kind,
+                price: market.price_per_unit(),
+                lot_size: market.lot_size(),
+            }
+        }).collect();
+        let entities = self.entities.iter().map(|(id, name, entity)| {
+            let (kind, parameters) = kind_of(serde_json::to_value(entity));
+            entity_kinds.entry(kind.clone()).or_insert(parameters);
+            EntityDescription {
+                id,
+                name: name.to_owned(),
+                region: self.regions[self.entities.region(id)].name.clone(),
+                kind,
+                goods: entity.get_required_markets().0.into_iter().map(|x| self.goods.get_good_name(x)).collect(),
+                money: entity.money_balance(),
+                inventory: entity.inventory().into_iter()
+                    .map(|(good_uid, quantity)| (self.goods.get_good_name(good_uid), self.goods.to_units(good_uid, quantity)))
+                    .collect(),
+            }
+        }).collect();
+        Description {
+            goods: self.goods.goods().iter().enumerate()
+                .map(|(uid, good)| GoodDescription { uid, good: good.clone(), unit_scale: good.unit_scale() })
+                .collect(),
+            regions: self.regions.iter().map(|x| x.name.clone()).collect(),
+            markets,
+            entities,
+            market_kinds,
+            entity_kinds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scenario::Scenario;
+
+    #[test]
+    fn kinds_list_their_parameters() {
+        let sim = Scenario::from_toml(include_str!("../scenarios/wheat_bread.toml")).unwrap().build().unwrap();
+        let description = sim.describe();
+        assert_eq!(description.goods.len(), sim.goods.goods().len());
+        assert_eq!(description.entities.len(), sim.entities.len());
+        assert_eq!(description.entity_kinds["RGOSingle"]["max_production_rate"], "integer");
+        assert_eq!(description.entity_kinds["RGOSingle"]["per_unit_cost"], "number");
+        assert!(description.market_kinds.values().all(|x| !x.contains_key("kind")));
+    }
+}
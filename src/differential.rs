@@ -0,0 +1,176 @@
+use std::fmt;
+use std::str::FromStr;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+use crate::orderbook::OrderBookMarket;
+use crate::rng::SimRng;
+use crate::tiers::TierPolicy;
+use crate::{EntityId, Market, OrderError, OrderType, Owner, Price, Quantity, TestMarket};
+
+// Differential testing of the market mechanisms: the same seeded flow of orders goes through two
+// markets, tick after tick, and what every order got in the two is compared. A new mechanism can
+// be validated against a known one, and the differences in allocations and prices measured.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mechanism {
+    // TestMarket, equal split in prestige tiers
+    Test,
+    // TestMarket, equal split among all the orders
+    Pooled,
+    // OrderBookMarket, call auction on the limit prices
+    OrderBook,
+}
+
+impl Mechanism {
+    pub fn build(self, price: Price) -> Box<dyn Market> {
+        match self {
+            Mechanism::Test => Box::new(TestMarket::new(0, 1, price)),
+            Mechanism::Pooled => Box::new(TestMarket { tier_policy: TierPolicy::Pooled, ..TestMarket::new(0, 1, price) }),
+            Mechanism::OrderBook => Box::new(OrderBookMarket::new(0, 1, 1, price)),
+        }
+    }
+}
+
+impl FromStr for Mechanism {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mechanism, String> {
+        match s {
+            "test" => Ok(Mechanism::Test),
+            "pooled" => Ok(Mechanism::Pooled),
+            "order_book" => Ok(Mechanism::OrderBook),
+            _ => Err(format!("Unknown market mechanism {s}, expected test, pooled or order_book")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FlowOrder {
+    pub owner: EntityId,
+    pub otype: OrderType,
+    pub quantity: Quantity,
+    pub prestige: f64,
+    pub limit_price: Option<Price>,
+}
+
+// `orders` random orders every tick: up to 100 units, prestige from 0 to 2 and half of them
+// with a limit within 20% of `price`. The owners are the positions of the orders in the tick.
+pub fn order_flow(seed: u64, ticks: u64, orders: usize, price: Price) -> Vec<Vec<FlowOrder>> {
+    let mut rng = SimRng::seed_from_u64(seed);
+    (0..ticks).map(|_| {
+        (0..orders).map(|owner| FlowOrder {
+            owner: EntityId(owner),
+            otype: if rng.gen_bool(0.5) { OrderType::Buy } else { OrderType::Sell },
+            quantity: rng.gen_range(1..=100),
+            prestige: rng.gen_range(0..3) as f64,
+            limit_price: rng.gen_bool(0.5).then(|| price * rng.gen_range(0.8..1.2)),
+        }).collect()
+    }).collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TickDiff {
+    pub tick: u64,
+    pub traded: (Quantity, Quantity),
+    pub price: (Price, Price),
+    // Sum over the orders of the difference of their traded quantities
+    pub allocation_gap: Quantity,
+    // Orders that traded a different quantity
+    pub orders_differing: usize,
+}
+
+pub struct DiffReport {
+    pub names: (String, String),
+    pub ticks: Vec<TickDiff>,
+}
+
+fn register(market: &mut dyn Market, order: &FlowOrder) -> Result<Uuid, OrderError> {
+    let owner = Owner::Entity(order.owner);
+    match order.limit_price {
+        Some(limit) => market.register_limit_order(owner, order.otype, order.quantity, order.prestige, limit),
+        None => market.register_order(owner, order.otype, order.quantity, order.prestige),
+    }
+}
+
+// Run the flow through the two markets, they are cleared after every tick
+pub fn compare(flow: &[Vec<FlowOrder>], names: (&str, &str), mut a: Box<dyn Market>, mut b: Box<dyn Market>)
+    -> Result<DiffReport, String> {
+    let mut ticks = vec![];
+    for (tick, orders) in flow.iter().enumerate() {
+        let uuids: Vec<(Uuid, Uuid)> = orders.iter()
+            .map(|x| Ok((register(a.as_mut(), x)?, register(b.as_mut(), x)?)))
+            .collect::<Result<_, OrderError>>()
+            .map_err(|e| format!("an order of tick {tick} was refused, {e}"))?;
+        let traded_a = a.run_trade().map_err(|e| format!("{} failed the trade of tick {tick}, {e}", names.0))?;
+        let traded_b = b.run_trade().map_err(|e| format!("{} failed the trade of tick {tick}, {e}", names.1))?;
+        let mut diff = TickDiff {
+            tick: tick as u64,
+            traded: (traded_a, traded_b),
+            price: (a.price_per_unit(), b.price_per_unit()),
+            ..TickDiff::default()
+        };
+        for (uuid_a, uuid_b) in uuids.iter() {
+            let quantity_a = a.retrieve_order_result(uuid_a).map_or(0, |x| x.traded_quantity);
+            let quantity_b = b.retrieve_order_result(uuid_b).map_or(0, |x| x.traded_quantity);
+            diff.allocation_gap += quantity_a.abs_diff(quantity_b);
+            diff.orders_differing += (quantity_a != quantity_b) as usize;
+        }
+        ticks.push(diff);
+        a.clear_state().map_err(|e| format!("{} cleared tick {tick} with {e}", names.0))?;
+        b.clear_state().map_err(|e| format!("{} cleared tick {tick} with {e}", names.1))?;
+    }
+    Ok(DiffReport { names: (names.0.to_owned(), names.1.to_owned()), ticks })
+}
+
+impl DiffReport {
+    pub fn is_identical(&self) -> bool {
+        self.ticks.iter().all(|x| x.orders_differing == 0 && x.price.0 == x.price.1)
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = (&self.names.0, &self.names.1);
+        let total = |x: fn(&TickDiff) -> Quantity| self.ticks.iter().map(x).sum::<Quantity>();
+        let traded_a = total(|x| x.traded.0);
+        let traded_b = total(|x| x.traded.1);
+        let gap = total(|x| x.allocation_gap);
+        let differing: usize = self.ticks.iter().map(|x| x.orders_differing).sum();
+        let price_gap = self.ticks.iter().map(|x| (x.price.0 - x.price.1).abs()).fold(0., f64::max);
+        writeln!(f, "{a} vs {b}, {} ticks", self.ticks.len())?;
+        writeln!(f, "  traded:         {traded_a} vs {traded_b}")?;
+        writeln!(f, "  allocation gap: {gap} ({:.1}% of the traded)", 100. * gap as f64 / traded_a.max(traded_b).max(1) as f64)?;
+        writeln!(f, "  orders differing: {differing}")?;
+        writeln!(f, "  max price gap:  {price_gap:.4}")?;
+        let ticks: Vec<&TickDiff> = self.ticks.iter().filter(|x| x.orders_differing > 0 || x.price.0 != x.price.1).collect();
+        if ticks.is_empty() {
+            return writeln!(f, "  identical");
+        }
+        writeln!(f, "  {:>6} {:>10} {:>10} {:>10} {:>10} {:>8}", "tick", "traded a", "traded b", "price a", "price b", "gap")?;
+        // The first ticks that differ
+        for x in ticks.iter().take(10) {
+            writeln!(f, "  {:>6} {:>10} {:>10} {:>10.4} {:>10.4} {:>8}",
+                     x.tick, x.traded.0, x.traded.1, x.price.0, x.price.1, x.allocation_gap)?;
+        }
+        if ticks.len() > 10 {
+            writeln!(f, "  ... {} more ticks differ", ticks.len() - 10)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_mechanism_is_identical() {
+        let flow = order_flow(7, 20, 10, 1.);
+        let report = compare(&flow, ("a", "b"), Mechanism::Test.build(1.), Mechanism::Test.build(1.)).unwrap();
+        assert!(report.is_identical());
+        let report = compare(&flow, ("test", "pooled"), Mechanism::Test.build(1.), Mechanism::Pooled.build(1.)).unwrap();
+        assert!(!report.is_identical());
+        // Both split the same total
+        assert!(report.ticks.iter().all(|x| x.traded.0 == x.traded.1));
+    }
+}
@@ -0,0 +1,902 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::accounting::{Accounts, BalanceSheet};
+use crate::bank::Bank;
+use crate::central_bank::CentralBank;
+use crate::convergence::SteadyStateDetector;
+use crate::currency::{CurrencyId, FxMarket, Money};
+use crate::dashboard;
+use crate::equity::EquityMarket;
+use crate::events::{EventBus, SimEvent};
+use crate::freeze::{FreezePlan, RecordedOrder};
+use crate::goods::GoodRegistry;
+use crate::government::Government;
+use crate::monetary::MonetaryAuthority;
+use crate::ledger::{FlowKind, Ledger, MoneyFlow};
+use crate::market::guarded_trade;
+use crate::national::NationalMarket;
+use crate::pollution::Pollution;
+use crate::recorder::Recorder;
+use crate::region::{Region, RegionId, TradeRoute};
+use crate::registry::EntityRegistry;
+use crate::rng::RngStreams;
+use crate::shock::ShockSchedule;
+use crate::trace::DecisionTrace;
+use crate::{EcoEntity, EntityId, GoodUid, Market, MarketError, OrderResult, Owner, Price, UnretrievedOrders};
+
+#[derive(Serialize, Deserialize)]
+pub struct Simulation {
+    pub goods: GoodRegistry,
+    // Names of the currencies of the regions
+    #[serde(default)]
+    pub currencies: Vec<String>,
+    pub regions: Vec<Region>,
+    pub routes: Vec<TradeRoute>,
+    // Exchange between the currencies, cleared after the goods
+    #[serde(default)]
+    pub fx: Vec<FxMarket>,
+    // Second stage of the clearing, at most one for every good
+    pub nationals: Vec<NationalMarket>,
+    pub entities: EntityRegistry,
+    #[serde(default)]
+    pub government: Government,
+    // Pollution of the regions, None when the production doesn't pollute
+    pub pollution: Option<Pollution>,
+    pub bank: Option<Bank>,
+    // Price index, policy rate and base money, None without monetary policy
+    #[serde(default)]
+    pub central_bank: Option<CentralBank>,
+    // Shares of the firms and their dividends, None without ownership
+    #[serde(default)]
+    pub equity: Option<EquityMarket>,
+    // Scheduled money scaling and injections
+    #[serde(default)]
+    pub monetary: MonetaryAuthority,
+    // Droughts, demand spikes, embargoes and taxes started before the production
+    #[serde(default)]
+    pub shocks: ShockSchedule,
+    pub ledger: Ledger,
+    // Balance sheets and income statements of some entities, None when nobody keeps them
+    #[serde(default)]
+    pub accounts: Option<Accounts>,
+    pub rng_streams: RngStreams,
+    pub recorder: Recorder,
+    // Decisions of the traced entities, None when nothing is traced
+    #[serde(skip)]
+    pub trace: Option<DecisionTrace>,
+    // Warm-up ticks left out of the statistics and the charts
+    pub burn_in: u64,
+    pub steady_state: Option<SteadyStateDetector>,
+    // Entities to record and then freeze, kept until the freeze happens
+    pub freeze: Option<FreezePlan>,
+    #[serde(skip)]
+    pub events: EventBus,
+    // Print a row of the dashboard every tick
+    #[serde(skip)]
+    pub dashboard: bool,
+    #[serde(skip)]
+    dashboard_rows: u64,
+    // Check the invariants after every stage of every tick, panicking at the first violation
+    #[serde(skip)]
+    pub paranoid: bool,
+    // Money flows collected during the current tick
+    #[serde(skip)]
+    tick_flows: Vec<MoneyFlow>,
+    pub tick: u64,
+}
+
+impl Simulation {
+    pub fn new(goods: GoodRegistry, seed: u64) -> Simulation {
+        Simulation {
+            goods,
+            currencies: vec![],
+            regions: vec![],
+            routes: vec![],
+            fx: vec![],
+            nationals: vec![],
+            entities: EntityRegistry::default(),
+            government: Government::default(),
+            pollution: None,
+            bank: None,
+            central_bank: None,
+            equity: None,
+            monetary: MonetaryAuthority::default(),
+            shocks: ShockSchedule::default(),
+            ledger: Ledger::default(),
+            accounts: None,
+            rng_streams: RngStreams::new(seed),
+            recorder: Recorder::default(),
+            trace: None,
+            burn_in: 0,
+            steady_state: None,
+            freeze: None,
+            events: EventBus::default(),
+            dashboard: false,
+            dashboard_rows: 0,
+            paranoid: false,
+            tick_flows: vec![],
+            tick: 0,
+        }
+    }
+
+    pub fn add_currency(&mut self, name: &str) -> CurrencyId {
+        self.currencies.push(name.to_owned());
+        self.currencies.len() - 1
+    }
+
+    pub fn add_region(&mut self, name: &str) -> RegionId {
+        self.regions.push(Region::new(name));
+        self.regions.len() - 1
+    }
+
+    pub fn add_market(&mut self, region: RegionId, market: Box<dyn Market>) {
+        self.regions[region].markets.push(market);
+    }
+
+    pub fn add_entity(&mut self, name: &str, region: RegionId, mut entity: Box<dyn EcoEntity>) -> EntityId {
+        entity.attach_rng(self.rng_streams.stream(&format!("entity/{name}")));
+        self.entities.add(name, region, entity)
+    }
+
+    pub fn add_route(&mut self, route: TradeRoute) {
+        self.routes.push(route);
+    }
+
+    // Name of an exchange market in the reports
+    pub fn fx_label(&self, market: &FxMarket) -> String {
+        format!("{}/{}", self.currencies[market.base], self.currencies[market.quote])
+    }
+
+    // Name of a market in the reports, the region is omitted when there is only one
+    pub fn market_label(&self, region: RegionId, good_uid: GoodUid) -> String {
+        let good = self.goods.get_good_name(good_uid);
+        if self.regions.len() > 1 {
+            format!("{}/{good}", self.regions[region].name)
+        } else {
+            good
+        }
+    }
+
+    // Name of the owner of an order in the reports
+    pub fn owner_label(&self, owner: Owner) -> String {
+        match owner {
+            Owner::Entity(id) => self.entities.get(id).map_or(id.to_string(), |_| self.entities.name(id).to_owned()),
+            Owner::Route => "route".to_owned(),
+            Owner::ClearingHouse => "clearing_house".to_owned(),
+        }
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item=(RegionId, &dyn Market)> {
+        self.regions.iter().enumerate()
+            .flat_map(|(i, region)| region.markets.iter().map(move |x| (i, x.as_ref())))
+    }
+
+    // The currency of the first region: the bank, the national markets and the central bank pay
+    // in it and the totals are measured in it
+    pub fn home_currency(&self) -> CurrencyId {
+        self.regions.first().map_or(0, |x| x.currency)
+    }
+
+    // An entity pays and is paid in the currency of its region
+    pub fn currency_of(&self, id: EntityId) -> CurrencyId {
+        self.regions[self.entities.region(id)].currency
+    }
+
+    // Every balance of money with its holder, in its own currency
+    pub fn balances(&self) -> Vec<(String, Money)> {
+        let home = self.home_currency();
+        let mut balances: Vec<(String, Money)> = self.entities.iter()
+            .map(|(id, name, x)| (name.to_owned(), Money::new(x.money_balance(), self.currency_of(id))))
+            .collect();
+        for route in self.routes.iter() {
+            balances.push((route.name.clone(), Money::new(route.money_balance(), self.regions[route.from].currency)));
+            balances.push((route.name.clone(), Money::new(route.proceeds(), self.regions[route.to].currency)));
+        }
+        for market in self.fx.iter() {
+            let (base, quote) = market.reserves();
+            balances.extend([base, quote].map(|x| (format!("fx/{}", self.fx_label(market)), x)));
+        }
+        for national in self.nationals.iter() {
+            let name = format!("national/{}", self.goods.get_good_name(national.good_uid));
+            balances.push((name, Money::new(national.money_balance(), home)));
+        }
+        balances.extend(self.government.balances().into_iter().map(|x| ("government".to_owned(), x)));
+        if let Some(bank) = &self.bank {
+            balances.push(("bank".to_owned(), Money::new(bank.money_balance(), home)));
+        }
+        balances
+    }
+
+    // Worth of `money` in the home currency at the current rate, at par without an exchange
+    // between the two currencies
+    pub fn in_home_currency(&self, money: Money) -> f64 {
+        let home = self.home_currency();
+        match self.fx.iter().find(|x| money.currency != home && x.trades(money.currency, home)) {
+            Some(market) => money.amount * market.rate_of(money.currency, home),
+            None => money.amount,
+        }
+    }
+
+    // All the money, in the home currency
+    pub fn total_money(&self) -> f64 {
+        self.balances().into_iter().map(|x| self.in_home_currency(x.1)).sum()
+    }
+
+    // All the money of every currency, by CurrencyId
+    pub fn money_by_currency(&self) -> Vec<f64> {
+        let mut totals = vec![0.; self.currencies.len().max(1)];
+        for (_, money) in self.balances() {
+            totals[money.currency] += money.amount;
+        }
+        totals
+    }
+
+    // The amounts summed as they are, whatever their currency. The flows are recorded the same
+    // way, so it changes only with them like the amount of every currency. For the audit only.
+    fn money_at_face_value(&self) -> f64 {
+        self.balances().into_iter().map(|x| x.1.amount).sum()
+    }
+
+    // The money of the entities paying in the home currency, their deposits included
+    pub fn money_supply(&self) -> f64 {
+        let home = self.home_currency();
+        self.entities.iter().filter(|x| self.currency_of(x.0) == home).map(|x| x.2.money_balance().max(0.)).sum::<f64>()
+            + self.bank.as_ref().map_or(0., |x| x.deposits())
+    }
+
+    // The steady state has been reached and the run should end here
+    pub fn should_stop(&self) -> bool {
+        self.steady_state.as_ref().is_some_and(|x| x.stop && x.since().is_some())
+    }
+
+    // Mean of the standard of living of the entities that have one, the pops
+    pub fn average_standard_of_living(&self) -> Option<f64> {
+        let sol: Vec<f64> = self.entities.iter()
+            .filter_map(|(_, _, x)| x.state_fields().into_iter().find(|f| f.0 == "standard_of_living").map(|f| f.1))
+            .collect();
+        (!sol.is_empty()).then(|| sol.iter().sum::<f64>() / sol.len() as f64)
+    }
+
+    // Replace the entities of the freeze plan with their frozen copies
+    fn freeze_entities(&mut self) {
+        let Some(mut plan) = self.freeze.take_if(|x| x.at == self.tick) else {
+            return;
+        };
+        for (_, name, entity) in self.entities.iter_mut() {
+            if let Some(frozen) = plan.freeze(name, entity.as_ref()) {
+                *entity = Box::new(frozen);
+            }
+        }
+    }
+
+    // Every entity gets the results of its orders, the entities in order and the results of an
+    // entity in the order of the markets
+    fn settle_orders(&mut self) {
+        let mut queue: Vec<(EntityId, GoodUid, OrderResult)> = self.regions.iter_mut()
+            .flat_map(|region| region.markets.iter_mut())
+            .flat_map(|market| {
+                let good_uid = market.good_uid();
+                market.take_entity_results().into_iter().map(move |(id, result)| (id, good_uid, result))
+            })
+            .collect();
+        queue.sort_by_key(|x| x.0);
+        for (id, good_uid, result) in queue {
+            if self.events.is_active() && result.traded_quantity > 0 {
+                let event = SimEvent::OrderSettled {
+                    entity: self.entities.name(id).to_owned(),
+                    market: self.market_label(self.entities.region(id), good_uid),
+                    side: result.ordertype,
+                    quantity: self.goods.to_units(good_uid, result.traded_quantity),
+                    cost: result.total_cost,
+                    counterparties: result.counterparties.iter().map(|x| self.owner_label(x.0)).collect(),
+                };
+                self.events.emit(self.tick, event);
+            }
+            let tax = self.regions[self.entities.region(id)].taxes.get(&good_uid).map_or(0., |x| x * result.total_cost);
+            self.entities[id].settle_order(good_uid, result);
+            // Paid with the money of the trade, never more than the entity has
+            let tax = tax.min(self.entities[id].money_balance()).max(0.);
+            if tax > 0. && self.entities[id].transfer(FlowKind::Tax, -tax) {
+                self.government.collect(Money::new(tax, self.currency_of(id)));
+            }
+        }
+        for (_, _, entity) in self.entities.iter_mut() {
+            entity.end_settlement();
+        }
+    }
+
+    // The shocks over end and the ones of the tick start
+    fn apply_shocks(&mut self) {
+        if self.shocks.is_empty() {
+            return;
+        }
+        let mut shocks = std::mem::take(&mut self.shocks);
+        shocks.apply(self);
+        for scheduled in shocks.shocks() {
+            self.recorder.record(&format!("shock/{}", scheduled.name), scheduled.is_running() as u8 as f64);
+        }
+        self.shocks = shocks;
+    }
+
+    // The orders registered in the markets by every entity
+    fn entity_orders(&self) -> Vec<Vec<RecordedOrder>> {
+        let mut orders = vec![vec![]; self.entities.len()];
+        for (_, market) in self.markets() {
+            for (owner, order) in market.registered_orders() {
+                // The orphaned orders are reported by the paranoid checks
+                if let Some(orders) = owner.entity().and_then(|id| orders.get_mut(id.0)) {
+                    orders.push(order);
+                }
+            }
+        }
+        orders
+    }
+
+    // Record the orders of the entities going to be frozen and emit the posted orders
+    fn record_posted_orders(&mut self) {
+        let recording = self.freeze.as_ref()
+            .is_some_and(|plan| self.entities.names().iter().any(|x| plan.is_recording(self.tick, x)));
+        if !recording && !self.events.is_active() {
+            return;
+        }
+        for (id, orders) in self.entities.ids().zip(self.entity_orders()) {
+            let name = self.entities.name(id);
+            if self.events.is_active() {
+                for order in orders.iter() {
+                    let event = SimEvent::OrderPosted {
+                        entity: name.to_owned(),
+                        market: self.market_label(self.entities.region(id), order.good_uid),
+                        side: order.otype,
+                        quantity: self.goods.to_units(order.good_uid, order.quantity),
+                        prestige: order.prestige,
+                        limit_price: order.limit_price,
+                    };
+                    self.events.emit(self.tick, event);
+                }
+            }
+            if let Some(plan) = self.freeze.as_mut().filter(|x| x.is_recording(self.tick, name)) {
+                plan.record(self.tick, name, orders);
+            }
+        }
+    }
+
+    fn prices(&self) -> Vec<Price> {
+        self.markets().map(|(_, x)| x.price_per_unit()).collect()
+    }
+
+    // The trade of `market` failed, its orders of the tick were returned untraded
+    fn halt_market(&mut self, market: String, error: MarketError) {
+        eprintln!("tick {}: market {market} halted, {error}", self.tick);
+        if self.events.is_active() {
+            self.events.emit(self.tick, SimEvent::MarketHalted { market, error: error.to_string() });
+        }
+    }
+
+    // Results of orders of `market` nobody retrieved, what they traded was lost by the owners
+    fn report_unretrieved(&mut self, market: String, error: UnretrievedOrders) {
+        let owners: Vec<String> = error.0.iter().map(|x| self.owner_label(x.0)).collect();
+        eprintln!("tick {}: market {market} cleared with {error}, of {}", self.tick, owners.join(", "));
+        if self.events.is_active() {
+            self.events.emit(self.tick, SimEvent::ResultsUnretrieved { market, owners });
+        }
+    }
+
+    fn emit_trades(&mut self) {
+        let mut events = vec![];
+        for (region, market) in self.markets() {
+            let traded = market.trade_report().traded;
+            if traded > 0 {
+                events.push(SimEvent::TradeExecuted {
+                    market: self.market_label(region, market.good_uid()),
+                    quantity: self.goods.to_units(market.good_uid(), traded),
+                    price: market.price_per_unit(),
+                });
+            }
+        }
+        for event in events {
+            self.events.emit(self.tick, event);
+        }
+    }
+
+    fn emit_price_changes(&mut self, before: &[Price]) {
+        let mut events = vec![];
+        for ((region, market), from) in self.markets().zip(before.iter()) {
+            if market.price_per_unit() != *from {
+                events.push(SimEvent::PriceChanged {
+                    market: self.market_label(region, market.good_uid()),
+                    from: *from,
+                    to: market.price_per_unit(),
+                });
+            }
+        }
+        for event in events {
+            self.events.emit(self.tick, event);
+        }
+    }
+
+    // Record the state of the entities at the start of the tick
+    fn record_entities(&mut self) {
+        self.recorder.begin_tick(self.tick);
+        for (_, name, entity) in self.entities.iter() {
+            self.recorder.record(&format!("{name}/money"), entity.money_balance());
+            for (good_uid, quantity) in entity.inventory() {
+                let good = self.goods.get_good_name(good_uid);
+                self.recorder.record(&format!("{name}/inventory/{good}"), self.goods.to_units(good_uid, quantity));
+            }
+            for (field, value) in entity.state_fields().into_iter()
+                .filter(|x| matches!(x.0, "population" | "level" | "reserve" | "efficiency")) {
+                self.recorder.record(&format!("{name}/{field}"), value);
+            }
+        }
+        for route in self.routes.iter() {
+            self.recorder.record(&format!("route/{}/money", route.name), route.money_balance());
+            if self.regions[route.from].currency != self.regions[route.to].currency {
+                self.recorder.record(&format!("route/{}/proceeds", route.name), route.proceeds());
+            }
+            self.recorder.record(&format!("route/{}/in_transit", route.name),
+                                 self.goods.to_units(route.good_uid, route.in_transit()));
+        }
+        if let Some(bank) = &self.bank {
+            self.recorder.record("bank/money", bank.money_balance());
+            self.recorder.record("bank/deposits", bank.deposits());
+            self.recorder.record("bank/loans", bank.loans());
+            self.recorder.record("bank/written_off", bank.written_off());
+            for account in bank.accounts.iter() {
+                let name = self.entities.name(account.entity);
+                self.recorder.record(&format!("{name}/deposit"), account.deposit);
+                self.recorder.record(&format!("{name}/loan"), account.loan);
+            }
+        }
+    }
+
+    // Record the averages of the markets, the tick included
+    fn record_market_stats(&mut self) {
+        let mut records = vec![];
+        for (region, market) in self.markets() {
+            let good_uid = market.good_uid();
+            let label = self.market_label(region, good_uid);
+            let stats = market.stats();
+            records.push((format!("market/{label}/price_avg"), stats.mean_price().unwrap_or(f64::NAN)));
+            records.push((format!("market/{label}/traded_avg"),
+                          stats.mean_traded().map_or(f64::NAN, |x| x / market.unit_scale() as f64)));
+            records.push((format!("market/{label}/excess_demand_avg"),
+                          stats.mean_excess_demand().map_or(f64::NAN, |x| x / market.unit_scale() as f64)));
+            records.push((format!("market/{label}/volatility"), stats.volatility().unwrap_or(f64::NAN)));
+        }
+        for (key, value) in records {
+            self.recorder.record(&key, value);
+        }
+    }
+
+    // The central bank measures the prices of the tick, the mean of the regions for every good
+    fn observe_prices(&mut self) {
+        let Some(mut central_bank) = self.central_bank.take() else {
+            return;
+        };
+        central_bank.observe(|good_uid| {
+            let prices: Vec<Price> = self.markets().filter(|x| x.1.good_uid() == good_uid).map(|x| x.1.price_per_unit()).collect();
+            (!prices.is_empty()).then(|| prices.iter().sum::<Price>() / prices.len() as f64)
+        });
+        self.recorder.record("central_bank/price_index", central_bank.price_index());
+        self.recorder.record("central_bank/inflation", central_bank.inflation());
+        self.recorder.record("central_bank/policy_rate", central_bank.policy_rate());
+        self.recorder.record("central_bank/money_supply", self.money_supply());
+        self.central_bank = Some(central_bank);
+    }
+
+    // Price, dividends and trades of the shares of every firm and the shares of every holder
+    fn record_equity(&mut self) {
+        let Some(equity) = &self.equity else {
+            return;
+        };
+        for firm in equity.firms.iter() {
+            let name = self.entities.name(firm.firm);
+            self.recorder.record(&format!("equity/{name}/price"), firm.price());
+            self.recorder.record(&format!("equity/{name}/dividends"), firm.dividends());
+            self.recorder.record(&format!("equity/{name}/traded"), firm.traded() as f64);
+            self.recorder.record(&format!("equity/{name}/treasury"), firm.treasury() as f64);
+            for (id, holder, _) in self.entities.iter() {
+                if firm.held_by(id) > 0 || self.recorder.series(&format!("{holder}/shares/{name}")).is_some() {
+                    self.recorder.record(&format!("{holder}/shares/{name}"), firm.held_by(id) as f64);
+                }
+            }
+        }
+    }
+
+    // Record the results of the trade of the tick
+    fn record_markets(&mut self) {
+        let mut records = vec![];
+        for (region, market) in self.markets() {
+            let good_uid = market.good_uid();
+            let label = self.market_label(region, good_uid);
+            let report = market.trade_report();
+            records.push((format!("market/{label}/price"), market.price_per_unit()));
+            records.push((format!("market/{label}/traded"), self.goods.to_units(good_uid, report.traded)));
+            records.push((format!("market/{label}/unfilled_buy"), self.goods.to_units(good_uid, report.unfilled_buy)));
+            records.push((format!("market/{label}/unfilled_sell"), self.goods.to_units(good_uid, report.unfilled_sell)));
+            records.push((format!("market/{label}/untraded_tiers"), report.untraded_tiers as f64));
+            records.push((format!("market/{label}/lot_remainder"), self.goods.to_units(good_uid, report.remainder)));
+        }
+        // Only the goods with forward contracts
+        for (region_id, region) in self.regions.iter().enumerate() {
+            for (good_uid, report) in region.contracts.report() {
+                let label = self.market_label(region_id, *good_uid);
+                records.push((format!("market/{label}/contract_delivered"), self.goods.to_units(*good_uid, report.delivered)));
+                records.push((format!("market/{label}/contract_defaulted"), self.goods.to_units(*good_uid, report.defaulted)));
+            }
+        }
+        for national in self.nationals.iter() {
+            let good_uid = national.good_uid;
+            let good = self.goods.get_good_name(good_uid);
+            records.push((format!("national/{good}/price"), national.price_per_unit()));
+            records.push((format!("national/{good}/traded"), self.goods.to_units(good_uid, national.traded())));
+            records.push((format!("national/{good}/stock"), self.goods.to_units(good_uid, national.stock())));
+            records.push((format!("national/{good}/money"), national.money_balance()));
+        }
+        for market in self.fx.iter() {
+            let label = self.fx_label(market);
+            let (base, quote) = market.reserves();
+            records.push((format!("fx/{label}/rate"), market.rate()));
+            records.push((format!("fx/{label}/traded"), market.traded()));
+            records.push((format!("fx/{label}/reserves_base"), base.amount));
+            records.push((format!("fx/{label}/reserves_quote"), quote.amount));
+        }
+        for (key, value) in records {
+            self.recorder.record(&key, value);
+        }
+    }
+
+    fn collect_flows(&mut self) {
+        let start = self.tick_flows.len();
+        let mut owners = vec![];
+        for (_, name, entity) in self.entities.iter_mut() {
+            let flows = entity.take_money_flows();
+            if let Some(accounts) = self.accounts.as_mut() {
+                accounts.add_flows(name, &flows);
+            }
+            owners.extend(std::iter::repeat_n(name.to_owned(), flows.len()));
+            self.tick_flows.extend(flows);
+        }
+        for route in self.routes.iter_mut() {
+            let flows = route.take_money_flows();
+            owners.extend(std::iter::repeat_n(route.name.clone(), flows.len()));
+            self.tick_flows.extend(flows);
+        }
+        for national in self.nationals.iter_mut() {
+            let flows = national.take_money_flows();
+            let name = format!("national/{}", self.goods.get_good_name(national.good_uid));
+            owners.extend(std::iter::repeat_n(name, flows.len()));
+            self.tick_flows.extend(flows);
+        }
+        let labels: Vec<String> = self.fx.iter().map(|x| format!("fx/{}", self.fx_label(x))).collect();
+        for (market, label) in self.fx.iter_mut().zip(labels) {
+            let flows = market.take_money_flows();
+            owners.extend(std::iter::repeat_n(label, flows.len()));
+            self.tick_flows.extend(flows);
+        }
+        let flows = self.government.take_money_flows();
+        owners.extend(std::iter::repeat_n("government".to_owned(), flows.len()));
+        self.tick_flows.extend(flows);
+        if let Some(flows) = self.bank.as_mut().map(|x| x.take_money_flows()) {
+            owners.extend(std::iter::repeat_n("bank".to_owned(), flows.len()));
+            self.tick_flows.extend(flows);
+        }
+        if self.events.is_active() {
+            for (owner, flow) in owners.into_iter().zip(self.tick_flows[start..].iter()) {
+                let event = SimEvent::MoneyFlow { entity: owner, kind: flow.kind, amount: flow.amount };
+                self.events.emit(self.tick, event);
+            }
+        }
+    }
+
+    // The balance sheets of the entities keeping accounts, at the start or at the end of the tick
+    fn balance_sheets(&self) -> Vec<(String, BalanceSheet)> {
+        let Some(accounts) = &self.accounts else {
+            return vec![];
+        };
+        self.entities.iter().filter(|x| accounts.keeps(x.1))
+            .map(|(id, name, _)| (name.to_owned(), BalanceSheet::of(self, id)))
+            .collect()
+    }
+
+    // The entities suffer the pollution of their region before producing
+    fn apply_pollution(&mut self) {
+        let Some(pollution) = &self.pollution else {
+            return;
+        };
+        for id in self.entities.ids() {
+            let damage = pollution.damage(self.entities.region(id));
+            self.entities[id].suffer_pollution(damage);
+        }
+    }
+
+    // Waste of the production of the tick, by producer and in total. The waste pollutes the
+    // region of the producer, which pays the tax on it.
+    fn collect_waste(&mut self) {
+        let mut records = vec![];
+        for id in self.entities.ids() {
+            let (name, region) = (self.entities.name(id).to_owned(), self.entities.region(id));
+            for record in self.entities[id].take_waste() {
+                let good = self.goods.get_good_name(record.good_uid);
+                let quantity = self.goods.to_units(record.good_uid, record.quantity);
+                records.push((format!("{name}/waste/{}/{good}", record.kind.name()), quantity));
+                records.push((format!("waste/{}/{good}", record.kind.name()), quantity));
+                if let Some(pollution) = self.pollution.as_mut() {
+                    let emitted = pollution.emit(region, record.kind, quantity);
+                    let tax = pollution.tax(emitted);
+                    if tax > 0. {
+                        let currency = self.regions[region].currency;
+                        self.government.collect(Money::new(self.entities[id].pay_tax(tax), currency));
+                    }
+                }
+            }
+        }
+        if let Some(pollution) = self.pollution.as_mut() {
+            pollution.decay();
+            for (region_id, region) in self.regions.iter().enumerate() {
+                records.push((format!("pollution/{}", region.name), pollution.stock(region_id)));
+            }
+            let money = self.government.balances().into_iter().map(|x| self.in_home_currency(x)).sum();
+            records.push(("government/money".to_owned(), money));
+        }
+        for (key, value) in records {
+            let total = self.recorder.series(&key).and_then(|x| x.last().copied()).filter(|x| !x.is_nan());
+            self.recorder.record(&key, total.unwrap_or(0.) + value);
+        }
+    }
+
+    fn collect_decisions(&mut self) {
+        for (_, name, entity) in self.entities.iter_mut() {
+            let decisions = entity.take_decisions();
+            for decision in decisions.iter() {
+                let entity = name.to_owned();
+                let event = match decision.action {
+                    "go_bankrupt" => SimEvent::EntityBankrupt { entity },
+                    "go_dormant" => SimEvent::EntityDormant { entity },
+                    "restart" => SimEvent::EntityRestarted { entity },
+                    _ => continue,
+                };
+                self.events.emit(self.tick, event);
+            }
+            if let Some(trace) = self.trace.as_mut().filter(|x| x.is_traced(name)) {
+                trace.add(self.tick, name, &self.goods, decisions);
+            }
+        }
+    }
+
+    fn check_invariants(&mut self, stage: &str, money_before: f64) {
+        if !self.paranoid {
+            return;
+        }
+        let mut violations = vec![];
+        for (name, Money { amount: money, .. }) in self.balances() {
+            if !money.is_finite() {
+                violations.push(format!("{name} has money {money}"));
+            } else if money < 0. {
+                violations.push(format!("{name} has negative money {money}"));
+            }
+        }
+        // Conservation: every change of the total money since the start of the tick has a flow
+        self.collect_flows();
+        let explained: f64 = self.tick_flows.iter().map(|x| x.amount).sum();
+        let money = self.money_at_face_value();
+        let tolerance = 1e-9 * money_before.abs().max(1.);
+        if (money - money_before - explained).abs() > tolerance {
+            violations.push(format!("total money changed by {} but the flows explain {explained}", money - money_before));
+        }
+        if stage == "retrieve" {
+            // The clearing houses placed only the goods they had
+            for national in self.nationals.iter().filter(|x| !x.conserves_goods()) {
+                violations.push(format!("national/{} doesn't conserve its goods, it holds {}",
+                                        self.goods.get_good_name(national.good_uid), national.stock()));
+            }
+            // All the trades have been paid and collected
+            let transfers: f64 = self.tick_flows.iter().filter(|x| x.kind.is_transfer()).map(|x| x.amount).sum();
+            if transfers.abs() > tolerance {
+                violations.push(format!("transfers don't cancel out, {transfers} left"));
+            }
+        }
+        for (region, market) in self.markets() {
+            let good = self.market_label(region, market.good_uid());
+            // Orders of entities that don't exist or that trade outside their region, nobody is
+            // going to settle them before the state is cleared
+            for (owner, order) in market.registered_orders() {
+                let Some(id) = owner.entity() else {
+                    continue;
+                };
+                if id.0 >= self.entities.len() || self.entities.region(id) != region {
+                    violations.push(format!("market {good} has an orphaned {:?} order of entity {id}", order.otype));
+                }
+            }
+            let price = market.price_per_unit();
+            if !price.is_finite() || price < 0. {
+                violations.push(format!("market {good} has price {price}"));
+            }
+            let report = market.trade_report();
+            if report.traded != report.sold {
+                violations.push(format!("market {good} bought {} but sold {}", report.traded, report.sold));
+            }
+            if report.overfilled > 0 {
+                violations.push(format!("market {good} has {} orders traded over the required", report.overfilled));
+            }
+        }
+        if !violations.is_empty() {
+            panic!("paranoid check failed at tick {} after {stage}:\n  {}", self.tick, violations.join("\n  "));
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.freeze_entities();
+        self.record_entities();
+        let openings = self.balance_sheets();
+        if let Some(accounts) = self.accounts.as_mut() {
+            for (name, opening) in openings {
+                accounts.open(&name, opening);
+            }
+        }
+        let money_before = self.money_at_face_value();
+        self.apply_shocks();
+        self.apply_pollution();
+        let created = self.monetary.apply(self.tick, &mut self.entities);
+        if !self.monetary.is_empty() {
+            self.recorder.record("monetary/created", created);
+        }
+        let supply = self.money_supply();
+        if let Some(central_bank) = &self.central_bank {
+            let created = central_bank.inject(&mut self.entities, supply);
+            self.recorder.record("central_bank/created", created);
+        }
+        self.check_invariants("monetary", money_before);
+        if let Some(bank) = self.bank.as_mut() {
+            if let Some(central_bank) = &self.central_bank {
+                bank.set_policy_rate(central_bank.policy_rate());
+            }
+            bank.settle(&mut self.entities);
+        }
+        self.check_invariants("bank", money_before);
+        // Step 0 - The stocks spoil and overflow the storage before they are used
+        let goods = &self.goods;
+        self.entities.par_iter_mut().for_each(|entity| entity.store(goods));
+        self.check_invariants("store", money_before);
+        //   The forward contracts deliver what is used in the production
+        for region in self.regions.iter_mut() {
+            region.contracts.deliver(&mut self.entities);
+        }
+        self.check_invariants("contracts", money_before);
+        // Step 1 - Resolve production and consumption of Economic Entities
+        //   The entities are independent here, they produce in parallel.
+        self.entities.par_iter_mut().for_each(|entity| {
+            entity.produce_and_consume();
+        });
+        self.collect_waste();
+        self.check_invariants("produce_and_consume", money_before);
+        // Step 2 - Get requested goods and custom zone metadata to choose what market expose to entities
+        //   An entity sees only the markets of its region for its goods tagged with all its metadata.
+        let selections: Vec<Vec<usize>> = self.entities.iter().map(|(id, _, entity)| {
+            let (goods, metadata) = entity.get_required_markets();
+            self.regions[self.entities.region(id)].eligible_markets(&goods, &metadata)
+        }).collect();
+        // Step 3 - Tell the entities to register their orders to the markets
+        for (id, selection) in self.entities.ids().zip(selections.iter()) {
+            let region = &mut self.regions[self.entities.region(id)];
+            let entity = &mut self.entities[id];
+            region.with_markets(selection, |markets, _, _| entity.post_orders_to_markets(id, markets));
+        }
+        for (id, selection) in self.entities.ids().zip(selections.iter()) {
+            let region = &mut self.regions[self.entities.region(id)];
+            let entity = &mut self.entities[id];
+            region.with_markets(selection, |markets, baskets, _| entity.post_basket_orders(id, markets, baskets));
+        }
+        for (id, selection) in self.entities.ids().zip(selections.iter()) {
+            let region = &mut self.regions[self.entities.region(id)];
+            let entity = &mut self.entities[id];
+            region.with_markets(selection, |markets, _, contracts| entity.post_contract_offers(id, markets, contracts));
+        }
+        self.record_posted_orders();
+        for route in self.routes.iter_mut() {
+            route.post_orders(&mut self.regions[..], &mut self.fx[..]);
+        }
+        self.check_invariants("post_orders", money_before);
+        let prices = self.prices();
+        // Step 4 - Run the trade algo in the markets
+        //   Every market clears on its own orders, so all of them clear in parallel. A market
+        //   that fails is halted for the tick and the others go on.
+        let mut failed: Vec<(Option<RegionId>, GoodUid, MarketError)> = self.regions.par_iter_mut().enumerate()
+            .flat_map(|(region_id, region)| region.markets.par_iter_mut().map(move |market| (region_id, market)))
+            .filter_map(|(region_id, market)| guarded_trade(market.as_mut()).err().map(|e| (Some(region_id), market.good_uid(), e)))
+            .collect();
+        // Baskets that didn't fill completely are cancelled and their markets traded again, before
+        // the clearing houses read the fills of their orders: a later trade would change them
+        for (region_id, region) in self.regions.iter_mut().enumerate() {
+            failed.extend(region.baskets.settle(&mut region.markets[..]).into_iter().map(|(good_uid, e)| (Some(region_id), good_uid, e)));
+        }
+        // The residuals of the regional markets go to the national ones
+        for national in self.nationals.iter_mut() {
+            let good_uid = national.good_uid;
+            failed.extend(national.clear(&mut self.regions[..]).into_iter().map(|(region_id, e)| (region_id, good_uid, e)));
+        }
+        let failures: Vec<(String, MarketError)> = failed.into_iter().map(|(region_id, good_uid, e)| match region_id {
+            Some(region_id) => (self.market_label(region_id, good_uid), e),
+            None => (format!("national/{}", self.goods.get_good_name(good_uid)), e),
+        }).collect();
+        // Contracts signed at the prices of the tick
+        for region in self.regions.iter_mut() {
+            region.contracts.sign(&region.markets[..]);
+        }
+        for (market, e) in failures {
+            self.halt_market(market, e);
+        }
+        //   The money exchanged by the routes, after the goods
+        for market in self.fx.iter_mut() {
+            market.clear();
+        }
+        self.check_invariants("run_trade", money_before);
+        if self.events.is_active() {
+            self.emit_trades();
+        }
+        // Step 5 - Deliver to the entities the results of the trade
+        self.settle_orders();
+        for route in self.routes.iter_mut() {
+            route.retrieve_orders(&mut self.regions[..], &self.fx[..]);
+        }
+        for national in self.nationals.iter_mut() {
+            national.retrieve_orders(&mut self.regions[..]);
+        }
+        self.check_invariants("retrieve", money_before);
+        //   The firms pay their dividends and their shares change hands
+        if let Some(equity) = self.equity.as_mut() {
+            equity.settle(&mut self.entities);
+        }
+        self.check_invariants("equity", money_before);
+        self.record_markets();
+        self.observe_prices();
+        self.record_equity();
+        // The warm-up is not expected to be steady
+        if let Some(detector) = self.steady_state.as_mut().filter(|_| self.tick >= self.burn_in) {
+            detector.observe(&self.recorder, self.tick);
+        }
+        if self.dashboard {
+            // The header is repeated to keep it on screen in long runs
+            if self.dashboard_rows.is_multiple_of(20) {
+                println!("{}", dashboard::header(self));
+            }
+            println!("{}", dashboard::row(self));
+            self.dashboard_rows += 1;
+        }
+        // Step 6 - Clear the market internal status
+        let mut unretrieved = vec![];
+        for (region_id, region) in self.regions.iter_mut().enumerate() {
+            for market in region.markets.iter_mut() {
+                if let Err(e) = market.clear_state() {
+                    unretrieved.push((region_id, market.good_uid(), e));
+                }
+            }
+            region.baskets.clear_state();
+            region.contracts.clear_state();
+        }
+        for (region_id, good_uid, e) in unretrieved {
+            self.report_unretrieved(self.market_label(region_id, good_uid), e);
+        }
+        for market in self.fx.iter_mut() {
+            market.clear_state();
+        }
+        self.check_invariants("clear_state", money_before);
+        self.record_market_stats();
+        if self.events.is_active() {
+            self.emit_price_changes(&prices);
+        }
+        // Audit the money flows of the tick
+        self.collect_flows();
+        let flows = std::mem::take(&mut self.tick_flows);
+        let money_after = self.money_at_face_value();
+        self.ledger.close_tick(self.tick, money_before, money_after, &flows);
+        let closings = self.balance_sheets();
+        if let Some(accounts) = self.accounts.as_mut() {
+            for (name, closing) in closings {
+                accounts.close(&name, closing, &mut self.recorder);
+            }
+        }
+        self.collect_decisions();
+        self.tick += 1;
+    }
+}
@@ -0,0 +1,1186 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::basket::BasketBook;
+use crate::building::Building;
+use crate::contract::{ContractBook, ContractPolicy};
+use crate::demography::Demography;
+use crate::deposit::Deposit;
+use crate::expectation::Expectations;
+use crate::goods::{self, GoodRegistry};
+use crate::labor::Workforce;
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::market::{GoodUid, Market, MarketMetadata, OrderResult, OrderType, Owner, Quantity};
+use crate::needs::{Need, NeedTier, Needs};
+use crate::pipeline::Pipeline;
+use crate::pollution::PollutionDamage;
+use crate::pricing::{self, PricingStrategy};
+use crate::profit::{ProducerState, ProfitTracker, Solvency};
+use crate::rng::SimRng;
+use crate::script::ScriptPolicy;
+use crate::shock;
+use crate::storage::Storage;
+use crate::trace::{Decision, DecisionLog};
+use crate::waste::{WasteProfile, WasteRecord};
+
+// Index of an entity in the simulation, given by the EntityRegistry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EntityId(pub usize);
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[typetag::serde(tag = "kind")]
+pub trait EcoEntity: Send {
+    // Step 1
+    fn produce_and_consume(&mut self) -> f64;
+    // Step 2
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>);
+    // Step 4, the orders are registered as owned by `id`
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]);
+    // Step 4, after all the single orders. The legs of the baskets are settled as usual in Step 5.
+    fn post_basket_orders(&mut self, _id: EntityId, _markets: &mut [Box<dyn Market>], _baskets: &mut BasketBook) {}
+    // Step 4, forward contracts offered to the entities of the region. The deliveries of the
+    // contracts signed are settled from the next tick on, before the production.
+    fn post_contract_offers(&mut self, _id: EntityId, _markets: &[Box<dyn Market>], _contracts: &mut ContractBook) {}
+    // Step 5, the result of every order of the entity, cancelled basket legs included
+    fn settle_order(&mut self, good_uid: GoodUid, result: OrderResult);
+    // Step 5, after all the orders have been settled
+    fn end_settlement(&mut self) {}
+    // Reporting
+    fn money_balance(&self) -> f64;
+    // Every change of the money balance since the last call, see Ledger
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow>;
+    // Random stream of its own, given when the entity joins the simulation
+    fn attach_rng(&mut self, _rng: SimRng) {}
+    // Decisions taken since the last call, see DecisionTrace
+    fn take_decisions(&mut self) -> Vec<Decision> {
+        vec![]
+    }
+    // Inputs lost and byproducts emitted since the last call
+    fn take_waste(&mut self) -> Vec<WasteRecord> {
+        vec![]
+    }
+    // Money received, or paid when negative, outside of the markets. False if the entity
+    // doesn't handle money this way.
+    fn transfer(&mut self, _kind: FlowKind, _amount: f64) -> bool {
+        false
+    }
+    // Pays up to `amount` of taxes, returns what was paid
+    fn pay_tax(&mut self, _amount: f64) -> f64 {
+        0.
+    }
+    // Damage of the pollution of its region for the coming production, see Pollution
+    fn suffer_pollution(&mut self, _damage: PollutionDamage) {}
+    // Relative change of the production, or of the consumption, added by a shock and taken back
+    // when it ends. False if the entity doesn't take the shock, see Shock
+    fn shock_production(&mut self, _change: f64) -> bool {
+        false
+    }
+    fn shock_consumption(&mut self, _change: f64) -> bool {
+        false
+    }
+    // Before the production, the stock spoils and what doesn't fit the storage is dumped or
+    // paid for, see Storage
+    fn store(&mut self, _goods: &GoodRegistry) {}
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)>;
+    // Other numeric state worth inspecting, money and inventory excluded
+    fn state_fields(&self) -> Vec<(&'static str, f64)> {
+        vec![]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RGOSingle {
+    pub(crate) good_uid: GoodUid,
+    // Inventory
+    pub(crate) quantity: Quantity,
+    // Inventory desired quantity
+    pub(crate) target_quantity: Quantity,
+    // Production
+    pub(crate) max_production_rate: Quantity,
+    // Costs, per unit of the good
+    pub(crate) per_unit_cost: f64,
+    pub(crate) fixed_cost: f64,
+    // Labor hired for the production
+    pub(crate) workforce: Workforce,
+    // The good is extracted from it, None for an endless source
+    #[serde(default)]
+    pub(crate) deposit: Option<Deposit>,
+    // Fraction of the production lost to the pollution
+    #[serde(default)]
+    pub(crate) productivity_loss: f64,
+    // Relative change of the production by the shocks
+    #[serde(default)]
+    pub(crate) production_shock: f64,
+    #[serde(default)]
+    pub(crate) storage: Storage,
+    // Forward sales of the production
+    #[serde(default)]
+    pub(crate) contracts: ContractPolicy,
+    // Ask of the production, None to sell at the price of the market
+    #[serde(default)]
+    pub(crate) pricing: Option<PricingStrategy>,
+    #[serde(default)]
+    pub(crate) solvency: Solvency,
+    // Bankrupt when it can't pay the fixed costs for a while, it never goes dormant
+    #[serde(default)]
+    pub(crate) state: ProducerState,
+    // Decisions overridden by the scenario
+    #[serde(default)]
+    pub(crate) script: ScriptPolicy,
+    // Tags the markets of the entity must have, see Region::eligible_markets
+    #[serde(default)]
+    pub(crate) market_tags: Vec<MarketMetadata>,
+    // Others
+    pub(crate) unit_scale: Quantity,
+    pub(crate) money_balance: f64,
+    pub(crate) money_flows: MoneyFlows,
+    #[serde(skip)]
+    pub(crate) decisions: DecisionLog,
+    pub(crate) prestige: f64,
+}
+
+#[typetag::serde]
+impl EcoEntity for RGOSingle {
+    fn produce_and_consume(&mut self) -> f64 {
+        let short = self.state == ProducerState::Active && self.solvency.check(self.money_balance, self.fixed_cost);
+        if short && self.solvency.should_go_bankrupt() {
+            self.decisions.record("go_bankrupt", None, vec![
+                ("money", self.money_balance),
+                ("fixed_cost", self.fixed_cost),
+            ], 0);
+            self.state = ProducerState::Bankrupt;
+        } else if short {
+            // Short of money it idles, paying what it can of the fixed costs
+            let paid = self.money_balance.clamp(0., self.fixed_cost);
+            self.decisions.record("idle", None, vec![
+                ("money", self.money_balance),
+                ("fixed_cost", self.fixed_cost),
+            ], 0);
+            self.money_balance -= paid;
+            self.money_flows.record(FlowKind::FixedCost, -paid);
+        }
+        if self.state != ProducerState::Active || short {
+            self.workforce.end_production();
+            if let Some(deposit) = self.deposit.as_mut() {
+                deposit.regenerate();
+            }
+            return 0.;
+        }
+        if let Some(deposit) = self.deposit.as_mut() {
+            let cost = deposit.prospect(self.good_uid, self.money_balance - self.fixed_cost, self.unit_scale, &mut self.decisions);
+            self.money_balance -= cost;
+            self.money_flows.record(FlowKind::Prospecting, -cost);
+        }
+        let enough_money_to_output =
+            ((self.money_balance - self.fixed_cost) / self.per_unit_cost * self.unit_scale as f64) as Quantity;
+        let max_production = shock::shocked((self.max_production_rate as f64 * (1. - self.productivity_loss)) as Quantity,
+                                            self.production_shock);
+        let output_value = max_production.min(enough_money_to_output)
+            .min(self.workforce.max_production(self.unit_scale));
+        let inputs = vec![
+            ("money", self.money_balance),
+            ("max_production_rate", goods::to_units(self.max_production_rate, self.unit_scale)),
+            ("productivity_loss", self.productivity_loss),
+            ("labor_available", self.workforce.available() as f64),
+        ];
+        // A script can produce less, not more than the RGO can
+        let output_value = self.script.decide("produce", &inputs, output_value, self.unit_scale).min(output_value);
+        self.decisions.record("produce", Some(self.good_uid), inputs, output_value);
+        self.workforce.end_production();
+        self.quantity += match self.deposit.as_mut() {
+            Some(deposit) => {
+                let extracted = deposit.extract(output_value);
+                deposit.regenerate();
+                extracted
+            }
+            None => output_value,
+        };
+        let variable_cost = goods::to_units(output_value, self.unit_scale) * self.per_unit_cost;
+        self.money_balance -= variable_cost + self.fixed_cost;
+        self.money_flows.record(FlowKind::VariableCost, -variable_cost);
+        self.money_flows.record(FlowKind::FixedCost, -self.fixed_cost);
+        0.
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        let mut goods = vec![self.good_uid];
+        goods.extend(self.workforce.good_uid());
+        (goods, self.market_tags.clone())
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        // Workers for the next production, keeping the money for the costs of the production
+        let budget = self.money_balance - self.fixed_cost
+            - goods::to_units(self.max_production_rate, self.unit_scale) * self.per_unit_cost;
+        // A bankrupt RGO only sells its stock
+        if self.state == ProducerState::Active {
+            self.workforce.hire(id, markets, self.max_production_rate, self.unit_scale, budget, self.prestige, &mut self.decisions);
+        }
+        if self.quantity < self.target_quantity {
+            return;
+        }
+        let mut inputs = vec![
+            ("stock", goods::to_units(self.quantity, self.unit_scale)),
+            ("target", goods::to_units(self.target_quantity, self.unit_scale)),
+        ];
+        let required = self.script.decide("sell", &inputs, self.quantity - self.target_quantity, self.unit_scale)
+            .min(self.quantity);
+        let unit_cost = pricing::unit_cost(self.per_unit_cost + self.workforce.wages_per_unit(markets), self.fixed_cost,
+                                           goods::to_units(self.max_production_rate, self.unit_scale));
+        let ask = self.pricing.as_mut().map(|x| x.ask(unit_cost, required));
+        if let Some(ask) = ask {
+            inputs.extend([("unit_cost", unit_cost), ("ask", ask)]);
+        }
+        self.decisions.record("sell", Some(self.good_uid), inputs, required);
+        let Some(market) = markets.iter_mut().find(|x| x.good_uid() == self.good_uid) else {
+            return;
+        };
+        // Refused when the script sells nothing
+        let _ = match ask {
+            Some(ask) => market.register_limit_order(Owner::Entity(id), OrderType::Sell, required, self.prestige, ask),
+            None => market.register_order(Owner::Entity(id), OrderType::Sell, required, self.prestige),
+        };
+    }
+
+    fn post_contract_offers(&mut self, id: EntityId, markets: &[Box<dyn Market>], contracts: &mut ContractBook) {
+        let Some(market) = markets.iter().find(|x| x.good_uid() == self.good_uid) else {
+            return;
+        };
+        let offered = self.contracts.offer(id, market.as_ref(), OrderType::Sell, self.max_production_rate, self.prestige, contracts);
+        if offered > 0 {
+            self.decisions.record("offer_contract", Some(self.good_uid), vec![
+                ("price", market.price_per_unit()),
+                ("ticks", self.contracts.ticks as f64),
+            ], offered);
+        }
+    }
+
+    fn settle_order(&mut self, good_uid: GoodUid, result: OrderResult) {
+        if let Some(wages) = self.workforce.settle(good_uid, &result) {
+            self.money_balance -= wages;
+            self.money_flows.record(FlowKind::Wages, -wages);
+            return;
+        }
+        if result.remainder > 0 {
+            self.decisions.record("lot_remainder", Some(good_uid), vec![], result.remainder);
+        }
+        match result.ordertype {
+            OrderType::Buy => {
+                self.quantity += result.traded_quantity;
+                self.money_balance -= result.total_cost;
+                unreachable!()
+            }
+            OrderType::Sell => {
+                self.quantity -= result.traded_quantity;
+                self.money_balance += result.total_cost;
+                self.money_flows.record(FlowKind::Trade, result.total_cost);
+                if let Some(pricing) = self.pricing.as_mut() {
+                    pricing.add_sale(result.traded_quantity);
+                }
+            }
+        }
+    }
+
+    fn end_settlement(&mut self) {
+        if let Some(pricing) = self.pricing.as_mut() {
+            pricing.adjust();
+        }
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn transfer(&mut self, kind: FlowKind, amount: f64) -> bool {
+        self.money_balance += amount;
+        self.money_flows.record(kind, amount);
+        true
+    }
+
+    fn take_decisions(&mut self) -> Vec<Decision> {
+        self.decisions.take()
+    }
+
+    fn attach_rng(&mut self, rng: SimRng) {
+        if let Some(deposit) = self.deposit.as_mut() {
+            deposit.attach_rng(rng);
+        }
+    }
+
+    fn suffer_pollution(&mut self, damage: PollutionDamage) {
+        self.productivity_loss = damage.productivity;
+    }
+
+    fn shock_production(&mut self, change: f64) -> bool {
+        self.production_shock += change;
+        true
+    }
+
+    fn store(&mut self, goods: &GoodRegistry) {
+        let cost = self.storage.keep(goods, vec![(self.good_uid, &mut self.quantity)], self.money_balance);
+        self.money_balance -= cost;
+        self.money_flows.record(FlowKind::Storage, -cost);
+    }
+
+    fn take_waste(&mut self) -> Vec<WasteRecord> {
+        self.storage.take()
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        vec![(self.good_uid, self.quantity)]
+    }
+
+    fn state_fields(&self) -> Vec<(&'static str, f64)> {
+        let mut fields = vec![
+            ("target_quantity", goods::to_units(self.target_quantity, self.unit_scale)),
+            ("max_production_rate", goods::to_units(self.max_production_rate, self.unit_scale)),
+            ("labor_available", self.workforce.available() as f64),
+            ("bankrupt", (self.state == ProducerState::Bankrupt) as u8 as f64),
+        ];
+        if let Some(deposit) = &self.deposit {
+            fields.push(("reserve", goods::to_units(deposit.reserve(), self.unit_scale)));
+            fields.push(("efficiency", deposit.efficiency()));
+        }
+        fields
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BasicPop {
+    // The pop require full goods input and ask them with a priority order
+    // Invetory
+    pub(crate) goods_inventory: HashMap<GoodUid, Quantity>,
+    // Inventory desired quantity, per head
+    pub(crate) goods_priority_order: Vec<GoodUid>,
+    pub(crate) goods_desired_inventory: HashMap<GoodUid, Quantity>,
+    // Consumption, per head
+    pub(crate) consumed_goods_per_tick: HashMap<GoodUid, Quantity>,
+    // What the goods are consumed for, the goods left out are life needs of their own
+    #[serde(default)]
+    pub(crate) needs: Needs,
+    // Labor sold every tick per head, the wages are the income of the pop
+    pub(crate) labor_good_uid: Option<GoodUid>,
+    pub(crate) labor_per_tick: Quantity,
+    #[serde(default)]
+    pub(crate) demography: Demography,
+    // Standard of living lost to the pollution every tick
+    #[serde(default)]
+    pub(crate) pollution: f64,
+    // Relative change of the consumption and of the desired inventory by the shocks
+    #[serde(default)]
+    pub(crate) consumption_shock: f64,
+    #[serde(default)]
+    pub(crate) storage: Storage,
+    // Changes the desired inventory with the price trends
+    #[serde(default)]
+    pub(crate) expectations: Expectations,
+    // Decisions overridden by the scenario
+    #[serde(default)]
+    pub(crate) script: ScriptPolicy,
+    // Tags the markets of the entity must have, see Region::eligible_markets
+    #[serde(default)]
+    pub(crate) market_tags: Vec<MarketMetadata>,
+    // Others
+    pub(crate) money_balance: f64,
+    pub(crate) money_flows: MoneyFlows,
+    #[serde(skip)]
+    pub(crate) decisions: DecisionLog,
+    pub(crate) prestige: f64,
+    pub(crate) standard_of_living: f64,
+}
+
+impl BasicPop {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        goods_in_prio_order: Vec<GoodUid>,
+        inventory_goods_in_order: Vec<Quantity>,
+        desired_inv_goods_in_order: Vec<Quantity>,
+        consumed_goods_in_order: Vec<Quantity>,
+        labor_offered: Option<(GoodUid, Quantity)>,
+        demography: Demography,
+        storage: Storage,
+        expectations: Expectations,
+        money_balance: f64,
+        prestige: f64,
+        standard_of_living: f64,
+    ) -> BasicPop {
+        assert_eq!(goods_in_prio_order.len(), consumed_goods_in_order.len());
+        let goods_inventory = HashMap::from_iter(goods_in_prio_order.clone().into_iter().zip(inventory_goods_in_order));
+        let goods_desired_inventory = HashMap::from_iter(goods_in_prio_order.clone().into_iter().zip(desired_inv_goods_in_order));
+        let consumed_goods_per_tick = HashMap::from_iter(goods_in_prio_order.clone().into_iter().zip(consumed_goods_in_order));
+        BasicPop {
+            goods_inventory,
+            goods_priority_order: goods_in_prio_order,
+            goods_desired_inventory,
+            consumed_goods_per_tick,
+            needs: Needs::default(),
+            labor_good_uid: labor_offered.map(|x| x.0),
+            labor_per_tick: labor_offered.map(|x| x.1).unwrap_or(0),
+            demography,
+            pollution: 0.,
+            consumption_shock: 0.,
+            storage,
+            expectations,
+            script: ScriptPolicy::default(),
+            market_tags: vec![],
+            money_balance,
+            money_flows: MoneyFlows::default(),
+            decisions: DecisionLog::default(),
+            prestige,
+            standard_of_living,
+        }
+    }
+}
+
+#[typetag::serde]
+impl EcoEntity for BasicPop {
+    fn produce_and_consume(&mut self) -> f64 {
+        let mut delta_sol = 0.;
+        let needs = self.needs.resolve(&self.goods_priority_order);
+        for need in needs.iter() {
+            let covered = need.consume(&mut self.goods_inventory,
+                                       |x| shock::shocked(self.demography.scale(self.consumed_goods_per_tick[&x]), self.consumption_shock));
+            delta_sol += if covered >= 1. { 1. } else { covered - 1. };
+        }
+        delta_sol -= self.pollution;
+        self.standard_of_living += delta_sol;
+        let satisfaction = delta_sol / needs.len().max(1) as f64;
+        let change = self.demography.update(satisfaction);
+        self.decisions.record("grow", None, vec![
+            ("satisfaction", satisfaction),
+            ("change", change as f64),
+        ], self.demography.population());
+        delta_sol
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        let mut goods = self.goods_priority_order.clone();
+        goods.extend(self.labor_good_uid);
+        (goods, self.market_tags.clone())
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        // A good without a market among the ones of the pop is neither sold nor bought
+        if let Some(market) = self.labor_good_uid.and_then(|x| markets.iter_mut().find(|m| m.good_uid() == x)) {
+            let labor_good_uid = market.good_uid();
+            let inputs = vec![
+                ("wage", market.price_per_unit()),
+            ];
+            let labor = self.script.decide("work", &inputs, self.demography.scale(self.labor_per_tick), market.unit_scale());
+            // Refused when there's nobody to work
+            let _ = market.register_order(Owner::Entity(id), OrderType::Sell, labor, self.prestige);
+            self.decisions.record("work", Some(labor_good_uid), inputs, labor);
+        }
+        // Trend of the price of every good and their mean, see Expectations
+        let trends: Vec<f64> = self.goods_priority_order.iter()
+            .map(|good| markets.iter().find(|x| x.good_uid() == *good).map_or(0., |x| Expectations::trend(x.as_ref())))
+            .collect();
+        let mean_trend = trends.iter().sum::<f64>() / trends.len().max(1) as f64;
+        let trends: HashMap<GoodUid, f64> = self.goods_priority_order.iter().copied().zip(trends).collect();
+        let per_tick = |good: GoodUid| shock::shocked(self.demography.scale(self.consumed_goods_per_tick[&good]), self.consumption_shock);
+        let mut actual_expense = 0.;
+        // The lowest tier with a need that can't be stocked up, the higher ones buy nothing
+        let mut blocked: Option<NeedTier> = None;
+        for need in self.needs.resolve(&self.goods_priority_order) {
+            if blocked.is_some_and(|x| need.tier > x) {
+                break;
+            }
+            let good = need.choose(markets, per_tick, self.money_balance - actual_expense);
+            let trend = trends[&good];
+            let Some(market) = markets.iter_mut().find(|x| x.good_uid() == good) else {
+                continue;
+            };
+            let target_quantity = self.expectations.target(
+                shock::shocked(self.demography.scale(self.goods_desired_inventory[&good]), self.consumption_shock), trend, mean_trend);
+            // The stocks of the other substitutes count as the ticks of the cheapest one they cover
+            let others = Need { goods: need.goods.iter().copied().filter(|x| *x != good).collect(), ..need };
+            let stock = self.goods_inventory[&good]
+                + (others.coverage(&self.goods_inventory, per_tick) * per_tick(good) as f64) as Quantity;
+            if stock >= target_quantity {
+                continue;
+            }
+            let aval_money = self.money_balance - actual_expense;
+            // The money for less than a lot buys nothing
+            let enough_money_to_buy = market.affordable_order(aval_money).unwrap_or(0);
+            if enough_money_to_buy < target_quantity - stock {
+                blocked = Some(need.tier);
+            }
+            let required = (target_quantity - stock).min(enough_money_to_buy);
+            let unit_scale = market.unit_scale();
+            let inputs = vec![
+                ("price", market.price_per_unit()),
+                ("trend", trend),
+                ("money_available", aval_money),
+                ("stock", goods::to_units(stock, unit_scale)),
+                ("target", goods::to_units(target_quantity, unit_scale)),
+            ];
+            let required = self.script.decide("buy", &inputs, required, unit_scale).min(enough_money_to_buy);
+            self.decisions.record("buy", Some(good), inputs, required);
+            actual_expense += market.cost_of(required);
+            // Never pay more than the price used to compute the budget
+            let limit_price = market.price_per_unit();
+            let _ = market.register_limit_order(Owner::Entity(id), OrderType::Buy, required, self.prestige, limit_price);
+        }
+    }
+
+    fn settle_order(&mut self, good_uid: GoodUid, result: OrderResult) {
+        if Some(good_uid) == self.labor_good_uid {
+            assert!(matches!(result.ordertype, OrderType::Sell));
+            self.money_balance += result.total_cost;
+            self.money_flows.record(FlowKind::Wages, result.total_cost);
+            return;
+        }
+        if result.remainder > 0 {
+            self.decisions.record("lot_remainder", Some(good_uid), vec![], result.remainder);
+        }
+        match result.ordertype {
+            OrderType::Buy => {
+                *self.goods_inventory.get_mut(&good_uid).unwrap() += result.traded_quantity;
+                self.money_balance -= result.total_cost;
+                self.money_flows.record(FlowKind::Trade, -result.total_cost);
+            }
+            OrderType::Sell => {
+                *self.goods_inventory.get_mut(&good_uid).unwrap() -= result.traded_quantity;
+                self.money_balance += result.total_cost;
+                unreachable!()
+            }
+        }
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn transfer(&mut self, kind: FlowKind, amount: f64) -> bool {
+        self.money_balance += amount;
+        self.money_flows.record(kind, amount);
+        true
+    }
+
+    fn take_decisions(&mut self) -> Vec<Decision> {
+        self.decisions.take()
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        self.goods_priority_order.iter().map(|x| (*x, self.goods_inventory[x])).collect()
+    }
+
+    fn suffer_pollution(&mut self, damage: PollutionDamage) {
+        self.pollution = damage.standard_of_living;
+    }
+
+    fn shock_consumption(&mut self, change: f64) -> bool {
+        self.consumption_shock += change;
+        true
+    }
+
+    fn store(&mut self, goods: &GoodRegistry) {
+        let mut stocks: Vec<(GoodUid, &mut Quantity)> = self.goods_inventory.iter_mut().map(|(x, q)| (*x, q)).collect();
+        stocks.sort_by_key(|x| x.0);
+        let cost = self.storage.keep(goods, stocks, self.money_balance);
+        self.money_balance -= cost;
+        self.money_flows.record(FlowKind::Storage, -cost);
+    }
+
+    fn take_waste(&mut self) -> Vec<WasteRecord> {
+        self.storage.take()
+    }
+
+    fn state_fields(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("standard_of_living", self.standard_of_living),
+            ("population", self.demography.population() as f64),
+            ("labor_per_tick", self.demography.scale(self.labor_per_tick) as f64),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProductorOneToOne {
+    pub(crate) input_good_uid: GoodUid,
+    pub(crate) output_good_uid: GoodUid,
+    // Inventory
+    pub(crate) input_quantity: Quantity,
+    pub(crate) output_quantity: Quantity,
+    // Inventory desired quantity
+    pub(crate) target_input_quantity: Quantity,
+    pub(crate) target_output_quantity: Quantity,
+    // Conversions, output units per input unit
+    pub(crate) conversion_rateo: f64,
+    pub(crate) target_input_per_tick: Quantity,
+    // Input is used only in multiples of the batch, 0 for no batches
+    #[serde(default)]
+    pub(crate) batch: Quantity,
+    // Operation costs TODO: use better parameters
+    pub(crate) per_input_unit_cost: f64,
+    pub(crate) fixed_cost: f64,
+    // Labor hired for the production, required per input unit
+    pub(crate) workforce: Workforce,
+    // Caps the input used per tick, None for no limit
+    #[serde(default)]
+    pub(crate) building: Option<Building>,
+    // Input lost and byproduct emitted per input unit
+    pub(crate) waste: WasteProfile,
+    #[serde(default)]
+    pub(crate) storage: Storage,
+    // Changes the desired input stock with the price trend
+    #[serde(default)]
+    pub(crate) expectations: Expectations,
+    // Forward purchases of the input
+    #[serde(default)]
+    pub(crate) contracts: ContractPolicy,
+    // Ask of the output and bid of the input, None to trade at the price of the market
+    #[serde(default)]
+    pub(crate) pricing: Option<PricingStrategy>,
+    // Output being produced
+    #[serde(default)]
+    pub(crate) pipeline: Pipeline,
+    // Scales target_input_per_tick with the sales and decides when to stop producing
+    pub(crate) profit: ProfitTracker,
+    pub(crate) state: ProducerState,
+    // Decisions overridden by the scenario
+    #[serde(default)]
+    pub(crate) script: ScriptPolicy,
+    // Tags the markets of the entity must have, see Region::eligible_markets
+    #[serde(default)]
+    pub(crate) market_tags: Vec<MarketMetadata>,
+    // Others
+    pub(crate) input_unit_scale: Quantity,
+    pub(crate) output_unit_scale: Quantity,
+    pub(crate) money_balance: f64,
+    pub(crate) money_flows: MoneyFlows,
+    #[serde(skip)]
+    pub(crate) decisions: DecisionLog,
+    pub(crate) prestige: f64,
+}
+
+impl ProductorOneToOne {
+    // The fixed cost with the one of the levels of the building
+    fn total_fixed_cost(&self) -> f64 {
+        self.fixed_cost + self.building.as_ref().map_or(0., |x| x.fixed_cost())
+    }
+
+    // Cost of a unit of output at the current prices of the input and of the labor, producing
+    // the target input per tick
+    fn unit_cost(&self, markets: &[Box<dyn Market>]) -> f64 {
+        let input_price = markets.iter().find(|x| x.good_uid() == self.input_good_uid).map_or(0., |x| x.price_per_unit());
+        let output_per_input = self.conversion_rateo * self.waste.efficiency();
+        if output_per_input <= 0. {
+            return 0.;
+        }
+        let variable_cost = (input_price + self.per_input_unit_cost + self.workforce.wages_per_unit(markets)) / output_per_input;
+        pricing::unit_cost(variable_cost, self.total_fixed_cost(),
+                           goods::to_units(self.target_input_per_tick, self.input_unit_scale) * output_per_input)
+    }
+}
+
+#[typetag::serde]
+impl EcoEntity for ProductorOneToOne {
+    fn produce_and_consume(&mut self) -> f64 {
+        // A dormant producer restarts when its stock has been sold
+        if self.state == ProducerState::Dormant && self.output_quantity <= self.target_output_quantity {
+            self.decisions.record("restart", Some(self.output_good_uid), vec![
+                ("stock", goods::to_units(self.output_quantity, self.output_unit_scale)),
+            ], 0);
+            self.state = ProducerState::Active;
+            self.profit.reset();
+        }
+        let fixed_cost = self.total_fixed_cost();
+        let short = self.state == ProducerState::Active && self.profit.solvency().check(self.money_balance, fixed_cost);
+        if short && self.profit.solvency().should_go_bankrupt() {
+            self.decisions.record("go_bankrupt", None, vec![
+                ("money", self.money_balance),
+                ("fixed_cost", fixed_cost),
+            ], 0);
+            self.state = ProducerState::Bankrupt;
+        } else if short {
+            // Short of money it idles, paying what it can of the fixed costs
+            let paid = self.money_balance.clamp(0., fixed_cost);
+            self.decisions.record("idle", None, vec![
+                ("money", self.money_balance),
+                ("fixed_cost", fixed_cost),
+            ], 0);
+            self.money_balance -= paid;
+            self.money_flows.record(FlowKind::FixedCost, -paid);
+            self.profit.add_cost(paid);
+        }
+        if self.state != ProducerState::Active || short {
+            self.workforce.end_production();
+            // What was started before still completes
+            self.output_quantity += self.pipeline.advance(0);
+            return 0.;
+        }
+        let enough_money_to_input =
+            ((self.money_balance - fixed_cost) / self.per_input_unit_cost * self.input_unit_scale as f64) as Quantity;
+        let input_value = self.input_quantity.min(self.target_input_per_tick).min(enough_money_to_input)
+            .min(self.workforce.max_production(self.input_unit_scale))
+            .min(self.building.as_ref().map_or(Quantity::MAX, |x| x.capacity()));
+        let inputs = vec![
+            ("money", self.money_balance),
+            ("input_stock", goods::to_units(self.input_quantity, self.input_unit_scale)),
+            ("target_input_per_tick", goods::to_units(self.target_input_per_tick, self.input_unit_scale)),
+            ("batch", goods::to_units(self.batch, self.input_unit_scale)),
+            ("labor_available", self.workforce.available() as f64),
+        ];
+        let mut input_value = self.script.decide("produce", &inputs, input_value, self.input_unit_scale).min(input_value);
+        // Below a batch the producer idles
+        if self.batch > 0 {
+            input_value -= input_value % self.batch;
+        }
+        self.decisions.record("produce", Some(self.input_good_uid), inputs, input_value);
+        self.workforce.end_production();
+        let input_units = goods::to_units(input_value, self.input_unit_scale);
+        self.waste.lose(self.input_good_uid, input_value);
+        self.waste.emit(input_units);
+        let output_value = (input_units * self.waste.efficiency() * self.conversion_rateo * self.output_unit_scale as f64) as Quantity;
+        self.input_quantity -= input_value;
+        self.output_quantity += self.pipeline.advance(output_value);
+        let variable_cost = input_units * self.per_input_unit_cost;
+        self.money_balance -= variable_cost + fixed_cost;
+        self.money_flows.record(FlowKind::VariableCost, -variable_cost);
+        self.money_flows.record(FlowKind::FixedCost, -fixed_cost);
+        self.profit.add_cost(variable_cost + fixed_cost);
+        0.
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        let mut goods = vec![self.input_good_uid, self.output_good_uid];
+        goods.extend(self.building.as_ref().map(|x| x.capital_good_uid()));
+        goods.extend(self.workforce.good_uid());
+        (goods, self.market_tags.clone())
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        // Individuate input and output markets
+        // see https://stackoverflow.com/questions/30073684/how-to-get-mutable-references-to-two-array-elements-at-the-same-time
+        // for why we need to allow us to take two mutable from the slice
+        // we need to take them separately in separate scopes so that the &mut on
+        // markets get free again after you finished the use of input_market
+        // Workers for the next production, keeping the money for the costs of the production
+        let budget = self.money_balance - self.total_fixed_cost()
+            - goods::to_units(self.target_input_per_tick, self.input_unit_scale) * self.per_input_unit_cost;
+        let unit_cost = self.unit_cost(markets);
+        // Dormant and bankrupt producers only sell their stock
+        if self.state == ProducerState::Active {
+            let expected_wages = self.workforce.hire(
+                id, markets, self.target_input_per_tick, self.input_unit_scale, budget, self.prestige, &mut self.decisions);
+            let input_market = markets.iter_mut().find(|x| x.good_uid() == self.input_good_uid);
+            // Check if more input is needed, a single input has nothing to be replaced with
+            let trend = input_market.as_deref().map_or(0., |x| Expectations::trend(x.as_ref()));
+            let target_input_quantity = self.expectations.target(self.target_input_quantity, trend, trend);
+            if let Some(input_market) = input_market.filter(|_| self.input_quantity < target_input_quantity) {
+                let mut required = target_input_quantity - self.input_quantity;
+                // The money set aside for the building is not spent on the input, and a bid above
+                // the price can cost more
+                let aval_money = self.money_balance - expected_wages - self.building.as_ref().map_or(0., |x| x.budget());
+                let aval_money = aval_money / (1. + self.pricing.as_ref().map_or(0., |x| x.premium().max(0.)));
+                if input_market.cost_of(required) > aval_money {
+                    required = input_market.affordable_order(aval_money).unwrap_or(0);
+                }
+                let inputs = vec![
+                    ("price", input_market.price_per_unit()),
+                    ("trend", trend),
+                    ("money_available", aval_money),
+                    ("expected_wages", expected_wages),
+                    ("stock", goods::to_units(self.input_quantity, self.input_unit_scale)),
+                    ("target", goods::to_units(target_input_quantity, self.input_unit_scale)),
+                ];
+                let mut required = self.script.decide("buy", &inputs, required, self.input_unit_scale);
+                if input_market.cost_of(required) > aval_money {
+                    required = input_market.affordable_order(aval_money).unwrap_or(0);
+                }
+                let limit_price = match self.pricing.as_mut() {
+                    Some(pricing) => pricing.bid(input_market.price_per_unit(), required),
+                    None => input_market.price_per_unit(),
+                };
+                let mut inputs = inputs;
+                if self.pricing.is_some() {
+                    inputs.push(("bid", limit_price));
+                }
+                self.decisions.record("buy", Some(self.input_good_uid), inputs, required);
+                let _ = input_market.register_limit_order(Owner::Entity(id), OrderType::Buy, required, self.prestige, limit_price);
+            }
+            if let Some(building) = self.building.as_mut() {
+                building.invest(id, markets, self.money_balance - expected_wages, self.prestige, &mut self.decisions);
+            }
+        }
+        {
+            let Some(output_market) = markets.iter_mut().find(|x| x.good_uid() == self.output_good_uid) else {
+                return;
+            };
+            // Check if you have output to sell
+            if self.output_quantity > self.target_output_quantity {
+                let mut inputs = vec![
+                    ("stock", goods::to_units(self.output_quantity, self.output_unit_scale)),
+                    ("target", goods::to_units(self.target_output_quantity, self.output_unit_scale)),
+                ];
+                let required = self.output_quantity - self.target_output_quantity;
+                let required = self.script.decide("sell", &inputs, required, self.output_unit_scale).min(self.output_quantity);
+                let ask = self.pricing.as_mut().map(|x| x.ask(unit_cost, required));
+                if let Some(ask) = ask {
+                    inputs.extend([("unit_cost", unit_cost), ("ask", ask)]);
+                }
+                self.decisions.record("sell", Some(self.output_good_uid), inputs, required);
+                let registered = match ask {
+                    Some(ask) => output_market.register_limit_order(Owner::Entity(id), OrderType::Sell, required, self.prestige, ask),
+                    None => output_market.register_order(Owner::Entity(id), OrderType::Sell, required, self.prestige),
+                };
+                if registered.is_ok() {
+                    self.profit.add_offer(required);
+                }
+            }
+        }
+    }
+
+    fn post_contract_offers(&mut self, id: EntityId, markets: &[Box<dyn Market>], contracts: &mut ContractBook) {
+        if self.state != ProducerState::Active {
+            return;
+        }
+        let Some(market) = markets.iter().find(|x| x.good_uid() == self.input_good_uid) else {
+            return;
+        };
+        let offered = self.contracts.offer(id, market.as_ref(), OrderType::Buy, self.target_input_per_tick, self.prestige, contracts);
+        if offered > 0 {
+            self.decisions.record("offer_contract", Some(self.input_good_uid), vec![
+                ("price", market.price_per_unit()),
+                ("ticks", self.contracts.ticks as f64),
+            ], offered);
+        }
+    }
+
+    fn settle_order(&mut self, good_uid: GoodUid, result: OrderResult) {
+        if let Some(wages) = self.workforce.settle(good_uid, &result) {
+            self.money_balance -= wages;
+            self.money_flows.record(FlowKind::Wages, -wages);
+            self.profit.add_cost(wages);
+            return;
+        }
+        // An investment, not a cost of the production
+        if let Some(cost) = self.building.as_mut().and_then(|x| x.settle(good_uid, &result, &mut self.decisions)) {
+            self.money_balance -= cost;
+            self.money_flows.record(FlowKind::Trade, -cost);
+            return;
+        }
+        if result.remainder > 0 {
+            self.decisions.record("lot_remainder", Some(good_uid), vec![], result.remainder);
+        }
+        match result.ordertype {
+            OrderType::Buy => {
+                assert_eq!(good_uid, self.input_good_uid);
+                self.input_quantity += result.traded_quantity;
+                self.money_balance -= result.total_cost;
+                self.money_flows.record(FlowKind::Trade, -result.total_cost);
+                self.profit.add_cost(result.total_cost);
+                if let Some(pricing) = self.pricing.as_mut() {
+                    pricing.add_purchase(result.traded_quantity);
+                }
+            }
+            OrderType::Sell => {
+                assert_eq!(good_uid, self.output_good_uid);
+                self.output_quantity -= result.traded_quantity;
+                self.money_balance += result.total_cost;
+                self.money_flows.record(FlowKind::Trade, result.total_cost);
+                self.profit.add_sale(result.traded_quantity, result.total_cost);
+                if let Some(pricing) = self.pricing.as_mut() {
+                    pricing.add_sale(result.traded_quantity);
+                }
+            }
+        }
+    }
+
+    fn end_settlement(&mut self) {
+        if let Some(pricing) = self.pricing.as_mut() {
+            pricing.adjust();
+        }
+        let fixed_cost = self.total_fixed_cost();
+        let factor = self.profit.close_tick(fixed_cost);
+        if self.state != ProducerState::Active {
+            return;
+        }
+        if self.profit.should_go_dormant() {
+            self.decisions.record("go_dormant", None, vec![("fixed_cost", fixed_cost)], 0);
+            self.state = ProducerState::Dormant;
+            self.profit.reset();
+        } else if factor != 1. || self.script.overrides("scale") {
+            let inputs = vec![
+                ("factor", factor),
+                ("money", self.money_balance),
+                ("input_stock", goods::to_units(self.input_quantity, self.input_unit_scale)),
+                ("output_stock", goods::to_units(self.output_quantity, self.output_unit_scale)),
+            ];
+            let target = (self.target_input_per_tick as f64 * factor) as Quantity;
+            let target = self.script.decide("scale", &inputs, target, self.input_unit_scale);
+            self.target_input_per_tick = target.min(self.target_input_quantity).max(self.input_unit_scale);
+            self.decisions.record("scale", Some(self.input_good_uid), inputs, self.target_input_per_tick);
+        }
+        // Growing past the building, a share of the margin goes to a new level
+        if factor > 1. {
+            if let Some(building) = self.building.as_mut() {
+                building.plan(self.target_input_per_tick, self.input_unit_scale, self.profit.last_margin(), &mut self.decisions);
+            }
+        }
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn transfer(&mut self, kind: FlowKind, amount: f64) -> bool {
+        self.money_balance += amount;
+        self.money_flows.record(kind, amount);
+        true
+    }
+
+    fn take_decisions(&mut self) -> Vec<Decision> {
+        self.decisions.take()
+    }
+
+    fn pay_tax(&mut self, amount: f64) -> f64 {
+        let paid = amount.min(self.money_balance).max(0.);
+        self.money_balance -= paid;
+        self.money_flows.record(FlowKind::Tax, -paid);
+        paid
+    }
+
+    fn store(&mut self, goods: &GoodRegistry) {
+        let stocks = vec![(self.input_good_uid, &mut self.input_quantity), (self.output_good_uid, &mut self.output_quantity)];
+        let cost = self.storage.keep(goods, stocks, self.money_balance);
+        self.money_balance -= cost;
+        self.money_flows.record(FlowKind::Storage, -cost);
+        self.profit.add_cost(cost);
+    }
+
+    fn take_waste(&mut self) -> Vec<WasteRecord> {
+        let mut records = self.waste.take();
+        records.extend(self.storage.take());
+        records
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        let mut inventory = vec![(self.input_good_uid, self.input_quantity), (self.output_good_uid, self.output_quantity)];
+        inventory.extend(self.waste.inventory());
+        inventory.extend(self.building.as_ref().map(|x| (x.capital_good_uid(), x.capital())));
+        inventory
+    }
+
+    fn state_fields(&self) -> Vec<(&'static str, f64)> {
+        let mut fields = vec![
+            ("target_input_quantity", goods::to_units(self.target_input_quantity, self.input_unit_scale)),
+            ("target_output_quantity", goods::to_units(self.target_output_quantity, self.output_unit_scale)),
+            ("target_input_per_tick", goods::to_units(self.target_input_per_tick, self.input_unit_scale)),
+            ("output_in_progress", goods::to_units(self.pipeline.in_progress(), self.output_unit_scale)),
+            ("labor_available", self.workforce.available() as f64),
+            ("sales_ratio", self.profit.sales_ratio().unwrap_or(0.)),
+            ("dormant", (self.state == ProducerState::Dormant) as u8 as f64),
+            ("bankrupt", (self.state == ProducerState::Bankrupt) as u8 as f64),
+        ];
+        if let Some(building) = &self.building {
+            fields.push(("level", building.level() as f64));
+            fields.push(("investment_budget", building.budget()));
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{Price, TestMarket};
+
+    #[test]
+    fn pops_stock_up_on_rising_prices_and_substitute() {
+        let market = |good_uid: GoodUid, old_price: Price, price: Price| -> Box<dyn Market> {
+            let mut market = TestMarket::new(good_uid, 1, old_price);
+            market.clear_state().unwrap();
+            market.price_per_unit = price;
+            Box::new(market)
+        };
+        let ordered = |markets: &[Box<dyn Market>]| -> Vec<Quantity> {
+            markets.iter().map(|x| x.registered_orders()[0].1.quantity).collect()
+        };
+        let pop = |expectations: Expectations| BasicPop::new(
+            vec![0, 1], vec![0, 0], vec![10, 10], vec![1, 1], None, Demography::new(1, 0., 0.),
+            Storage::default(), expectations, 1000., 0., 0.);
+        // Both prices doubled, everything is stocked up
+        let mut markets = vec![market(0, 1., 2.), market(1, 1., 2.)];
+        pop(Expectations::new(0.5, 1., 0.5)).post_orders_to_markets(EntityId(0), &mut markets);
+        assert_eq!(ordered(&markets), vec![15, 15]);
+        // Only the first one rose, it is replaced with the second one
+        let mut markets = vec![market(0, 1., 1.2), market(1, 1., 1.)];
+        pop(Expectations::new(0., 1., 0.5)).post_orders_to_markets(EntityId(0), &mut markets);
+        assert_eq!(ordered(&markets), vec![9, 11]);
+        // Without expectations the targets are fixed
+        let mut markets = vec![market(0, 1., 2.), market(1, 1., 1.)];
+        pop(Expectations::default()).post_orders_to_markets(EntityId(0), &mut markets);
+        assert_eq!(ordered(&markets), vec![10, 10]);
+    }
+
+    #[test]
+    fn pops_buy_the_cheapest_substitute_and_luxuries_last() {
+        let new_markets = || -> Vec<Box<dyn Market>> {
+            vec![Box::new(TestMarket::new(0, 1, 3.)), Box::new(TestMarket::new(1, 1, 2.)), Box::new(TestMarket::new(2, 1, 1.))]
+        };
+        let ordered = |markets: &[Box<dyn Market>]| -> Vec<Quantity> {
+            markets.iter().map(|x| x.registered_orders().first().map_or(0, |x| x.1.quantity)).collect()
+        };
+        let pop = |money_balance: f64| BasicPop {
+            needs: Needs::new(vec![
+                Need { name: "fun".to_owned(), tier: NeedTier::Luxury, goods: vec![2], by_quality: false },
+                Need { name: "food".to_owned(), tier: NeedTier::Life, goods: vec![0, 1], by_quality: false },
+            ]),
+            ..BasicPop::new(
+                vec![0, 1, 2], vec![0, 5, 0], vec![10, 10, 10], vec![1, 1, 1], None, Demography::new(1, 0., 0.),
+                Storage::default(), Expectations::default(), money_balance, 0., 0.)
+        };
+        // Only the cheaper food is bought, for what the stock of the other one doesn't cover
+        let mut markets = new_markets();
+        pop(100.).post_orders_to_markets(EntityId(0), &mut markets);
+        assert_eq!(ordered(&markets), vec![0, 5, 10]);
+        // Without the money for the food nothing is left for the luxury
+        let mut markets = new_markets();
+        pop(8.).post_orders_to_markets(EntityId(0), &mut markets);
+        assert_eq!(ordered(&markets), vec![0, 4, 0]);
+    }
+
+    // A producer of Groceries without input, alone in the scenario
+    fn factory(money_balance: f64, output_quantity: u64, profitability: &str) -> crate::Simulation {
+        let source = format!(r#"
+            [simulation]
+            ticks = 1
+
+            [[goods]]
+            name = "Grain"
+
+            [[goods]]
+            name = "Groceries"
+
+            [[markets]]
+            kind = "test"
+            good = "Groceries"
+            price = 10.0
+
+            [[entities]]
+            kind = "producer"
+            name = "Factory"
+            input_good = "Grain"
+            output_good = "Groceries"
+            input_quantity = 0
+            output_quantity = {output_quantity}
+            target_input_quantity = 900
+            target_output_quantity = 900
+            conversion_rateo = 0.5
+            target_input_per_tick = 300
+            fixed_cost = 500.0
+            money_balance = {money_balance}
+            profitability = {{ {profitability} }}
+        "#);
+        crate::Scenario::from_toml(&source).unwrap().build().unwrap()
+    }
+
+    fn field(entity: &dyn EcoEntity, name: &str) -> f64 {
+        entity.state_fields().into_iter().find(|x| x.0 == name).unwrap().1
+    }
+
+    fn tick(entity: &mut dyn EcoEntity, sold: Option<(Quantity, Price)>) {
+        entity.produce_and_consume();
+        if let Some((quantity, revenue)) = sold {
+            entity.settle_order(1, OrderResult::new(OrderType::Sell, quantity, revenue));
+        }
+        entity.end_settlement();
+    }
+
+    #[test]
+    fn dormant_producers_restart_with_a_clean_streak() {
+        let mut sim = factory(10000., 1000, "dormancy_after = 2");
+        let factory = &mut sim.entities[EntityId(0)];
+        tick(factory.as_mut(), None);
+        assert_eq!(field(factory.as_ref(), "dormant"), 0.);
+        tick(factory.as_mut(), None);
+        assert_eq!(field(factory.as_ref(), "dormant"), 1.);
+        // The losses while dormant don't count once it restarts
+        for _ in 0..5 {
+            tick(factory.as_mut(), None);
+        }
+        tick(factory.as_mut(), Some((200, 1.)));
+        tick(factory.as_mut(), None);
+        assert_eq!(field(factory.as_ref(), "dormant"), 0.);
+        tick(factory.as_mut(), None);
+        assert_eq!(field(factory.as_ref(), "dormant"), 1.);
+    }
+
+    #[test]
+    fn producers_go_bankrupt_after_a_streak_without_money() {
+        let mut sim = factory(600., 0, "dormancy_after = 0, bankruptcy_after = 3");
+        let factory = &mut sim.entities[EntityId(0)];
+        // Short of money twice, then a sale pays the fixed costs again
+        for _ in 0..2 {
+            tick(factory.as_mut(), None);
+            assert_eq!(field(factory.as_ref(), "bankrupt"), 0.);
+        }
+        tick(factory.as_mut(), Some((0, 1000.)));
+        assert_eq!(factory.money_balance(), 1000.);
+        for _ in 0..4 {
+            tick(factory.as_mut(), None);
+            assert_eq!(field(factory.as_ref(), "bankrupt"), 0.);
+            assert!(factory.money_balance() >= 0.);
+        }
+        tick(factory.as_mut(), None);
+        assert_eq!(field(factory.as_ref(), "bankrupt"), 1.);
+    }
+
+    #[test]
+    fn rgos_idle_without_money_and_go_bankrupt() {
+        let source = r#"
+            [simulation]
+            ticks = 1
+
+            [[goods]]
+            name = "Grain"
+
+            [[markets]]
+            kind = "test"
+            good = "Grain"
+            price = 2.0
+
+            [[entities]]
+            kind = "rgo"
+            name = "RGO"
+            good = "Grain"
+            quantity = 0
+            target_quantity = 100
+            max_production_rate = 50
+            fixed_cost = 100.0
+            money_balance = 250.0
+        "#;
+        let mut sim = crate::Scenario::from_toml(source).unwrap().build().unwrap();
+        let rgo = &mut sim.entities[EntityId(0)];
+        // Two ticks of fixed costs, then the last 50$ and two more ticks short of money
+        for _ in 0..4 {
+            tick(rgo.as_mut(), None);
+            assert_eq!(field(rgo.as_ref(), "bankrupt"), 0.);
+            assert!(rgo.money_balance() >= 0.);
+        }
+        assert_eq!(rgo.inventory(), vec![(0, 100)]);
+        tick(rgo.as_mut(), None);
+        assert_eq!(field(rgo.as_ref(), "bankrupt"), 1.);
+        assert_eq!(rgo.money_balance(), 0.);
+    }
+}
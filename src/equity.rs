@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::ledger::FlowKind;
+use crate::registry::EntityRegistry;
+use crate::EntityId;
+
+// Ownership of the firms. The shares of a firm are held by the investors, the ones nobody holds
+// by the firm itself. At the end of every tick a firm pays `payout` of its profit, the change of
+// its money over the tick, to the holders in proportion to their shares.
+// Then the shares change hands at a single price per firm: the money of the firm per share plus
+// the dividend per share of the tick capitalized at `yield`. Every investor spends `savings` of
+// its money above `reserve` on the shares of all the firms, in equal parts, and sells the shares
+// it needs to get back to the reserve when it falls below it. The shares offered by the investors
+// are sold first, then the ones held by the firm, and the buyers get them in proportion to what
+// they ask.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EquityPolicy {
+    pub savings: f64,
+    pub reserve: f64,
+    #[serde(rename = "yield")]
+    pub dividend_yield: f64,
+}
+
+impl Default for EquityPolicy {
+    fn default() -> Self {
+        EquityPolicy { savings: 0.1, reserve: 0., dividend_yield: 0.05 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Firm {
+    pub firm: EntityId,
+    pub shares: u64,
+    // Fraction of the profit paid as dividends
+    pub payout: f64,
+    holders: BTreeMap<EntityId, u64>,
+    // Of the firm after the dividends and the trades of the last tick, None before the first one
+    last_money: Option<f64>,
+    // Of the last tick
+    price: f64,
+    dividends: f64,
+    traded: u64,
+}
+
+impl Firm {
+    // Shares held by the firm
+    pub fn treasury(&self) -> u64 {
+        self.shares - self.holders.values().sum::<u64>()
+    }
+
+    pub fn held_by(&self, id: EntityId) -> u64 {
+        self.holders.get(&id).copied().unwrap_or(0)
+    }
+
+    pub fn price(&self) -> f64 {
+        self.price
+    }
+
+    pub fn dividends(&self) -> f64 {
+        self.dividends
+    }
+
+    pub fn traded(&self) -> u64 {
+        self.traded
+    }
+
+    fn pay_dividends(&mut self, entities: &mut EntityRegistry) {
+        let money = entities[self.firm].money_balance();
+        let profit = money - self.last_money.unwrap_or(money);
+        self.dividends = 0.;
+        if profit <= 0. || self.shares == 0 {
+            return;
+        }
+        let per_share = (profit * self.payout).min(money.max(0.)) / self.shares as f64;
+        for (holder, shares) in self.holders.iter() {
+            if pay(entities, self.firm, *holder, per_share * *shares as f64, FlowKind::Dividend) {
+                self.dividends += per_share * *shares as f64;
+            }
+        }
+        // The dividends of the shares of the firm stay in the firm
+        self.dividends += per_share * self.treasury() as f64;
+    }
+
+    fn trade(&mut self, entities: &mut EntityRegistry, investors: &[EntityId], policy: &EquityPolicy, firms: usize) {
+        self.traded = 0;
+        let per_share = self.dividends / self.shares.max(1) as f64;
+        let capitalized = match policy.dividend_yield > 0. {
+            true => per_share / policy.dividend_yield,
+            false => 0.,
+        };
+        self.price = entities[self.firm].money_balance().max(0.) / self.shares.max(1) as f64 + capitalized;
+        if self.price <= 0. {
+            return;
+        }
+        let mut bids = vec![];
+        let mut offers = vec![];
+        for id in investors.iter().filter(|x| **x != self.firm) {
+            let money = entities[*id].money_balance();
+            if money > policy.reserve {
+                let budget = (money - policy.reserve) * policy.savings / firms as f64;
+                bids.push((*id, (budget / self.price) as u64));
+            } else {
+                let needed = ((policy.reserve - money) / self.price).ceil() as u64;
+                offers.push((*id, needed.min(self.held_by(*id))));
+            }
+        }
+        offers.push((self.firm, self.treasury()));
+        bids.retain(|x| x.1 > 0);
+        offers.retain(|x| x.1 > 0);
+        let demand: u64 = bids.iter().map(|x| x.1).sum();
+        let supply: u64 = offers.iter().map(|x| x.1).sum();
+        let bought = pro_rata(demand.min(supply), &bids.iter().map(|x| x.1).collect::<Vec<_>>());
+        let mut offers = offers.into_iter();
+        let mut offer = offers.next();
+        for ((buyer, _), mut quantity) in bids.into_iter().zip(bought) {
+            while quantity > 0 {
+                let Some((seller, left)) = offer.as_mut() else {
+                    break;
+                };
+                let fill = quantity.min(*left);
+                if !pay(entities, buyer, *seller, fill as f64 * self.price, FlowKind::Shares) {
+                    // A seller that can't take the money keeps its shares
+                    offer = offers.next();
+                    continue;
+                }
+                self.transfer_shares(*seller, buyer, fill);
+                self.traded += fill;
+                quantity -= fill;
+                *left -= fill;
+                if *left == 0 {
+                    offer = offers.next();
+                }
+            }
+        }
+    }
+
+    fn transfer_shares(&mut self, from: EntityId, to: EntityId, shares: u64) {
+        if from != self.firm {
+            let held = self.holders.get_mut(&from).unwrap();
+            *held -= shares;
+            if *held == 0 {
+                self.holders.remove(&from);
+            }
+        }
+        *self.holders.entry(to).or_insert(0) += shares;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EquityMarket {
+    policy: EquityPolicy,
+    investors: Vec<EntityId>,
+    pub firms: Vec<Firm>,
+}
+
+impl EquityMarket {
+    pub fn new(policy: EquityPolicy, investors: Vec<EntityId>) -> EquityMarket {
+        EquityMarket { policy, investors, firms: vec![] }
+    }
+
+    // The shares not given to the owners are held by the firm
+    pub fn add_firm(&mut self, firm: EntityId, shares: u64, payout: f64, owners: Vec<(EntityId, u64)>) {
+        let holders = owners.into_iter().filter(|x| x.1 > 0).collect();
+        self.firms.push(Firm { firm, shares, payout, holders, last_money: None, price: 0., dividends: 0., traded: 0 });
+    }
+
+    // The dividends and the trades of the shares of the tick
+    pub fn settle(&mut self, entities: &mut EntityRegistry) {
+        let firms = self.firms.len();
+        for firm in self.firms.iter_mut() {
+            firm.pay_dividends(entities);
+            firm.trade(entities, &self.investors, &self.policy, firms);
+            firm.last_money = Some(entities[firm.firm].money_balance());
+        }
+    }
+}
+
+// `total` split in proportion to the weights, the rest of the rounding to the first ones
+fn pro_rata(total: u64, weights: &[u64]) -> Vec<u64> {
+    let sum: u64 = weights.iter().sum();
+    if sum == 0 {
+        return vec![0; weights.len()];
+    }
+    let mut split: Vec<u64> = weights.iter().map(|x| (*x as u128 * total as u128 / sum as u128) as u64).collect();
+    let mut rest = total - split.iter().sum::<u64>();
+    for (x, weight) in split.iter_mut().zip(weights) {
+        if rest == 0 {
+            break;
+        }
+        if *x < *weight {
+            *x += 1;
+            rest -= 1;
+        }
+    }
+    split
+}
+
+// Moves `amount` from the payer to the payee, false if either can't take part
+fn pay(entities: &mut EntityRegistry, payer: EntityId, payee: EntityId, amount: f64, kind: FlowKind) -> bool {
+    if amount <= 0. || amount > entities[payer].money_balance() || !entities[payer].transfer(kind, -amount) {
+        return false;
+    }
+    if !entities[payee].transfer(kind, amount) {
+        entities[payer].transfer(kind, amount);
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demography::Demography;
+    use crate::expectation::Expectations;
+    use crate::storage::Storage;
+    use crate::BasicPop;
+
+    fn pop(money: f64) -> Box<BasicPop> {
+        Box::new(BasicPop::new(vec![], vec![], vec![], vec![], None, Demography::default(), Storage::default(),
+                               Expectations::default(), money, 0., 0.))
+    }
+
+    #[test]
+    fn profits_are_paid_out_and_shares_bought() {
+        let mut entities = EntityRegistry::default();
+        let firm = entities.add("Firm", 0, pop(1000.));
+        let owner = entities.add("Owner", 0, pop(0.));
+        let saver = entities.add("Saver", 0, pop(500.));
+        let policy = EquityPolicy { savings: 0.5, reserve: 100., dividend_yield: 0.1 };
+        let mut equity = EquityMarket::new(policy, vec![owner, saver]);
+        equity.add_firm(firm, 100, 0.5, vec![(owner, 40)]);
+        // 10 per share without dividends: the owner sells the 10 shares it needs to get back
+        // to the reserve, the saver buys 20 with half of its 400 above the reserve
+        equity.settle(&mut entities);
+        assert_eq!((equity.firms[0].price(), equity.firms[0].traded()), (10., 20));
+        assert_eq!((equity.firms[0].held_by(owner), equity.firms[0].held_by(saver), equity.firms[0].treasury()), (30, 20, 50));
+        // 200 of profit, 100 paid out: 30 to the owner, 20 to the saver and 50 kept by the firm
+        entities[firm].transfer(FlowKind::Trade, 200.);
+        equity.settle(&mut entities);
+        let firm_state = &equity.firms[0];
+        assert_eq!(firm_state.dividends(), 100.);
+        // 1250 / 100 per share plus 1 / 0.1 capitalized, the saver buys 4 shares with 110
+        assert_eq!((firm_state.price(), firm_state.traded()), (22.5, 4));
+        assert_eq!((firm_state.held_by(owner), firm_state.held_by(saver), firm_state.treasury()), (30, 24, 46));
+        assert_eq!(entities[owner].money_balance(), 130.);
+        assert_eq!(entities[saver].money_balance(), 230.);
+        assert_eq!(entities[firm].money_balance(), 1340.);
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::Serialize;
+use crate::ledger::FlowKind;
+use crate::OrderType;
+
+// Structured log of what happens in every tick. The engine emits the events of the markets and
+// of the entities into the bus and the bus hands them to every sink. Without sinks nothing is
+// built, so the bus costs nothing when it is not used.
+// Quantities are in units of their good.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event")]
+pub enum SimEvent {
+    OrderPosted { entity: String, market: String, side: OrderType, quantity: f64, prestige: f64, limit_price: Option<f64> },
+    TradeExecuted { market: String, quantity: f64, price: f64 },
+    OrderSettled { entity: String, market: String, side: OrderType, quantity: f64, cost: f64, counterparties: Vec<String> },
+    PriceChanged { market: String, from: f64, to: f64 },
+    // The trade of the market failed and its orders of the tick were cancelled
+    MarketHalted { market: String, error: String },
+    // The state of the market was cleared with results of these owners never retrieved
+    ResultsUnretrieved { market: String, owners: Vec<String> },
+    MoneyFlow { entity: String, kind: FlowKind, amount: f64 },
+    EntityBankrupt { entity: String },
+    EntityDormant { entity: String },
+    EntityRestarted { entity: String },
+    ShockStarted { shock: String },
+    ShockEnded { shock: String },
+}
+
+impl fmt::Display for SimEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimEvent::OrderPosted { entity, market, side, quantity, prestige, limit_price } => {
+                write!(f, "{entity} posted {side:?} {quantity} on {market}, prestige {prestige}")?;
+                match limit_price {
+                    Some(x) => write!(f, ", limit {x}"),
+                    None => Ok(()),
+                }
+            }
+            SimEvent::TradeExecuted { market, quantity, price } => write!(f, "{market} traded {quantity} at {price}"),
+            SimEvent::OrderSettled { entity, market, side, quantity, cost, counterparties } => {
+                write!(f, "{entity} settled {side:?} {quantity} on {market} for {cost:.2}")?;
+                if !counterparties.is_empty() {
+                    write!(f, " with {}", counterparties.join(", "))?;
+                }
+                Ok(())
+            }
+            SimEvent::PriceChanged { market, from, to } => write!(f, "{market} price {from} -> {to}"),
+            SimEvent::MarketHalted { market, error } => write!(f, "{market} halted, {error}"),
+            SimEvent::ResultsUnretrieved { market, owners } => write!(f, "{market} lost the results of {}", owners.join(", ")),
+            SimEvent::MoneyFlow { entity, kind, amount } => write!(f, "{entity} {kind:?} {amount:+.2}"),
+            SimEvent::EntityBankrupt { entity } => write!(f, "{entity} went bankrupt"),
+            SimEvent::EntityDormant { entity } => write!(f, "{entity} went dormant"),
+            SimEvent::EntityRestarted { entity } => write!(f, "{entity} restarted"),
+            SimEvent::ShockStarted { shock } => write!(f, "shock {shock} started"),
+            SimEvent::ShockEnded { shock } => write!(f, "shock {shock} ended"),
+        }
+    }
+}
+
+pub trait EventSink: Send {
+    fn emit(&mut self, tick: u64, event: &SimEvent);
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct StdoutSink;
+
+impl EventSink for StdoutSink {
+    fn emit(&mut self, tick: u64, event: &SimEvent) {
+        println!("tick {tick}: {event}");
+    }
+}
+
+// One JSON object per line, the tick included
+pub struct JsonlSink {
+    out: BufWriter<File>,
+}
+
+impl JsonlSink {
+    pub fn create(path: &Path) -> std::io::Result<JsonlSink> {
+        Ok(JsonlSink { out: BufWriter::new(File::create(path)?) })
+    }
+}
+
+impl EventSink for JsonlSink {
+    fn emit(&mut self, tick: u64, event: &SimEvent) {
+        #[derive(Serialize)]
+        struct Line<'a> {
+            tick: u64,
+            #[serde(flatten)]
+            event: &'a SimEvent,
+        }
+        // A failed write shows up at the flush
+        if serde_json::to_writer(&mut self.out, &Line { tick, event }).is_ok() {
+            let _ = writeln!(self.out);
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Keeps the events in memory, readable from a clone while the simulation owns the sink
+#[cfg(test)]
+#[derive(Default, Clone)]
+pub struct MemorySink {
+    pub events: std::sync::Arc<std::sync::Mutex<Vec<(u64, SimEvent)>>>,
+}
+
+#[cfg(test)]
+impl EventSink for MemorySink {
+    fn emit(&mut self, tick: u64, event: &SimEvent) {
+        self.events.lock().unwrap().push((tick, event.clone()));
+    }
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    sinks: Vec<Box<dyn EventSink>>,
+}
+
+impl EventBus {
+    pub fn add_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    pub fn emit(&mut self, tick: u64, event: SimEvent) {
+        for sink in self.sinks.iter_mut() {
+            sink.emit(tick, &event);
+        }
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        for sink in self.sinks.iter_mut() {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::Scenario;
+
+    #[test]
+    fn events_reach_the_memory_sink() {
+        let scenario = Scenario::from_toml(include_str!("../scenarios/wheat_bread.toml")).unwrap();
+        let mut sim = scenario.build().unwrap();
+        let sink = MemorySink::default();
+        sim.events.add_sink(Box::new(sink.clone()));
+        sim.step();
+        sim.step();
+        let events = sink.events.lock().unwrap();
+        assert!(events.iter().any(|(_, x)| matches!(x, SimEvent::OrderPosted { entity, .. } if entity == "RGO")));
+        assert!(events.iter().any(|(_, x)| matches!(x, SimEvent::TradeExecuted { market, .. } if market == "Grain")));
+        assert!(events.iter().any(|(_, x)| matches!(x, SimEvent::OrderSettled { entity, .. } if entity == "Pop")));
+        assert!(events.iter().any(|(_, x)| matches!(x, SimEvent::MoneyFlow { kind: FlowKind::FixedCost, .. })));
+        assert!(events.iter().any(|(tick, _)| *tick == 1));
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use crate::{Market, Quantity};
+
+// Adaptive expectations of a buyer on the prices of the goods it buys. The trend of a good is
+// how far its price is from the average of the last ticks, see MarketStats. A rising price makes
+// the buyer stock up before it rises more, a falling one makes it wait. Among the goods of a
+// buyer, the one rising more than the others is replaced with the others. Without expectations
+// the buyer keeps its fixed targets whatever the prices.
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Expectations {
+    // Relative change of the target stock for a trend of +100%
+    stockpile: f64,
+    // Relative change of the target stock for a trend 100% below the mean trend of the goods
+    substitution: f64,
+    // Limit of the relative change of the target stock
+    max_change: f64,
+}
+
+impl Expectations {
+    pub fn new(stockpile: f64, substitution: f64, max_change: f64) -> Expectations {
+        Expectations { stockpile, substitution, max_change }
+    }
+
+    // Relative distance of the price from its average, 0 before the market has stats
+    pub fn trend(market: &dyn Market) -> f64 {
+        match market.stats().mean_price() {
+            Some(mean) if mean > 0. => market.price_per_unit() / mean - 1.,
+            _ => 0.,
+        }
+    }
+
+    // The target stock of a good given its trend and the mean trend of all the goods bought
+    pub fn target(&self, target: Quantity, trend: f64, mean_trend: f64) -> Quantity {
+        let change = self.stockpile * trend - self.substitution * (trend - mean_trend);
+        let change = change.clamp(-self.max_change.abs(), self.max_change.abs());
+        if change == 0. {
+            return target;
+        }
+        (target as f64 * (1. + change)).round() as Quantity
+    }
+}
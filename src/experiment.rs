@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+use rayon::prelude::*;
+use serde::Deserialize;
+use crate::recorder::Recorder;
+use crate::scenario::Scenario;
+
+// An experiment is a list of runs, every run a scenario played with some seeds. Every seed is
+// run by an `ecosim run` process of its own, up to `jobs` at a time, in {out}/{run}/seed-{seed}
+// with its output in log.txt. The outcome of all of them is collected in {out}/index.csv.
+// The paths of the manifest are relative to the manifest.
+
+#[derive(Debug, Deserialize)]
+pub struct Manifest {
+    pub out: PathBuf,
+    pub runs: Vec<RunSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunSpec {
+    pub name: String,
+    pub scenario: PathBuf,
+    // The seed of the scenario if empty
+    #[serde(default)]
+    pub seeds: Vec<u64>,
+    // Overrides the ticks of the scenario
+    pub ticks: Option<u64>,
+    // More arguments of `ecosim run`, like `--paranoid`
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+struct Job<'a> {
+    spec: &'a RunSpec,
+    seed: Option<u64>,
+    dir: PathBuf,
+}
+
+pub struct JobOutcome {
+    pub run: String,
+    pub seed: Option<u64>,
+    pub dir: PathBuf,
+    // Exit code of the process, None if it couldn't start or was killed
+    pub status: Option<i32>,
+    // Ticks in the exported series
+    pub ticks: usize,
+    pub seconds: f64,
+}
+
+impl JobOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+pub struct ExperimentReport {
+    pub index: PathBuf,
+    pub outcomes: Vec<JobOutcome>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let mut manifest: Manifest = toml::from_str(&text)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        manifest.out = base.join(&manifest.out);
+        for run in manifest.runs.iter_mut() {
+            run.scenario = base.join(&run.scenario);
+        }
+        Ok(manifest)
+    }
+
+    fn jobs(&self) -> Vec<Job<'_>> {
+        let mut jobs = vec![];
+        for spec in self.runs.iter() {
+            let seeds = match spec.seeds.is_empty() {
+                true => vec![None],
+                false => spec.seeds.iter().copied().map(Some).collect(),
+            };
+            for seed in seeds {
+                let dir = match seed {
+                    Some(seed) => self.out.join(&spec.name).join(format!("seed-{seed}")),
+                    None => self.out.join(&spec.name),
+                };
+                jobs.push(Job { spec, seed, dir });
+            }
+        }
+        jobs
+    }
+}
+
+impl Job<'_> {
+    fn run(&self, exe: &Path) -> JobOutcome {
+        let start = Instant::now();
+        // The series of an older run would be taken for the ones of this run
+        let _ = fs::remove_file(self.dir.join("series.csv"));
+        let status = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::File::create(self.dir.join("log.txt")))
+            .and_then(|log| {
+                let mut command = Command::new(exe);
+                command.arg("run").arg("--scenario").arg(&self.spec.scenario).arg("--out").arg(&self.dir);
+                if let Some(seed) = self.seed {
+                    command.arg("--seed").arg(seed.to_string());
+                }
+                if let Some(ticks) = self.spec.ticks {
+                    command.arg("--ticks").arg(ticks.to_string());
+                }
+                command.args(&self.spec.args)
+                    .stdout(Stdio::from(log.try_clone()?))
+                    .stderr(Stdio::from(log))
+                    .status()
+            });
+        let ticks = Recorder::from_csv(&self.dir.join("series.csv")).map_or(0, |x| x.ticks().len());
+        JobOutcome {
+            run: self.spec.name.clone(),
+            seed: self.seed,
+            dir: self.dir.clone(),
+            status: status.ok().and_then(|x| x.code()),
+            ticks,
+            seconds: start.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+// Run all the seeds of all the runs of the manifest, `jobs` processes at a time
+pub fn run(manifest: &Manifest, jobs: usize) -> Result<ExperimentReport, Box<dyn Error>> {
+    // A broken scenario stops the experiment before anything is run
+    for spec in manifest.runs.iter() {
+        Scenario::load(&spec.scenario).map_err(|e| format!("run `{}`: {e}", spec.name))?;
+    }
+    let exe = std::env::current_exe()?;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs.max(1)).build()?;
+    let outcomes: Vec<JobOutcome> = pool.install(|| {
+        manifest.jobs().par_iter().map(|job| job.run(&exe)).collect()
+    });
+    fs::create_dir_all(&manifest.out)?;
+    let index = manifest.out.join("index.csv");
+    let mut text = "run,seed,status,ticks,seconds,dir\n".to_owned();
+    for x in outcomes.iter() {
+        let _ = writeln!(text, "{},{},{},{},{:.3},{}",
+                         x.run,
+                         x.seed.map_or(String::new(), |x| x.to_string()),
+                         x.status.map_or("-".to_owned(), |x| x.to_string()),
+                         x.ticks,
+                         x.seconds,
+                         x.dir.display());
+    }
+    fs::write(&index, text)?;
+    Ok(ExperimentReport { index, outcomes })
+}
+
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for x in self.outcomes.iter() {
+            let seed = x.seed.map_or("-".to_owned(), |x| x.to_string());
+            let result = match x.status {
+                Some(0) => format!("ok, {} ticks", x.ticks),
+                Some(code) => format!("failed with code {code}, see {}", x.dir.join("log.txt").display()),
+                None => format!("failed, see {}", x.dir.join("log.txt").display()),
+            };
+            writeln!(f, "{:<20} seed {seed:>6} {:>8.2}s  {result}", x.run, x.seconds)?;
+        }
+        let failed = self.outcomes.iter().filter(|x| !x.succeeded()).count();
+        writeln!(f, "{} runs, {failed} failed, index in {}", self.outcomes.len(), self.index.display())
+    }
+}
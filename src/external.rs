@@ -0,0 +1,427 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::goods;
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::trace::{Decision, DecisionLog};
+use crate::{EcoEntity, EntityId, GoodUid, Market, MarketMetadata, OrderResult, OrderType, Owner, Price, Quantity};
+
+// An entity whose decisions are taken by another process, so that agents can be written in any
+// language. The simulation keeps its money and its stock of the goods it trades, the agent only
+// sees them and decides the orders. They talk in JSON lines: every tick the agent gets an
+// observation and must reply with the orders of the tick and the same `seq`:
+//
+//   > {"seq":3,"money":980.0,"goods":[{"good":"Grain","stock":10.0,"price":2.0,"mean_price":2.0,"lot":1.0}],
+//      "fills":[{"good":"Grain","side":"Buy","traded":10.0,"cost":20.0}]}
+//   < {"seq":3,"orders":[{"good":"Grain","side":"Sell","quantity":5.0,"limit":2.5}]}
+//
+// Quantities are in units of the good, the fills are the trades of the last tick and `limit` is
+// optional. Buy orders are cut to the money left, sell orders to the stock. When the agent
+// doesn't reply in time, or replies with something else, the fallback policy decides the orders
+// of the tick; late replies are ignored. When the connection is lost the agent is not asked
+// again. The connection is not saved in checkpoints, a resumed run starts the agent again.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+    // A process started with this command line, talking on its stdin and stdout
+    Command(Vec<String>),
+    // A process already listening on this address, like 127.0.0.1:7000
+    Connect(String),
+}
+
+// What the entity does in a tick without a reply
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackPolicy {
+    // No orders
+    #[default]
+    Idle,
+    // The orders of the last reply
+    Repeat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalOrder {
+    pub good: String,
+    pub side: OrderType,
+    pub quantity: f64,
+    #[serde(default)]
+    pub limit: Option<Price>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fill {
+    good: String,
+    side: OrderType,
+    traded: f64,
+    cost: Price,
+}
+
+#[derive(Serialize)]
+struct Observation<'a> {
+    seq: u64,
+    money: f64,
+    goods: Vec<GoodObservation<'a>>,
+    fills: &'a [Fill],
+}
+
+#[derive(Serialize)]
+struct GoodObservation<'a> {
+    good: &'a str,
+    stock: f64,
+    price: Price,
+    // Over the last ticks, None before the market has stats
+    mean_price: Option<Price>,
+    lot: f64,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    seq: u64,
+    #[serde(default)]
+    orders: Vec<ExternalOrder>,
+}
+
+// A good traded by the agent and its stock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalGood {
+    pub good_uid: GoodUid,
+    pub name: String,
+    pub unit_scale: Quantity,
+    pub stock: Quantity,
+}
+
+// The lines written by the agent are read by a thread of their own, so that the wait for a
+// reply can time out
+struct Connection {
+    writer: Box<dyn Write + Send>,
+    lines: Receiver<String>,
+    child: Option<Child>,
+}
+
+impl Connection {
+    fn open(endpoint: &Endpoint) -> io::Result<Connection> {
+        let (writer, reader, child): (Box<dyn Write + Send>, Box<dyn Read + Send>, _) = match endpoint {
+            Endpoint::Command(command) => {
+                let (program, args) = command.split_first()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+                let mut child = Command::new(program).args(args)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+                let stdin = child.stdin.take().expect("Piped stdin");
+                let stdout = child.stdout.take().expect("Piped stdout");
+                (Box::new(stdin), Box::new(stdout), Some(child))
+            }
+            Endpoint::Connect(address) => {
+                let stream = TcpStream::connect(address)?;
+                stream.set_nodelay(true)?;
+                (Box::new(stream.try_clone()?), Box::new(stream), None)
+            }
+        };
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Connection { writer, lines, child })
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.writer, "{line}")?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// Why an observation was left without orders
+enum Silence {
+    Timeout,
+    Invalid,
+    Closed,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExternalAgent {
+    endpoint: Endpoint,
+    // Milliseconds the agent has to reply
+    timeout: u64,
+    fallback: FallbackPolicy,
+    goods: Vec<ExternalGood>,
+    money_balance: f64,
+    prestige: f64,
+    money_flows: MoneyFlows,
+    // Of the last observation
+    seq: u64,
+    // Trades since the last observation
+    fills: Vec<Fill>,
+    // Orders of the last reply, for the Repeat fallback
+    last_orders: Vec<ExternalOrder>,
+    // Observations left without a valid reply
+    failures: u64,
+    // The connection was lost, the fallback decides from now on
+    closed: bool,
+    #[serde(skip)]
+    connection: Option<Connection>,
+    #[serde(skip)]
+    decisions: DecisionLog,
+}
+
+impl ExternalAgent {
+    pub fn new(endpoint: Endpoint, timeout: u64, fallback: FallbackPolicy, goods: Vec<ExternalGood>,
+               money_balance: f64, prestige: f64) -> ExternalAgent {
+        ExternalAgent {
+            endpoint,
+            timeout,
+            fallback,
+            goods,
+            money_balance,
+            prestige,
+            money_flows: MoneyFlows::default(),
+            seq: 0,
+            fills: vec![],
+            last_orders: vec![],
+            failures: 0,
+            closed: false,
+            connection: None,
+            decisions: DecisionLog::default(),
+        }
+    }
+
+    // Start the agent, or connect to it, before the first tick
+    pub fn connect(&mut self) -> io::Result<()> {
+        self.connection = Some(Connection::open(&self.endpoint)?);
+        Ok(())
+    }
+
+    fn observe(&self, markets: &[Box<dyn Market>]) -> String {
+        let goods = self.goods.iter().filter_map(|good| {
+            let market = markets.iter().find(|x| x.good_uid() == good.good_uid)?;
+            Some(GoodObservation {
+                good: &good.name,
+                stock: goods::to_units(good.stock, good.unit_scale),
+                price: market.price_per_unit(),
+                mean_price: market.stats().mean_price(),
+                lot: goods::to_units(market.lot_size(), good.unit_scale),
+            })
+        }).collect();
+        let observation = Observation { seq: self.seq, money: self.money_balance, goods, fills: &self.fills };
+        serde_json::to_string(&observation).expect("Serializable observation")
+    }
+
+    fn ask(&mut self, observation: &str) -> Result<Vec<ExternalOrder>, Silence> {
+        if self.closed {
+            return Err(Silence::Closed);
+        }
+        if self.connection.is_none() && self.connect().is_err() {
+            return Err(Silence::Closed);
+        }
+        let connection = self.connection.as_mut().expect("Open connection");
+        if connection.send(observation).is_err() {
+            return Err(Silence::Closed);
+        }
+        let deadline = Instant::now() + Duration::from_millis(self.timeout);
+        loop {
+            let line = match connection.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Err(Silence::Timeout),
+                Err(RecvTimeoutError::Disconnected) => return Err(Silence::Closed),
+            };
+            match serde_json::from_str::<Reply>(&line) {
+                Ok(reply) if reply.seq == self.seq => return Ok(reply.orders),
+                // Late reply to an observation that timed out
+                Ok(reply) if reply.seq < self.seq => continue,
+                _ => return Err(Silence::Invalid),
+            }
+        }
+    }
+
+    fn post(&mut self, id: EntityId, markets: &mut [Box<dyn Market>], orders: &[ExternalOrder]) {
+        let mut budget = self.money_balance;
+        let mut offered = vec![0; self.goods.len()];
+        for order in orders {
+            let Some(i) = self.goods.iter().position(|x| x.name == order.good) else {
+                continue;
+            };
+            let good = &self.goods[i];
+            let Some(market) = markets.iter_mut().find(|x| x.good_uid() == good.good_uid) else {
+                continue;
+            };
+            let quantity = goods::to_base_units(order.quantity.max(0.), good.unit_scale);
+            // A buy order may pay up to its limit
+            let price = order.limit.unwrap_or(0.).max(market.price_per_unit());
+            let quantity = match order.side {
+                OrderType::Buy => quantity.min((budget.max(0.) / price * good.unit_scale as f64) as Quantity),
+                OrderType::Sell => quantity.min(good.stock - offered[i]),
+            };
+            // Orders of nothing or with an invalid limit are dropped
+            let posted = match order.limit {
+                Some(limit) => market.register_limit_order(Owner::Entity(id), order.side, quantity, self.prestige, limit),
+                None => market.register_order(Owner::Entity(id), order.side, quantity, self.prestige),
+            };
+            if posted.is_err() {
+                continue;
+            }
+            match order.side {
+                OrderType::Buy => budget -= goods::to_units(quantity, good.unit_scale) * price,
+                OrderType::Sell => offered[i] += quantity,
+            }
+            let action = match order.side {
+                OrderType::Buy => "buy",
+                OrderType::Sell => "sell",
+            };
+            self.decisions.record(action, Some(good.good_uid), vec![
+                ("price", market.price_per_unit()),
+                ("stock", goods::to_units(good.stock, good.unit_scale)),
+            ], quantity);
+        }
+    }
+}
+
+#[typetag::serde]
+impl EcoEntity for ExternalAgent {
+    fn produce_and_consume(&mut self) -> f64 {
+        0.
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        (self.goods.iter().map(|x| x.good_uid).collect(), vec![])
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        self.seq += 1;
+        let observation = self.observe(markets);
+        self.fills.clear();
+        let orders = match self.ask(&observation) {
+            Ok(orders) => {
+                self.last_orders = orders.clone();
+                orders
+            }
+            Err(silence) => {
+                self.failures += 1;
+                let reason = match silence {
+                    Silence::Timeout => "timeout",
+                    Silence::Invalid => "invalid_reply",
+                    Silence::Closed => {
+                        self.closed = true;
+                        self.connection = None;
+                        "closed"
+                    }
+                };
+                self.decisions.record(reason, None, vec![("failures", self.failures as f64)], 0);
+                match self.fallback {
+                    FallbackPolicy::Idle => vec![],
+                    FallbackPolicy::Repeat => self.last_orders.clone(),
+                }
+            }
+        };
+        self.post(id, markets, &orders);
+    }
+
+    fn settle_order(&mut self, good_uid: GoodUid, result: OrderResult) {
+        let good = self.goods.iter_mut().find(|x| x.good_uid == good_uid).expect("Good of the agent");
+        match result.ordertype {
+            OrderType::Buy => {
+                good.stock += result.traded_quantity;
+                self.money_balance -= result.total_cost;
+                self.money_flows.record(FlowKind::Trade, -result.total_cost);
+            }
+            OrderType::Sell => {
+                good.stock -= result.traded_quantity;
+                self.money_balance += result.total_cost;
+                self.money_flows.record(FlowKind::Trade, result.total_cost);
+            }
+        }
+        if result.traded_quantity > 0 {
+            self.fills.push(Fill {
+                good: good.name.clone(),
+                side: result.ordertype,
+                traded: goods::to_units(result.traded_quantity, good.unit_scale),
+                cost: result.total_cost,
+            });
+        }
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn take_decisions(&mut self) -> Vec<Decision> {
+        self.decisions.take()
+    }
+
+    fn transfer(&mut self, kind: FlowKind, amount: f64) -> bool {
+        self.money_balance += amount;
+        self.money_flows.record(kind, amount);
+        true
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        self.goods.iter().map(|x| (x.good_uid, x.stock)).collect()
+    }
+
+    fn state_fields(&self) -> Vec<(&'static str, f64)> {
+        vec![("failures", self.failures as f64)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestMarket;
+
+    fn agent(command: &str, fallback: FallbackPolicy) -> ExternalAgent {
+        let goods = vec![ExternalGood { good_uid: 0, name: "Grain".to_owned(), unit_scale: 1, stock: 0 }];
+        let endpoint = Endpoint::Command(vec!["sh".to_owned(), "-c".to_owned(), command.to_owned()]);
+        ExternalAgent::new(endpoint, 200, fallback, goods, 100., 0.)
+    }
+
+    fn buy_orders(agent: &mut ExternalAgent) -> Quantity {
+        let mut markets: Vec<Box<dyn Market>> = vec![Box::new(TestMarket::new(0, 1, 2.))];
+        markets[0].register_order(Owner::Entity(EntityId(1)), OrderType::Sell, 1000, 0.).unwrap();
+        agent.post_orders_to_markets(EntityId(0), &mut markets);
+        markets[0].run_trade().unwrap();
+        markets[0].take_entity_results().into_iter()
+            .filter(|(id, x)| *id == EntityId(0) && x.ordertype == OrderType::Buy)
+            .map(|(_, x)| x.traded_quantity)
+            .sum()
+    }
+
+    #[test]
+    fn replies_become_orders() {
+        // Buys 5 Grain every tick, or 80 that are more than it can afford
+        let reply = r#"{"seq":\1,"orders":[{"good":"Grain","side":"Buy","quantity":5},{"good":"Grain","side":"Buy","quantity":80}]}"#;
+        let mut agent = agent(&format!("sed -u 's/^{{\"seq\":\\([0-9]*\\).*/{reply}/'"), FallbackPolicy::Idle);
+        assert_eq!(buy_orders(&mut agent), 50);
+        assert_eq!(agent.failures, 0);
+    }
+
+    #[test]
+    fn silent_agents_fall_back() {
+        let mut agent = agent("cat > /dev/null", FallbackPolicy::Repeat);
+        agent.last_orders = vec![ExternalOrder { good: "Grain".to_owned(), side: OrderType::Buy, quantity: 3., limit: None }];
+        assert_eq!(buy_orders(&mut agent), 3);
+        assert_eq!(agent.failures, 1);
+        assert!(!agent.closed);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::{EcoEntity, EntityId, GoodUid, Market, MarketMetadata, OrderResult, OrderType, Owner, Price, Quantity};
+
+// Partial-world simulation: some entities are frozen so that the others can be studied against
+// a fixed environment. The orders of the entities to freeze are recorded for some ticks, then
+// every one of them is replaced by a FrozenEntity that keeps its money and inventory and posts
+// the recorded orders again, tick after tick.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedOrder {
+    pub good_uid: GoodUid,
+    pub otype: OrderType,
+    pub quantity: Quantity,
+    pub prestige: f64,
+    // None for the markets with a single price
+    pub limit_price: Option<Price>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezePlan {
+    pub entities: Vec<String>,
+    // First frozen tick
+    pub at: u64,
+    // Ticks before `at` whose orders are replayed
+    pub record: u64,
+    // Orders of every tick of the recording, by entity
+    tapes: BTreeMap<String, Vec<Vec<RecordedOrder>>>,
+}
+
+impl FreezePlan {
+    pub fn new(entities: Vec<String>, at: u64, record: u64) -> FreezePlan {
+        FreezePlan { entities, at, record, tapes: BTreeMap::new() }
+    }
+
+    pub fn is_recording(&self, tick: u64, entity: &str) -> bool {
+        tick < self.at && tick + self.record >= self.at && self.entities.iter().any(|x| x == entity)
+    }
+
+    // Add the orders the entity registered at `tick`
+    pub fn record(&mut self, tick: u64, entity: &str, orders: Vec<RecordedOrder>) {
+        let tape = self.tapes.entry(entity.to_owned()).or_default();
+        let index = (tick + self.record - self.at) as usize;
+        tape.resize(tape.len().max(index + 1), vec![]);
+        tape[index].extend(orders);
+    }
+
+    // The frozen copy of the entity, None if it isn't frozen
+    pub fn freeze(&mut self, entity: &str, original: &dyn EcoEntity) -> Option<FrozenEntity> {
+        if !self.entities.iter().any(|x| x == entity) {
+            return None;
+        }
+        let mut tape = self.tapes.remove(entity).unwrap_or_default();
+        tape.resize(self.record as usize, vec![]);
+        Some(FrozenEntity {
+            money_balance: original.money_balance(),
+            inventory: original.inventory(),
+            market_tags: original.get_required_markets().1,
+            tape,
+            next: 0,
+            money_flows: MoneyFlows::default(),
+        })
+    }
+}
+
+// An entity whose state doesn't change. The money it pays and receives in the trades is created
+// and destroyed, the goods it buys and sells are not counted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FrozenEntity {
+    money_balance: f64,
+    inventory: Vec<(GoodUid, Quantity)>,
+    // Of the original, the recorded orders go to the same markets
+    #[serde(default)]
+    market_tags: Vec<MarketMetadata>,
+    // Orders of every tick, replayed in a loop
+    tape: Vec<Vec<RecordedOrder>>,
+    next: usize,
+    money_flows: MoneyFlows,
+}
+
+#[typetag::serde]
+impl EcoEntity for FrozenEntity {
+    fn produce_and_consume(&mut self) -> f64 {
+        0.
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        let mut goods: Vec<GoodUid> = self.inventory.iter().map(|x| x.0)
+            .chain(self.tape.iter().flatten().map(|x| x.good_uid)).collect();
+        goods.sort();
+        goods.dedup();
+        (goods, self.market_tags.clone())
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        if self.tape.is_empty() {
+            return;
+        }
+        for order in self.tape[self.next].iter() {
+            let Some(market) = markets.iter_mut().find(|x| x.good_uid() == order.good_uid) else {
+                continue;
+            };
+            // The recorded orders were accepted once, the markets refuse them again only if edited
+            let _ = match order.limit_price {
+                Some(limit_price) => market.register_limit_order(Owner::Entity(id), order.otype, order.quantity, order.prestige, limit_price),
+                None => market.register_order(Owner::Entity(id), order.otype, order.quantity, order.prestige),
+            };
+        }
+        self.next = (self.next + 1) % self.tape.len();
+    }
+
+    fn settle_order(&mut self, _good_uid: GoodUid, result: OrderResult) {
+        let amount = match result.ordertype {
+            OrderType::Buy => -result.total_cost,
+            OrderType::Sell => result.total_cost,
+        };
+        self.money_flows.record(FlowKind::Trade, amount);
+        self.money_flows.record(FlowKind::Frozen, -amount);
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        self.inventory.clone()
+    }
+}
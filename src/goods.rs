@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use crate::{GoodUid, Quantity};
+
+// Quantities are always stored as integer base units. An indivisible good has a single base unit
+// per unit, a divisible good with N decimals has 10^N base units per unit (e.g. Grain tonnage
+// with 3 decimals is tracked in kg). In this way the market distribution algorithms work on
+// integers for every kind of good. A good can also be traded only in lots of some units, the
+// markets round the orders down to a whole number of lots.
+
+pub fn to_units(quantity: Quantity, unit_scale: Quantity) -> f64 {
+    quantity as f64 / unit_scale as f64
+}
+
+pub fn to_base_units(units: f64, unit_scale: Quantity) -> Quantity {
+    (units * unit_scale as f64).round() as Quantity
+}
+
+// A good can come in quality grades, every grade a good of its own named `{name} Q{quality}`
+// and traded in a market of its own. The grade a producer makes is set by its inputs: the
+// lowest grade among them, one more with capital to make it.
+pub fn grade_name(name: &str, quality: u32) -> String {
+    format!("{name} Q{quality}")
+}
+
+// The lot of the markets saved before lots existed
+pub(crate) fn single_lot() -> Quantity {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Good {
+    pub name: String,
+    // Number of decimal digits of a unit that can be traded. 0 means indivisible.
+    pub decimals: u32,
+    // Fraction of the stock that spoils every tick, see Storage
+    #[serde(default)]
+    pub decay: f64,
+    // Units of a lot, 0 to trade any quantity
+    #[serde(default)]
+    pub lot: f64,
+    // Name of the good it is a grade of and grade, 1 the lowest, None without grades
+    #[serde(default)]
+    pub grade: Option<(String, u32)>,
+}
+
+impl Good {
+    pub fn unit_scale(&self) -> Quantity {
+        10_u64.pow(self.decimals)
+    }
+
+    // Base units of a lot, at least one
+    pub fn lot_size(&self) -> Quantity {
+        to_base_units(self.lot, self.unit_scale()).max(1)
+    }
+}
+
+// The registry is the only place where a GoodUid gets its meaning: the uid is the position
+// of the good inside the registry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GoodRegistry {
+    goods: Vec<Good>,
+}
+
+impl GoodRegistry {
+    pub fn register(&mut self, name: &str, decimals: u32, decay: f64, lot: f64) -> GoodUid {
+        if let Some(uid) = self.uid_of(name) {
+            return uid;
+        }
+        self.goods.push(Good { name: name.to_owned(), decimals, decay, lot, grade: None });
+        self.goods.len() - 1
+    }
+
+    // A good for every grade of `name`, from the lowest
+    pub fn register_grades(&mut self, name: &str, grades: u32, decimals: u32, decay: f64, lot: f64) -> Vec<GoodUid> {
+        (1..=grades).map(|quality| {
+            let uid = self.register(&grade_name(name, quality), decimals, decay, lot);
+            self.goods[uid].grade = Some((name.to_owned(), quality));
+            uid
+        }).collect()
+    }
+
+    // The grades of `name` from the lowest, empty if it has none
+    pub fn grades(&self, name: &str) -> Vec<GoodUid> {
+        let mut grades: Vec<(u32, GoodUid)> = self.goods.iter().enumerate()
+            .filter_map(|(uid, x)| x.grade.as_ref().filter(|g| g.0 == name).map(|g| (g.1, uid)))
+            .collect();
+        grades.sort();
+        grades.into_iter().map(|x| x.1).collect()
+    }
+
+    // 0 for a good without grades
+    pub fn quality(&self, gooduid: GoodUid) -> u32 {
+        self.goods[gooduid].grade.as_ref().map_or(0, |x| x.1)
+    }
+
+    // The grade of `name` made from the inputs, None if it has no grades. The inputs without
+    // grades count as the lowest grade.
+    pub fn grade_made(&self, name: &str, inputs: &[GoodUid], capital: bool) -> Option<GoodUid> {
+        let grades = self.grades(name);
+        let quality = inputs.iter().map(|x| self.quality(*x).max(1)).min().unwrap_or(1) + capital as u32;
+        grades.get((quality as usize).min(grades.len()).checked_sub(1)?).copied()
+    }
+
+    pub fn uid_of(&self, name: &str) -> Option<GoodUid> {
+        self.goods.iter().position(|x| x.name == name)
+    }
+
+    // The goods by uid
+    pub fn goods(&self) -> &[Good] {
+        &self.goods
+    }
+
+    pub fn get_good_name(&self, gooduid: GoodUid) -> String {
+        self.goods[gooduid].name.clone()
+    }
+
+    pub fn unit_scale(&self, gooduid: GoodUid) -> Quantity {
+        self.goods[gooduid].unit_scale()
+    }
+
+    pub fn lot_size(&self, gooduid: GoodUid) -> Quantity {
+        self.goods[gooduid].lot_size()
+    }
+
+    pub fn decay(&self, gooduid: GoodUid) -> f64 {
+        self.goods[gooduid].decay
+    }
+
+    pub fn to_units(&self, gooduid: GoodUid, quantity: Quantity) -> f64 {
+        to_units(quantity, self.unit_scale(gooduid))
+    }
+
+    pub fn to_base_units(&self, gooduid: GoodUid, units: f64) -> Quantity {
+        to_base_units(units, self.unit_scale(gooduid))
+    }
+}
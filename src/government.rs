@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::currency::{CurrencyId, Money};
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+
+// The government of the world. For now it only collects the taxes, the money it collects is
+// kept and not spent, in the currency it was paid in.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Government {
+    #[serde(default)]
+    treasury: BTreeMap<CurrencyId, f64>,
+    money_flows: MoneyFlows,
+}
+
+impl Government {
+    // The money of every currency it holds
+    pub fn balances(&self) -> Vec<Money> {
+        self.treasury.iter().map(|(currency, amount)| Money::new(*amount, *currency)).collect()
+    }
+
+    pub fn collect(&mut self, tax: Money) {
+        *self.treasury.entry(tax.currency).or_default() += tax.amount;
+        self.money_flows.record(FlowKind::Tax, tax.amount);
+    }
+
+    pub fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+}
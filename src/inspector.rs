@@ -0,0 +1,119 @@
+use std::fmt;
+use crate::engine::Simulation;
+
+// Debug tool: the numeric state of the whole world at a tick, and the differences between two
+// of those states, to find out where an anomaly starts.
+
+#[derive(Debug, Clone)]
+pub struct ObjectSnapshot {
+    pub name: String,
+    pub fields: Vec<(String, f64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorldSnapshot {
+    pub tick: u64,
+    pub objects: Vec<ObjectSnapshot>,
+}
+
+impl WorldSnapshot {
+    pub fn capture(sim: &Simulation) -> WorldSnapshot {
+        let mut objects = vec![];
+        for (_, name, entity) in sim.entities.iter() {
+            let mut fields = vec![("money".to_owned(), entity.money_balance())];
+            for (good_uid, quantity) in entity.inventory() {
+                let good = sim.goods.get_good_name(good_uid);
+                fields.push((format!("inventory/{good}"), sim.goods.to_units(good_uid, quantity)));
+            }
+            for (field, value) in entity.state_fields() {
+                fields.push((field.to_owned(), value));
+            }
+            objects.push(ObjectSnapshot { name: name.to_owned(), fields });
+        }
+        for (region, market) in sim.markets() {
+            objects.push(ObjectSnapshot {
+                name: format!("market/{}", sim.market_label(region, market.good_uid())),
+                fields: vec![("price".to_owned(), market.price_per_unit())],
+            });
+        }
+        for route in sim.routes.iter() {
+            objects.push(ObjectSnapshot {
+                name: format!("route/{}", route.name),
+                fields: vec![
+                    ("money".to_owned(), route.money_balance()),
+                    ("in_transit".to_owned(), sim.goods.to_units(route.good_uid, route.in_transit())),
+                ],
+            });
+        }
+        WorldSnapshot { tick: sim.tick, objects }
+    }
+
+    pub fn diff(&self, later: &WorldSnapshot) -> SnapshotDiff {
+        let mut changes = vec![];
+        for after in later.objects.iter() {
+            let before = self.objects.iter().find(|x| x.name == after.name);
+            for (field, value) in after.fields.iter() {
+                let old = before.and_then(|x| x.fields.iter().find(|f| &f.0 == field)).map(|f| f.1);
+                if old != Some(*value) {
+                    changes.push(FieldChange {
+                        object: after.name.clone(),
+                        field: field.clone(),
+                        before: old,
+                        after: Some(*value),
+                    });
+                }
+            }
+        }
+        // What disappeared
+        for before in self.objects.iter() {
+            let after = later.objects.iter().find(|x| x.name == before.name);
+            for (field, value) in before.fields.iter() {
+                if after.and_then(|x| x.fields.iter().find(|f| &f.0 == field)).is_none() {
+                    changes.push(FieldChange {
+                        object: before.name.clone(),
+                        field: field.clone(),
+                        before: Some(*value),
+                        after: None,
+                    });
+                }
+            }
+        }
+        SnapshotDiff { from_tick: self.tick, to_tick: later.tick, changes }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldChange {
+    pub object: String,
+    pub field: String,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotDiff {
+    pub from_tick: u64,
+    pub to_tick: u64,
+    pub changes: Vec<FieldChange>,
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Diff tick {} -> tick {}: {} changes", self.from_tick, self.to_tick, self.changes.len())?;
+        let mut last_object = None;
+        for change in self.changes.iter() {
+            if last_object != Some(&change.object) {
+                writeln!(f, "  {}", change.object)?;
+                last_object = Some(&change.object);
+            }
+            match (change.before, change.after) {
+                (Some(before), Some(after)) => writeln!(
+                    f, "    {}: {before} -> {after} ({:+})", change.field, after - before)?,
+                (None, Some(after)) => writeln!(f, "    {}: added {after}", change.field)?,
+                (Some(before), None) => writeln!(f, "    {}: removed {before}", change.field)?,
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use crate::freeze::RecordedOrder;
+use crate::rng::OrderIds;
+use crate::stats::MarketStats;
+use crate::tiers::TierPolicy;
+use crate::trace::DecisionLog;
+use crate::{goods, EntityId, GoodUid, Market, MarketError, OrderBatches, OrderError, OrderIndex, OrderResult, OrderType, Owner, Price, Quantity, TestMarket, TradeReport, UnretrievedOrders};
+
+// Labor is a service good: pops sell it every tick, producers buy it and use it in the
+// production of the next tick. It can't be stored, what is not used is lost.
+
+// Market for a labor good. The matching is the same of TestMarket at the current wage, then the
+// wage moves toward the balance between labor demand and labor supply.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LaborMarket {
+    inner: TestMarket,
+    // Relative wage change per tick when demand and supply don't match
+    wage_adjustment: f64,
+    // Wage for the next tick, applied when the state is cleared
+    pending_wage: Option<Price>,
+}
+
+impl LaborMarket {
+    pub fn new(good_uid: GoodUid, unit_scale: Quantity, wage: Price, wage_adjustment: f64) -> LaborMarket {
+        LaborMarket {
+            inner: TestMarket {
+                good_uid,
+                price_per_unit: wage,
+                unit_scale,
+                lot_size: 1,
+                buy_orders: vec![],
+                sell_orders: vec![],
+                rng: None,
+                friction: 0.,
+                order_ids: OrderIds::new(good_uid),
+                tier_policy: TierPolicy::Prestige,
+                untraded_tiers: 0,
+                stats: MarketStats::default(),
+                batches: OrderBatches::default(),
+                index: OrderIndex::default(),
+            },
+            wage_adjustment,
+            pending_wage: None,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Market for LaborMarket {
+    fn good_uid(&self) -> GoodUid {
+        self.inner.good_uid()
+    }
+
+    fn price_per_unit(&self) -> Price {
+        self.inner.price_per_unit()
+    }
+
+    fn unit_scale(&self) -> Quantity {
+        self.inner.unit_scale()
+    }
+
+    fn register_order(&mut self, owner: Owner, otype: OrderType, quantity: Quantity, prestige: f64) -> Result<Uuid, OrderError> {
+        self.inner.register_order(owner, otype, quantity, prestige)
+    }
+
+    fn order_batches(&mut self) -> &mut OrderBatches {
+        self.inner.order_batches()
+    }
+
+    fn run_trade(&mut self) -> Result<Quantity, MarketError> {
+        let demand: Quantity = self.inner.buy_orders.iter().map(|x| x.required_quantity).sum();
+        let supply: Quantity = self.inner.sell_orders.iter().map(|x| x.required_quantity).sum();
+        let traded = self.inner.run_trade()?;
+        // The new wage is used from the next tick, this tick trades at the old one
+        self.pending_wage = Some(match demand.cmp(&supply) {
+            std::cmp::Ordering::Greater => self.inner.price_per_unit * (1. + self.wage_adjustment),
+            std::cmp::Ordering::Less => self.inner.price_per_unit * (1. - self.wage_adjustment),
+            std::cmp::Ordering::Equal => self.inner.price_per_unit,
+        });
+        Ok(traded)
+    }
+
+    fn peek_order_result(&self, uuid: &Uuid) -> Option<OrderResult> {
+        self.inner.peek_order_result(uuid)
+    }
+
+    fn retrieve_order_result(&mut self, uuid: &Uuid) -> Option<OrderResult> {
+        self.inner.retrieve_order_result(uuid)
+    }
+
+    fn take_entity_results(&mut self) -> Vec<(EntityId, OrderResult)> {
+        self.inner.take_entity_results()
+    }
+
+    fn cancel_order(&mut self, uuid: &Uuid) -> bool {
+        self.inner.cancel_order(uuid)
+    }
+
+    fn halt(&mut self) {
+        // The wage doesn't move on a halted trade
+        self.inner.halt();
+        self.pending_wage = None;
+    }
+
+    fn trade_report(&self) -> TradeReport {
+        self.inner.trade_report()
+    }
+
+    fn registered_orders(&self) -> Vec<(Owner, RecordedOrder)> {
+        self.inner.registered_orders()
+    }
+
+    fn stats(&self) -> &MarketStats {
+        self.inner.stats()
+    }
+
+    fn clear_state(&mut self) -> Result<(), UnretrievedOrders> {
+        // The stats keep the wage of the trade, the new one is applied after
+        let retrieved = self.inner.clear_state();
+        if let Some(wage) = self.pending_wage.take() {
+            self.inner.price_per_unit = wage;
+        }
+        retrieved
+    }
+}
+
+// Labor requirement of a producer. Without a labor good the producer doesn't need workers.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Workforce {
+    labor_good_uid: Option<GoodUid>,
+    // Labor base units needed for every unit of production
+    labor_per_unit: f64,
+    // Labor hired in the last trade, usable in the next production
+    available: Quantity,
+}
+
+impl Workforce {
+    pub fn new(labor_good_uid: GoodUid, labor_per_unit: f64) -> Workforce {
+        Workforce { labor_good_uid: Some(labor_good_uid), labor_per_unit, ..Default::default() }
+    }
+
+    // Max production in base units allowed by the available labor
+    pub fn max_production(&self, unit_scale: Quantity) -> Quantity {
+        if self.labor_good_uid.is_none() || self.labor_per_unit <= 0. {
+            return Quantity::MAX;
+        }
+        (self.available as f64 / self.labor_per_unit * unit_scale as f64) as Quantity
+    }
+
+    pub fn available(&self) -> Quantity {
+        self.available
+    }
+
+    pub fn good_uid(&self) -> Option<GoodUid> {
+        self.labor_good_uid
+    }
+
+    // Wages of the labor of a unit of production at the current wage
+    pub fn wages_per_unit(&self, markets: &[Box<dyn Market>]) -> f64 {
+        let Some(market) = self.labor_good_uid.and_then(|x| markets.iter().find(|m| m.good_uid() == x)) else {
+            return 0.;
+        };
+        self.labor_per_unit / market.unit_scale() as f64 * market.price_per_unit()
+    }
+
+    // The labor not used by the production is lost
+    pub fn end_production(&mut self) {
+        self.available = 0;
+    }
+
+    // Hire the labor for the production of `production` base units, spending at most `budget`.
+    // Returns the expected expense.
+    #[allow(clippy::too_many_arguments)]
+    pub fn hire(&mut self, id: EntityId, markets: &mut [Box<dyn Market>], production: Quantity, unit_scale: Quantity,
+                budget: f64, prestige: f64, log: &mut DecisionLog) -> f64 {
+        let Some(market) = self.labor_good_uid.and_then(|x| markets.iter_mut().find(|m| m.good_uid() == x)) else {
+            return 0.;
+        };
+        let labor_good_uid = market.good_uid();
+        let required = (goods::to_units(production, unit_scale) * self.labor_per_unit).ceil() as Quantity;
+        let required = required.min(market.affordable_order(budget).unwrap_or(0));
+        log.record("hire", Some(labor_good_uid), vec![
+            ("production", goods::to_units(production, unit_scale)),
+            ("labor_per_unit", self.labor_per_unit),
+            ("wage", market.price_per_unit()),
+            ("budget", budget),
+        ], required);
+        let limit_price = market.price_per_unit();
+        if market.register_limit_order(Owner::Entity(id), OrderType::Buy, required, prestige, limit_price).is_err() {
+            return 0.;
+        }
+        market.cost_of(required)
+    }
+
+    // Collect the hired labor, returns the wages paid or None if the order wasn't for labor
+    pub fn settle(&mut self, good_uid: GoodUid, result: &OrderResult) -> Option<f64> {
+        if self.labor_good_uid != Some(good_uid) || result.ordertype != OrderType::Buy {
+            return None;
+        }
+        self.available += result.traded_quantity;
+        Some(result.total_cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trades `demand` against `supply` and returns the wage of the next tick
+    fn next_wage(demand: Quantity, supply: Quantity) -> Price {
+        let mut market = LaborMarket::new(0, 1, 1., 0.1);
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, demand, 0.).unwrap();
+        market.register_order(Owner::Entity(EntityId(1)), OrderType::Sell, supply, 0.).unwrap();
+        market.run_trade().unwrap();
+        // The trade is at the old wage
+        assert_eq!(market.price_per_unit(), 1.);
+        market.take_entity_results();
+        market.clear_state().unwrap();
+        market.price_per_unit()
+    }
+
+    #[test]
+    fn the_wage_follows_the_labor_demand() {
+        assert_eq!(next_wage(20, 10), 1.1);
+        assert_eq!(next_wage(10, 20), 0.9);
+        assert_eq!(next_wage(10, 10), 1.);
+    }
+
+    #[test]
+    fn the_hired_labor_caps_the_production() {
+        let mut markets: Vec<Box<dyn Market>> = vec![Box::new(LaborMarket::new(0, 1, 1., 0.1))];
+        let mut workforce = Workforce::new(0, 2.);
+        // 50 units of production need 100 of labor, only 60 are offered
+        let expense = workforce.hire(EntityId(0), &mut markets, 50, 1, 1000., 0., &mut DecisionLog::default());
+        assert_eq!(expense, 100.);
+        markets[0].register_order(Owner::Entity(EntityId(1)), OrderType::Sell, 60, 0.).unwrap();
+        markets[0].run_trade().unwrap();
+        for (_, result) in markets[0].take_entity_results().into_iter().filter(|x| x.0 == EntityId(0)) {
+            assert_eq!(workforce.settle(0, &result), Some(60.));
+        }
+        assert_eq!(workforce.max_production(1), 30);
+        workforce.end_production();
+        assert_eq!(workforce.max_production(1), 0);
+        // The budget limits the labor hired
+        let expense = workforce.hire(EntityId(0), &mut markets, 50, 1, 40., 0., &mut DecisionLog::default());
+        assert_eq!(expense, 40.);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+// Every change of an entity money balance is recorded as a flow. Transfers move money between
+// entities and must cancel out over the whole system, sinks and sources destroy or create money.
+// What changes the total money without a flow is a leak in the simulation.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FlowKind {
+    // Transfers
+    Trade,
+    Wages,
+    Tax,
+    Deposit,
+    Loan,
+    // Paid for a default on a forward contract
+    Penalty,
+    // Money given or received for another currency, see FxMarket
+    Exchange,
+    // Paid by a firm to the holders of its shares, see EquityMarket
+    Dividend,
+    // Paid for the shares of a firm
+    Shares,
+    // Sinks
+    FixedCost,
+    VariableCost,
+    Transport,
+    // Room rented above the storage capacity
+    Storage,
+    // Spent looking for new deposits, see Deposit
+    Prospecting,
+    // Sink or source, money scaled or injected by the monetary events
+    Monetary,
+    // Sink or source, paid to or by a frozen entity
+    Frozen,
+}
+
+impl FlowKind {
+    pub fn is_transfer(&self) -> bool {
+        matches!(self, FlowKind::Trade | FlowKind::Wages | FlowKind::Tax | FlowKind::Deposit | FlowKind::Loan
+            | FlowKind::Penalty | FlowKind::Exchange | FlowKind::Dividend | FlowKind::Shares)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MoneyFlow {
+    pub kind: FlowKind,
+    // Positive when the entity receives money
+    pub amount: f64,
+}
+
+// Flows of a single entity, collected by the engine at the end of the tick
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MoneyFlows {
+    flows: Vec<MoneyFlow>,
+}
+
+impl MoneyFlows {
+    pub fn record(&mut self, kind: FlowKind, amount: f64) {
+        if amount != 0. {
+            self.flows.push(MoneyFlow { kind, amount });
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<MoneyFlow> {
+        std::mem::take(&mut self.flows)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickAudit {
+    pub tick: u64,
+    pub money_before: f64,
+    pub money_after: f64,
+    // Net of all the transfers, should be zero
+    pub transfer_imbalance: f64,
+    pub sinks: f64,
+    pub sources: f64,
+    // Change of the total money not explained by any flow
+    pub unattributed: f64,
+    // Net of every kind of flow
+    pub by_kind: BTreeMap<FlowKind, f64>,
+}
+
+impl TickAudit {
+    pub fn is_flagged(&self) -> bool {
+        let tolerance = 1e-9 * self.money_before.abs().max(1.);
+        self.transfer_imbalance.abs() > tolerance || self.unattributed.abs() > tolerance
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    pub history: Vec<TickAudit>,
+}
+
+impl Ledger {
+    pub fn close_tick(&mut self, tick: u64, money_before: f64, money_after: f64, flows: &[MoneyFlow]) -> &TickAudit {
+        let mut transfer_imbalance = 0.;
+        let mut sinks = 0.;
+        let mut sources = 0.;
+        let mut by_kind = BTreeMap::new();
+        for flow in flows.iter() {
+            *by_kind.entry(flow.kind).or_default() += flow.amount;
+            if flow.kind.is_transfer() {
+                transfer_imbalance += flow.amount;
+            } else if flow.amount < 0. {
+                sinks -= flow.amount;
+            } else {
+                sources += flow.amount;
+            }
+        }
+        let explained = transfer_imbalance + sources - sinks;
+        self.history.push(TickAudit {
+            tick,
+            money_before,
+            money_after,
+            transfer_imbalance,
+            sinks,
+            sources,
+            unattributed: money_after - money_before - explained,
+            by_kind,
+        });
+        self.history.last().unwrap()
+    }
+
+    // Sum of every kind of flow from the tick `from` on
+    pub fn totals(&self, from: u64) -> BTreeMap<FlowKind, f64> {
+        let mut totals = BTreeMap::new();
+        for audit in self.history.iter().filter(|x| x.tick >= from) {
+            for (kind, amount) in audit.by_kind.iter() {
+                *totals.entry(*kind).or_default() += amount;
+            }
+        }
+        totals
+    }
+
+    // The ticks of the burn-in are left out
+    pub fn print_report(&self, burn_in: u64) {
+        let audits: Vec<&TickAudit> = self.history.iter().filter(|x| x.tick >= burn_in).collect();
+        let (Some(first), Some(last)) = (audits.first(), audits.last()) else {
+            return;
+        };
+        println!("Money audit: {:.2} at tick {}, {:.2} at tick {}",
+                 first.money_before, first.tick, last.money_after, last.tick);
+        for (kind, amount) in self.totals(burn_in).iter() {
+            println!("  {kind:?}: {amount:.2}");
+        }
+        for audit in audits {
+            print!("  tick {}: money {:.2}, sinks {:.2}, sources {:.2}",
+                   audit.tick, audit.money_after, audit.sinks, audit.sources);
+            if audit.is_flagged() {
+                print!(" - LEAK: unattributed {:.6}, transfer imbalance {:.6}",
+                       audit.unattributed, audit.transfer_imbalance);
+            }
+            println!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(kind: FlowKind, amount: f64) -> MoneyFlow {
+        MoneyFlow { kind, amount }
+    }
+
+    #[test]
+    fn recorded_flows_explain_the_money_change() {
+        let mut ledger = Ledger::default();
+        // A trade of 50, wages of 20 and a fixed cost of 10 destroyed
+        let flows = [flow(FlowKind::Trade, -50.), flow(FlowKind::Trade, 50.), flow(FlowKind::Wages, -20.),
+                     flow(FlowKind::Wages, 20.), flow(FlowKind::FixedCost, -10.)];
+        let audit = ledger.close_tick(0, 1000., 990., &flows);
+        assert!(!audit.is_flagged());
+        assert_eq!((audit.sinks, audit.sources, audit.unattributed), (10., 0., 0.));
+        assert_eq!(ledger.totals(0)[&FlowKind::FixedCost], -10.);
+    }
+
+    #[test]
+    fn an_unrecorded_change_is_a_leak() {
+        let mut ledger = Ledger::default();
+        // 25 appeared without a flow, and a trade was paid without being collected
+        let audit = ledger.close_tick(3, 1000., 1015., &[flow(FlowKind::Trade, -10.)]);
+        assert!(audit.is_flagged());
+        assert_eq!(audit.unattributed, 25.);
+        assert_eq!(audit.transfer_imbalance, -10.);
+    }
+}
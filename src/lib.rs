@@ -0,0 +1,66 @@
+// The simulation as a library. A Simulation is built from a Scenario, or assembled with markets
+// and entities of your own implementing Market and EcoEntity, and then stepped a tick at a time.
+// The `ecosim` binary is a command line frontend of it.
+
+pub mod accounting;
+pub mod archetype;
+pub mod bank;
+mod basket;
+pub mod bench;
+pub mod branch;
+pub mod building;
+pub mod central_bank;
+pub mod checkpoint;
+pub mod compare;
+pub mod contract;
+pub mod convergence;
+pub mod currency;
+mod dashboard;
+pub mod demography;
+pub mod deposit;
+pub mod describe;
+pub mod differential;
+pub mod engine;
+pub mod entity;
+pub mod equity;
+pub mod events;
+pub mod expectation;
+pub mod experiment;
+pub mod external;
+pub mod freeze;
+pub mod goods;
+pub mod government;
+pub mod inspector;
+pub mod labor;
+pub mod ledger;
+pub mod market;
+pub mod monetary;
+pub mod national;
+pub mod needs;
+pub mod orderbook;
+pub mod pipeline;
+pub mod plot;
+pub mod pollution;
+pub mod pricing;
+pub mod profit;
+pub mod recipe;
+pub mod recorder;
+pub mod region;
+pub mod registry;
+pub mod rng;
+pub mod rundir;
+pub mod scenario;
+pub mod script;
+pub mod shock;
+pub mod stats;
+pub mod storage;
+pub mod sweep;
+pub mod tiers;
+pub mod trace;
+pub mod waste;
+
+pub use crate::engine::Simulation;
+pub use crate::entity::{BasicPop, EcoEntity, EntityId, ProductorOneToOne, RGOSingle};
+pub use crate::market::{BatchResult, BatchTotals, GoodUid, Market, MarketError, MarketMetadata, OrderBatchId, OrderBatches, OrderError, OrderResult, OrderType, Owner, Price, Quantity, TestMarket, TradeReport, UnretrievedOrders};
+pub use crate::scenario::Scenario;
+pub(crate) use crate::market::{OrderIndex, OrderInfo};
@@ -0,0 +1,1102 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::fmt::{self, Debug};
+use uuid::Uuid;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use crate::entity::EntityId;
+use crate::freeze::RecordedOrder;
+use crate::goods;
+use crate::rng::{OrderIds, SimRng};
+use crate::stats::MarketStats;
+use crate::tiers::{Exhausted, TierPolicy, TierWalk};
+
+pub type GoodUid = usize;
+pub type Price = f64;
+// Amount of a good expressed in base units, see GoodRegistry for the conversion to units
+pub type Quantity = u64;
+
+pub type MarketMetadata = String;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OrderType {
+    Buy,
+    Sell,
+}
+
+// Who registered an order. The results of the orders of the entities are delivered to them by
+// the simulation after the trade, the routes and the clearing houses retrieve theirs by uuid.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Owner {
+    Entity(EntityId),
+    Route,
+    ClearingHouse,
+}
+
+impl Owner {
+    pub fn entity(&self) -> Option<EntityId> {
+        match self {
+            Owner::Entity(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct OrderInfo {
+    pub(crate) uuid: Uuid,
+    pub(crate) owner: Owner,
+    pub(crate) required_quantity: Quantity,
+    pub(crate) traded_quantity: Quantity,
+    pub(crate) prestige: f64,
+    // Part of the registered quantity below a lot, left out of the order
+    #[serde(default)]
+    pub(crate) remainder: Quantity,
+    // The result was handed to the owner, see Market::retrieve_order_result
+    #[serde(default)]
+    pub(crate) retrieved: bool,
+}
+
+impl OrderInfo {
+    pub(crate) fn new(uuid: Uuid, owner: Owner, required_quantity: Quantity, prestige: f64) -> OrderInfo {
+        OrderInfo { uuid, owner, required_quantity, prestige, traded_quantity: 0, remainder: 0, retrieved: false }
+    }
+
+    // The order of a whole number of lots, the rest of the quantity is its remainder
+    pub(crate) fn in_lots(uuid: Uuid, owner: Owner, quantity: Quantity, prestige: f64, lot_size: Quantity) -> OrderInfo {
+        let remainder = quantity % lot_size.max(1);
+        OrderInfo { remainder, ..OrderInfo::new(uuid, owner, quantity - remainder, prestige) }
+    }
+
+    // The result of the order for the owner when the traded quantity costs `total_cost`
+    pub(crate) fn result(&self, otype: OrderType, total_cost: Price) -> OrderResult {
+        OrderResult { remainder: self.remainder, ..OrderResult::new(otype, self.traded_quantity, total_cost) }
+    }
+
+    pub(crate) fn missing_quantity(&self) -> Quantity {
+        self.required_quantity - self.traded_quantity
+    }
+}
+
+// The orders whose result was never retrieved, an error when the state is cleared
+pub(crate) fn unretrieved<'a>(orders: impl Iterator<Item=&'a OrderInfo>) -> Result<(), UnretrievedOrders> {
+    let orders: Vec<(Owner, Uuid)> = orders.filter(|x| !x.retrieved).map(|x| (x.owner, x.uuid)).collect();
+    match orders.is_empty() {
+        true => Ok(()),
+        false => Err(UnretrievedOrders(orders)),
+    }
+}
+
+// Position of every order of a market in its buy or sell orders, so the results can be found
+// without scanning the orders. Rebuilt every time the market reorders them, with the
+// counterparties paired again after every trade.
+#[derive(Debug, Default)]
+pub(crate) struct OrderIndex {
+    pub(crate) positions: HashMap<Uuid, (OrderType, usize)>,
+    counterparties: HashMap<Uuid, Vec<(Owner, Quantity)>>,
+}
+
+impl OrderIndex {
+    pub(crate) fn insert(&mut self, uuid: Uuid, otype: OrderType, position: usize) {
+        self.positions.insert(uuid, (otype, position));
+    }
+
+    pub(crate) fn get(&self, uuid: &Uuid) -> Option<(OrderType, usize)> {
+        self.positions.get(uuid).copied()
+    }
+
+    pub(crate) fn rebuild<'a>(&mut self, buy_orders: impl Iterator<Item=&'a OrderInfo>, sell_orders: impl Iterator<Item=&'a OrderInfo>) {
+        self.positions.clear();
+        self.counterparties.clear();
+        for (i, x) in buy_orders.enumerate() {
+            self.insert(x.uuid, OrderType::Buy, i);
+        }
+        for (i, x) in sell_orders.enumerate() {
+            self.insert(x.uuid, OrderType::Sell, i);
+        }
+    }
+
+    // After the orders traded
+    pub(crate) fn pair<'a>(&mut self, buy_orders: impl Iterator<Item=&'a OrderInfo>, sell_orders: impl Iterator<Item=&'a OrderInfo>) {
+        self.counterparties = counterparties(buy_orders, sell_orders);
+    }
+
+    pub(crate) fn counterparties(&self, uuid: &Uuid) -> Vec<(Owner, Quantity)> {
+        self.counterparties.get(uuid).cloned().unwrap_or_default()
+    }
+
+    // For a result retrieved once and for all
+    pub(crate) fn take_counterparties(&mut self, uuid: &Uuid) -> Vec<(Owner, Quantity)> {
+        self.counterparties.remove(uuid).unwrap_or_default()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.positions.clear();
+        self.counterparties.clear();
+    }
+}
+
+// Orders registered together, see Market::register_orders. The id is the position of the
+// batch, valid until the state is cleared.
+pub type OrderBatchId = usize;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OrderBatches {
+    // The uuids of the orders accepted and the number of the refused ones, None once the batch
+    // was retrieved
+    batches: Vec<Option<(Vec<Uuid>, usize)>>,
+}
+
+impl OrderBatches {
+    pub(crate) fn push(&mut self, uuids: Vec<Uuid>, refused: usize) -> OrderBatchId {
+        self.batches.push(Some((uuids, refused)));
+        self.batches.len() - 1
+    }
+
+    pub(crate) fn take(&mut self, id: OrderBatchId) -> Option<(Vec<Uuid>, usize)> {
+        self.batches.get_mut(id)?.take()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.batches.clear();
+    }
+}
+
+// Sum of the results of the orders of a batch of the same type
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BatchTotals {
+    pub orders: usize,
+    pub traded_quantity: Quantity,
+    pub total_cost: Price,
+    pub remainder: Quantity,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BatchResult {
+    pub buy: BatchTotals,
+    pub sell: BatchTotals,
+    // Orders of the batch the market refused, see validate_order
+    pub refused: usize,
+}
+
+impl BatchResult {
+    fn add(&mut self, result: &OrderResult) {
+        let totals = match result.ordertype {
+            OrderType::Buy => &mut self.buy,
+            OrderType::Sell => &mut self.sell,
+        };
+        totals.orders += 1;
+        totals.traded_quantity += result.traded_quantity;
+        totals.total_cost += result.total_cost;
+        totals.remainder += result.remainder;
+    }
+}
+
+pub struct OrderResult {
+    pub ordertype: OrderType,
+    pub traded_quantity: Quantity,
+    pub total_cost: Price,
+    // Quantity left out of the order because it was not a whole number of lots
+    pub remainder: Quantity,
+    // Who the order traded with and how much, see counterparties
+    pub counterparties: Vec<(Owner, Quantity)>,
+}
+
+impl OrderResult {
+    pub fn new(ordertype: OrderType, traded_quantity: Quantity, total_cost: Price) -> OrderResult {
+        OrderResult { ordertype, traded_quantity, total_cost, remainder: 0, counterparties: vec![] }
+    }
+}
+
+// Who traded with whom. A market clears all its orders together, so the orders that traded are
+// paired in their order after the trade, the tiers or the prices met first together: the first
+// buy order with the first sell orders for as much as it traded, and so on.
+pub(crate) fn counterparties<'a>(buy_orders: impl Iterator<Item=&'a OrderInfo>, sell_orders: impl Iterator<Item=&'a OrderInfo>)
+    -> HashMap<Uuid, Vec<(Owner, Quantity)>> {
+    let mut pairs = HashMap::<Uuid, Vec<(Owner, Quantity)>>::new();
+    let mut sell_orders = sell_orders.filter(|x| x.traded_quantity > 0).map(|x| (x, x.traded_quantity)).peekable();
+    for bo in buy_orders.filter(|x| x.traded_quantity > 0) {
+        let mut left = bo.traded_quantity;
+        while left > 0 {
+            let Some((so, so_left)) = sell_orders.peek_mut() else {
+                break;
+            };
+            let quantity = left.min(*so_left);
+            pairs.entry(bo.uuid).or_default().push((so.owner, quantity));
+            pairs.entry(so.uuid).or_default().push((bo.owner, quantity));
+            left -= quantity;
+            *so_left -= quantity;
+            if *so_left == 0 {
+                sell_orders.next();
+            }
+        }
+    }
+    pairs
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TradeReport {
+    // Bought quantity, equal to `sold` in a sane market
+    pub traded: Quantity,
+    pub sold: Quantity,
+    pub unfilled_buy: Quantity,
+    pub unfilled_sell: Quantity,
+    // Orders that traded more than required
+    pub overfilled: usize,
+    // Prestige tiers left without trade because the other side ran out, see TierWalk
+    pub untraded_tiers: usize,
+    // Quantity left out of the orders because it was not a whole number of lots
+    pub remainder: Quantity,
+}
+
+impl TradeReport {
+    pub(crate) fn from_orders<'a>(buy_orders: impl Iterator<Item=&'a OrderInfo>, sell_orders: impl Iterator<Item=&'a OrderInfo>) -> TradeReport {
+        let mut report = TradeReport::default();
+        for bo in buy_orders {
+            report.traded += bo.traded_quantity;
+            report.unfilled_buy += bo.required_quantity.saturating_sub(bo.traded_quantity);
+            report.overfilled += (bo.traded_quantity > bo.required_quantity) as usize;
+            report.remainder += bo.remainder;
+        }
+        for so in sell_orders {
+            report.sold += so.traded_quantity;
+            report.unfilled_sell += so.required_quantity.saturating_sub(so.traded_quantity);
+            report.overfilled += (so.traded_quantity > so.required_quantity) as usize;
+            report.remainder += so.remainder;
+        }
+        report
+    }
+}
+
+// Why the trade of a market failed. The market is halted for the tick, see Market::halt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketError {
+    // A check of the matching failed
+    Invariant(String),
+    // The trade panicked, the market may have lost some orders
+    Panic(String),
+}
+
+impl fmt::Display for MarketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketError::Invariant(e) => write!(f, "broken invariant: {e}"),
+            MarketError::Panic(e) => write!(f, "panic: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MarketError {}
+
+// Orders whose result nobody retrieved before the state was cleared: what they traded never
+// reached their owners, the goods and the money are lost
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnretrievedOrders(pub Vec<(Owner, Uuid)>);
+
+impl fmt::Display for UnretrievedOrders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} results never retrieved", self.0.len())
+    }
+}
+
+impl std::error::Error for UnretrievedOrders {}
+
+// Why a market refused an order. Nothing is registered and the order gets no id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderError {
+    // An order of nothing would only slow down the matching and take a share of the remainder
+    ZeroQuantity,
+    // The limit price is not a number or is negative
+    InvalidLimit(Price),
+    // The money doesn't pay for a lot at the price of the market, see Market::affordable_order
+    InsufficientFunds { money: f64, price: Price },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::ZeroQuantity => write!(f, "order of zero quantity"),
+            OrderError::InvalidLimit(x) => write!(f, "invalid limit price {x}"),
+            OrderError::InsufficientFunds { money, price } => write!(f, "{money} doesn't pay for a lot at {price} per unit"),
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+// The checks of every order before it is registered. Orders smaller than a lot are accepted,
+// their result reports the remainder to the owner.
+pub fn validate_order(quantity: Quantity, limit_price: Option<Price>) -> Result<(), OrderError> {
+    if quantity == 0 {
+        return Err(OrderError::ZeroQuantity);
+    }
+    match limit_price {
+        Some(x) if x.is_nan() || x < 0. => Err(OrderError::InvalidLimit(x)),
+        _ => Ok(()),
+    }
+}
+
+// All the quantities exchanged with a market are in base units of its good, while the price
+// is always referred to a whole unit.
+#[typetag::serde(tag = "kind")]
+pub trait Market: Debug + Send {
+    fn good_uid(&self) -> GoodUid;
+    fn price_per_unit(&self) -> Price;
+    fn unit_scale(&self) -> Quantity;
+    fn cost_of(&self, quantity: Quantity) -> Price {
+        goods::to_units(quantity, self.unit_scale()) * self.price_per_unit()
+    }
+    fn affordable_quantity(&self, money: f64) -> Quantity {
+        (money / self.price_per_unit() * self.unit_scale() as f64) as Quantity
+    }
+    // Base units of a lot. The orders are rounded down to a whole number of lots and only
+    // whole lots are traded.
+    fn lot_size(&self) -> Quantity {
+        1
+    }
+    // The quantity `money` buys, refused when it doesn't pay for a whole lot
+    fn affordable_order(&self, money: f64) -> Result<Quantity, OrderError> {
+        let quantity = self.affordable_quantity(money.max(0.));
+        if quantity < self.lot_size() {
+            return Err(OrderError::InsufficientFunds { money, price: self.price_per_unit() });
+        }
+        Ok(quantity)
+    }
+    // called from Step 2 in EcoEntity, see validate_order
+    fn register_order(&mut self, owner: Owner, otype: OrderType, quantity: Quantity, prestige: f64) -> Result<Uuid, OrderError>;
+    // Max price per unit for a buy order, min price per unit for a sell order.
+    // Markets with a single price ignore the limit.
+    fn register_limit_order(&mut self, owner: Owner, otype: OrderType, quantity: Quantity, prestige: f64, limit_price: Price) -> Result<Uuid, OrderError> {
+        validate_order(quantity, Some(limit_price))?;
+        self.register_order(owner, otype, quantity, prestige)
+    }
+    // Registers all the orders of `owner` at once, the refused ones are left out of the batch
+    fn register_orders(&mut self, owner: Owner, orders: &[(OrderType, Quantity, f64)]) -> OrderBatchId {
+        let uuids: Vec<Uuid> = orders.iter()
+            .filter_map(|(otype, quantity, prestige)| self.register_order(owner, *otype, *quantity, *prestige).ok())
+            .collect();
+        let refused = orders.len() - uuids.len();
+        self.order_batches().push(uuids, refused)
+    }
+    // Like register_orders, every order with its limit price, see register_limit_order
+    fn register_limit_orders(&mut self, owner: Owner, orders: &[(OrderType, Quantity, f64, Price)]) -> OrderBatchId {
+        let uuids: Vec<Uuid> = orders.iter()
+            .filter_map(|(otype, quantity, prestige, limit_price)| {
+                self.register_limit_order(owner, *otype, *quantity, *prestige, *limit_price).ok()
+            })
+            .collect();
+        let refused = orders.len() - uuids.len();
+        self.order_batches().push(uuids, refused)
+    }
+    // Retrieves the results of all the orders of the batch, only once like retrieve_order_result
+    fn retrieve_batch_results(&mut self, id: OrderBatchId) -> Option<BatchResult> {
+        let (uuids, refused) = self.order_batches().take(id)?;
+        let mut batch = BatchResult { refused, ..BatchResult::default() };
+        for result in uuids.iter().filter_map(|x| self.retrieve_order_result(x)) {
+            batch.add(&result);
+        }
+        Some(batch)
+    }
+    // The batches registered since the state was cleared, cleared with it
+    fn order_batches(&mut self) -> &mut OrderBatches;
+    // Step 3
+    // Running the trade again must start from the registered orders, ignoring the previous run.
+    // A market that fails its trade halts before returning the error.
+    fn run_trade(&mut self) -> Result<Quantity, MarketError>;
+    // The result of the order, without handing it to the owner
+    fn peek_order_result(&self, uuid: &Uuid) -> Option<OrderResult>;
+    // Hands the result of the order to its owner, only once: None when it was already retrieved
+    fn retrieve_order_result(&mut self, uuid: &Uuid) -> Option<OrderResult>;
+    // Retrieves the results of the orders registered by the entities, delivered to them in Step 5
+    fn take_entity_results(&mut self) -> Vec<(EntityId, OrderResult)>;
+    // Shrink the order to zero so it doesn't trade anymore. Used to revoke basket legs, the
+    // market trades again after.
+    fn cancel_order(&mut self, uuid: &Uuid) -> bool;
+    // Cancel all the orders until the state is cleared, after a failed trade: the owners get
+    // them back untraded and nothing changes hands, the trade can run again without effects.
+    fn halt(&mut self);
+    // Traded and unfilled quantities of the last trade, valid until the state is cleared
+    fn trade_report(&self) -> TradeReport;
+    // Orders registered since the state was cleared and their owners
+    fn registered_orders(&self) -> Vec<(Owner, RecordedOrder)>;
+    // Price and quantities of the last ticks, recorded when the state is cleared
+    fn stats(&self) -> &MarketStats;
+    // Step 6, the state is cleared even when some results were never retrieved
+    fn clear_state(&mut self) -> Result<(), UnretrievedOrders>;
+}
+// quando market registra un order ritorna un uuid che va segnato e usato per il recovery del result
+
+// The trade of `market`, a panic halts the market like a failed trade instead of unwinding
+pub fn guarded_trade(market: &mut dyn Market) -> Result<Quantity, MarketError> {
+    match panic::catch_unwind(AssertUnwindSafe(|| market.run_trade())) {
+        Ok(result) => result,
+        Err(payload) => {
+            market.halt();
+            let message = payload.downcast_ref::<&str>().map(|x| x.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(MarketError::Panic(message))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TestMarket {
+    pub(crate) good_uid: GoodUid,
+    pub(crate) price_per_unit: Price,
+    pub(crate) unit_scale: Quantity,
+    #[serde(default = "goods::single_lot")]
+    pub(crate) lot_size: Quantity,
+    pub(crate) buy_orders: Vec<OrderInfo>,
+    pub(crate) sell_orders: Vec<OrderInfo>,
+    // Stochastic mechanisms, enabled only with a random stream:
+    //  the remainder of the equal distribution goes to random orders instead of the first ones
+    //  and every order has `friction` probability to miss the trade of the tick.
+    pub(crate) rng: Option<SimRng>,
+    pub(crate) friction: f64,
+    pub(crate) order_ids: OrderIds,
+    #[serde(default)]
+    pub(crate) tier_policy: TierPolicy,
+    // Of the last trade, valid until the state is cleared
+    #[serde(default)]
+    pub(crate) untraded_tiers: usize,
+    #[serde(default)]
+    pub(crate) stats: MarketStats,
+    #[serde(default)]
+    pub(crate) batches: OrderBatches,
+    #[serde(skip)]
+    pub(crate) index: OrderIndex,
+}
+
+impl TestMarket {
+    pub fn new(good_uid: GoodUid, unit_scale: Quantity, price_per_unit: Price) -> TestMarket {
+        TestMarket {
+            good_uid,
+            price_per_unit,
+            unit_scale,
+            lot_size: 1,
+            buy_orders: vec![],
+            sell_orders: vec![],
+            rng: None,
+            friction: 0.,
+            order_ids: OrderIds::new(good_uid),
+            tier_policy: TierPolicy::Prestige,
+            untraded_tiers: 0,
+            stats: MarketStats::default(),
+            batches: OrderBatches::default(),
+            index: OrderIndex::default(),
+        }
+    }
+
+    fn sits_out(&mut self) -> bool {
+        match self.rng.as_mut() {
+            Some(rng) if self.friction > 0. => rng.gen_bool(self.friction.min(1.)),
+            _ => false,
+        }
+    }
+
+    // The orders and `total_to_dist` are whole numbers of lots, and so is every share
+    fn distribute(&mut self, total_to_dist: Quantity, recvarray: &mut [OrderInfo]) -> Quantity {
+        let lot = self.lot_size.max(1);
+        let mut dist_for_now = 0_u64;
+        loop {
+            let not_fulled = recvarray.iter().filter(|x| x.traded_quantity != x.required_quantity).count();
+            if not_fulled == 0 { break; }
+            let eq_chunks = (total_to_dist - dist_for_now) / not_fulled as Quantity / lot * lot;
+            if eq_chunks == 0 { break; }
+            let distributed = recvarray.iter_mut().filter(|x| x.traded_quantity != x.required_quantity)
+                .fold(0_u64, |distributed, x| {
+                    x.traded_quantity += eq_chunks;
+                    if x.traded_quantity > x.required_quantity {
+                        let rem = x.traded_quantity - x.required_quantity;
+                        x.traded_quantity -= rem;
+                        return distributed + eq_chunks - rem;
+                    }
+                    distributed + eq_chunks
+                });
+            dist_for_now += distributed;
+            if distributed == 0 { break; }
+        }
+        // Distribute the remainder, a lot to every order with room for it
+        let mut remainder = total_to_dist - dist_for_now;
+        let mut receivers: Vec<usize> = (0..recvarray.len())
+            .filter(|i| recvarray[*i].missing_quantity() >= lot)
+            .collect();
+        if let Some(rng) = self.rng.as_mut() {
+            receivers.shuffle(rng);
+        }
+        for i in receivers {
+            if remainder >= lot {
+                recvarray[i].traded_quantity += lot;
+                dist_for_now += lot;
+                remainder -= lot;
+            } else {
+                break;
+            }
+        }
+        // Return the distributed quantity
+        dist_for_now
+    }
+
+    fn trade_loop(
+        &mut self,
+        distrarray: &mut [OrderInfo],
+        recvarray: &mut [OrderInfo],
+        total_to_dist: Quantity,
+    ) -> Result<Quantity, MarketError> {
+        // This function thinks that recvarray has more receiving quantity than the one that is been distributing.
+        // This is how to obtain here the value. Unnecessary heavy task that I already do one time outside the fn
+        // let total_dist = distrarray.iter().fold(0, |acc, x| acc + x.required_quantity - x.traded_quantity);
+        // Distribute the trade value equally between all the orders not full
+        let distributed = self.distribute(total_to_dist, recvarray);
+        // Report the distribution to the distributors
+        // We have to run the distribution algo for the distributors too to see who selled what
+        let chk_dist = self.distribute(distributed, distrarray);
+        if distributed != chk_dist {
+            return Err(MarketError::Invariant(format!("{distributed} distributed to a side and {chk_dist} to the other")));
+        }
+        // Return the total distributed
+        Ok(distributed)
+    }
+}
+
+#[typetag::serde]
+impl Market for TestMarket {
+    fn good_uid(&self) -> GoodUid {
+        self.good_uid
+    }
+
+    fn price_per_unit(&self) -> Price {
+        self.price_per_unit
+    }
+
+    fn unit_scale(&self) -> Quantity {
+        self.unit_scale
+    }
+
+    fn lot_size(&self) -> Quantity {
+        self.lot_size
+    }
+
+    fn register_order(&mut self, owner: Owner, otype: OrderType, quantity: Quantity, prestige: f64) -> Result<Uuid, OrderError> {
+        validate_order(quantity, None)?;
+        let uuid = self.order_ids.next_id();
+        let orders = match otype {
+            OrderType::Buy => &mut self.buy_orders,
+            OrderType::Sell => &mut self.sell_orders,
+        };
+        self.index.insert(uuid, otype, orders.len());
+        orders.push(OrderInfo::in_lots(uuid, owner, quantity, prestige, self.lot_size));
+        // println!("register_order: {:?} {:?} - {uuid}", &self.buy_orders, &self.sell_orders);
+        Ok(uuid)
+    }
+
+    fn order_batches(&mut self) -> &mut OrderBatches {
+        &mut self.batches
+    }
+
+    fn run_trade(&mut self) -> Result<Quantity, MarketError> {
+        // TODO: calculate price delta
+        for bo in self.buy_orders.iter_mut().chain(self.sell_orders.iter_mut()) {
+            bo.traded_quantity = 0;
+            bo.retrieved = false;
+        }
+        let mut total_final_traded: Quantity = 0;
+        // Orders that miss this trade because of the frictions, there is no trade to miss with
+        // a side empty
+        let one_sided = self.buy_orders.is_empty() || self.sell_orders.is_empty();
+        let buy_idle: Vec<bool> = (0..self.buy_orders.len()).map(|_| !one_sided && self.sits_out()).collect();
+        let sell_idle: Vec<bool> = (0..self.sell_orders.len()).map(|_| !one_sided && self.sits_out()).collect();
+        let mut idle_buyarray = Vec::<OrderInfo>::new();
+        let mut idle_sellarray = Vec::<OrderInfo>::new();
+        let mut buyarray = Vec::<OrderInfo>::new();
+        let mut sellarray = Vec::<OrderInfo>::new();
+        for (bo, idle) in self.buy_orders.drain(..).zip(buy_idle) {
+            match idle {
+                true => idle_buyarray.push(bo),
+                false => buyarray.push(bo),
+            }
+        }
+        for (bo, idle) in self.sell_orders.drain(..).zip(sell_idle) {
+            match idle {
+                true => idle_sellarray.push(bo),
+                false => sellarray.push(bo),
+            }
+        }
+        let mut walk = TierWalk::new(self.tier_policy, buyarray, sellarray);
+        let mut error = None;
+        while let Some((buyarray, sellarray)) = walk.current() {
+            let total_buy = buyarray.iter().fold(0, |acc, x| acc + x.missing_quantity());
+            let total_sell = sellarray.iter().fold(0, |acc, x| acc + x.missing_quantity());
+            let (total_traded, expected, exhausted) = match total_sell.cmp(&total_buy) {
+                Ordering::Greater => {
+                    // TS > TB => Distribute the product from the buyers to the sellers that are more of them so
+                    //   it's guaranteed that all the buyers will finish with full trade!
+                    (self.trade_loop(&mut buyarray[..], &mut sellarray[..], total_buy), total_buy, Exhausted::Buy)
+                }
+                Ordering::Less => {
+                    // TS < TB => Distribute the product from the sellers to the buyers that are more of them so
+                    //   it's guaranteed that all the sellers will finish with full trade!
+                    (self.trade_loop(&mut sellarray[..], &mut buyarray[..], total_sell), total_sell, Exhausted::Sell)
+                }
+                Ordering::Equal => {
+                    // TS == TB => this batch of sellers and buyers have the exact same quantity!
+                    for bo in buyarray.iter_mut().chain(sellarray.iter_mut()) {
+                        bo.traded_quantity = bo.required_quantity;
+                    }
+                    (Ok(total_buy), total_buy, Exhausted::Both)  // Same as total_sell
+                }
+            };
+            match total_traded {
+                Ok(traded) if traded == expected => total_final_traded += traded,
+                Ok(traded) => {
+                    error = Some(MarketError::Invariant(format!("{traded} traded of the {expected} expected")));
+                    break;
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+            walk.advance(exhausted);
+        }
+        // The tiers left out of the trade keep their orders, untraded
+        let (mut result_buyarray, mut result_sellarray, untraded_tiers) = walk.finish();
+        result_buyarray.append(&mut idle_buyarray);
+        result_sellarray.append(&mut idle_sellarray);
+        self.buy_orders = result_buyarray;
+        self.sell_orders = result_sellarray;
+        self.untraded_tiers = untraded_tiers;
+        self.index.rebuild(self.buy_orders.iter(), self.sell_orders.iter());
+        self.index.pair(self.buy_orders.iter(), self.sell_orders.iter());
+        if let Some(e) = error {
+            self.halt();
+            return Err(e);
+        }
+        Ok(total_final_traded)
+    }
+
+    fn peek_order_result(&self, uuid: &Uuid) -> Option<OrderResult> {
+        let (otype, i) = self.index.get(uuid)?;
+        let x = match otype {
+            OrderType::Buy => &self.buy_orders[i],
+            OrderType::Sell => &self.sell_orders[i],
+        };
+        Some(OrderResult { counterparties: self.index.counterparties(uuid), ..x.result(otype, self.cost_of(x.traded_quantity)) })
+    }
+
+    fn retrieve_order_result(&mut self, uuid: &Uuid) -> Option<OrderResult> {
+        let (otype, i) = self.index.get(uuid)?;
+        let x = match otype {
+            OrderType::Buy => &mut self.buy_orders[i],
+            OrderType::Sell => &mut self.sell_orders[i],
+        };
+        if x.retrieved {
+            return None;
+        }
+        x.retrieved = true;
+        self.peek_order_result(uuid)
+    }
+
+    fn take_entity_results(&mut self) -> Vec<(EntityId, OrderResult)> {
+        let index = &mut self.index;
+        let unit_scale = self.unit_scale;
+        let price = self.price_per_unit;
+        let mut results = |otype: OrderType, orders: &mut [OrderInfo]| -> Vec<(EntityId, OrderResult)> {
+            orders.iter_mut().filter(|x| !x.retrieved).filter_map(|x| match x.owner {
+                Owner::Entity(id) => {
+                    x.retrieved = true;
+                    Some((id, OrderResult {
+                        counterparties: index.take_counterparties(&x.uuid),
+                        ..x.result(otype, goods::to_units(x.traded_quantity, unit_scale) * price)
+                    }))
+                }
+                _ => None,
+            }).collect()
+        };
+        let buy = results(OrderType::Buy, &mut self.buy_orders);
+        buy.into_iter().chain(results(OrderType::Sell, &mut self.sell_orders)).collect()
+    }
+
+    fn cancel_order(&mut self, uuid: &Uuid) -> bool {
+        let Some((otype, i)) = self.index.get(uuid) else {
+            return false;
+        };
+        let x = match otype {
+            OrderType::Buy => &mut self.buy_orders[i],
+            OrderType::Sell => &mut self.sell_orders[i],
+        };
+        x.required_quantity = 0;
+        x.traded_quantity = 0;
+        true
+    }
+
+    fn halt(&mut self) {
+        for x in self.buy_orders.iter_mut().chain(self.sell_orders.iter_mut()) {
+            x.required_quantity = 0;
+            x.traded_quantity = 0;
+        }
+        self.untraded_tiers = 0;
+        // A panic may have left the orders out of place
+        self.index.rebuild(self.buy_orders.iter(), self.sell_orders.iter());
+    }
+
+    fn trade_report(&self) -> TradeReport {
+        TradeReport {
+            untraded_tiers: self.untraded_tiers,
+            ..TradeReport::from_orders(self.buy_orders.iter(), self.sell_orders.iter())
+        }
+    }
+
+    fn registered_orders(&self) -> Vec<(Owner, RecordedOrder)> {
+        let orders = |otype: OrderType, orders: &[OrderInfo]| -> Vec<(Owner, RecordedOrder)> {
+            orders.iter().map(|x| (x.owner, RecordedOrder {
+                good_uid: self.good_uid,
+                otype,
+                quantity: x.required_quantity,
+                prestige: x.prestige,
+                limit_price: None,
+            })).collect()
+        };
+        [orders(OrderType::Buy, &self.buy_orders), orders(OrderType::Sell, &self.sell_orders)].concat()
+    }
+
+    fn stats(&self) -> &MarketStats {
+        &self.stats
+    }
+
+    fn clear_state(&mut self) -> Result<(), UnretrievedOrders> {
+        let report = self.trade_report();
+        self.stats.record(self.price_per_unit, &report);
+        let retrieved = unretrieved(self.buy_orders.iter().chain(self.sell_orders.iter()));
+        self.buy_orders.clear();
+        self.sell_orders.clear();
+        self.untraded_tiers = 0;
+        self.batches.clear();
+        self.index.clear();
+        retrieved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_market() -> TestMarket {
+        TestMarket::new(0, 1, 1.)
+    }
+
+    fn traded(market: &mut TestMarket, uuid: &Uuid) -> Quantity {
+        market.peek_order_result(uuid).unwrap().traded_quantity
+    }
+
+    #[test]
+    fn higher_prestige_buyers_are_filled_first() {
+        let mut market = test_market();
+        let low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        let high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 5.).unwrap();
+        let mid = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 3.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 15, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(15));
+        assert_eq!(traded(&mut market, &high), 10);
+        assert_eq!(traded(&mut market, &mid), 5);
+        assert_eq!(traded(&mut market, &low), 0);
+    }
+
+    #[test]
+    fn higher_prestige_sellers_are_filled_first() {
+        let mut market = test_market();
+        let low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 10, 0.).unwrap();
+        let high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 10, 2.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 4, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 4, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(8));
+        assert_eq!(traded(&mut market, &high), 8);
+        assert_eq!(traded(&mut market, &low), 0);
+    }
+
+    #[test]
+    fn same_prestige_shares_equally() {
+        let mut market = test_market();
+        let a = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        let b = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 12, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(12));
+        assert_eq!(traded(&mut market, &a), 6);
+        assert_eq!(traded(&mut market, &b), 6);
+    }
+
+    #[test]
+    fn untraded_tiers_keep_their_orders() {
+        let mut market = test_market();
+        let high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 2.).unwrap();
+        let low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 1.).unwrap();
+        let seller = market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 5, 2.).unwrap();
+        let late = market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 5, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(10));
+        assert_eq!(traded(&mut market, &high), 5);
+        assert_eq!(traded(&mut market, &low), 5);
+        assert_eq!(traded(&mut market, &seller), 5);
+        assert_eq!(traded(&mut market, &late), 5);
+        let mut market = test_market();
+        let high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 2.).unwrap();
+        let low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 5, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(5));
+        assert_eq!(traded(&mut market, &high), 5);
+        assert_eq!(traded(&mut market, &low), 0);
+    }
+
+    #[test]
+    fn simultaneous_exhaustion_moves_both_sides() {
+        // The top tiers meet exactly, the next ones trade with each other
+        let mut market = test_market();
+        let buy_high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 6, 3.).unwrap();
+        let buy_low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        let sell_high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 6, 2.).unwrap();
+        let sell_low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 4, 0.).unwrap();
+        assert_eq!(market.run_trade(), Ok(10));
+        assert_eq!(traded(&mut market, &buy_high), 6);
+        assert_eq!(traded(&mut market, &buy_low), 4);
+        assert_eq!(traded(&mut market, &sell_high), 6);
+        assert_eq!(traded(&mut market, &sell_low), 4);
+        assert_eq!(market.trade_report().untraded_tiers, 0);
+        // Only the buyers have another tier, nobody is left to sell to it
+        let mut market = test_market();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 2.).unwrap();
+        let left = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 1.).unwrap();
+        let lower = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 5, 0.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 5, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(5));
+        assert_eq!(traded(&mut market, &left), 0);
+        assert_eq!(traded(&mut market, &lower), 0);
+        let report = market.trade_report();
+        assert_eq!(report.untraded_tiers, 2);
+        assert_eq!(report.unfilled_buy, 10);
+    }
+
+    #[test]
+    fn partially_traded_tier_is_not_untraded() {
+        let mut market = test_market();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 2.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 15, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(15));
+        assert_eq!(market.trade_report().untraded_tiers, 0);
+        // With a side empty all the tiers of the other one are untraded
+        let mut market = test_market();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 10, 2.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 10, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(0));
+        assert_eq!(market.trade_report().untraded_tiers, 2);
+    }
+
+    #[test]
+    fn pooled_tiers_share_among_all_prestiges() {
+        let mut market = TestMarket { tier_policy: TierPolicy::Pooled, ..test_market() };
+        let high = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 5.).unwrap();
+        let low = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 12, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(12));
+        assert_eq!(traded(&mut market, &high), 6);
+        assert_eq!(traded(&mut market, &low), 6);
+        assert_eq!(market.trade_report().untraded_tiers, 0);
+    }
+
+    #[test]
+    fn orders_trade_in_whole_lots() {
+        let mut market = TestMarket { lot_size: 10, ..test_market() };
+        let a = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 25, 1.).unwrap();
+        let b = market.register_order(Owner::Entity(EntityId(1)), OrderType::Buy, 20, 1.).unwrap();
+        let sell = market.register_order(Owner::Entity(EntityId(2)), OrderType::Sell, 34, 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(30));
+        assert_eq!(traded(&mut market, &a) + traded(&mut market, &b), 30);
+        assert_eq!(traded(&mut market, &a) % 10, 0);
+        assert_eq!(traded(&mut market, &b) % 10, 0);
+        let result = market.retrieve_order_result(&sell).unwrap();
+        assert_eq!((result.traded_quantity, result.remainder), (30, 4));
+        assert_eq!(market.trade_report().remainder, 9);
+    }
+
+    #[test]
+    fn failed_trade_halts_the_market() {
+        let mut market = TestMarket { lot_size: 10, ..test_market() };
+        let a = market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 10, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(1)), OrderType::Buy, 10, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(2)), OrderType::Sell, 30, 1.).unwrap();
+        // Not a whole number of lots, the sellers get less than the buyers ask
+        market.buy_orders[1].required_quantity = 5;
+        assert!(matches!(guarded_trade(&mut market), Err(MarketError::Invariant(_))));
+        assert!(market.take_entity_results().iter().all(|x| x.1.traded_quantity == 0));
+        assert_eq!(traded(&mut market, &a), 0);
+        assert_eq!(market.run_trade(), Ok(0));
+    }
+
+    #[test]
+    fn results_are_retrieved_once_and_the_lost_ones_reported() {
+        let mut market = test_market();
+        let buy = market.register_order(Owner::Route, OrderType::Buy, 10, 0.).unwrap();
+        let sell = market.register_order(Owner::ClearingHouse, OrderType::Sell, 10, 0.).unwrap();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 10, 0.).unwrap();
+        market.run_trade().unwrap();
+        assert!(market.retrieve_order_result(&buy).is_some());
+        assert!(market.retrieve_order_result(&buy).is_none());
+        assert_eq!(market.take_entity_results().len(), 1);
+        assert!(market.take_entity_results().is_empty());
+        assert_eq!(market.clear_state(), Err(UnretrievedOrders(vec![(Owner::ClearingHouse, sell)])));
+        assert_eq!(market.clear_state(), Ok(()));
+    }
+
+    #[test]
+    fn batches_sum_the_results_per_order_type() {
+        let mut market = TestMarket::new(0, 10, 2.);
+        let batch = market.register_orders(Owner::Route, &[
+            (OrderType::Buy, 20, 0.), (OrderType::Buy, 0, 0.), (OrderType::Sell, 5, 0.), (OrderType::Buy, 10, 0.),
+        ]);
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Sell, 25, 0.).unwrap();
+        market.run_trade().unwrap();
+        let result = market.retrieve_batch_results(batch).unwrap();
+        // 30 bought from the 30 sold, 6 units at 2
+        assert_eq!(result.buy, BatchTotals { orders: 2, traded_quantity: 30, total_cost: 6., remainder: 0 });
+        assert_eq!(result.sell, BatchTotals { orders: 1, traded_quantity: 5, total_cost: 1., remainder: 0 });
+        assert_eq!(result.refused, 1);
+        assert!(market.retrieve_batch_results(batch).is_none());
+        market.take_entity_results();
+        assert_eq!(market.clear_state(), Ok(()));
+        assert!(market.retrieve_batch_results(batch).is_none());
+    }
+
+    #[test]
+    fn limit_batches_trade_within_their_limits() {
+        let mut market = crate::orderbook::OrderBookMarket::new(0, 1, 1, 2.);
+        let batch = market.register_limit_orders(Owner::Route, &[
+            (OrderType::Buy, 10, 0., 3.), (OrderType::Buy, 10, 0., 1.), (OrderType::Buy, 10, 0., -1.),
+        ]);
+        market.register_limit_order(Owner::Entity(EntityId(0)), OrderType::Sell, 20, 0., 2.).unwrap();
+        market.run_trade().unwrap();
+        // The bid below the ask doesn't trade, the negative limit is refused
+        let result = market.retrieve_batch_results(batch).unwrap();
+        assert_eq!((result.buy.orders, result.buy.traded_quantity, result.refused), (2, 10, 1));
+    }
+
+    #[test]
+    fn invalid_orders_are_refused() {
+        let mut market = TestMarket { lot_size: 10, ..test_market() };
+        let owner = Owner::Entity(EntityId(0));
+        assert_eq!(market.register_order(owner, OrderType::Buy, 0, 1.), Err(OrderError::ZeroQuantity));
+        assert!(matches!(market.register_limit_order(owner, OrderType::Buy, 10, 1., Price::NAN), Err(OrderError::InvalidLimit(_))));
+        assert_eq!(market.registered_orders().len(), 0);
+        // 9.5 pays for 9 units, less than a lot
+        assert_eq!(market.affordable_order(9.5), Err(OrderError::InsufficientFunds { money: 9.5, price: 1. }));
+        assert_eq!(market.affordable_order(10.), Ok(10));
+    }
+
+    #[test]
+    fn results_pair_the_counterparties() {
+        let mut market = test_market();
+        market.register_order(Owner::Entity(EntityId(0)), OrderType::Buy, 30, 1.).unwrap();
+        market.register_order(Owner::Entity(EntityId(1)), OrderType::Buy, 10, 0.).unwrap();
+        market.register_order(Owner::Entity(EntityId(2)), OrderType::Sell, 20, 0.).unwrap();
+        let route = market.register_order(Owner::Route, OrderType::Sell, 20, 0.).unwrap();
+        market.run_trade().unwrap();
+        let results: Vec<_> = market.take_entity_results().into_iter().map(|(id, x)| (id.0, x.counterparties)).collect();
+        assert_eq!(results, vec![
+            (0, vec![(Owner::Entity(EntityId(2)), 20), (Owner::Route, 10)]),
+            (1, vec![(Owner::Route, 10)]),
+            (2, vec![(Owner::Entity(EntityId(0)), 20)]),
+        ]);
+        let expected = vec![(Owner::Entity(EntityId(0)), 10), (Owner::Entity(EntityId(1)), 10)];
+        assert_eq!(market.peek_order_result(&route).unwrap().counterparties, expected);
+        assert_eq!(market.retrieve_order_result(&route).unwrap().counterparties, expected);
+        market.clear_state().unwrap();
+        assert!(market.index.counterparties(&route).is_empty());
+    }
+}
+
+// Invariants of the matching on arbitrary orders: single huge orders among many small ones,
+// orders of zero, lots, prestige tiers and frictions
+#[cfg(test)]
+mod properties {
+    use proptest::prelude::*;
+    use rand::SeedableRng;
+    use super::*;
+
+    // Quantity, prestige. The huge orders are still far from overflowing the totals.
+    fn order() -> impl Strategy<Value=(Quantity, f64)> {
+        let quantity = prop_oneof![4 => 0..100_u64, 1 => Just(0_u64), 1 => 1_000_000_000..1_000_000_000_000_u64];
+        (quantity, (0..3_u8).prop_map(f64::from))
+    }
+
+    fn receivers() -> impl Strategy<Value=Vec<OrderInfo>> {
+        prop::collection::vec((0..100_u64, 0..100_u64), 0..20).prop_map(|orders| {
+            orders.into_iter().enumerate().map(|(i, (required, traded))| OrderInfo {
+                traded_quantity: traded.min(required),
+                ..OrderInfo::new(Uuid::nil(), Owner::Entity(EntityId(i)), required, 0.)
+            }).collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn distribute_never_exceeds_the_orders(mut orders in receivers(), total in 0..2_000_u64, lot in 1..10_u64) {
+            let mut market = TestMarket { lot_size: lot, ..TestMarket::new(0, 1, 1.) };
+            let before: Vec<Quantity> = orders.iter().map(|x| x.traded_quantity).collect();
+            let missing: Quantity = orders.iter().map(|x| x.missing_quantity()).sum();
+            let distributed = market.distribute(total, &mut orders);
+            prop_assert!(distributed <= total);
+            prop_assert!(distributed <= missing);
+            prop_assert!(orders.iter().all(|x| x.traded_quantity <= x.required_quantity));
+            let added: Quantity = orders.iter().zip(before).map(|(x, before)| x.traded_quantity - before).sum();
+            prop_assert_eq!(added, distributed);
+        }
+
+        #[test]
+        fn trade_conserves_the_quantity(
+            buy in prop::collection::vec(order(), 0..15),
+            sell in prop::collection::vec(order(), 0..15),
+            lot in 1..5_u64,
+            pooled in any::<bool>(),
+            friction in prop_oneof![Just(0.), 0. ..1.],
+            seed in any::<u64>(),
+        ) {
+            let mut market = TestMarket {
+                lot_size: lot,
+                tier_policy: if pooled { TierPolicy::Pooled } else { TierPolicy::Prestige },
+                rng: Some(SimRng::seed_from_u64(seed)),
+                friction,
+                ..TestMarket::new(0, 1, 1.)
+            };
+            let mut uuids = vec![];
+            for (otype, orders) in [(OrderType::Buy, &buy), (OrderType::Sell, &sell)] {
+                for (i, (quantity, prestige)) in orders.iter().enumerate() {
+                    match market.register_order(Owner::Entity(EntityId(i)), otype, *quantity, *prestige) {
+                        Ok(uuid) => uuids.push((otype, *quantity, uuid)),
+                        Err(e) => prop_assert_eq!((*quantity, e), (0, OrderError::ZeroQuantity)),
+                    }
+                }
+            }
+            let traded = market.run_trade();
+            prop_assert!(traded.is_ok());
+            let traded = traded.unwrap();
+            let (mut bought, mut sold) = (0, 0);
+            for (otype, quantity, uuid) in uuids.iter() {
+                let result = market.retrieve_order_result(uuid).unwrap();
+                prop_assert!(result.traded_quantity + result.remainder <= *quantity);
+                prop_assert_eq!(result.traded_quantity % lot, 0);
+                match otype {
+                    OrderType::Buy => bought += result.traded_quantity,
+                    OrderType::Sell => sold += result.traded_quantity,
+                }
+            }
+            prop_assert_eq!(bought, traded);
+            prop_assert_eq!(sold, traded);
+            // Without frictions the smaller side is filled
+            if friction == 0. {
+                let side = |orders: &[(Quantity, f64)]| orders.iter().map(|x| x.0 - x.0 % lot).sum::<Quantity>();
+                prop_assert_eq!(traded, side(&buy).min(side(&sell)));
+            }
+        }
+    }
+}
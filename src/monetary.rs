@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use crate::ledger::FlowKind;
+use crate::registry::EntityRegistry;
+use crate::EntityId;
+
+// Money created or destroyed at scheduled ticks, to script inflation and deflation experiments
+// and watch the prices react. An event either scales the money of its entities, a factor of 1.1
+// gives them 10% more, or injects an amount into every one of them, negative to take it. It
+// happens at tick `at` and then every `every` ticks up to `until`, if given. The money has no
+// counterpart: it is a Monetary source or sink of the ledger.
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonetaryAction {
+    Scale(f64),
+    Inject(f64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonetaryEvent {
+    pub at: u64,
+    // 0 for a single time
+    pub every: u64,
+    pub until: Option<u64>,
+    pub entities: Vec<EntityId>,
+    pub action: MonetaryAction,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MonetaryAuthority {
+    events: Vec<MonetaryEvent>,
+}
+
+impl MonetaryEvent {
+    fn happens(&self, tick: u64) -> bool {
+        if tick < self.at || self.until.is_some_and(|x| tick > x) {
+            return false;
+        }
+        tick == self.at || (self.every > 0 && (tick - self.at).is_multiple_of(self.every))
+    }
+}
+
+impl MonetaryAuthority {
+    pub fn new(events: Vec<MonetaryEvent>) -> MonetaryAuthority {
+        MonetaryAuthority { events }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // Apply the events of the tick, returns the money created, negative when destroyed
+    pub fn apply(&self, tick: u64, entities: &mut EntityRegistry) -> f64 {
+        let mut created = 0.;
+        for event in self.events.iter().filter(|x| x.happens(tick)) {
+            for id in event.entities.iter() {
+                let entity = &mut entities[*id];
+                let money = entity.money_balance();
+                // Nobody is left with negative money
+                let amount = match event.action {
+                    MonetaryAction::Scale(factor) => money * (factor.max(0.) - 1.),
+                    MonetaryAction::Inject(amount) => amount.max(-money.max(0.)),
+                };
+                if entity.transfer(FlowKind::Monetary, amount) {
+                    created += amount;
+                }
+            }
+        }
+        created
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::orderbook::OrderBookMarket;
+use crate::region::{Region, RegionId};
+use crate::market::guarded_trade;
+use crate::{GoodUid, Market, MarketError, OrderType, Owner, Price, Quantity};
+
+// Second stage of the clearing. The regional markets of a good clear first, then what they
+// left unfilled is forwarded to the national market as a single aggregate order per region,
+// with the regional price as limit. The national fills go back to the regional markets as
+// orders of the national clearing house, and the regional markets trade again with them.
+// The clearing house pays and is paid at the regional prices, so it keeps the price
+// differences. The goods it bought and couldn't place are kept in stock and offered first in
+// the next tick. It buys in the regions first and sells in the others only what it holds:
+// with frictions a regional market can fill less than what the national book matched. The
+// regional markets must not trade again after the clearing, the fills it read would change.
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NationalMarket {
+    pub good_uid: GoodUid,
+    book: OrderBookMarket,
+    money_balance: f64,
+    money_flows: MoneyFlows,
+    stock: Quantity,
+    // Traded in the national book in the last clearing
+    traded: Quantity,
+    // Orders of the clearing house in the regional markets
+    orders: Vec<(RegionId, Uuid)>,
+    // Stock at the start of the clearing and goods moved since, see conserves_goods
+    #[serde(default)]
+    opening_stock: Quantity,
+    #[serde(default)]
+    bought: Quantity,
+    #[serde(default)]
+    sold: Quantity,
+}
+
+impl NationalMarket {
+    pub fn new(good_uid: GoodUid, unit_scale: Quantity, price: Price) -> NationalMarket {
+        NationalMarket {
+            good_uid,
+            // What the regional markets left unfilled is already in whole lots
+            book: OrderBookMarket::new(good_uid, unit_scale, 1, price),
+            money_balance: 0.,
+            money_flows: MoneyFlows::default(),
+            stock: 0,
+            traded: 0,
+            orders: vec![],
+            opening_stock: 0,
+            bought: 0,
+            sold: 0,
+        }
+    }
+
+    pub fn price_per_unit(&self) -> Price {
+        self.book.price_per_unit()
+    }
+
+    pub fn traded(&self) -> Quantity {
+        self.traded
+    }
+
+    pub fn stock(&self) -> Quantity {
+        self.stock
+    }
+
+    pub fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    pub fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    // Whether the stock is what it held at the start of the clearing and what it bought and sold
+    pub fn conserves_goods(&self) -> bool {
+        self.opening_stock as u128 + self.bought as u128 == self.stock as u128 + self.sold as u128
+    }
+
+    // Called after the regional markets ran their trade. Returns the markets that failed, None
+    // for the clearing house: a failed clearing house trades nothing, a failed regional market
+    // is halted and the other regions still trade.
+    pub fn clear(&mut self, regions: &mut [Region]) -> Vec<(Option<RegionId>, MarketError)> {
+        self.opening_stock = self.stock;
+        self.bought = 0;
+        self.sold = 0;
+        // Aggregate order of every region with a residual
+        let mut aggregates = vec![];
+        for (region_id, region) in regions.iter().enumerate() {
+            let Some(market) = region.market(self.good_uid) else {
+                continue;
+            };
+            let report = market.trade_report();
+            let price = market.price_per_unit();
+            for (otype, quantity) in [(OrderType::Buy, report.unfilled_buy), (OrderType::Sell, report.unfilled_sell)] {
+                if let Ok(uuid) = self.book.register_limit_order(Owner::ClearingHouse, otype, quantity, 0., price) {
+                    aggregates.push((region_id, otype, uuid));
+                }
+            }
+        }
+        // Nothing to register without stock. The stock is sold through the orders of the regions.
+        let stock_order = self.book.register_order(Owner::ClearingHouse, OrderType::Sell, self.stock, 0.).ok();
+        self.traded = match guarded_trade(&mut self.book) {
+            Ok(traded) => traded,
+            Err(e) => {
+                // Nothing traded, there are no results to lose
+                let _ = self.book.clear_state();
+                return vec![(None, e)];
+            }
+        };
+        if let Some(uuid) = stock_order {
+            self.book.retrieve_order_result(&uuid);
+        }
+        // The clearing house buys what the regions sold and sells what they bought, net in every
+        // region
+        let mut positions: BTreeMap<RegionId, (Quantity, Quantity)> = BTreeMap::new();
+        for (region_id, otype, uuid) in aggregates {
+            let traded = self.book.retrieve_order_result(&uuid).unwrap().traded_quantity;
+            let position = positions.entry(region_id).or_default();
+            match otype {
+                OrderType::Sell => position.0 += traded,
+                OrderType::Buy => position.1 += traded,
+            }
+        }
+        self.book.clear_state().expect("The clearing house retrieves all its orders");
+        let (purchases, sales): (Vec<_>, Vec<_>) = positions.into_iter()
+            .map(|(region_id, (bought, sold))| match bought >= sold {
+                true => (region_id, OrderType::Buy, bought - sold),
+                false => (region_id, OrderType::Sell, sold - bought),
+            })
+            .filter(|x| x.2 > 0)
+            .partition(|x| x.1 == OrderType::Buy);
+        // The regions fill their residuals with the clearing house, the purchases first
+        let mut failures = vec![];
+        let mut available = self.stock;
+        for (region_id, otype, quantity) in purchases {
+            available += self.fill_residual(regions, region_id, otype, quantity, &mut failures);
+        }
+        for (region_id, otype, quantity) in sales {
+            let quantity = quantity.min(available);
+            if quantity > 0 {
+                available -= self.fill_residual(regions, region_id, otype, quantity, &mut failures);
+            }
+        }
+        failures
+    }
+
+    // Registers an order of the clearing house in the market of the region and trades it again.
+    // Returns the quantity traded by the order.
+    fn fill_residual(&mut self, regions: &mut [Region], region_id: RegionId, otype: OrderType, quantity: Quantity,
+                     failures: &mut Vec<(Option<RegionId>, MarketError)>) -> Quantity {
+        let market = regions[region_id].market_mut(self.good_uid).unwrap();
+        let limit_price = market.price_per_unit();
+        let Ok(uuid) = market.register_limit_order(Owner::ClearingHouse, otype, quantity, 0., limit_price) else {
+            return 0;
+        };
+        self.orders.push((region_id, uuid));
+        if let Err(e) = guarded_trade(market.as_mut()) {
+            failures.push((Some(region_id), e));
+        }
+        market.peek_order_result(&uuid).map_or(0, |x| x.traded_quantity)
+    }
+
+    pub fn retrieve_orders(&mut self, regions: &mut [Region]) {
+        // Purchases first, the sales are paid with the goods just bought
+        let mut results = vec![];
+        // The orders lost by a market that panicked never traded
+        for (region_id, uuid) in self.orders.drain(..) {
+            results.extend(regions[region_id].market_mut(self.good_uid).unwrap().retrieve_order_result(&uuid));
+        }
+        results.sort_by_key(|x| x.ordertype == OrderType::Sell);
+        for result in results {
+            match result.ordertype {
+                OrderType::Buy => {
+                    self.stock += result.traded_quantity;
+                    self.bought += result.traded_quantity;
+                    self.money_balance -= result.total_cost;
+                    self.money_flows.record(FlowKind::Trade, -result.total_cost);
+                }
+                OrderType::Sell => {
+                    // The sales never exceed the stock and the purchases, see clear. If they did
+                    // conserves_goods reports it.
+                    self.stock = self.stock.saturating_sub(result.traded_quantity);
+                    self.sold += result.traded_quantity;
+                    self.money_balance += result.total_cost;
+                    self.money_flows.record(FlowKind::Trade, result.total_cost);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Scenario;
+
+    #[test]
+    fn the_clearing_house_sells_only_what_it_holds() {
+        // With frictions the regional markets fill less than what the national book matched
+        let source = include_str!("../scenarios/national_grain.toml")
+            .replace("price = 2.0\n", "price = 2.0\nfriction = 0.5\nrandomized = true\n")
+            .replace("price = 3.0\n", "price = 3.0\nfriction = 0.5\nrandomized = true\n");
+        for seed in 0..8 {
+            let source = source.replace("ticks = 20\n", &format!("ticks = 20\nseed = {seed}\n"));
+            let mut sim = Scenario::from_toml(&source).unwrap().build().unwrap();
+            for _ in 0..20 {
+                sim.step();
+                assert!(sim.nationals[0].conserves_goods(), "seed {seed} at tick {}", sim.tick);
+            }
+        }
+    }
+
+    #[test]
+    fn baskets_settle_before_the_clearing_house() {
+        // The bakery in the North buys Grain in baskets with Fuel, in the same market where the
+        // clearing house buys the Grain for the South. The cancelled baskets trade the market
+        // again and, with frictions, change the fills of the clearing house.
+        let source = r#"
+            [simulation]
+            ticks = 20
+
+            [[goods]]
+            name = "Grain"
+
+            [[goods]]
+            name = "Fuel"
+
+            [[goods]]
+            name = "Bread"
+
+            [[regions]]
+            name = "North"
+
+            [[regions]]
+            name = "South"
+
+            [[markets]]
+            kind = "test"
+            region = "North"
+            good = "Grain"
+            price = 2.0
+            friction = 0.5
+            randomized = true
+
+            [[markets]]
+            kind = "test"
+            region = "North"
+            good = "Fuel"
+            price = 3.0
+            friction = 0.5
+            randomized = true
+
+            [[markets]]
+            kind = "test"
+            region = "North"
+            good = "Bread"
+            price = 6.0
+
+            [[markets]]
+            kind = "test"
+            region = "South"
+            good = "Grain"
+            price = 3.0
+
+            [[nationals]]
+            good = "Grain"
+            price = 2.5
+
+            [[entities]]
+            kind = "rgo"
+            name = "Farm"
+            region = "North"
+            good = "Grain"
+            quantity = 1000
+            target_quantity = 200
+            max_production_rate = 500
+            fixed_cost = 10.0
+            money_balance = 10000.0
+
+            [[entities]]
+            kind = "rgo"
+            name = "Mine"
+            region = "North"
+            good = "Fuel"
+            quantity = 1000
+            target_quantity = 200
+            max_production_rate = 500
+            fixed_cost = 10.0
+            money_balance = 10000.0
+
+            [[entities]]
+            kind = "recipe"
+            name = "Bakery"
+            region = "North"
+            inputs = { Grain = 20, Fuel = 10 }
+            outputs = { Bread = 30 }
+            target_runs = 10
+            stock_runs = 20
+            fixed_cost = 10.0
+            money_balance = 10000.0
+
+            [[entities]]
+            kind = "pop"
+            name = "Pop North"
+            region = "North"
+            money_balance = 10000.0
+
+            [[entities.goods]]
+            good = "Bread"
+            inventory = 0
+            desired = 600
+            consumed = 300
+
+            [[entities]]
+            kind = "pop"
+            name = "Pop South"
+            region = "South"
+            money_balance = 10000.0
+
+            [[entities.goods]]
+            good = "Grain"
+            inventory = 0
+            desired = 600
+            consumed = 300
+        "#;
+        let mut traded = 0;
+        for seed in 0..8 {
+            let source = source.replace("ticks = 20\n", &format!("ticks = 20\nseed = {seed}\n"));
+            let mut sim = Scenario::from_toml(&source).unwrap().build().unwrap();
+            for _ in 0..20 {
+                sim.step();
+                assert!(sim.nationals[0].conserves_goods(), "seed {seed} at tick {}", sim.tick);
+                traded += sim.nationals[0].traded();
+            }
+        }
+        assert!(traded > 0);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::{GoodUid, Market, Price, Quantity};
+
+// What a pop consumes its goods for. A need is satisfied by any of its goods, the substitutes,
+// and is met for a tick when the stocks of the substitutes together cover the consumption of a
+// tick: they are consumed in the order of the need. The needs are bought in the order of their
+// tiers and a need of a tier that can't be stocked up blocks the spending on the higher tiers.
+// Every good of the pop that isn't in a need is a life need of its own, so a pop without needs
+// buys and consumes its goods one by one in priority order.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeedTier {
+    #[default]
+    Life,
+    Everyday,
+    Luxury,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Need {
+    pub name: String,
+    pub tier: NeedTier,
+    // Substitutes, the first ones are consumed first
+    pub goods: Vec<GoodUid>,
+    // The goods are grades from the best, the best one affordable is bought instead of the
+    // cheapest, see GoodRegistry::grades
+    #[serde(default)]
+    pub by_quality: bool,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Needs(Vec<Need>);
+
+impl Needs {
+    pub fn new(needs: Vec<Need>) -> Needs {
+        Needs(needs)
+    }
+
+    // Every need of the pop of `goods`, in tier order and then in the order given. The goods
+    // not in a need follow the life needs given in their priority order.
+    pub fn resolve(&self, goods: &[GoodUid]) -> Vec<Need> {
+        let mut needs = self.0.clone();
+        // Stable, the order within a tier is kept
+        needs.sort_by_key(|x| x.tier);
+        let alone = goods.iter()
+            .filter(|good| !self.0.iter().any(|x| x.goods.contains(good)))
+            .map(|good| Need { name: format!("good {good}"), tier: NeedTier::Life, goods: vec![*good], by_quality: false });
+        let life = needs.iter().take_while(|x| x.tier == NeedTier::Life).count();
+        needs.splice(life..life, alone);
+        needs
+    }
+}
+
+impl Need {
+    // Ticks of consumption covered by the stocks of all the substitutes
+    pub fn coverage(&self, inventory: &HashMap<GoodUid, Quantity>, per_tick: impl Fn(GoodUid) -> Quantity) -> f64 {
+        self.goods.iter()
+            .filter(|good| per_tick(**good) > 0)
+            .map(|good| inventory[good] as f64 / per_tick(*good) as f64)
+            .sum()
+    }
+
+    // Consumes a tick of the need if the stocks cover it, nothing otherwise. Returns the
+    // fraction of the tick covered, 1 when the need is met.
+    pub fn consume(&self, inventory: &mut HashMap<GoodUid, Quantity>, per_tick: impl Fn(GoodUid) -> Quantity) -> f64 {
+        if self.goods.iter().all(|good| per_tick(*good) == 0) {
+            return 1.;
+        }
+        let coverage = self.coverage(inventory, &per_tick);
+        if coverage < 1. {
+            return coverage;
+        }
+        let mut missing = 1.;
+        for good in self.goods.iter() {
+            let per_tick = per_tick(*good);
+            if per_tick == 0 || missing <= 0. {
+                continue;
+            }
+            let stock = inventory.get_mut(good).unwrap();
+            let consumed = ((per_tick as f64 * missing).ceil() as Quantity).min(*stock);
+            *stock -= consumed;
+            missing -= consumed as f64 / per_tick as f64;
+        }
+        1.
+    }
+
+    // The substitute costing the least for a tick of consumption at the current prices, the
+    // first one on a tie
+    pub fn cheapest(&self, markets: &[Box<dyn Market>], per_tick: impl Fn(GoodUid) -> Quantity) -> GoodUid {
+        let cost = |good: GoodUid| -> Price {
+            markets.iter().find(|x| x.good_uid() == good)
+                .map_or(Price::INFINITY, |x| x.cost_of(per_tick(good)))
+        };
+        let mut cheapest = self.goods[0];
+        for good in self.goods.iter().skip(1) {
+            if cost(*good) < cost(cheapest) {
+                cheapest = *good;
+            }
+        }
+        cheapest
+    }
+
+    // The substitute to buy with `money`: the first one whose tick of consumption it can pay for
+    // a need by quality, the cheapest otherwise
+    pub fn choose(&self, markets: &[Box<dyn Market>], per_tick: impl Fn(GoodUid) -> Quantity, money: f64) -> GoodUid {
+        if self.by_quality {
+            let affordable = self.goods.iter().find(|good| {
+                markets.iter().find(|x| x.good_uid() == **good).is_some_and(|x| x.cost_of(per_tick(**good)) <= money)
+            });
+            if let Some(good) = affordable {
+                return *good;
+            }
+        }
+        self.cheapest(markets, per_tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestMarket;
+
+    #[test]
+    fn substitutes_together_meet_a_need() {
+        let food = Need { name: "food".to_owned(), tier: NeedTier::Life, goods: vec![0, 1], by_quality: false };
+        let per_tick = |good: GoodUid| [10, 5][good];
+        let mut inventory = HashMap::from([(0, 4), (1, 2)]);
+        // 0.4 + 0.4 of a tick, nothing is consumed
+        assert!((food.consume(&mut inventory, per_tick) - 0.8).abs() < 1e-9);
+        assert_eq!(inventory, HashMap::from([(0, 4), (1, 2)]));
+        // The first good covers 0.6 of the tick, the second the rest
+        *inventory.get_mut(&0).unwrap() = 6;
+        assert_eq!(food.consume(&mut inventory, per_tick), 1.);
+        assert_eq!(inventory, HashMap::from([(0, 0), (1, 0)]));
+    }
+
+    #[test]
+    fn goods_without_a_need_are_life_needs() {
+        let needs = Needs::new(vec![
+            Need { name: "fun".to_owned(), tier: NeedTier::Luxury, goods: vec![3], by_quality: false },
+            Need { name: "food".to_owned(), tier: NeedTier::Life, goods: vec![0, 1], by_quality: false },
+        ]);
+        let goods: Vec<Vec<GoodUid>> = needs.resolve(&[0, 1, 2, 3]).into_iter().map(|x| x.goods).collect();
+        assert_eq!(goods, vec![vec![0, 1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn the_best_affordable_grade_is_bought() {
+        let bread = Need { name: "bread".to_owned(), tier: NeedTier::Life, goods: vec![2, 1, 0], by_quality: true };
+        let markets: Vec<Box<dyn Market>> = vec![
+            Box::new(TestMarket::new(0, 1, 1.)),
+            Box::new(TestMarket::new(1, 1, 2.)),
+            Box::new(TestMarket::new(2, 1, 4.)),
+        ];
+        let per_tick = |_| 10;
+        assert_eq!(bread.choose(&markets, per_tick, 50.), 2);
+        assert_eq!(bread.choose(&markets, per_tick, 30.), 1);
+        // Nothing affordable, the cheapest
+        assert_eq!(bread.choose(&markets, per_tick, 5.), 0);
+        assert_eq!(Need { by_quality: false, ..bread }.choose(&markets, per_tick, 50.), 0);
+    }
+}
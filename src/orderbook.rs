@@ -0,0 +1,303 @@
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use crate::freeze::RecordedOrder;
+use crate::goods;
+use crate::rng::OrderIds;
+use crate::market::{unretrieved, validate_order, MarketError, OrderBatches, OrderError, UnretrievedOrders};
+use crate::stats::MarketStats;
+use crate::{EntityId, GoodUid, Market, OrderIndex, OrderInfo, OrderResult, OrderType, Owner, Price, Quantity, TradeReport};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LimitOrder {
+    info: OrderInfo,
+    // Max price for a buyer, min price for a seller
+    limit_price: Price,
+}
+
+// Market with a limit price for every order. The orders are matched by price priority (best bid
+// with best ask, prestige breaks the ties) and everything is traded at the single clearing price
+// found at the intersection of the supply and demand curves.
+// Orders registered without a limit are market orders and accept any price.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderBookMarket {
+    good_uid: GoodUid,
+    unit_scale: Quantity,
+    #[serde(default = "goods::single_lot")]
+    lot_size: Quantity,
+    // Clearing price of the last tick with trades
+    price_per_unit: Price,
+    buy_orders: Vec<LimitOrder>,
+    sell_orders: Vec<LimitOrder>,
+    order_ids: OrderIds,
+    #[serde(default)]
+    stats: MarketStats,
+    #[serde(default)]
+    batches: OrderBatches,
+    #[serde(skip)]
+    index: OrderIndex,
+}
+
+impl OrderBookMarket {
+    pub fn new(good_uid: GoodUid, unit_scale: Quantity, lot_size: Quantity, initial_price: Price) -> OrderBookMarket {
+        OrderBookMarket {
+            good_uid,
+            unit_scale,
+            lot_size,
+            price_per_unit: initial_price,
+            buy_orders: vec![],
+            sell_orders: vec![],
+            order_ids: OrderIds::new(good_uid),
+            stats: MarketStats::default(),
+            batches: OrderBatches::default(),
+            index: OrderIndex::default(),
+        }
+    }
+
+    fn push_order(&mut self, otype: OrderType, info: OrderInfo, limit_price: Price) {
+        let orders = match otype {
+            OrderType::Buy => &mut self.buy_orders,
+            OrderType::Sell => &mut self.sell_orders,
+        };
+        self.index.insert(info.uuid, otype, orders.len());
+        orders.push(LimitOrder { info, limit_price });
+    }
+
+    fn order_mut(&mut self, uuid: &Uuid) -> Option<(OrderType, &mut OrderInfo)> {
+        let (otype, i) = self.index.get(uuid)?;
+        let order = match otype {
+            OrderType::Buy => &mut self.buy_orders[i],
+            OrderType::Sell => &mut self.sell_orders[i],
+        };
+        Some((otype, &mut order.info))
+    }
+
+    fn clearing_price(&self, lower: Price, upper: Price) -> Price {
+        // Every price in [lower, upper] clears the book, take the nearest to the previous one
+        // so that the price moves only when supply and demand force it to.
+        if lower > upper {
+            return (lower + upper) / 2.;
+        }
+        self.price_per_unit.clamp(lower, upper)
+    }
+}
+
+#[typetag::serde]
+impl Market for OrderBookMarket {
+    fn good_uid(&self) -> GoodUid {
+        self.good_uid
+    }
+
+    fn price_per_unit(&self) -> Price {
+        self.price_per_unit
+    }
+
+    fn unit_scale(&self) -> Quantity {
+        self.unit_scale
+    }
+
+    fn lot_size(&self) -> Quantity {
+        self.lot_size
+    }
+
+    fn register_order(&mut self, owner: Owner, otype: OrderType, quantity: Quantity, prestige: f64) -> Result<Uuid, OrderError> {
+        let limit_price = match otype {
+            OrderType::Buy => Price::INFINITY,
+            OrderType::Sell => 0.,
+        };
+        self.register_limit_order(owner, otype, quantity, prestige, limit_price)
+    }
+
+    fn register_limit_order(&mut self, owner: Owner, otype: OrderType, quantity: Quantity, prestige: f64, limit_price: Price) -> Result<Uuid, OrderError> {
+        validate_order(quantity, Some(limit_price))?;
+        let uuid = self.order_ids.next_id();
+        // Orders of whole lots match in whole lots
+        self.push_order(otype, OrderInfo::in_lots(uuid, owner, quantity, prestige, self.lot_size), limit_price);
+        Ok(uuid)
+    }
+
+    fn order_batches(&mut self) -> &mut OrderBatches {
+        &mut self.batches
+    }
+
+    fn run_trade(&mut self) -> Result<Quantity, MarketError> {
+        for bo in self.buy_orders.iter_mut().chain(self.sell_orders.iter_mut()) {
+            bo.info.traded_quantity = 0;
+            bo.info.retrieved = false;
+        }
+        // Price priority, then prestige priority. The sort is stable so the equal orders
+        // are matched in arrival order.
+        self.buy_orders.sort_by(|a, b| b.limit_price.total_cmp(&a.limit_price)
+            .then(b.info.prestige.total_cmp(&a.info.prestige)));
+        self.sell_orders.sort_by(|a, b| a.limit_price.total_cmp(&b.limit_price)
+            .then(b.info.prestige.total_cmp(&a.info.prestige)));
+        self.index.rebuild(self.buy_orders.iter().map(|x| &x.info), self.sell_orders.iter().map(|x| &x.info));
+        let mut total_traded: Quantity = 0;
+        // Limit prices of the last matched couple of orders
+        let mut marginal: Option<(Price, Price)> = None;
+        let (mut i, mut j) = (0, 0);
+        while i < self.buy_orders.len() && j < self.sell_orders.len() {
+            let bo = &self.buy_orders[i];
+            let so = &self.sell_orders[j];
+            if bo.limit_price < so.limit_price {
+                // The curves crossed
+                break;
+            }
+            let traded = bo.info.missing_quantity().min(so.info.missing_quantity());
+            if traded > 0 {
+                marginal = Some((bo.limit_price, so.limit_price));
+            }
+            self.buy_orders[i].info.traded_quantity += traded;
+            self.sell_orders[j].info.traded_quantity += traded;
+            total_traded += traded;
+            if self.buy_orders[i].info.missing_quantity() == 0 { i += 1; }
+            if self.sell_orders[j].info.missing_quantity() == 0 { j += 1; }
+        }
+        if let Some((bid, ask)) = marginal {
+            // The price must satisfy the marginal couple and must not attract the first
+            // limit orders that did not trade. Market orders don't bound the price.
+            let mut lower = ask;
+            let mut upper = bid;
+            if let Some(bo) = self.buy_orders.get(i).filter(|x| x.limit_price.is_finite()) {
+                if bo.limit_price <= upper { lower = lower.max(bo.limit_price); }
+            }
+            if let Some(so) = self.sell_orders.get(j).filter(|x| x.limit_price > 0.) {
+                if so.limit_price >= lower { upper = upper.min(so.limit_price); }
+            }
+            self.price_per_unit = self.clearing_price(lower, upper);
+        }
+        self.index.pair(self.buy_orders.iter().map(|x| &x.info), self.sell_orders.iter().map(|x| &x.info));
+        Ok(total_traded)
+    }
+
+    fn peek_order_result(&self, uuid: &Uuid) -> Option<OrderResult> {
+        let (otype, i) = self.index.get(uuid)?;
+        let x = match otype {
+            OrderType::Buy => &self.buy_orders[i].info,
+            OrderType::Sell => &self.sell_orders[i].info,
+        };
+        Some(OrderResult { counterparties: self.index.counterparties(uuid), ..x.result(otype, self.cost_of(x.traded_quantity)) })
+    }
+
+    fn retrieve_order_result(&mut self, uuid: &Uuid) -> Option<OrderResult> {
+        let (_, x) = self.order_mut(uuid)?;
+        if x.retrieved {
+            return None;
+        }
+        x.retrieved = true;
+        self.peek_order_result(uuid)
+    }
+
+    fn take_entity_results(&mut self) -> Vec<(EntityId, OrderResult)> {
+        let index = &mut self.index;
+        let unit_scale = self.unit_scale;
+        let price = self.price_per_unit;
+        let mut results = |otype: OrderType, orders: &mut [LimitOrder]| -> Vec<(EntityId, OrderResult)> {
+            orders.iter_mut().map(|x| &mut x.info).filter(|x| !x.retrieved).filter_map(|x| match x.owner {
+                Owner::Entity(id) => {
+                    x.retrieved = true;
+                    Some((id, OrderResult {
+                        counterparties: index.take_counterparties(&x.uuid),
+                        ..x.result(otype, goods::to_units(x.traded_quantity, unit_scale) * price)
+                    }))
+                }
+                _ => None,
+            }).collect()
+        };
+        let buy = results(OrderType::Buy, &mut self.buy_orders);
+        buy.into_iter().chain(results(OrderType::Sell, &mut self.sell_orders)).collect()
+    }
+
+    fn cancel_order(&mut self, uuid: &Uuid) -> bool {
+        let Some((_, x)) = self.order_mut(uuid) else {
+            return false;
+        };
+        x.required_quantity = 0;
+        x.traded_quantity = 0;
+        true
+    }
+
+    fn halt(&mut self) {
+        for x in self.buy_orders.iter_mut().chain(self.sell_orders.iter_mut()) {
+            x.info.required_quantity = 0;
+            x.info.traded_quantity = 0;
+        }
+        self.index.rebuild(self.buy_orders.iter().map(|x| &x.info), self.sell_orders.iter().map(|x| &x.info));
+    }
+
+    fn trade_report(&self) -> TradeReport {
+        TradeReport::from_orders(self.buy_orders.iter().map(|x| &x.info), self.sell_orders.iter().map(|x| &x.info))
+    }
+
+    fn registered_orders(&self) -> Vec<(Owner, RecordedOrder)> {
+        let orders = |otype: OrderType, orders: &[LimitOrder]| -> Vec<(Owner, RecordedOrder)> {
+            orders.iter().map(|x| (x.info.owner, RecordedOrder {
+                good_uid: self.good_uid,
+                otype,
+                quantity: x.info.required_quantity,
+                prestige: x.info.prestige,
+                limit_price: Some(x.limit_price),
+            })).collect()
+        };
+        [orders(OrderType::Buy, &self.buy_orders), orders(OrderType::Sell, &self.sell_orders)].concat()
+    }
+
+    fn stats(&self) -> &MarketStats {
+        &self.stats
+    }
+
+    fn clear_state(&mut self) -> Result<(), UnretrievedOrders> {
+        let report = self.trade_report();
+        self.stats.record(self.price_per_unit, &report);
+        let retrieved = unretrieved(self.buy_orders.iter().chain(self.sell_orders.iter()).map(|x| &x.info));
+        self.buy_orders.clear();
+        self.sell_orders.clear();
+        self.batches.clear();
+        self.index.clear();
+        retrieved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(id: usize) -> Owner {
+        Owner::Entity(EntityId(id))
+    }
+
+    #[test]
+    fn crossing_orders_trade_at_the_clearing_price() {
+        let mut market = OrderBookMarket::new(0, 1, 1, 1.);
+        let high_bid = market.register_limit_order(entity(0), OrderType::Buy, 10, 0., 5.).unwrap();
+        let low_bid = market.register_limit_order(entity(1), OrderType::Buy, 10, 0., 3.).unwrap();
+        let low_ask = market.register_limit_order(entity(2), OrderType::Sell, 10, 0., 2.).unwrap();
+        market.register_limit_order(entity(3), OrderType::Sell, 10, 0., 4.).unwrap();
+        assert_eq!(market.run_trade(), Ok(10));
+        // Any price in [3, 4] keeps the bid at 3 and the ask at 4 out, the nearest to the last one
+        assert_eq!(market.price_per_unit(), 3.);
+        assert_eq!(market.peek_order_result(&high_bid).unwrap().total_cost, 30.);
+        assert_eq!(market.peek_order_result(&low_bid).unwrap().traded_quantity, 0);
+        assert_eq!(market.peek_order_result(&low_ask).unwrap().traded_quantity, 10);
+    }
+
+    #[test]
+    fn an_ask_above_every_bid_does_not_trade() {
+        let mut market = OrderBookMarket::new(0, 1, 1, 2.5);
+        market.register_limit_order(entity(0), OrderType::Buy, 10, 0., 2.).unwrap();
+        let ask = market.register_limit_order(entity(1), OrderType::Sell, 10, 0., 3.).unwrap();
+        assert_eq!(market.run_trade(), Ok(0));
+        assert_eq!(market.peek_order_result(&ask).unwrap().traded_quantity, 0);
+        assert_eq!(market.price_per_unit(), 2.5);
+    }
+
+    #[test]
+    fn the_higher_bid_is_filled_first() {
+        let mut market = OrderBookMarket::new(0, 1, 1, 1.);
+        let low = market.register_limit_order(entity(0), OrderType::Buy, 10, 0., 2.).unwrap();
+        let high = market.register_limit_order(entity(1), OrderType::Buy, 10, 0., 3.).unwrap();
+        market.register_limit_order(entity(2), OrderType::Sell, 15, 0., 1.).unwrap();
+        assert_eq!(market.run_trade(), Ok(15));
+        assert_eq!(market.peek_order_result(&high).unwrap().traded_quantity, 10);
+        assert_eq!(market.peek_order_result(&low).unwrap().traded_quantity, 5);
+    }
+}
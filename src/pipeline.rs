@@ -0,0 +1,33 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use crate::Quantity;
+
+// Work in progress of a producer. What is started in a tick is ready `lead_time` ticks later,
+// with no lead time it is ready in the same tick. The producers decide what the quantities are,
+// output base units or runs of a recipe.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Pipeline {
+    lead_time: u64,
+    // Started in the last ticks, the oldest first
+    slots: VecDeque<Quantity>,
+}
+
+impl Pipeline {
+    pub fn new(lead_time: u64) -> Pipeline {
+        Pipeline { lead_time, slots: VecDeque::new() }
+    }
+
+    // Start `quantity` and return what is ready in this tick. Called once every tick, also
+    // when nothing is started.
+    pub fn advance(&mut self, quantity: Quantity) -> Quantity {
+        self.slots.push_back(quantity);
+        match self.slots.len() as u64 > self.lead_time {
+            true => self.slots.pop_front().unwrap(),
+            false => 0,
+        }
+    }
+
+    pub fn in_progress(&self) -> Quantity {
+        self.slots.iter().sum()
+    }
+}
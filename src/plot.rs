@@ -0,0 +1,207 @@
+use std::error::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::full_palette::PURPLE;
+use crate::recorder::Recorder;
+
+// Plots of a run, made from the recorded series so that they can be drawn again from an
+// exported CSV without running the simulation. A PlotBuilder draws a chart of the series it
+// is given, in PNG or SVG after the extension of the output path.
+
+const COLORS: [RGBColor; 8] = [RED, YELLOW, BLUE, PURPLE, GREEN, CYAN, MAGENTA, BLACK];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlotFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl PlotFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PlotFormat::Png => "png",
+            PlotFormat::Svg => "svg",
+        }
+    }
+
+    fn of(path: &Path) -> Result<PlotFormat, String> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(extension) => extension.parse(),
+            None => Ok(PlotFormat::Png),
+        }
+    }
+}
+
+impl FromStr for PlotFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PlotFormat, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(PlotFormat::Png),
+            "svg" => Ok(PlotFormat::Svg),
+            _ => Err(format!("Unknown plot format {s}, expected png or svg")),
+        }
+    }
+}
+
+pub struct PlotBuilder<'a> {
+    recorder: &'a Recorder,
+    // Name of the series in the recorder and its label in the legend
+    series: Vec<(String, String)>,
+    title: String,
+    x_label: String,
+    y_label: String,
+    // From 0 to the largest value when not given
+    y_range: Option<Range<f64>>,
+    burn_in: u64,
+    size: (u32, u32),
+}
+
+impl<'a> PlotBuilder<'a> {
+    pub fn new(recorder: &'a Recorder) -> PlotBuilder<'a> {
+        PlotBuilder {
+            recorder,
+            series: vec![],
+            title: String::new(),
+            x_label: String::new(),
+            y_label: String::new(),
+            y_range: None,
+            burn_in: 0,
+            size: (800, 600),
+        }
+    }
+
+    // A series by name, or all the series starting with a prefix when it ends with `*`. The
+    // label is the name of the series.
+    pub fn series(mut self, pattern: &str) -> PlotBuilder<'a> {
+        let recorder = self.recorder;
+        let selected = recorder.names().iter().filter(|x| match pattern.strip_suffix('*') {
+            Some(prefix) => x.starts_with(prefix),
+            None => *x == pattern,
+        });
+        self.series.extend(selected.map(|x| (x.clone(), x.clone())));
+        self
+    }
+
+    // The series for which `select` gives a label
+    pub fn select(mut self, select: impl Fn(&str) -> Option<String>) -> PlotBuilder<'a> {
+        for name in self.recorder.names() {
+            if let Some(label) = select(name) {
+                self.series.push((name.clone(), label));
+            }
+        }
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> PlotBuilder<'a> {
+        self.title = title.to_owned();
+        self
+    }
+
+    pub fn axes(mut self, x_label: &str, y_label: &str) -> PlotBuilder<'a> {
+        self.x_label = x_label.to_owned();
+        self.y_label = y_label.to_owned();
+        self
+    }
+
+    pub fn y_range(mut self, range: Range<f64>) -> PlotBuilder<'a> {
+        self.y_range = Some(range);
+        self
+    }
+
+    // The ticks before `burn_in` are not drawn
+    pub fn burn_in(mut self, burn_in: u64) -> PlotBuilder<'a> {
+        self.burn_in = burn_in;
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> PlotBuilder<'a> {
+        self.size = (width, height);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+
+    // Draw the chart to `path`, an SVG when its extension is svg and a PNG otherwise
+    pub fn draw(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        match PlotFormat::of(path)? {
+            PlotFormat::Png => self.draw_on(BitMapBackend::new(path, self.size).into_drawing_area()),
+            PlotFormat::Svg => self.draw_on(SVGBackend::new(path, self.size).into_drawing_area()),
+        }
+    }
+
+    fn draw_on<DB: DrawingBackend>(&self, root: DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+    where DB::ErrorType: 'static {
+        let recorder = self.recorder;
+        let start = recorder.ticks().iter().position(|x| *x >= self.burn_in).unwrap_or(recorder.ticks().len());
+        let ticks = &recorder.ticks()[start..];
+        let lines: Vec<(&str, &[f64])> = self.series.iter()
+            .filter_map(|(name, label)| Some((label.as_str(), &recorder.series(name)?[start..])))
+            .collect();
+        root.fill(&WHITE)?;
+        let y_range = self.y_range.clone().unwrap_or_else(|| {
+            let max = lines.iter().flat_map(|x| x.1.iter()).copied().filter(|x| !x.is_nan())
+                .max_by(|a, b| a.total_cmp(b)).unwrap_or(0.0);
+            0.0..max
+        });
+        let first_tick = ticks.first().copied().unwrap_or(0) as f64;
+        let last_tick = ticks.last().copied().unwrap_or(0) as f64;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(5)
+            .caption(&self.title, ("sans-serif", 20).into_font())
+            .set_left_and_bottom_label_area_size(40)
+            .build_cartesian_2d(first_tick..last_tick + 1., y_range)?;
+        chart.configure_mesh().x_desc(&self.x_label).y_desc(&self.y_label).draw()?;
+        for (i, (label, series)) in lines.into_iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            chart
+                .draw_series(LineSeries::new(
+                    ticks.iter().map(|x| *x as f64).zip(series.iter().copied()),
+                    ShapeStyle::from(color).stroke_width(2),
+                ))?
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart.configure_series_labels()
+            .position(SeriesLabelPosition::LowerRight)
+            .border_style(BLACK)
+            .draw()?;
+        root.present()?;
+        Ok(())
+    }
+}
+
+// The path of a plot named `name` in `out_dir`
+pub fn plot_path(out_dir: &Path, name: &str, format: PlotFormat) -> PathBuf {
+    out_dir.join(format!("{name}.{}", format.extension()))
+}
+
+// Money and inventory of every entity, written as out_money and out_inventory
+pub fn plot_run(recorder: &Recorder, out_dir: &Path, burn_in: u64, format: PlotFormat) -> Result<(), Box<dyn Error>> {
+    // Entity series are `{name}/money` and `{name}/inventory/{good}`
+    PlotBuilder::new(recorder)
+        .select(|name| match name.split('/').collect::<Vec<_>>()[..] {
+            ["market" | "route", ..] => None,
+            [entity, "money"] => Some(entity.to_owned()),
+            _ => None,
+        })
+        .title("Money Balance")
+        .burn_in(burn_in)
+        .draw(&plot_path(out_dir, "out_money", format))?;
+    PlotBuilder::new(recorder)
+        .select(|name| match name.split('/').collect::<Vec<_>>()[..] {
+            ["market" | "route", ..] => None,
+            [entity, "inventory", good] => Some(format!("{entity} {good}")),
+            _ => None,
+        })
+        .title("Goods Inventory")
+        .burn_in(burn_in)
+        .draw(&plot_path(out_dir, "out_inventory", format))?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use crate::region::RegionId;
+use crate::waste::WasteKind;
+
+// Pollution of the regions. The waste of the production adds to the stock of the region of the
+// producer and a fraction of the stock is absorbed every tick. The stock lowers the production
+// of the RGOs and the standard of living of the pops of the region. With a tax the producers
+// pay for every unit of pollution they cause, the government collects it.
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PollutionPolicy {
+    // Pollution per unit of byproduct emitted and per unit of input lost, spoiled or dumped
+    pub emitted: f64,
+    pub lost: f64,
+    // Fraction of the stock absorbed every tick
+    pub decay: f64,
+    // Fraction of the RGO production lost per unit of pollution
+    pub rgo_damage: f64,
+    // Standard of living lost by a pop every tick per unit of pollution
+    pub sol_damage: f64,
+    // Paid by the producer per unit of pollution
+    pub tax: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PollutionDamage {
+    // Fraction of the production lost
+    pub productivity: f64,
+    pub standard_of_living: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Pollution {
+    pub policy: PollutionPolicy,
+    // Stock of every region
+    stocks: Vec<f64>,
+}
+
+impl Pollution {
+    pub fn new(policy: PollutionPolicy, regions: usize) -> Pollution {
+        Pollution { policy, stocks: vec![0.; regions] }
+    }
+
+    pub fn stock(&self, region: RegionId) -> f64 {
+        self.stocks[region]
+    }
+
+    pub fn damage(&self, region: RegionId) -> PollutionDamage {
+        let stock = self.stocks[region];
+        PollutionDamage {
+            productivity: (stock * self.policy.rgo_damage).clamp(0., 1.),
+            standard_of_living: stock * self.policy.sol_damage,
+        }
+    }
+
+    // Add the pollution of `units` units of waste, returns the pollution added
+    pub fn emit(&mut self, region: RegionId, kind: WasteKind, units: f64) -> f64 {
+        let pollution = units * match kind {
+            WasteKind::Emitted => self.policy.emitted,
+            WasteKind::Lost | WasteKind::Spoiled | WasteKind::Dumped => self.policy.lost,
+        };
+        self.stocks[region] += pollution;
+        pollution
+    }
+
+    // The tax due for `pollution`
+    pub fn tax(&self, pollution: f64) -> f64 {
+        pollution * self.policy.tax
+    }
+
+    pub fn decay(&mut self) {
+        for stock in self.stocks.iter_mut() {
+            *stock *= 1. - self.policy.decay.clamp(0., 1.);
+        }
+    }
+}
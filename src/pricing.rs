@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use crate::{Price, Quantity};
+
+// Prices a producer sets on its orders instead of taking the price of the market. It asks for
+// its output the unit cost plus a margin: the margin grows by `step` when the whole offer sold
+// in the tick and shrinks by `step` when nothing sold, within its bounds. It bids for its input
+// the price of the market plus a premium, growing by `step` when the bid didn't fill and
+// shrinking when it filled, within `max_premium` above or below the price. The limits are
+// honoured by the OrderBookMarket, the markets with a single price ignore them, see
+// Market::register_limit_order.
+
+// Cost of a unit of a production of `units` units
+pub fn unit_cost(variable_cost: f64, fixed_cost: f64, units: f64) -> f64 {
+    match units > 0. {
+        true => variable_cost + fixed_cost / units,
+        false => variable_cost,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingStrategy {
+    // Of the ask over the unit cost
+    margin: f64,
+    min_margin: f64,
+    max_margin: f64,
+    // Of the bid over the price of the market
+    premium: f64,
+    max_premium: f64,
+    // Change of the margin and of the premium in a tick
+    step: f64,
+    // Of the orders of the tick, in base units
+    offered: Quantity,
+    sold: Quantity,
+    bid: Quantity,
+    bought: Quantity,
+}
+
+impl PricingStrategy {
+    pub fn new(margin: f64, min_margin: f64, max_margin: f64, max_premium: f64, step: f64) -> PricingStrategy {
+        PricingStrategy {
+            margin: margin.clamp(min_margin, max_margin),
+            min_margin,
+            max_margin,
+            premium: 0.,
+            max_premium: max_premium.abs(),
+            step,
+            offered: 0,
+            sold: 0,
+            bid: 0,
+            bought: 0,
+        }
+    }
+
+    pub fn margin(&self) -> f64 {
+        self.margin
+    }
+
+    pub fn premium(&self) -> f64 {
+        self.premium
+    }
+
+    // Min price per unit of an offer of `quantity`, never below 0
+    pub fn ask(&mut self, unit_cost: Price, quantity: Quantity) -> Price {
+        self.offered += quantity;
+        (unit_cost * (1. + self.margin)).max(0.)
+    }
+
+    // Max price per unit of a bid of `quantity`
+    pub fn bid(&mut self, price: Price, quantity: Quantity) -> Price {
+        self.bid += quantity;
+        (price * (1. + self.premium)).max(0.)
+    }
+
+    pub fn add_sale(&mut self, quantity: Quantity) {
+        self.sold += quantity;
+    }
+
+    pub fn add_purchase(&mut self, quantity: Quantity) {
+        self.bought += quantity;
+    }
+
+    // After the settlement, the orders of the tick move the margin and the premium
+    pub fn adjust(&mut self) {
+        if self.offered > 0 {
+            if self.sold >= self.offered {
+                self.margin += self.step;
+            } else if self.sold == 0 {
+                self.margin -= self.step;
+            }
+            self.margin = self.margin.clamp(self.min_margin, self.max_margin);
+        }
+        if self.bid > 0 {
+            if self.bought < self.bid {
+                self.premium += self.step;
+            } else {
+                self.premium -= self.step;
+            }
+            self.premium = self.premium.clamp(-self.max_premium, self.max_premium);
+        }
+        self.offered = 0;
+        self.sold = 0;
+        self.bid = 0;
+        self.bought = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_margin_follows_the_sales_and_the_premium_the_purchases() {
+        let mut pricing = PricingStrategy::new(0.2, 0., 0.3, 0.1, 0.1);
+        assert_eq!(pricing.ask(10., 100), 12.);
+        pricing.add_sale(100);
+        pricing.adjust();
+        assert!((pricing.margin() - 0.3).abs() < 1e-12);
+        // At the bound it stops growing
+        pricing.ask(10., 100);
+        pricing.add_sale(100);
+        pricing.adjust();
+        assert!((pricing.margin() - 0.3).abs() < 1e-12);
+        // A partial sale keeps the margin, an unsold offer cuts it
+        pricing.ask(10., 100);
+        pricing.add_sale(50);
+        pricing.adjust();
+        assert!((pricing.margin() - 0.3).abs() < 1e-12);
+        pricing.ask(10., 100);
+        pricing.adjust();
+        assert!((pricing.margin() - 0.2).abs() < 1e-12);
+        // Without orders nothing moves
+        pricing.adjust();
+        assert!((pricing.margin() - 0.2).abs() < 1e-12);
+        assert_eq!(pricing.bid(10., 100), 10.);
+        pricing.add_purchase(40);
+        pricing.adjust();
+        assert!((pricing.premium() - 0.1).abs() < 1e-12);
+        pricing.bid(10., 100);
+        pricing.add_purchase(100);
+        pricing.adjust();
+        assert!(pricing.premium().abs() < 1e-12);
+    }
+}
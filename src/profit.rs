@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use crate::Quantity;
+
+// Profitability of a producer over the last ticks. At the end of every window the producer is
+// told whether to grow or shrink its production: it shrinks when most of its output doesn't
+// sell or when it lost money, it grows when everything sold with a profit.
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct TickResult {
+    offered: Quantity,
+    sold: Quantity,
+    revenue: f64,
+    costs: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProducerState {
+    #[default]
+    Active,
+    // Mothballed: no production and no fixed costs, the stock is still sold
+    Dormant,
+    // Couldn't pay the fixed costs for a while, only sells what is left
+    Bankrupt,
+}
+
+// Ticks in a row a producer didn't have the money for its fixed costs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Solvency {
+    // Of them before going bankrupt
+    bankruptcy_after: u32,
+    short_streak: u32,
+}
+
+impl Default for Solvency {
+    fn default() -> Self {
+        Solvency::new(1)
+    }
+}
+
+impl Solvency {
+    pub fn new(bankruptcy_after: u32) -> Solvency {
+        Solvency { bankruptcy_after: bankruptcy_after.max(1), short_streak: 0 }
+    }
+
+    pub fn should_go_bankrupt(&self) -> bool {
+        self.short_streak >= self.bankruptcy_after
+    }
+
+    // At the start of a tick of production, whether the money doesn't cover the fixed costs
+    pub fn check(&mut self, money: f64, fixed_cost: f64) -> bool {
+        if money < fixed_cost {
+            self.short_streak += 1;
+        } else {
+            self.short_streak = 0;
+        }
+        money < fixed_cost
+    }
+
+    pub fn reset(&mut self) {
+        self.short_streak = 0;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfitTracker {
+    window: usize,
+    // Relative change of the production at the end of a window
+    scale_step: f64,
+    // Below this fraction of the offered output sold the production shrinks
+    min_sales_ratio: f64,
+    // Consecutive ticks without covering the fixed costs before going dormant
+    dormancy_after: u32,
+    current: TickResult,
+    history: Vec<TickResult>,
+    loss_streak: u32,
+    #[serde(default)]
+    solvency: Solvency,
+    // Margin of the last full window
+    #[serde(default)]
+    last_margin: f64,
+}
+
+impl ProfitTracker {
+    pub fn new(window: usize, scale_step: f64, min_sales_ratio: f64, dormancy_after: u32, bankruptcy_after: u32) -> ProfitTracker {
+        ProfitTracker {
+            window,
+            scale_step,
+            min_sales_ratio,
+            dormancy_after,
+            current: TickResult::default(),
+            history: vec![],
+            loss_streak: 0,
+            solvency: Solvency::new(bankruptcy_after),
+            last_margin: 0.,
+        }
+    }
+
+    pub fn add_cost(&mut self, amount: f64) {
+        self.current.costs += amount;
+    }
+
+    pub fn add_offer(&mut self, quantity: Quantity) {
+        self.current.offered += quantity;
+    }
+
+    pub fn add_sale(&mut self, quantity: Quantity, revenue: f64) {
+        self.current.sold += quantity;
+        self.current.revenue += revenue;
+    }
+
+    // Sold fraction of the output offered in the current window, None if nothing was offered
+    pub fn sales_ratio(&self) -> Option<f64> {
+        let offered: Quantity = self.history.iter().map(|x| x.offered).sum();
+        let sold: Quantity = self.history.iter().map(|x| x.sold).sum();
+        (offered > 0).then(|| sold as f64 / offered as f64)
+    }
+
+    pub fn margin(&self) -> f64 {
+        self.history.iter().map(|x| x.revenue - x.costs).sum()
+    }
+
+    pub fn last_margin(&self) -> f64 {
+        self.last_margin
+    }
+
+    pub fn should_go_dormant(&self) -> bool {
+        self.dormancy_after > 0 && self.loss_streak >= self.dormancy_after
+    }
+
+    pub fn solvency(&mut self) -> &mut Solvency {
+        &mut self.solvency
+    }
+
+    // Close the tick and return the factor to apply to the production, 1 until the window is full
+    pub fn close_tick(&mut self, fixed_cost: f64) -> f64 {
+        let result = std::mem::take(&mut self.current);
+        if result.revenue < fixed_cost {
+            self.loss_streak += 1;
+        } else {
+            self.loss_streak = 0;
+        }
+        self.history.push(result);
+        if self.history.len() < self.window {
+            return 1.;
+        }
+        let sales_ratio = self.sales_ratio();
+        let margin = self.margin();
+        self.last_margin = margin;
+        self.history.clear();
+        match sales_ratio {
+            Some(x) if x < self.min_sales_ratio || margin < 0. => 1. - self.scale_step,
+            Some(x) if x >= 1. && margin > 0. => 1. + self.scale_step,
+            // Nothing offered, the stock is still below its target
+            _ => 1.,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.current = TickResult::default();
+        self.history.clear();
+        self.loss_streak = 0;
+        self.solvency.reset();
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::basket::{BasketBook, BasketLeg, BasketOrder};
+use crate::labor::Workforce;
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::pipeline::Pipeline;
+use crate::profit::ProducerState;
+use crate::trace::{Decision, DecisionLog};
+use crate::waste::{WasteProfile, WasteRecord};
+use crate::{goods, EcoEntity, EntityId, GoodUid, Market, MarketMetadata, OrderResult, OrderType, Owner, Quantity};
+
+// Production with many inputs and many outputs. A recipe describes a single run of the
+// production, the producer repeats it up to `target_runs` times every tick. All the quantities
+// are in base units of their good.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recipe {
+    // Consumed by a run
+    pub inputs: BTreeMap<GoodUid, Quantity>,
+    // Produced by a run
+    pub outputs: BTreeMap<GoodUid, Quantity>,
+    // Held and not consumed, a run needs this much of every capital good
+    pub capital: BTreeMap<GoodUid, Quantity>,
+    // Paid for every run besides the inputs and the labor
+    pub cost_per_run: f64,
+}
+
+impl Recipe {
+    // Runs allowed by the goods in `stock`
+    fn runs_allowed(&self, stock: &BTreeMap<GoodUid, Quantity>) -> u64 {
+        self.inputs.iter().chain(self.capital.iter())
+            .map(|(good_uid, required)| stock.get(good_uid).copied().unwrap_or(0) / (*required).max(1))
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipeMode {
+    // As many runs as the inputs allow
+    #[default]
+    Proportional,
+    // All the target runs or nothing
+    Complete,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecipeProducer {
+    recipe: Recipe,
+    mode: RecipeMode,
+    target_runs: u64,
+    // Inputs kept in stock, in runs
+    stock_runs: u64,
+    // Runs are done only in multiples of the batch, 0 for no batches
+    #[serde(default)]
+    batch_runs: u64,
+    fixed_cost: f64,
+    // Labor required per run
+    workforce: Workforce,
+    // Inputs lost and byproduct emitted per run
+    waste: WasteProfile,
+    // Runs being produced
+    #[serde(default)]
+    pipeline: Pipeline,
+    state: ProducerState,
+    inventory: BTreeMap<GoodUid, Quantity>,
+    // Tags the markets of the producer must have, see Region::eligible_markets
+    #[serde(default)]
+    pub(crate) market_tags: Vec<MarketMetadata>,
+    money_balance: f64,
+    money_flows: MoneyFlows,
+    #[serde(skip)]
+    decisions: DecisionLog,
+    prestige: f64,
+    // Money the orders of the tick may cost, the baskets are posted after the other orders
+    committed: f64,
+}
+
+impl RecipeProducer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        recipe: Recipe,
+        mode: RecipeMode,
+        target_runs: u64,
+        stock_runs: u64,
+        batch_runs: u64,
+        fixed_cost: f64,
+        workforce: Workforce,
+        waste: WasteProfile,
+        lead_time: u64,
+        inventory: BTreeMap<GoodUid, Quantity>,
+        money_balance: f64,
+        prestige: f64,
+    ) -> RecipeProducer {
+        // Every good of the recipe is in the inventory, also when there is none
+        let mut inventory = inventory;
+        for good_uid in recipe.inputs.keys().chain(recipe.outputs.keys()).chain(recipe.capital.keys()) {
+            inventory.entry(*good_uid).or_default();
+        }
+        RecipeProducer {
+            recipe,
+            mode,
+            target_runs,
+            stock_runs,
+            batch_runs,
+            fixed_cost,
+            workforce,
+            waste,
+            pipeline: Pipeline::new(lead_time),
+            state: ProducerState::Active,
+            inventory,
+            market_tags: vec![],
+            money_balance,
+            money_flows: MoneyFlows::default(),
+            decisions: DecisionLog::default(),
+            prestige,
+            committed: 0.,
+        }
+    }
+
+    fn stock(&self, good_uid: GoodUid) -> Quantity {
+        self.inventory.get(&good_uid).copied().unwrap_or(0)
+    }
+
+    // The outputs of `runs` completed runs
+    fn complete(&mut self, runs: u64) {
+        for (good_uid, x) in self.recipe.outputs.iter() {
+            *self.inventory.entry(*good_uid).or_default() += ((x * runs) as f64 * self.waste.efficiency()) as Quantity;
+        }
+    }
+
+    // Missing quantity of every good to hold `runs` runs of `required`
+    fn missing(&self, required: &BTreeMap<GoodUid, Quantity>, runs: u64) -> Vec<(GoodUid, Quantity)> {
+        required.iter()
+            .map(|(good_uid, x)| (*good_uid, (x * runs).saturating_sub(self.stock(*good_uid))))
+            .filter(|x| x.1 > 0)
+            .collect()
+    }
+}
+
+#[typetag::serde]
+impl EcoEntity for RecipeProducer {
+    fn produce_and_consume(&mut self) -> f64 {
+        if self.state == ProducerState::Active && self.money_balance < self.fixed_cost {
+            self.decisions.record("go_bankrupt", None, vec![
+                ("money", self.money_balance),
+                ("fixed_cost", self.fixed_cost),
+            ], 0);
+            self.state = ProducerState::Bankrupt;
+        }
+        if self.state != ProducerState::Active {
+            self.workforce.end_production();
+            // What was started before still completes
+            let ready = self.pipeline.advance(0);
+            self.complete(ready);
+            return 0.;
+        }
+        let affordable = match self.recipe.cost_per_run {
+            x if x > 0. => ((self.money_balance - self.fixed_cost) / x).max(0.) as u64,
+            _ => u64::MAX,
+        };
+        let mut runs = self.target_runs.min(self.recipe.runs_allowed(&self.inventory)).min(affordable)
+            .min(self.workforce.max_production(1));
+        if self.mode == RecipeMode::Complete && runs < self.target_runs {
+            runs = 0;
+        }
+        // Below a batch the producer idles
+        if self.batch_runs > 0 {
+            runs -= runs % self.batch_runs;
+        }
+        self.decisions.record("produce", None, vec![
+            ("money", self.money_balance),
+            ("target_runs", self.target_runs as f64),
+            ("batch_runs", self.batch_runs as f64),
+            ("runs_allowed", self.recipe.runs_allowed(&self.inventory) as f64),
+            ("labor_available", self.workforce.available() as f64),
+        ], runs);
+        self.workforce.end_production();
+        for (good_uid, x) in self.recipe.inputs.iter() {
+            *self.inventory.entry(*good_uid).or_default() -= x * runs;
+            self.waste.lose(*good_uid, x * runs);
+        }
+        self.waste.emit(runs as f64);
+        let ready = self.pipeline.advance(runs);
+        self.complete(ready);
+        let variable_cost = runs as f64 * self.recipe.cost_per_run;
+        self.money_balance -= variable_cost + self.fixed_cost;
+        self.money_flows.record(FlowKind::VariableCost, -variable_cost);
+        self.money_flows.record(FlowKind::FixedCost, -self.fixed_cost);
+        0.
+    }
+
+    fn get_required_markets(&self) -> (Vec<GoodUid>, Vec<MarketMetadata>) {
+        let goods = self.recipe.inputs.keys().chain(self.recipe.outputs.keys()).chain(self.recipe.capital.keys())
+            .copied().chain(self.workforce.good_uid()).collect();
+        (goods, self.market_tags.clone())
+    }
+
+    fn post_orders_to_markets(&mut self, id: EntityId, markets: &mut [Box<dyn Market>]) {
+        // All the outputs are sold
+        for good_uid in self.recipe.outputs.keys() {
+            let stock = self.stock(*good_uid);
+            if stock == 0 {
+                continue;
+            }
+            let Some(market) = markets.iter_mut().find(|x| x.good_uid() == *good_uid) else {
+                continue;
+            };
+            self.decisions.record("sell", Some(*good_uid), vec![
+                ("stock", goods::to_units(stock, market.unit_scale())),
+            ], stock);
+            let _ = market.register_order(Owner::Entity(id), OrderType::Sell, stock, self.prestige);
+        }
+        if self.state != ProducerState::Active {
+            return;
+        }
+        let budget = self.money_balance - self.fixed_cost - self.target_runs as f64 * self.recipe.cost_per_run;
+        self.committed = self.workforce.hire(id, markets, self.target_runs, 1, budget, self.prestige, &mut self.decisions);
+        // Capital goods are bought one at a time, they are useful even if incomplete
+        for (good_uid, required) in self.missing(&self.recipe.capital, self.target_runs) {
+            let Some(market) = markets.iter_mut().find(|x| x.good_uid() == good_uid) else {
+                continue;
+            };
+            let required = required.min(market.affordable_order(budget - self.committed).unwrap_or(0));
+            self.decisions.record("buy", Some(good_uid), vec![
+                ("price", market.price_per_unit()),
+                ("capital", goods::to_units(self.stock(good_uid), market.unit_scale())),
+            ], required);
+            if market.register_order(Owner::Entity(id), OrderType::Buy, required, self.prestige).is_ok() {
+                self.committed += market.cost_of(required);
+            }
+        }
+    }
+
+    // The inputs are bought together in a basket, so that no input is bought without the others
+    fn post_basket_orders(&mut self, id: EntityId, markets: &mut [Box<dyn Market>], baskets: &mut BasketBook) {
+        if self.state != ProducerState::Active {
+            return;
+        }
+        let mut legs = vec![];
+        for (good_uid, quantity) in self.missing(&self.recipe.inputs, self.stock_runs) {
+            let Some(market) = markets.iter().find(|x| x.good_uid() == good_uid) else {
+                return;
+            };
+            legs.push(BasketLeg { good_uid, otype: OrderType::Buy, quantity, limit_price: market.price_per_unit() });
+        }
+        let cost: f64 = legs.iter()
+            .filter_map(|x| markets.iter().find(|m| m.good_uid() == x.good_uid).map(|m| goods::to_units(x.quantity, m.unit_scale()) * x.limit_price))
+            .sum();
+        // What the money can't pay shrinks every leg in the same proportion
+        let money = self.money_balance - self.fixed_cost - self.committed;
+        if cost > money {
+            let ratio = (money / cost).max(0.);
+            for leg in legs.iter_mut() {
+                leg.quantity = (leg.quantity as f64 * ratio) as Quantity;
+            }
+            legs.retain(|x| x.quantity > 0);
+        }
+        if legs.is_empty() {
+            return;
+        }
+        for leg in legs.iter() {
+            self.decisions.record("buy", Some(leg.good_uid), vec![
+                ("price", leg.limit_price),
+                ("money", money),
+                ("stock_runs", self.stock_runs as f64),
+            ], leg.quantity);
+        }
+        baskets.place(Owner::Entity(id), markets, BasketOrder { legs, prestige: self.prestige });
+    }
+
+    fn settle_order(&mut self, good_uid: GoodUid, result: OrderResult) {
+        if let Some(wages) = self.workforce.settle(good_uid, &result) {
+            self.money_balance -= wages;
+            self.money_flows.record(FlowKind::Wages, -wages);
+            return;
+        }
+        if result.remainder > 0 {
+            self.decisions.record("lot_remainder", Some(good_uid), vec![], result.remainder);
+        }
+        let stock = self.inventory.entry(good_uid).or_default();
+        match result.ordertype {
+            OrderType::Buy => {
+                *stock += result.traded_quantity;
+                self.money_balance -= result.total_cost;
+                self.money_flows.record(FlowKind::Trade, -result.total_cost);
+            }
+            OrderType::Sell => {
+                *stock -= result.traded_quantity;
+                self.money_balance += result.total_cost;
+                self.money_flows.record(FlowKind::Trade, result.total_cost);
+            }
+        }
+    }
+
+    fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    fn transfer(&mut self, kind: FlowKind, amount: f64) -> bool {
+        self.money_balance += amount;
+        self.money_flows.record(kind, amount);
+        true
+    }
+
+    fn take_decisions(&mut self) -> Vec<Decision> {
+        self.decisions.take()
+    }
+
+    fn pay_tax(&mut self, amount: f64) -> f64 {
+        let paid = amount.min(self.money_balance).max(0.);
+        self.money_balance -= paid;
+        self.money_flows.record(FlowKind::Tax, -paid);
+        paid
+    }
+
+    fn take_waste(&mut self) -> Vec<WasteRecord> {
+        self.waste.take()
+    }
+
+    fn inventory(&self) -> Vec<(GoodUid, Quantity)> {
+        let mut inventory: Vec<(GoodUid, Quantity)> = self.inventory.iter().map(|(good_uid, x)| (*good_uid, *x)).collect();
+        inventory.extend(self.waste.inventory());
+        inventory
+    }
+
+    fn state_fields(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("target_runs", self.target_runs as f64),
+            ("runs_in_stock", self.recipe.runs_allowed(&self.inventory).min(u32::MAX as u64) as f64),
+            ("runs_in_progress", self.pipeline.in_progress() as f64),
+            ("labor_available", self.workforce.available() as f64),
+            ("bankrupt", (self.state == ProducerState::Bankrupt) as u8 as f64),
+        ]
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde::ser::{SerializeMap, Serializer};
+
+// Time series of the simulation, one row per tick. Entities and markets report into it through
+// the engine, a series that starts late is padded with NaN.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Recorder {
+    ticks: Vec<u64>,
+    names: Vec<String>,
+    series: Vec<Vec<f64>>,
+    index: HashMap<String, usize>,
+}
+
+impl Recorder {
+    pub fn begin_tick(&mut self, tick: u64) {
+        self.ticks.push(tick);
+        for series in self.series.iter_mut() {
+            series.push(f64::NAN);
+        }
+    }
+
+    pub fn record(&mut self, name: &str, value: f64) {
+        let rows = self.ticks.len();
+        assert!(rows > 0, "record called before begin_tick");
+        let i = match self.index.get(name) {
+            Some(i) => *i,
+            None => {
+                self.names.push(name.to_owned());
+                self.series.push(vec![f64::NAN; rows]);
+                self.index.insert(name.to_owned(), self.series.len() - 1);
+                self.series.len() - 1
+            }
+        };
+        self.series[i][rows - 1] = value;
+    }
+
+    pub fn ticks(&self) -> &[u64] {
+        &self.ticks
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn series(&self, name: &str) -> Option<&[f64]> {
+        self.index.get(name).map(|i| &self.series[*i][..])
+    }
+
+    pub fn to_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "tick")?;
+        for name in self.names.iter() {
+            write!(out, ",{name}")?;
+        }
+        writeln!(out)?;
+        for (row, tick) in self.ticks.iter().enumerate() {
+            write!(out, "{tick}")?;
+            for series in self.series.iter() {
+                if series[row].is_nan() {
+                    write!(out, ",")?;
+                } else {
+                    write!(out, ",{}", series[row])?;
+                }
+            }
+            writeln!(out)?;
+        }
+        out.flush()
+    }
+
+    // Reads back a table written by to_csv
+    pub fn from_csv(path: &Path) -> std::io::Result<Recorder> {
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header = lines.next().ok_or_else(|| invalid("empty file".to_owned()))??;
+        let mut recorder = Recorder::default();
+        let names: Vec<&str> = header.split(',').skip(1).collect();
+        for line in lines {
+            let line = line?;
+            let mut cells = line.split(',');
+            let tick = cells.next().unwrap_or_default();
+            recorder.begin_tick(tick.parse().map_err(|_| invalid(format!("bad tick `{tick}`")))?);
+            for (name, cell) in names.iter().zip(cells) {
+                if cell.is_empty() {
+                    continue;
+                }
+                recorder.record(name, cell.parse().map_err(|_| invalid(format!("bad value `{cell}`")))?);
+            }
+        }
+        Ok(recorder)
+    }
+
+    // One array per column, `pandas.DataFrame(json.load(f))` gives back the table
+    pub fn to_json(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut out, &Columns(self))?;
+        out.flush()
+    }
+
+    // The format is chosen from the extension, CSV if it's not json
+    pub fn export(&self, path: &Path) -> std::io::Result<()> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => self.to_json(path),
+            _ => self.to_csv(path),
+        }
+    }
+}
+
+// The table as a JSON object of columns
+struct Columns<'a>(&'a Recorder);
+
+impl Serialize for Columns<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.names.len() + 1))?;
+        map.serialize_entry("tick", &self.0.ticks)?;
+        for (name, series) in self.0.names.iter().zip(self.0.series.iter()) {
+            map.serialize_entry(name, series)?;
+        }
+        map.end()
+    }
+}
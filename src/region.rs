@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::basket::BasketBook;
+use crate::contract::ContractBook;
+use crate::currency::{CurrencyId, FxMarket, FxOrderId, Money};
+use crate::ledger::{FlowKind, MoneyFlow, MoneyFlows};
+use crate::{goods, GoodUid, Market, MarketMetadata, OrderBatchId, OrderType, Owner, Price, Quantity};
+
+// The world is split in regions, every region has its own markets and the entities of a region
+// trade only there. Goods move between regions only along the trade routes. The prices and the
+// balances of a region are in its currency.
+
+pub type RegionId = usize;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    #[serde(default)]
+    pub currency: CurrencyId,
+    pub markets: Vec<Box<dyn Market>>,
+    pub baskets: BasketBook,
+    #[serde(default)]
+    pub contracts: ContractBook,
+    // Metadata of the markets, an entity sees only the markets with all of its own
+    #[serde(default)]
+    pub tags: BTreeMap<GoodUid, Vec<MarketMetadata>>,
+    // Share of the value of every trade of the entities paid to the government, see MarketTax
+    #[serde(default)]
+    pub taxes: BTreeMap<GoodUid, f64>,
+}
+
+impl Region {
+    pub fn new(name: &str) -> Region {
+        Region {
+            name: name.to_owned(),
+            currency: 0,
+            markets: vec![],
+            baskets: BasketBook::default(),
+            contracts: ContractBook::default(),
+            tags: BTreeMap::new(),
+            taxes: BTreeMap::new(),
+        }
+    }
+
+    pub fn market(&self, good_uid: GoodUid) -> Option<&dyn Market> {
+        self.markets.iter().find(|x| x.good_uid() == good_uid).map(|x| x.as_ref())
+    }
+
+    pub fn market_mut(&mut self, good_uid: GoodUid) -> Option<&mut Box<dyn Market>> {
+        self.markets.iter_mut().find(|x| x.good_uid() == good_uid)
+    }
+
+    // Positions of the markets of `goods` tagged with all the `metadata`, in ascending order
+    pub fn eligible_markets(&self, goods: &[GoodUid], metadata: &[MarketMetadata]) -> Vec<usize> {
+        let no_tags = vec![];
+        self.markets.iter().enumerate()
+            .filter(|(_, x)| goods.contains(&x.good_uid()))
+            .filter(|(_, x)| {
+                let tags = self.tags.get(&x.good_uid()).unwrap_or(&no_tags);
+                metadata.iter().all(|m| tags.contains(m))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // Calls `f` with only the markets at `positions`, ascending, moved to the front of the
+    // markets and put back in place after
+    pub fn with_markets<R>(&mut self, positions: &[usize],
+                           f: impl FnOnce(&mut [Box<dyn Market>], &mut BasketBook, &mut ContractBook) -> R) -> R {
+        for (j, i) in positions.iter().enumerate() {
+            self.markets.swap(j, *i);
+        }
+        let result = f(&mut self.markets[..positions.len()], &mut self.baskets, &mut self.contracts);
+        for (j, i) in positions.iter().enumerate().rev() {
+            self.markets.swap(j, *i);
+        }
+        result
+    }
+}
+
+// A trader moving a good from a region to another. When the price at destination covers the
+// price at origin plus the transport cost it buys at origin, then it sells at destination from
+// the next tick on. The goods bought and not sold yet can't exceed the capacity of the route.
+// Between regions with different currencies the price at destination is compared at the
+// exchange rate, and the proceeds of the sales are exchanged back to the currency of the origin
+// in the next tick.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TradeRoute {
+    pub name: String,
+    pub good_uid: GoodUid,
+    pub from: RegionId,
+    pub to: RegionId,
+    pub capacity: Quantity,
+    // Paid for every unit shipped
+    pub transport_cost: Price,
+    money_balance: f64,
+    // Sold at destination and not exchanged yet, in the currency of the destination
+    #[serde(default)]
+    proceeds: f64,
+    money_flows: MoneyFlows,
+    // Bought at origin and not sold at destination yet
+    in_transit: Quantity,
+    // Of the goods bought at origin
+    #[serde(default)]
+    buy_batch: Option<OrderBatchId>,
+    // Of the goods in transit at destination
+    #[serde(default)]
+    sell_batch: Option<OrderBatchId>,
+    // Exchanges of the proceeds, by exchange market
+    #[serde(default)]
+    fx_orders: Vec<(usize, FxOrderId)>,
+    // Embargoes in force, the route trades again when all of them end
+    #[serde(default)]
+    pub(crate) embargoes: u32,
+}
+
+impl TradeRoute {
+    pub fn new(name: &str, good_uid: GoodUid, from: RegionId, to: RegionId, capacity: Quantity,
+               transport_cost: Price, money_balance: f64) -> TradeRoute {
+        TradeRoute {
+            name: name.to_owned(),
+            good_uid,
+            from,
+            to,
+            capacity,
+            transport_cost,
+            money_balance,
+            proceeds: 0.,
+            money_flows: MoneyFlows::default(),
+            in_transit: 0,
+            buy_batch: None,
+            sell_batch: None,
+            fx_orders: vec![],
+            embargoes: 0,
+        }
+    }
+
+    pub fn money_balance(&self) -> f64 {
+        self.money_balance
+    }
+
+    pub fn proceeds(&self) -> f64 {
+        self.proceeds
+    }
+
+    pub fn in_transit(&self) -> Quantity {
+        self.in_transit
+    }
+
+    pub fn take_money_flows(&mut self) -> Vec<MoneyFlow> {
+        self.money_flows.take()
+    }
+
+    pub fn post_orders(&mut self, regions: &mut [Region], fx: &mut [FxMarket]) {
+        if self.embargoes > 0 {
+            return;
+        }
+        let (from_currency, to_currency) = (regions[self.from].currency, regions[self.to].currency);
+        let from_price = regions[self.from].market(self.good_uid)
+            .expect("No market for the route good at origin").price_per_unit();
+        let mut to_price = regions[self.to].market(self.good_uid)
+            .expect("No market for the route good at destination").price_per_unit();
+        if from_currency != to_currency {
+            let (id, market) = fx.iter_mut().enumerate().find(|x| x.1.trades(from_currency, to_currency))
+                .expect("No exchange between the currencies of the route");
+            to_price *= market.rate_of(to_currency, from_currency);
+            if let Ok(order) = market.register(Owner::Route, Money::new(self.proceeds, to_currency)) {
+                self.fx_orders.push((id, order));
+            }
+        }
+        let to_market = regions[self.to].market_mut(self.good_uid).unwrap();
+        self.sell_batch = Some(to_market.register_orders(Owner::Route, &[(OrderType::Sell, self.in_transit, 0.)]));
+        // Ship only when the price difference pays the transport
+        if to_price - self.transport_cost <= from_price {
+            return;
+        }
+        let from_market = regions[self.from].market_mut(self.good_uid).unwrap();
+        let unit_scale = from_market.unit_scale();
+        let affordable = (self.money_balance.max(0.) / (from_price + self.transport_cost) * unit_scale as f64) as Quantity;
+        let required = self.capacity.saturating_sub(self.in_transit).min(affordable);
+        let limit_price = to_price - self.transport_cost;
+        self.buy_batch = Some(from_market.register_limit_orders(Owner::Route, &[(OrderType::Buy, required, 0., limit_price)]));
+    }
+
+    pub fn retrieve_orders(&mut self, regions: &mut [Region], fx: &[FxMarket]) {
+        for result in self.fx_orders.drain(..).filter_map(|(id, order)| fx[id].retrieve_order_result(order)) {
+            self.proceeds -= result.given.amount;
+            self.money_balance += result.received.amount;
+            self.money_flows.record(FlowKind::Exchange, -result.given.amount);
+            self.money_flows.record(FlowKind::Exchange, result.received.amount);
+        }
+        let exchanged = regions[self.from].currency != regions[self.to].currency;
+        {
+            let to_market = regions[self.to].market_mut(self.good_uid).unwrap();
+            // The orders lost by a market that panicked never traded
+            if let Some(result) = self.sell_batch.take().and_then(|x| to_market.retrieve_batch_results(x)) {
+                self.in_transit -= result.sell.traded_quantity;
+                match exchanged {
+                    true => self.proceeds += result.sell.total_cost,
+                    false => self.money_balance += result.sell.total_cost,
+                }
+                self.money_flows.record(FlowKind::Trade, result.sell.total_cost);
+            }
+        }
+        {
+            let from_market = regions[self.from].market_mut(self.good_uid).unwrap();
+            let unit_scale = from_market.unit_scale();
+            if let Some(result) = self.buy_batch.take().and_then(|x| from_market.retrieve_batch_results(x)) {
+                self.in_transit += result.buy.traded_quantity;
+                let transport = goods::to_units(result.buy.traded_quantity, unit_scale) * self.transport_cost;
+                self.money_balance -= result.buy.total_cost + transport;
+                self.money_flows.record(FlowKind::Trade, -result.buy.total_cost);
+                self.money_flows.record(FlowKind::Transport, -transport);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Scenario, ScenarioError};
+    use crate::TestMarket;
+
+    #[test]
+    fn entities_see_only_the_markets_with_their_tags() {
+        let mut region = Region::new("North");
+        for good_uid in 0..4 {
+            region.markets.push(Box::new(TestMarket::new(good_uid, 1, 1.)));
+        }
+        region.tags.insert(1, vec!["fine".to_owned()]);
+        region.tags.insert(3, vec!["fine".to_owned(), "local".to_owned()]);
+        assert_eq!(region.eligible_markets(&[0, 1, 3], &[]), vec![0, 1, 3]);
+        let positions = region.eligible_markets(&[0, 1, 3], &["fine".to_owned()]);
+        assert_eq!(positions, vec![1, 3]);
+        let seen = region.with_markets(&positions, |markets, _, _| markets.iter().map(|x| x.good_uid()).collect::<Vec<_>>());
+        assert_eq!(seen, vec![1, 3]);
+        assert_eq!(region.markets.iter().map(|x| x.good_uid()).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn entities_skip_the_goods_their_tags_filter_out() {
+        let source = include_str!("../scenarios/wheat_bread.toml")
+            .replace("price = 2.0\n", "price = 2.0\ntags = [\"ita\"]\n")
+            .replace("money_balance = 6000.0\n", "money_balance = 6000.0\nmarket_tags = [\"ita\"]\n");
+        let error = Scenario::from_toml(&source).unwrap().build().err().unwrap();
+        assert!(matches!(error, ScenarioError::NoMarket(ref name, ref good) if name == "Pop" && good == "Groceries"), "{error}");
+        // Tagged after the build, the pop sees only the market of Grain and keeps its other goods
+        let source = source.replace("price = 10.0\n", "price = 10.0\ntags = [\"ita\"]\n")
+            .replace("wage_adjustment = 0.02\n", "wage_adjustment = 0.02\ntags = [\"ita\"]\n");
+        let mut sim = Scenario::from_toml(&source).unwrap().build().unwrap();
+        let groceries = sim.goods.uid_of("Groceries").unwrap();
+        let labor = sim.goods.uid_of("Labor").unwrap();
+        sim.regions[0].tags.remove(&groceries);
+        sim.regions[0].tags.remove(&labor);
+        for _ in 0..3 {
+            sim.step();
+        }
+        let pop = sim.entities.find("Pop").unwrap();
+        let held = |good_uid| sim.entities[pop].inventory().into_iter().find(|x| x.0 == good_uid).map_or(0, |x| x.1);
+        // It ate its 450 Groceries in 3 ticks without buying any
+        assert_eq!(held(groceries), 0);
+    }
+}
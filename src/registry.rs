@@ -0,0 +1,95 @@
+use std::ops::{Index, IndexMut};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::region::RegionId;
+use crate::{EcoEntity, EntityId};
+
+// The entities of the simulation with their names and regions. An entity gets its EntityId when
+// it is added and keeps it for the whole run, so the markets, the banks, the contracts and the
+// events can refer to it without holding it.
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct EntityRegistry {
+    entities: Vec<Box<dyn EcoEntity>>,
+    // Labels used for the plots and the events, unique
+    names: Vec<String>,
+    regions: Vec<RegionId>,
+}
+
+impl EntityRegistry {
+    pub fn add(&mut self, name: &str, region: RegionId, entity: Box<dyn EcoEntity>) -> EntityId {
+        self.entities.push(entity);
+        self.names.push(name.to_owned());
+        self.regions.push(region);
+        EntityId(self.entities.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item=EntityId> {
+        (0..self.entities.len()).map(EntityId)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&dyn EcoEntity> {
+        self.entities.get(id.0).map(|x| x.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Box<dyn EcoEntity>> {
+        self.entities.get_mut(id.0)
+    }
+
+    pub fn name(&self, id: EntityId) -> &str {
+        &self.names[id.0]
+    }
+
+    pub fn region(&self, id: EntityId) -> RegionId {
+        self.regions[id.0]
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn find(&self, name: &str) -> Option<EntityId> {
+        self.names.iter().position(|x| x == name).map(EntityId)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(EntityId, &str, &dyn EcoEntity)> {
+        self.entities.iter().zip(self.names.iter()).enumerate()
+            .map(|(i, (entity, name))| (EntityId(i), name.as_str(), entity.as_ref()))
+    }
+
+    // The entities can be replaced, as the freeze does
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=(EntityId, &str, &mut Box<dyn EcoEntity>)> {
+        self.entities.iter_mut().zip(self.names.iter()).enumerate()
+            .map(|(i, (entity, name))| (EntityId(i), name.as_str(), entity))
+    }
+
+    pub fn par_iter_mut(&mut self) -> rayon::slice::IterMut<'_, Box<dyn EcoEntity>> {
+        self.entities.par_iter_mut()
+    }
+}
+
+impl Index<EntityId> for EntityRegistry {
+    type Output = Box<dyn EcoEntity>;
+
+    fn index(&self, id: EntityId) -> &Box<dyn EcoEntity> {
+        &self.entities[id.0]
+    }
+}
+
+impl IndexMut<EntityId> for EntityRegistry {
+    fn index_mut(&mut self, id: EntityId) -> &mut Box<dyn EcoEntity> {
+        &mut self.entities[id.0]
+    }
+}
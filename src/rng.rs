@@ -0,0 +1,57 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use crate::GoodUid;
+
+pub type SimRng = ChaCha8Rng;
+
+// Source of the independent random streams of the simulation. Every stream is derived from the
+// global seed and its own name only, so adding a stream doesn't change the sequence of the others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RngStreams {
+    seed: u64,
+}
+
+impl RngStreams {
+    pub fn new(seed: u64) -> RngStreams {
+        RngStreams { seed }
+    }
+
+    pub fn stream(&self, name: &str) -> SimRng {
+        SimRng::seed_from_u64(fnv1a(self.seed.to_le_bytes().iter().chain(name.as_bytes())))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+// FNV-1a, stable across platforms and compiler versions unlike the std hashers
+pub fn fnv1a<'a>(bytes: impl Iterator<Item=&'a u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Ids of the orders of a market. They are a sequence so they depend only on the order the orders
+// are registered in, two runs of the same scenario give the same ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderIds {
+    good_uid: GoodUid,
+    next: u64,
+}
+
+impl OrderIds {
+    pub fn new(good_uid: GoodUid) -> OrderIds {
+        OrderIds { good_uid, next: 0 }
+    }
+
+    pub fn next_id(&mut self) -> Uuid {
+        self.next += 1;
+        Uuid::from_u64_pair(self.good_uid as u64, self.next)
+    }
+}
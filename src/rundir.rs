@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use crate::rng;
+
+// Every run writes its artifacts in a directory of its own, {runs}/{timestamp}-{scenario}
+// unless the directory is given, so a run never overwrites the outputs of another one. The
+// directory also has manifest.json with what is needed to run it again.
+
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    // UTC, when the run started
+    pub started: String,
+    pub version: &'static str,
+    // The scenario, or the checkpoint of a resumed run, None for the default scenario
+    pub source: Option<PathBuf>,
+    // FNV-1a of the bytes of the source, in hex
+    pub source_hash: String,
+    pub seed: u64,
+    pub ticks: u64,
+    // The command line of the run
+    pub args: Vec<String>,
+}
+
+impl RunManifest {
+    pub fn new(source: Option<&Path>, text: &[u8], seed: u64, ticks: u64) -> RunManifest {
+        RunManifest {
+            started: timestamp(SystemTime::now(), "-", ":", " "),
+            version: env!("CARGO_PKG_VERSION"),
+            source: source.map(Path::to_path_buf),
+            source_hash: format!("{:016x}", rng::fnv1a(text.iter())),
+            seed,
+            ticks,
+            args: std::env::args().collect(),
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        fs::write(dir.join("manifest.json"), serde_json::to_string_pretty(self)?)
+    }
+}
+
+// A new directory in `runs` for a run of `name`
+pub fn create(runs: &Path, name: &str) -> io::Result<PathBuf> {
+    let base = format!("{}-{name}", timestamp(SystemTime::now(), "", "", "-"));
+    let mut dir = runs.join(&base);
+    // Two runs started in the same second
+    let mut n = 1;
+    while dir.exists() {
+        n += 1;
+        dir = runs.join(format!("{base}-{n}"));
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// The path of an artifact given on the command line, relative paths are in the run directory
+pub fn artifact(dir: &Path, path: &Path) -> PathBuf {
+    match path.is_absolute() {
+        true => path.to_path_buf(),
+        false => dir.join(path),
+    }
+}
+
+// UTC date and time of `time`, like 2024-01-31 12:00:00 with `-`, `:` and ` ` as separators
+fn timestamp(time: SystemTime, date_sep: &str, time_sep: &str, sep: &str) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Civil date from the days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}{date_sep}{month:02}{date_sep}{day:02}{sep}{:02}{time_sep}{:02}{time_sep}{:02}",
+            secs / 3600, secs % 3600 / 60, secs % 60)
+}
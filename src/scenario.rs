@@ -0,0 +1,1384 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use crate::archetype;
+use crate::bank::Bank;
+use crate::central_bank::{CentralBank, MonetaryPolicy};
+use crate::equity::{EquityMarket, EquityPolicy};
+use crate::building::Building;
+use crate::deposit::{Deposit, Prospecting};
+use crate::contract::ContractPolicy;
+use crate::convergence::SteadyStateDetector;
+use crate::currency::{CurrencyId, FxMarket};
+use crate::demography::Demography;
+use crate::expectation::Expectations;
+use crate::engine::Simulation;
+use crate::external::{Endpoint, ExternalAgent, ExternalGood, FallbackPolicy};
+use crate::freeze::FreezePlan;
+use crate::goods::GoodRegistry;
+use crate::labor::{LaborMarket, Workforce};
+use crate::monetary::{MonetaryAction, MonetaryAuthority, MonetaryEvent};
+use crate::shock::{DemandSpike, Drought, Embargo, MarketTax, ScheduledShock, Shock, ShockSchedule, ShockTrigger};
+use crate::ledger::MoneyFlows;
+use crate::national::NationalMarket;
+use crate::needs::{Need, NeedTier, Needs};
+use crate::orderbook::OrderBookMarket;
+use crate::pipeline::Pipeline;
+use crate::pollution::{Pollution, PollutionPolicy};
+use crate::pricing::PricingStrategy;
+use crate::profit::{ProducerState, ProfitTracker, Solvency};
+use crate::recipe::{Recipe, RecipeMode, RecipeProducer};
+use crate::region::{RegionId, TradeRoute};
+use crate::rng::OrderIds;
+use crate::script::ScriptPolicy;
+use crate::stats::MarketStats;
+use crate::storage::Storage;
+use crate::tiers::TierPolicy;
+use crate::trace::DecisionLog;
+use crate::waste::{Byproduct, WasteProfile};
+use crate::{BasicPop, GoodUid, OrderBatches, OrderIndex, ProductorOneToOne, Quantity, RGOSingle, TestMarket};
+
+// A scenario describes a whole simulation: goods, markets, entities and run parameters.
+// It can be written either in TOML or in RON, the format is chosen from the file extension.
+// Quantities are written in units of the good and converted to base units when building.
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    // The extension of a scenario file that is neither TOML nor RON
+    UnknownFormat(String),
+    Parse(String),
+    UnknownGood(String),
+    UnknownRegion(String),
+    UnknownCurrency(String),
+    UnknownEntity(String),
+    // An entity whose market tags filter out all the markets of one of its goods in its region
+    NoMarket(String, String),
+    Agent(String, std::io::Error),
+    Script(String, String),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "cannot read scenario: {e}"),
+            ScenarioError::UnknownFormat(ext) => write!(f, "unknown scenario format `{ext}`, expected toml or ron"),
+            ScenarioError::Parse(e) => write!(f, "cannot parse scenario: {e}"),
+            ScenarioError::UnknownGood(name) => write!(f, "scenario references unknown good `{name}`"),
+            ScenarioError::UnknownRegion(name) => write!(f, "scenario references unknown region `{name}`"),
+            ScenarioError::UnknownCurrency(name) => write!(f, "scenario references unknown currency `{name}`"),
+            ScenarioError::UnknownEntity(name) => write!(f, "scenario references unknown entity `{name}`"),
+            ScenarioError::NoMarket(name, good) => write!(f, "no market of `{good}` matches the tags of entity `{name}`"),
+            ScenarioError::Agent(name, e) => write!(f, "cannot start the external agent `{name}`: {e}"),
+            ScenarioError::Script(name, e) => write!(f, "invalid script of `{name}`: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+#[derive(Debug, Deserialize)]
+pub struct SimulationParams {
+    pub ticks: u64,
+    // Global seed of all the random streams
+    #[serde(default)]
+    pub seed: u64,
+    // First ticks, part of `ticks`, left out of the statistics and the charts
+    #[serde(default)]
+    pub burn_in: u64,
+    #[serde(default)]
+    pub steady_state: Option<SteadyStateConfig>,
+    #[serde(default)]
+    pub freeze: Option<FreezeConfig>,
+}
+
+// The run is steady when the series starting with one of `metrics` (all the market series if
+// empty) changed less than `tolerance` per tick for `window` ticks
+#[derive(Debug, Clone, Deserialize)]
+pub struct SteadyStateConfig {
+    #[serde(default)]
+    pub metrics: Vec<String>,
+    pub tolerance: f64,
+    pub window: u64,
+    // Stop the run instead of only reporting the tick
+    #[serde(default)]
+    pub stop: bool,
+}
+
+// The entities are frozen at tick `at` and replay the orders of the `record` ticks before
+#[derive(Debug, Clone, Deserialize)]
+pub struct FreezeConfig {
+    pub entities: Vec<String>,
+    pub at: u64,
+    #[serde(default = "default_freeze_record")]
+    pub record: u64,
+}
+
+fn default_freeze_record() -> u64 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GoodConfig {
+    pub name: String,
+    // Decimal digits a good can be split into, omitted for indivisible goods
+    #[serde(default)]
+    pub decimals: u32,
+    // Fraction of the stock that spoils every tick
+    #[serde(default)]
+    pub decay: f64,
+    // Units traded together, the orders are rounded down to whole lots. Omitted to trade any
+    // quantity.
+    #[serde(default)]
+    pub lot: f64,
+    // Quality grades, the good is then traded as `{name} Q1` and up, see goods::grade_name.
+    // The markets of the good are one for every grade, every grade starting at `premium` more
+    // of the price of the one below.
+    #[serde(default)]
+    pub grades: u32,
+    #[serde(default)]
+    pub premium: f64,
+}
+
+// Without regions the whole world is a single region. A region without a currency pays in
+// the first one.
+#[derive(Debug, Deserialize)]
+pub struct RegionConfig {
+    pub name: String,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+// Without currencies all the regions share a single one
+#[derive(Debug, Deserialize)]
+pub struct CurrencyConfig {
+    pub name: String,
+}
+
+// Exchange of two currencies, `rate` units of quote for a unit of base, see FxMarket. The
+// reserves of the desk are in base and in quote.
+#[derive(Debug, Deserialize)]
+pub struct FxConfig {
+    pub base: String,
+    pub quote: String,
+    pub rate: f64,
+    #[serde(default)]
+    pub adjustment: f64,
+    pub reserves_base: f64,
+    pub reserves_quote: f64,
+}
+
+// Markets and entities without a region belong to the first one
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MarketConfig {
+    Test {
+        #[serde(default)]
+        region: Option<String>,
+        good: String,
+        price: f64,
+        // Random remainder allocation, with a stream of its own
+        #[serde(default)]
+        randomized: bool,
+        // Probability of an order to miss the trade of a tick, requires `randomized`
+        #[serde(default)]
+        friction: f64,
+        // `prestige` fills the most prestigious orders first, `pooled` shares among all of them
+        #[serde(default)]
+        tiers: TierPolicy,
+        // Matched against the market_tags of the entities, see Region::eligible_markets
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    // Limit order book, the price is the starting reference price
+    OrderBook {
+        #[serde(default)]
+        region: Option<String>,
+        good: String,
+        price: f64,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    // Market of a labor good, the wage changes by `wage_adjustment` every tick with unbalanced
+    // demand and supply
+    Labor {
+        #[serde(default)]
+        region: Option<String>,
+        good: String,
+        wage: f64,
+        #[serde(default)]
+        wage_adjustment: f64,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+}
+
+// Labor needed by a producer for every unit of production
+#[derive(Debug, Deserialize)]
+pub struct WorkforceConfig {
+    pub good: String,
+    pub per_unit: f64,
+}
+
+// Building of a producer, a level uses `input_per_level` units of input per tick and is built
+// with `capital_per_level` units of the capital `good`. See Building
+#[derive(Debug, Deserialize)]
+pub struct BuildingConfig {
+    pub good: String,
+    #[serde(default = "default_level")]
+    pub level: u32,
+    pub input_per_level: f64,
+    pub capital_per_level: f64,
+    #[serde(default)]
+    pub fixed_cost_per_level: f64,
+    // Share of the margin of a profitable window set aside for a new level
+    #[serde(default)]
+    pub reinvest: f64,
+}
+
+fn default_level() -> u32 {
+    1
+}
+
+// Deposit of an RGO holding `reserve` units, regrowing by `regeneration` every tick. See Deposit
+#[derive(Debug, Deserialize)]
+pub struct DepositConfig {
+    pub reserve: f64,
+    #[serde(default)]
+    pub regeneration: f64,
+    #[serde(default)]
+    pub prospecting: Option<ProspectingConfig>,
+}
+
+// Every tick the efficiency of the deposit is below `below`, `cost` is paid for a `chance` of
+// finding `discovery` more units
+#[derive(Debug, Deserialize)]
+pub struct ProspectingConfig {
+    pub cost: f64,
+    pub chance: f64,
+    pub discovery: f64,
+    #[serde(default = "default_prospecting_below")]
+    pub below: f64,
+}
+
+fn default_prospecting_below() -> f64 {
+    0.5
+}
+
+// Inefficiency of a producer: the fraction `loss` of the inputs is lost and `per_unit` units of
+// the byproduct `good` come out per unit of production
+#[derive(Debug, Deserialize)]
+pub struct WasteConfig {
+    #[serde(default)]
+    pub loss: f64,
+    #[serde(default)]
+    pub good: Option<String>,
+    #[serde(default)]
+    pub per_unit: f64,
+}
+
+// Room of an entity for all its goods together, in units. Without `overflow_cost` what doesn't
+// fit is dumped, with it the room above the capacity is rented at that cost per unit per tick.
+#[derive(Debug, Deserialize)]
+pub struct StorageConfig {
+    pub capacity: f64,
+    #[serde(default)]
+    pub overflow_cost: f64,
+}
+
+// How much a buyer changes its target stocks with the price trends, see Expectations
+#[derive(Debug, Deserialize)]
+pub struct ExpectationsConfig {
+    // Relative change of the target for a price 100% above its average
+    #[serde(default)]
+    pub stockpile: f64,
+    // Relative change of the target for a price rising 100% less than the other goods bought
+    #[serde(default)]
+    pub substitution: f64,
+    #[serde(default = "default_max_change")]
+    pub max_change: f64,
+}
+
+// Forward contracts offered by an entity every tick for `share` of its production, or of its
+// input, see ContractPolicy
+#[derive(Debug, Deserialize)]
+pub struct ContractConfig {
+    pub ticks: u64,
+    pub share: f64,
+    // Relative to the spot price
+    #[serde(default = "default_contract_price")]
+    pub price: f64,
+}
+
+// Own prices of a producer on the OrderBookMarket, see PricingStrategy. The margin starts at
+// `margin` over the unit cost and moves by `step` every tick within its bounds.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PricingConfig {
+    pub margin: f64,
+    pub min_margin: f64,
+    pub max_margin: f64,
+    // Bound of the bid above or below the price of the market
+    pub max_premium: f64,
+    pub step: f64,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        PricingConfig { margin: 0.2, min_margin: 0., max_margin: 1., max_premium: 0.2, step: 0.02 }
+    }
+}
+
+// The forward contracts of every region, see ContractBook
+#[derive(Debug, Deserialize)]
+pub struct ContractMarketConfig {
+    // Fraction of the value of what is not delivered, or not paid, paid by who defaults
+    pub penalty: f64,
+}
+
+// Labor sold by a pop every tick
+#[derive(Debug, Deserialize)]
+pub struct PopLaborConfig {
+    pub good: String,
+    pub per_tick: f64,
+}
+
+// How a producer reacts to its sales, see ProfitTracker
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ProfitabilityConfig {
+    pub window: usize,
+    pub scale_step: f64,
+    pub min_sales_ratio: f64,
+    // 0 never goes dormant
+    pub dormancy_after: u32,
+    // Consecutive ticks without the money for the fixed costs before going bankrupt
+    pub bankruptcy_after: u32,
+}
+
+impl Default for ProfitabilityConfig {
+    fn default() -> Self {
+        ProfitabilityConfig { window: 5, scale_step: 0.1, min_sales_ratio: 0.5, dormancy_after: 5, bankruptcy_after: default_bankruptcy_after() }
+    }
+}
+
+fn default_bankruptcy_after() -> u32 {
+    3
+}
+
+fn default_population() -> u64 {
+    1
+}
+
+fn default_timeout() -> u64 {
+    1000
+}
+
+fn default_max_change() -> f64 {
+    0.5
+}
+
+fn default_contract_price() -> f64 {
+    1.
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PopGoodConfig {
+    pub good: String,
+    pub inventory: f64,
+    pub desired: f64,
+    pub consumed: f64,
+}
+
+// Need of a pop met by any of the `goods`, which are goods of the pop, see Needs
+#[derive(Debug, Deserialize)]
+pub struct PopNeedConfig {
+    pub name: String,
+    #[serde(default)]
+    pub tier: NeedTier,
+    pub goods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EntityConfig {
+    Rgo {
+        name: String,
+        #[serde(default)]
+        region: Option<String>,
+        good: String,
+        quantity: f64,
+        target_quantity: f64,
+        max_production_rate: f64,
+        #[serde(default)]
+        per_unit_cost: f64,
+        fixed_cost: f64,
+        #[serde(default)]
+        labor: Option<WorkforceConfig>,
+        // Finite source of the good, endless without it
+        #[serde(default)]
+        deposit: Option<DepositConfig>,
+        #[serde(default)]
+        storage: Option<StorageConfig>,
+        // Sells forward a share of the max production
+        #[serde(default)]
+        contracts: Option<ContractConfig>,
+        // Asks for the good its unit cost plus a margin
+        #[serde(default)]
+        pricing: Option<PricingConfig>,
+        // Consecutive ticks without the money for the fixed costs before going bankrupt
+        #[serde(default = "default_bankruptcy_after")]
+        bankruptcy_after: u32,
+        // Rhai functions overriding the decisions, see ScriptPolicy
+        #[serde(default)]
+        script: Option<String>,
+        // Tags the markets of the entity must have, see Region::eligible_markets
+        #[serde(default)]
+        market_tags: Vec<String>,
+        money_balance: f64,
+        #[serde(default)]
+        prestige: f64,
+    },
+    Producer {
+        name: String,
+        #[serde(default)]
+        region: Option<String>,
+        input_good: String,
+        output_good: String,
+        input_quantity: f64,
+        output_quantity: f64,
+        target_input_quantity: f64,
+        target_output_quantity: f64,
+        conversion_rateo: f64,
+        target_input_per_tick: f64,
+        // Minimum input used at once, the input is used in multiples of it
+        #[serde(default)]
+        batch: f64,
+        #[serde(default)]
+        per_input_unit_cost: f64,
+        fixed_cost: f64,
+        // Labor required per input unit
+        #[serde(default)]
+        labor: Option<WorkforceConfig>,
+        // Caps the input used per tick
+        #[serde(default)]
+        building: Option<BuildingConfig>,
+        // Per input unit
+        #[serde(default)]
+        waste: Option<WasteConfig>,
+        // Ticks from the use of the input to the output in stock
+        #[serde(default)]
+        lead_time: u64,
+        #[serde(default)]
+        storage: Option<StorageConfig>,
+        #[serde(default)]
+        expectations: Option<ExpectationsConfig>,
+        // Buys forward a share of the input used every tick
+        #[serde(default)]
+        contracts: Option<ContractConfig>,
+        // Asks for the output its unit cost plus a margin and bids for the input
+        #[serde(default)]
+        pricing: Option<PricingConfig>,
+        #[serde(default)]
+        profitability: ProfitabilityConfig,
+        // Rhai functions overriding the decisions, see ScriptPolicy
+        #[serde(default)]
+        script: Option<String>,
+        // Tags the markets of the entity must have, see Region::eligible_markets
+        #[serde(default)]
+        market_tags: Vec<String>,
+        money_balance: f64,
+        #[serde(default)]
+        prestige: f64,
+    },
+    // Producer with many inputs and outputs, the quantities of the recipe are for a single run
+    Recipe {
+        name: String,
+        #[serde(default)]
+        region: Option<String>,
+        inputs: BTreeMap<String, f64>,
+        outputs: BTreeMap<String, f64>,
+        #[serde(default)]
+        capital: BTreeMap<String, f64>,
+        #[serde(default)]
+        cost_per_run: f64,
+        #[serde(default)]
+        mode: RecipeMode,
+        target_runs: u64,
+        // Inputs kept in stock, in runs
+        stock_runs: u64,
+        // Minimum runs done at once, the runs are done in multiples of it
+        #[serde(default)]
+        batch: u64,
+        fixed_cost: f64,
+        // Labor required per run
+        #[serde(default)]
+        labor: Option<WorkforceConfig>,
+        // Per run
+        #[serde(default)]
+        waste: Option<WasteConfig>,
+        // Ticks from the use of the inputs to the outputs in stock
+        #[serde(default)]
+        lead_time: u64,
+        #[serde(default)]
+        inventory: BTreeMap<String, f64>,
+        // Tags the markets of the entity must have, see Region::eligible_markets
+        #[serde(default)]
+        market_tags: Vec<String>,
+        money_balance: f64,
+        #[serde(default)]
+        prestige: f64,
+    },
+    // The goods desired and consumed and the labor are per head, the inventory is of the whole pop
+    Pop {
+        name: String,
+        #[serde(default)]
+        region: Option<String>,
+        // Goods in priority order
+        goods: Vec<PopGoodConfig>,
+        // Goods left out are life needs of their own
+        #[serde(default)]
+        needs: Vec<PopNeedConfig>,
+        #[serde(default)]
+        labor: Option<PopLaborConfig>,
+        #[serde(default = "default_population")]
+        population: u64,
+        // Fraction of the population born every tick with all the goods consumed
+        #[serde(default)]
+        birth_rate: f64,
+        // Fraction of the population dead every tick with none of the goods consumed
+        #[serde(default)]
+        death_rate: f64,
+        #[serde(default)]
+        storage: Option<StorageConfig>,
+        #[serde(default)]
+        expectations: Option<ExpectationsConfig>,
+        // Rhai functions overriding the decisions, see ScriptPolicy
+        #[serde(default)]
+        script: Option<String>,
+        // Tags the markets of the entity must have, see Region::eligible_markets
+        #[serde(default)]
+        market_tags: Vec<String>,
+        money_balance: f64,
+        #[serde(default)]
+        prestige: f64,
+        #[serde(default)]
+        standard_of_living: f64,
+    },
+    // Entity whose orders are decided by another process, see ExternalAgent. The process is
+    // started with `command` or is already listening at the `connect` address.
+    External {
+        name: String,
+        #[serde(default)]
+        region: Option<String>,
+        #[serde(default)]
+        command: Vec<String>,
+        #[serde(default)]
+        connect: Option<String>,
+        // The goods it trades and their stock
+        goods: BTreeMap<String, f64>,
+        // Milliseconds the process has to reply every tick
+        #[serde(default = "default_timeout")]
+        timeout: u64,
+        #[serde(default)]
+        fallback: FallbackPolicy,
+        money_balance: f64,
+        #[serde(default)]
+        prestige: f64,
+    },
+}
+
+impl EntityConfig {
+    // The `kind` of the entity in the scenario
+    fn kind(&self) -> &'static str {
+        match self {
+            EntityConfig::Rgo { .. } => "rgo",
+            EntityConfig::Producer { .. } => "producer",
+            EntityConfig::Recipe { .. } => "recipe",
+            EntityConfig::Pop { .. } => "pop",
+            EntityConfig::External { .. } => "external",
+        }
+    }
+}
+
+// National market of a good, cleared after the regional markets, see NationalMarket
+#[derive(Debug, Deserialize)]
+pub struct NationalConfig {
+    pub good: String,
+    pub price: f64,
+}
+
+// Bank keeping the accounts of some entities, the rates are per tick, see Bank
+#[derive(Debug, Deserialize)]
+pub struct BankConfig {
+    pub money_balance: f64,
+    #[serde(default)]
+    pub deposit_rate: f64,
+    #[serde(default)]
+    pub loan_rate: f64,
+    #[serde(default)]
+    pub accounts: Vec<AccountConfig>,
+}
+
+// The money of `entity` above `cash` is deposited, below it is withdrawn or borrowed
+#[derive(Debug, Deserialize)]
+pub struct AccountConfig {
+    pub entity: String,
+    pub cash: f64,
+    #[serde(default)]
+    pub credit_limit: f64,
+}
+
+// Trader moving `good` from a region to another, see TradeRoute
+#[derive(Debug, Deserialize)]
+pub struct RouteConfig {
+    pub name: String,
+    pub good: String,
+    pub from: String,
+    pub to: String,
+    // Max quantity bought and not sold yet
+    pub capacity: f64,
+    pub transport_cost: f64,
+    pub money_balance: f64,
+}
+
+// Monetary policy of the central bank, see CentralBank. The price index is of the `basket`, in
+// units of every good, one unit of every good not traded as labor if omitted. The base money goes
+// to the `recipients`, to every pop if omitted.
+#[derive(Debug, Deserialize)]
+pub struct CentralBankConfig {
+    #[serde(flatten)]
+    pub policy: MonetaryPolicy,
+    #[serde(default)]
+    pub basket: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+}
+
+// Shares of the firms held by the investors, see EquityMarket. The investors are every pop if
+// omitted, the shares of a firm not given to its `owners` are held by the firm.
+#[derive(Debug, Deserialize)]
+pub struct EquityConfig {
+    #[serde(flatten)]
+    pub policy: EquityPolicy,
+    #[serde(default)]
+    pub investors: Vec<String>,
+    pub firms: Vec<FirmConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FirmConfig {
+    pub firm: String,
+    pub shares: u64,
+    // Fraction of the profit paid as dividends
+    pub payout: f64,
+    #[serde(default)]
+    pub owners: BTreeMap<String, u64>,
+}
+
+// Money scaled, `scale = 1.1` for +10%, or injected, `inject = 100.0` to every entity, at tick
+// `at` and then every `every` ticks up to `until`. The event is for the entities named in
+// `entities` and the ones of kind `kind`, for all of them if both are omitted. See MonetaryAuthority
+#[derive(Debug, Deserialize)]
+pub struct MonetaryConfig {
+    pub at: u64,
+    #[serde(default)]
+    pub every: u64,
+    #[serde(default)]
+    pub until: Option<u64>,
+    #[serde(flatten)]
+    pub action: MonetaryAction,
+    #[serde(default)]
+    pub entities: Vec<String>,
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+// What a shock does, see Shock
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShockEffectConfig {
+    // The production of an RGO changes by `factor`
+    Drought { entity: String, factor: f64 },
+    // The consumption of a pop changes by `factor`
+    DemandSpike { entity: String, factor: f64 },
+    Embargo { route: String },
+    // The entities trading in the market pay `rate` of the value of their trades
+    MarketTax {
+        #[serde(default)]
+        region: Option<String>,
+        good: String,
+        rate: f64,
+    },
+}
+
+// A shock starts at tick `at`, or with a probability of `chance` every tick it isn't running,
+// and lasts `duration` ticks, for ever if 0. See ShockSchedule
+#[derive(Debug, Deserialize)]
+pub struct ShockConfig {
+    pub name: String,
+    #[serde(default)]
+    pub at: Option<u64>,
+    #[serde(default)]
+    pub chance: Option<f64>,
+    #[serde(default)]
+    pub duration: u64,
+    #[serde(flatten)]
+    pub effect: ShockEffectConfig,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Scenario {
+    pub simulation: SimulationParams,
+    pub goods: Vec<GoodConfig>,
+    #[serde(default)]
+    pub currencies: Vec<CurrencyConfig>,
+    #[serde(default)]
+    pub regions: Vec<RegionConfig>,
+    pub markets: Vec<MarketConfig>,
+    // An entity can start from an archetype, see archetype
+    #[serde(deserialize_with = "archetype::deserialize_entities")]
+    pub entities: Vec<EntityConfig>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    #[serde(default)]
+    pub nationals: Vec<NationalConfig>,
+    #[serde(default)]
+    pub fx: Vec<FxConfig>,
+    // Pollution caused by the waste of the production, see Pollution
+    #[serde(default)]
+    pub pollution: Option<PollutionPolicy>,
+    #[serde(default)]
+    pub bank: Option<BankConfig>,
+    #[serde(default)]
+    pub central_bank: Option<CentralBankConfig>,
+    #[serde(default)]
+    pub equity: Option<EquityConfig>,
+    #[serde(default)]
+    pub monetary: Vec<MonetaryConfig>,
+    #[serde(default)]
+    pub shocks: Vec<ShockConfig>,
+    #[serde(default)]
+    pub contracts: Option<ContractMarketConfig>,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Scenario, ScenarioError> {
+        let parse = match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Scenario::from_toml,
+            Some("ron") => Scenario::from_ron,
+            ext => return Err(ScenarioError::UnknownFormat(ext.unwrap_or_default().to_owned())),
+        };
+        parse(&fs::read_to_string(path).map_err(ScenarioError::Io)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Scenario, ScenarioError> {
+        toml::from_str(text).map_err(|e| ScenarioError::Parse(e.to_string()))
+    }
+
+    pub fn from_ron(text: &str) -> Result<Scenario, ScenarioError> {
+        ron::from_str(text).map_err(|e| ScenarioError::Parse(e.to_string()))
+    }
+
+    pub fn build(&self) -> Result<Simulation, ScenarioError> {
+        let mut registry = GoodRegistry::default();
+        for good in self.goods.iter() {
+            match good.grades {
+                0 => { registry.register(&good.name, good.decimals, good.decay, good.lot); }
+                grades => { registry.register_grades(&good.name, grades, good.decimals, good.decay, good.lot); }
+            }
+        }
+        let uid = |name: &str| -> Result<GoodUid, ScenarioError> {
+            registry.uid_of(name).ok_or_else(|| ScenarioError::UnknownGood(name.to_owned()))
+        };
+        // Every grade of a good with grades and its starting price
+        let priced_goods = |name: &str, price: f64| -> Result<Vec<(GoodUid, f64)>, ScenarioError> {
+            let grades = registry.grades(name);
+            if grades.is_empty() {
+                return Ok(vec![(uid(name)?, price)]);
+            }
+            let premium = self.goods.iter().find(|x| x.name == name).map_or(0., |x| x.premium);
+            Ok(grades.into_iter().zip(0..).map(|(good_uid, i)| (good_uid, price * (1. + premium).powi(i))).collect())
+        };
+        // The grade made of a good with grades, see GoodRegistry::grade_made
+        let made = |name: &str, inputs: &[GoodUid], capital: bool| -> Result<GoodUid, ScenarioError> {
+            registry.grade_made(name, inputs, capital).map_or_else(|| uid(name), Ok)
+        };
+        let workforce = |labor: &Option<WorkforceConfig>| -> Result<Workforce, ScenarioError> {
+            match labor {
+                Some(x) => Ok(Workforce::new(uid(&x.good)?, x.per_unit)),
+                None => Ok(Workforce::default()),
+            }
+        };
+        let waste = |waste: &Option<WasteConfig>| -> Result<WasteProfile, ScenarioError> {
+            let Some(x) = waste else {
+                return Ok(WasteProfile::default());
+            };
+            let byproduct = match &x.good {
+                Some(good) => {
+                    let good_uid = uid(good)?;
+                    Some(Byproduct { good_uid, unit_scale: registry.unit_scale(good_uid), per_unit: x.per_unit })
+                }
+                None => None,
+            };
+            Ok(WasteProfile::new(x.loss, byproduct))
+        };
+        let storage = |storage: &Option<StorageConfig>| -> Storage {
+            storage.as_ref().map_or_else(Storage::default, |x| Storage::new(x.capacity, x.overflow_cost))
+        };
+        let expectations = |expectations: &Option<ExpectationsConfig>| -> Expectations {
+            expectations.as_ref().map_or_else(Expectations::default, |x| Expectations::new(x.stockpile, x.substitution, x.max_change))
+        };
+        let pricing = |pricing: &Option<PricingConfig>| -> Option<PricingStrategy> {
+            pricing.as_ref().map(|x| PricingStrategy::new(x.margin, x.min_margin, x.max_margin, x.max_premium, x.step))
+        };
+        let contracts = |contracts: &Option<ContractConfig>| -> ContractPolicy {
+            contracts.as_ref().map_or_else(ContractPolicy::default, |x| ContractPolicy { ticks: x.ticks, share: x.share, price: x.price })
+        };
+        let script = |name: &str, source: &Option<String>| -> Result<ScriptPolicy, ScenarioError> {
+            match source {
+                Some(x) => ScriptPolicy::new(x).map_err(|e| ScenarioError::Script(name.to_owned(), e)),
+                None => Ok(ScriptPolicy::default()),
+            }
+        };
+        let region_names: Vec<&str> = match self.regions.is_empty() {
+            true => vec!["default"],
+            false => self.regions.iter().map(|x| x.name.as_str()).collect(),
+        };
+        let region_id = |name: &Option<String>| -> Result<RegionId, ScenarioError> {
+            match name {
+                Some(name) => region_names.iter().position(|x| x == name)
+                    .ok_or_else(|| ScenarioError::UnknownRegion(name.to_owned())),
+                None => Ok(0),
+            }
+        };
+        let mut sim = Simulation::new(registry.clone(), self.simulation.seed);
+        sim.burn_in = self.simulation.burn_in;
+        sim.steady_state = self.simulation.steady_state.as_ref()
+            .map(|x| SteadyStateDetector::new(x.metrics.clone(), x.tolerance, x.window, x.stop));
+        let currency_names: Vec<&str> = match self.currencies.is_empty() {
+            true => vec!["default"],
+            false => self.currencies.iter().map(|x| x.name.as_str()).collect(),
+        };
+        let currency_id = |name: &str| -> Result<CurrencyId, ScenarioError> {
+            currency_names.iter().position(|x| *x == name).ok_or_else(|| ScenarioError::UnknownCurrency(name.to_owned()))
+        };
+        for name in currency_names.iter() {
+            sim.add_currency(name);
+        }
+        for name in region_names.iter() {
+            sim.add_region(name);
+        }
+        for (region, config) in sim.regions.iter_mut().zip(self.regions.iter()) {
+            if let Some(currency) = &config.currency {
+                region.currency = currency_id(currency)?;
+            }
+        }
+        for config in self.fx.iter() {
+            let (base, quote) = (currency_id(&config.base)?, currency_id(&config.quote)?);
+            sim.fx.push(FxMarket::new(base, quote, config.rate, config.adjustment, (config.reserves_base, config.reserves_quote)));
+        }
+        if let Some(contracts) = &self.contracts {
+            for region in sim.regions.iter_mut() {
+                region.contracts.penalty = contracts.penalty;
+            }
+        }
+        for market in self.markets.iter() {
+            match market {
+                MarketConfig::Test { region, good, price, randomized, friction, tiers, tags } => for (good_uid, price) in priced_goods(good, *price)? {
+                    let region = region_id(region)?;
+                    let label = sim.market_label(region, good_uid);
+                    let rng = randomized.then(|| sim.rng_streams.stream(&format!("market/{label}")));
+                    sim.add_market(region, Box::new(TestMarket {
+                        good_uid,
+                        price_per_unit: price,
+                        unit_scale: registry.unit_scale(good_uid),
+                        lot_size: registry.lot_size(good_uid),
+                        buy_orders: vec![],
+                        sell_orders: vec![],
+                        rng,
+                        friction: *friction,
+                        order_ids: OrderIds::new(good_uid),
+                        tier_policy: *tiers,
+                        untraded_tiers: 0,
+                        stats: MarketStats::default(),
+                        batches: OrderBatches::default(),
+                        index: OrderIndex::default(),
+                    }));
+                    sim.regions[region].tags.insert(good_uid, tags.clone());
+                }
+                MarketConfig::OrderBook { region, good, price, tags } => for (good_uid, price) in priced_goods(good, *price)? {
+                    let region = region_id(region)?;
+                    sim.add_market(region, Box::new(OrderBookMarket::new(
+                        good_uid, registry.unit_scale(good_uid), registry.lot_size(good_uid), price)));
+                    sim.regions[region].tags.insert(good_uid, tags.clone());
+                }
+                MarketConfig::Labor { region, good, wage, wage_adjustment, tags } => {
+                    let region = region_id(region)?;
+                    let good_uid = uid(good)?;
+                    sim.add_market(region, Box::new(LaborMarket::new(
+                        good_uid, registry.unit_scale(good_uid), *wage, *wage_adjustment)));
+                    sim.regions[region].tags.insert(good_uid, tags.clone());
+                }
+            }
+        }
+        for entity in self.entities.iter() {
+            match entity {
+                EntityConfig::Rgo {
+                    name, region, good, quantity, target_quantity, max_production_rate,
+                    per_unit_cost, fixed_cost, labor, deposit, storage: storage_config, contracts: contracts_config,
+                    pricing: pricing_config, bankruptcy_after, script: source, market_tags, money_balance, prestige,
+                } => {
+                    let good_uid = made(good, &[], false)?;
+                    let base = |x: &f64| registry.to_base_units(good_uid, *x);
+                    let deposit = deposit.as_ref().map(|x| Deposit::new(base(&x.reserve), base(&x.regeneration),
+                        x.prospecting.as_ref().map(|p| Prospecting {
+                            cost: p.cost,
+                            chance: p.chance,
+                            discovery: base(&p.discovery),
+                            below: p.below,
+                        })));
+                    sim.add_entity(name, region_id(region)?, Box::new(RGOSingle {
+                        good_uid,
+                        quantity: base(quantity),
+                        target_quantity: base(target_quantity),
+                        max_production_rate: base(max_production_rate),
+                        per_unit_cost: *per_unit_cost,
+                        fixed_cost: *fixed_cost,
+                        workforce: workforce(labor)?,
+                        deposit,
+                        productivity_loss: 0.,
+                        production_shock: 0.,
+                        storage: storage(storage_config),
+                        contracts: contracts(contracts_config),
+                        pricing: pricing(pricing_config),
+                        solvency: Solvency::new(*bankruptcy_after),
+                        state: ProducerState::Active,
+                        script: script(name, source)?,
+                        market_tags: market_tags.clone(),
+                        unit_scale: registry.unit_scale(good_uid),
+                        money_balance: *money_balance,
+                        money_flows: MoneyFlows::default(),
+                        decisions: DecisionLog::default(),
+                        prestige: *prestige,
+                    }));
+                }
+                EntityConfig::Producer {
+                    name, region, input_good, output_good, input_quantity, output_quantity,
+                    target_input_quantity, target_output_quantity, conversion_rateo,
+                    target_input_per_tick, batch, per_input_unit_cost, fixed_cost, labor, building, waste: waste_config,
+                    lead_time, storage: storage_config, expectations: expectations_config, contracts: contracts_config,
+                    pricing: pricing_config, profitability, script: source, market_tags, money_balance,
+                    prestige,
+                } => {
+                    let input_good_uid = uid(input_good)?;
+                    let output_good_uid = made(output_good, &[input_good_uid], building.is_some())?;
+                    let input = |x: &f64| registry.to_base_units(input_good_uid, *x);
+                    let output = |x: &f64| registry.to_base_units(output_good_uid, *x);
+                    let building = match building {
+                        Some(x) => {
+                            let capital_good_uid = uid(&x.good)?;
+                            Some(Building::new(capital_good_uid, x.level, input(&x.input_per_level),
+                                               registry.to_base_units(capital_good_uid, x.capital_per_level), x.fixed_cost_per_level, x.reinvest))
+                        }
+                        None => None,
+                    };
+                    sim.add_entity(name, region_id(region)?, Box::new(ProductorOneToOne {
+                        input_good_uid,
+                        output_good_uid,
+                        input_quantity: input(input_quantity),
+                        output_quantity: output(output_quantity),
+                        target_input_quantity: input(target_input_quantity),
+                        target_output_quantity: output(target_output_quantity),
+                        conversion_rateo: *conversion_rateo,
+                        target_input_per_tick: input(target_input_per_tick),
+                        batch: input(batch),
+                        per_input_unit_cost: *per_input_unit_cost,
+                        fixed_cost: *fixed_cost,
+                        workforce: workforce(labor)?,
+                        building,
+                        waste: waste(waste_config)?,
+                        storage: storage(storage_config),
+                        expectations: expectations(expectations_config),
+                        contracts: contracts(contracts_config),
+                        pricing: pricing(pricing_config),
+                        pipeline: Pipeline::new(*lead_time),
+                        profit: ProfitTracker::new(
+                            profitability.window,
+                            profitability.scale_step,
+                            profitability.min_sales_ratio,
+                            profitability.dormancy_after,
+                            profitability.bankruptcy_after,
+                        ),
+                        state: ProducerState::Active,
+                        script: script(name, source)?,
+                        market_tags: market_tags.clone(),
+                        input_unit_scale: registry.unit_scale(input_good_uid),
+                        output_unit_scale: registry.unit_scale(output_good_uid),
+                        money_balance: *money_balance,
+                        money_flows: MoneyFlows::default(),
+                        decisions: DecisionLog::default(),
+                        prestige: *prestige,
+                    }));
+                }
+                EntityConfig::Recipe {
+                    name, region, inputs, outputs, capital, cost_per_run, mode, target_runs, stock_runs, batch,
+                    fixed_cost, labor, waste: waste_config, lead_time, inventory, market_tags, money_balance, prestige,
+                } => {
+                    let base = |goods: &BTreeMap<String, f64>| -> Result<BTreeMap<GoodUid, Quantity>, ScenarioError> {
+                        goods.iter()
+                            .map(|(good, x)| Ok((uid(good)?, registry.to_base_units(uid(good)?, *x))))
+                            .collect()
+                    };
+                    let recipe_inputs = base(inputs)?;
+                    let made_inputs: Vec<GoodUid> = recipe_inputs.keys().copied().collect();
+                    let recipe = Recipe {
+                        outputs: outputs.iter()
+                            .map(|(good, x)| {
+                                let good_uid = made(good, &made_inputs, !capital.is_empty())?;
+                                Ok((good_uid, registry.to_base_units(good_uid, *x)))
+                            })
+                            .collect::<Result<_, ScenarioError>>()?,
+                        inputs: recipe_inputs,
+                        capital: base(capital)?,
+                        cost_per_run: *cost_per_run,
+                    };
+                    let mut producer = RecipeProducer::new(
+                        recipe,
+                        *mode,
+                        *target_runs,
+                        *stock_runs,
+                        *batch,
+                        *fixed_cost,
+                        workforce(labor)?,
+                        waste(waste_config)?,
+                        *lead_time,
+                        base(inventory)?,
+                        *money_balance,
+                        *prestige,
+                    );
+                    producer.market_tags = market_tags.clone();
+                    sim.add_entity(name, region_id(region)?, Box::new(producer));
+                }
+                EntityConfig::Pop {
+                    name, region, goods, needs: needs_config, labor, population, birth_rate, death_rate, storage: storage_config,
+                    expectations: expectations_config, script: source, market_tags, money_balance, prestige, standard_of_living,
+                } => {
+                    // A good with grades is bought in all of them, the best first, and the inventory
+                    // is of the lowest
+                    let mut pop_goods: Vec<(GoodUid, &PopGoodConfig, bool)> = vec![];
+                    let mut graded: Vec<(&String, Vec<GoodUid>)> = vec![];
+                    for x in goods.iter() {
+                        let grades = registry.grades(&x.good);
+                        if grades.is_empty() {
+                            pop_goods.push((uid(&x.good)?, x, true));
+                            continue;
+                        }
+                        pop_goods.extend(grades.iter().rev().map(|good_uid| (*good_uid, x, *good_uid == grades[0])));
+                        graded.push((&x.good, grades.into_iter().rev().collect()));
+                    }
+                    let goods_in_prio_order: Vec<GoodUid> = pop_goods.iter().map(|x| x.0).collect();
+                    let labor_offered = match labor {
+                        Some(x) => {
+                            let good_uid = uid(&x.good)?;
+                            Some((good_uid, registry.to_base_units(good_uid, x.per_tick)))
+                        }
+                        None => None,
+                    };
+                    let base = |f: fn(&PopGoodConfig) -> f64| -> Vec<Quantity> {
+                        pop_goods.iter().map(|(good_uid, x, _)| registry.to_base_units(*good_uid, f(x))).collect()
+                    };
+                    let inventory = pop_goods.iter()
+                        .map(|(good_uid, x, stocked)| registry.to_base_units(*good_uid, x.inventory) * *stocked as Quantity)
+                        .collect();
+                    let mut needs = vec![];
+                    for x in needs_config.iter() {
+                        let mut substitutes = vec![];
+                        let mut by_quality = false;
+                        for good in x.goods.iter() {
+                            let grades = match graded.iter().position(|g| g.0 == good) {
+                                Some(i) => {
+                                    by_quality = true;
+                                    graded.remove(i).1
+                                }
+                                None => vec![uid(good)?],
+                            };
+                            for good_uid in grades {
+                                if !goods_in_prio_order.contains(&good_uid) || needs.iter().any(|need: &Need| need.goods.contains(&good_uid))
+                                    || substitutes.contains(&good_uid) {
+                                    return Err(ScenarioError::Parse(format!(
+                                        "`{good}` of the need `{}` of `{name}` is not a good of the pop or is in another need", x.name)));
+                                }
+                                substitutes.push(good_uid);
+                            }
+                        }
+                        needs.push(Need { name: x.name.clone(), tier: x.tier, goods: substitutes, by_quality });
+                    }
+                    // The grades not in a need are a life need of their own
+                    for (good, grades) in graded {
+                        needs.push(Need { name: good.clone(), tier: NeedTier::Life, goods: grades, by_quality: true });
+                    }
+                    sim.add_entity(name, region_id(region)?, Box::new(BasicPop {
+                        needs: Needs::new(needs),
+                        script: script(name, source)?,
+                        market_tags: market_tags.clone(),
+                        ..BasicPop::new(
+                            goods_in_prio_order.clone(),
+                            inventory,
+                            base(|x| x.desired),
+                            base(|x| x.consumed),
+                            labor_offered,
+                            Demography::new(*population, *birth_rate, *death_rate),
+                            storage(storage_config),
+                            expectations(expectations_config),
+                            *money_balance,
+                            *prestige,
+                            *standard_of_living,
+                        )
+                    }));
+                }
+                EntityConfig::External {
+                    name, region, command, connect, goods, timeout, fallback, money_balance, prestige,
+                } => {
+                    let endpoint = match (command.is_empty(), connect) {
+                        (false, None) => Endpoint::Command(command.clone()),
+                        (true, Some(address)) => Endpoint::Connect(address.clone()),
+                        _ => return Err(ScenarioError::Parse(format!("external entity `{name}` needs either `command` or `connect`"))),
+                    };
+                    let mut agent_goods = vec![];
+                    for (good, stock) in goods.iter() {
+                        let good_uid = uid(good)?;
+                        agent_goods.push(ExternalGood {
+                            good_uid,
+                            name: good.clone(),
+                            unit_scale: registry.unit_scale(good_uid),
+                            stock: registry.to_base_units(good_uid, *stock),
+                        });
+                    }
+                    let mut agent = ExternalAgent::new(endpoint, *timeout, *fallback, agent_goods, *money_balance, *prestige);
+                    agent.connect().map_err(|e| ScenarioError::Agent(name.clone(), e))?;
+                    sim.add_entity(name, region_id(region)?, Box::new(agent));
+                }
+            }
+        }
+        // A good without any market in the region is left to the entity, that goes without it
+        for (id, name, entity) in sim.entities.iter() {
+            let (goods, metadata) = entity.get_required_markets();
+            let region = &sim.regions[sim.entities.region(id)];
+            let seen: Vec<GoodUid> = region.eligible_markets(&goods, &metadata).into_iter()
+                .map(|x| region.markets[x].good_uid())
+                .collect();
+            if let Some(good) = goods.iter().find(|x| !seen.contains(x) && region.market(**x).is_some()) {
+                return Err(ScenarioError::NoMarket(name.to_owned(), registry.get_good_name(*good)));
+            }
+        }
+        if let Some(freeze) = &self.simulation.freeze {
+            if let Some(name) = freeze.entities.iter().find(|x| !sim.entities.contains(x)) {
+                return Err(ScenarioError::UnknownEntity(name.to_owned()));
+            }
+            sim.freeze = Some(FreezePlan::new(freeze.entities.clone(), freeze.at, freeze.record));
+        }
+        for route in self.routes.iter() {
+            let good_uid = uid(&route.good)?;
+            let (from, to) = (region_id(&Some(route.from.clone()))?, region_id(&Some(route.to.clone()))?);
+            let (a, b) = (sim.regions[from].currency, sim.regions[to].currency);
+            if a != b && !sim.fx.iter().any(|x| x.trades(a, b)) {
+                return Err(ScenarioError::Parse(format!(
+                    "route `{}` needs an exchange of {} and {}", route.name, sim.currencies[a], sim.currencies[b])));
+            }
+            sim.add_route(TradeRoute::new(
+                &route.name,
+                good_uid,
+                from,
+                to,
+                registry.to_base_units(good_uid, route.capacity),
+                route.transport_cost,
+                route.money_balance,
+            ));
+        }
+        // The clearing house pays every region at the same prices
+        if !self.nationals.is_empty() && sim.regions.iter().any(|x| x.currency != sim.regions[0].currency) {
+            return Err(ScenarioError::Parse("the national markets need the regions to share a currency".to_owned()));
+        }
+        for national in self.nationals.iter() {
+            let good_uid = uid(&national.good)?;
+            sim.nationals.push(NationalMarket::new(good_uid, registry.unit_scale(good_uid), national.price));
+        }
+        if let Some(policy) = &self.pollution {
+            sim.pollution = Some(Pollution::new(policy.clone(), sim.regions.len()));
+        }
+        if let Some(config) = &self.bank {
+            let mut bank = Bank::new(config.money_balance, config.deposit_rate, config.loan_rate);
+            for account in config.accounts.iter() {
+                let entity = sim.entities.find(&account.entity)
+                    .ok_or_else(|| ScenarioError::UnknownEntity(account.entity.clone()))?;
+                // The bank is in the first region and keeps the accounts in its currency
+                if sim.regions[sim.entities.region(entity)].currency != sim.regions[0].currency {
+                    return Err(ScenarioError::Parse(format!(
+                        "`{}` can't have an account, the bank pays in {}", account.entity, sim.currencies[sim.regions[0].currency])));
+                }
+                bank.open_account(entity, account.cash, account.credit_limit);
+            }
+            sim.bank = Some(bank);
+        }
+        if let Some(config) = &self.central_bank {
+            let mut basket = vec![];
+            for (good, quantity) in config.basket.iter() {
+                let good_uid = uid(good)?;
+                basket.push((good_uid, registry.to_base_units(good_uid, *quantity), registry.unit_scale(good_uid)));
+            }
+            if config.basket.is_empty() {
+                for market in self.markets.iter() {
+                    let good = match market {
+                        MarketConfig::Test { good, .. } | MarketConfig::OrderBook { good, .. } => good,
+                        MarketConfig::Labor { .. } => continue,
+                    };
+                    for (good_uid, _) in priced_goods(good, 0.)? {
+                        if !basket.iter().any(|x| x.0 == good_uid) {
+                            basket.push((good_uid, registry.to_base_units(good_uid, 1.), registry.unit_scale(good_uid)));
+                        }
+                    }
+                }
+            }
+            let mut recipients = vec![];
+            for name in config.recipients.iter() {
+                recipients.push(sim.entities.find(name).ok_or_else(|| ScenarioError::UnknownEntity(name.clone()))?);
+            }
+            if config.recipients.is_empty() {
+                recipients = self.entities.iter().zip(sim.entities.ids())
+                    .filter(|(entity, _)| entity.kind() == "pop")
+                    .map(|(_, id)| id)
+                    .collect();
+            }
+            // Like the bank, the central bank creates the money of the first region
+            if let Some(id) = recipients.iter().find(|x| sim.currency_of(**x) != sim.home_currency()) {
+                return Err(ScenarioError::Parse(format!(
+                    "`{}` can't receive base money, the central bank creates {}", sim.entities.name(*id), sim.currencies[sim.home_currency()])));
+            }
+            sim.central_bank = Some(CentralBank::new(config.policy.clone(), basket, recipients));
+        }
+        if let Some(config) = &self.equity {
+            let find = |name: &String| sim.entities.find(name).ok_or_else(|| ScenarioError::UnknownEntity(name.clone()));
+            let mut investors = config.investors.iter().map(find).collect::<Result<Vec<_>, _>>()?;
+            if config.investors.is_empty() {
+                investors = self.entities.iter().zip(sim.entities.ids())
+                    .filter(|(entity, _)| entity.kind() == "pop")
+                    .map(|(_, id)| id)
+                    .collect();
+            }
+            let mut equity = EquityMarket::new(config.policy.clone(), investors);
+            for firm in config.firms.iter() {
+                let id = find(&firm.firm)?;
+                if equity.firms.iter().any(|x| x.firm == id) {
+                    return Err(ScenarioError::Parse(format!("`{}` is listed twice in the firms", firm.firm)));
+                }
+                if firm.owners.values().sum::<u64>() > firm.shares {
+                    return Err(ScenarioError::Parse(format!("the owners of `{}` hold more than its {} shares", firm.firm, firm.shares)));
+                }
+                let owners = firm.owners.iter().map(|(name, shares)| Ok((find(name)?, *shares)))
+                    .collect::<Result<Vec<_>, ScenarioError>>()?;
+                equity.add_firm(id, firm.shares, firm.payout, owners);
+            }
+            sim.equity = Some(equity);
+        }
+        let mut events = vec![];
+        for config in self.monetary.iter() {
+            if let Some(kind) = config.kind.as_ref().filter(|x| !["rgo", "producer", "recipe", "pop"].contains(&x.as_str())) {
+                return Err(ScenarioError::Parse(format!("unknown entity kind `{kind}`")));
+            }
+            if let Some(name) = config.entities.iter().find(|x| !sim.entities.contains(x)) {
+                return Err(ScenarioError::UnknownEntity(name.to_owned()));
+            }
+            let everybody = config.entities.is_empty() && config.kind.is_none();
+            let entities = self.entities.iter().zip(sim.entities.iter())
+                .filter(|(entity, (_, name, _))| {
+                    everybody || config.entities.iter().any(|x| x == name) || config.kind.as_deref() == Some(entity.kind())
+                })
+                .map(|(_, (id, _, _))| id)
+                .collect();
+            events.push(MonetaryEvent {
+                at: config.at,
+                every: config.every,
+                until: config.until,
+                entities,
+                action: config.action,
+            });
+        }
+        sim.monetary = MonetaryAuthority::new(events);
+        let mut shocks = vec![];
+        for config in self.shocks.iter() {
+            let trigger = match (config.at, config.chance) {
+                (Some(at), None) => ShockTrigger::At(at),
+                (None, Some(chance)) => ShockTrigger::Chance(chance),
+                _ => return Err(ScenarioError::Parse(format!("shock `{}` needs either `at` or `chance`", config.name))),
+            };
+            // The entities of the kind that takes the shock
+            let entity = |name: &String, kind: &str| {
+                let id = sim.entities.find(name).ok_or_else(|| ScenarioError::UnknownEntity(name.to_owned()))?;
+                match self.entities[id.0].kind() == kind {
+                    true => Ok(id),
+                    false => Err(ScenarioError::Parse(format!("shock `{}` needs a {kind}, `{name}` is not", config.name))),
+                }
+            };
+            let shock: Box<dyn Shock> = match &config.effect {
+                ShockEffectConfig::Drought { entity: name, factor } => Box::new(Drought { entity: entity(name, "rgo")?, factor: *factor }),
+                ShockEffectConfig::DemandSpike { entity: name, factor } => Box::new(DemandSpike { entity: entity(name, "pop")?, factor: *factor }),
+                ShockEffectConfig::Embargo { route } => Box::new(Embargo {
+                    route: sim.routes.iter().position(|x| &x.name == route)
+                        .ok_or_else(|| ScenarioError::Parse(format!("shock `{}` references unknown route `{route}`", config.name)))?,
+                }),
+                ShockEffectConfig::MarketTax { region, good, rate } => Box::new(MarketTax { region: region_id(region)?, good_uid: uid(good)?, rate: *rate }),
+            };
+            let rng = matches!(trigger, ShockTrigger::Chance(_)).then(|| sim.rng_streams.stream(&format!("shock/{}", config.name)));
+            shocks.push(ScheduledShock::new(&config.name, trigger, config.duration, shock, rng));
+        }
+        sim.shocks = ShockSchedule::new(shocks);
+        Ok(sim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = r#"
+        entities = []
+
+        [simulation]
+        ticks = 10
+
+        [[goods]]
+        name = "Grain"
+
+        [[markets]]
+        kind = "test"
+        good = "Grain"
+        price = 1.0
+    "#;
+
+    #[test]
+    fn malformed_scenarios_are_parse_errors() {
+        assert!(matches!(Scenario::from_toml("[simulation\nticks = 10"), Err(ScenarioError::Parse(_))));
+        assert!(matches!(Scenario::from_toml("[simulation]\nticks = \"ten\""), Err(ScenarioError::Parse(_))));
+        assert!(matches!(Scenario::from_ron("(simulation: (ticks: 10)"), Err(ScenarioError::Parse(_))));
+    }
+
+    #[test]
+    fn unknown_references_are_reported_by_name() {
+        let scenario = Scenario::from_toml(&MINIMAL.replace("good = \"Grain\"", "good = \"Gold\"")).unwrap();
+        assert!(matches!(scenario.build().err().unwrap(), ScenarioError::UnknownGood(name) if name == "Gold"));
+        let source = MINIMAL.replace("ticks = 10", "ticks = 10\nfreeze = { entities = [\"Nobody\"], at = 5 }");
+        let scenario = Scenario::from_toml(&source).unwrap();
+        assert!(matches!(scenario.build().err().unwrap(), ScenarioError::UnknownEntity(name) if name == "Nobody"));
+    }
+
+    #[test]
+    fn unknown_extensions_are_refused_before_reading() {
+        let error = Scenario::load(Path::new("missing.json")).unwrap_err();
+        assert!(matches!(error, ScenarioError::UnknownFormat(ext) if ext == "json"));
+        assert!(matches!(Scenario::load(Path::new("missing")).unwrap_err(), ScenarioError::UnknownFormat(_)));
+        assert!(matches!(Scenario::load(Path::new("missing.toml")).unwrap_err(), ScenarioError::Io(_)));
+    }
+}
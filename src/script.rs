@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "scripting")]
+use crate::goods;
+use crate::Quantity;
+
+// Decisions of an entity overridden by a script in Rhai written in the scenario, with the
+// `scripting` feature. A decision is overridden by the function of the script with its name,
+// see the actions of the DecisionLog of the entity: the function gets the inputs of the decision
+// as a map and the quantity the entity would decide, both in units, and returns the quantity to
+// use. The entity keeps the result within what it can do, it never sells what it doesn't have
+// or buys what it can't pay.
+//
+//     fn sell(inputs, quantity) {
+//         if inputs.stock > 2.0 * inputs.target { quantity } else { quantity / 2.0 }
+//     }
+
+// Operations a single call can take before it is stopped, against endless loops
+#[cfg(feature = "scripting")]
+const MAX_OPERATIONS: u64 = 100_000;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ScriptPolicy {
+    // Kept to compile the script again when a checkpoint is restored
+    source: Option<String>,
+    #[cfg(feature = "scripting")]
+    #[serde(skip)]
+    compiled: Option<Compiled>,
+}
+
+#[cfg(feature = "scripting")]
+struct Compiled {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+impl ScriptPolicy {
+    #[cfg(feature = "scripting")]
+    pub fn new(source: &str) -> Result<ScriptPolicy, String> {
+        let mut policy = ScriptPolicy { source: Some(source.to_owned()), compiled: None };
+        policy.compile()?;
+        Ok(policy)
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn new(_source: &str) -> Result<ScriptPolicy, String> {
+        Err("the scripts need ecosim built with the scripting feature".to_owned())
+    }
+
+    #[cfg(feature = "scripting")]
+    fn compile(&mut self) -> Result<&Compiled, String> {
+        if self.compiled.is_none() {
+            let mut engine = rhai::Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            let ast = engine.compile(self.source.as_deref().unwrap_or_default()).map_err(|e| e.to_string())?;
+            self.compiled = Some(Compiled { engine, ast });
+        }
+        Ok(self.compiled.as_ref().unwrap())
+    }
+
+    // The script has a function for `action`
+    #[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+    pub fn overrides(&mut self, action: &str) -> bool {
+        #[cfg(feature = "scripting")]
+        if self.source.is_some() {
+            return self.compile().is_ok_and(|x| x.ast.iter_functions().any(|f| f.name == action));
+        }
+        false
+    }
+
+    // The quantity of the decision `action` of a good with `unit_scale`, the one of the script
+    // if it overrides the decision. Errors of the script keep the quantity of the entity.
+    #[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+    pub fn decide(&mut self, action: &str, inputs: &[(&'static str, f64)], quantity: Quantity, unit_scale: Quantity) -> Quantity {
+        #[cfg(feature = "scripting")]
+        if self.overrides(action) {
+            let compiled = self.compiled.as_ref().unwrap();
+            let inputs: rhai::Map = inputs.iter().map(|(name, value)| ((*name).into(), rhai::Dynamic::from_float(*value))).collect();
+            let units = goods::to_units(quantity, unit_scale);
+            let result = compiled.engine.call_fn::<rhai::Dynamic>(&mut rhai::Scope::new(), &compiled.ast, action, (inputs, units));
+            let units = match result.map(|x| x.as_float().or_else(|_| x.as_int().map(|x| x as f64))) {
+                Ok(Ok(units)) => units,
+                Ok(Err(kind)) => {
+                    eprintln!("script: {action} returned a {kind} instead of a number");
+                    return quantity;
+                }
+                Err(e) => {
+                    eprintln!("script: {action} failed, {e}");
+                    return quantity;
+                }
+            };
+            return (units.max(0.) * unit_scale as f64) as Quantity;
+        }
+        quantity
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_overrides_in_units() {
+        let mut policy = ScriptPolicy::new("fn sell(inputs, quantity) { inputs.stock - quantity } fn buy(inputs, quantity) { throw 1 }").unwrap();
+        assert!(policy.overrides("sell") && !policy.overrides("produce"));
+        assert_eq!(policy.decide("sell", &[("stock", 10.)], 400, 100), 600);
+        assert_eq!(policy.decide("produce", &[], 400, 100), 400);
+        // Errors keep the quantity of the entity
+        assert_eq!(policy.decide("buy", &[], 400, 100), 400);
+        assert!(ScriptPolicy::new("fn sell(").is_err());
+    }
+}
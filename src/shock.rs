@@ -0,0 +1,231 @@
+use std::fmt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::engine::Simulation;
+use crate::events::SimEvent;
+use crate::region::RegionId;
+use crate::rng::SimRng;
+use crate::{EntityId, GoodUid, Quantity};
+
+// Shocks hit the simulation to study how it absorbs them: a drought cuts the production of an
+// RGO, a demand spike multiplies the consumption of a pop, an embargo closes a trade route and a
+// tax takes a share of the value traded in a market. A shock starts at a given tick, or at random
+// with a chance every tick it isn't running, lasts `duration` ticks and is then undone. The
+// shocks start and end before the production, and the ones hitting the same target add up.
+
+#[typetag::serde(tag = "kind")]
+pub trait Shock: Send + fmt::Debug {
+    // False when the target doesn't take the shock
+    fn start(&mut self, sim: &mut Simulation) -> bool;
+    // Undoes the start
+    fn end(&mut self, sim: &mut Simulation);
+}
+
+// The production of the RGO changes by `factor`, 0.5 halves it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Drought {
+    pub entity: EntityId,
+    pub factor: f64,
+}
+
+#[typetag::serde]
+impl Shock for Drought {
+    fn start(&mut self, sim: &mut Simulation) -> bool {
+        sim.entities[self.entity].shock_production(self.factor - 1.)
+    }
+
+    fn end(&mut self, sim: &mut Simulation) {
+        sim.entities[self.entity].shock_production(1. - self.factor);
+    }
+}
+
+// The consumption and the desired inventory of the pop change by `factor`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DemandSpike {
+    pub entity: EntityId,
+    pub factor: f64,
+}
+
+#[typetag::serde]
+impl Shock for DemandSpike {
+    fn start(&mut self, sim: &mut Simulation) -> bool {
+        sim.entities[self.entity].shock_consumption(self.factor - 1.)
+    }
+
+    fn end(&mut self, sim: &mut Simulation) {
+        sim.entities[self.entity].shock_consumption(1. - self.factor);
+    }
+}
+
+// The route neither buys nor sells, the goods in transit wait for its end
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Embargo {
+    pub route: usize,
+}
+
+#[typetag::serde]
+impl Shock for Embargo {
+    fn start(&mut self, sim: &mut Simulation) -> bool {
+        sim.routes[self.route].embargoes += 1;
+        true
+    }
+
+    fn end(&mut self, sim: &mut Simulation) {
+        sim.routes[self.route].embargoes -= 1;
+    }
+}
+
+// Both sides of every trade of the entities in the market pay `rate` of its value to the
+// government
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarketTax {
+    pub region: RegionId,
+    pub good_uid: GoodUid,
+    pub rate: f64,
+}
+
+#[typetag::serde]
+impl Shock for MarketTax {
+    fn start(&mut self, sim: &mut Simulation) -> bool {
+        *sim.regions[self.region].taxes.entry(self.good_uid).or_insert(0.) += self.rate;
+        true
+    }
+
+    fn end(&mut self, sim: &mut Simulation) {
+        *sim.regions[self.region].taxes.entry(self.good_uid).or_insert(0.) -= self.rate;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShockTrigger {
+    At(u64),
+    // Probability to start in a tick
+    Chance(f64),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduledShock {
+    pub name: String,
+    pub trigger: ShockTrigger,
+    // Ticks the shock lasts, 0 for ever
+    pub duration: u64,
+    pub shock: Box<dyn Shock>,
+    // Stream of its own for the chance triggers
+    rng: Option<SimRng>,
+    // Tick of the end of the running shock, u64::MAX if it never ends
+    ends: Option<u64>,
+    // A shock at a tick happens once
+    over: bool,
+}
+
+impl ScheduledShock {
+    pub fn new(name: &str, trigger: ShockTrigger, duration: u64, shock: Box<dyn Shock>, rng: Option<SimRng>) -> ScheduledShock {
+        ScheduledShock { name: name.to_owned(), trigger, duration, shock, rng, ends: None, over: false }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.ends.is_some()
+    }
+
+    fn triggers(&mut self, tick: u64) -> bool {
+        match self.trigger {
+            ShockTrigger::At(at) => tick == at,
+            ShockTrigger::Chance(chance) => self.rng.as_mut().is_some_and(|x| x.gen_bool(chance.clamp(0., 1.))),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ShockSchedule {
+    shocks: Vec<ScheduledShock>,
+}
+
+impl ShockSchedule {
+    pub fn new(shocks: Vec<ScheduledShock>) -> ShockSchedule {
+        ShockSchedule { shocks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shocks.is_empty()
+    }
+
+    pub fn shocks(&self) -> &[ScheduledShock] {
+        &self.shocks
+    }
+
+    // Ends the shocks over and starts the ones of the tick
+    pub fn apply(&mut self, sim: &mut Simulation) {
+        let tick = sim.tick;
+        for scheduled in self.shocks.iter_mut() {
+            if scheduled.ends.is_some_and(|x| x <= tick) {
+                scheduled.shock.end(sim);
+                scheduled.ends = None;
+                scheduled.over = matches!(scheduled.trigger, ShockTrigger::At(_));
+                if sim.events.is_active() {
+                    sim.events.emit(tick, SimEvent::ShockEnded { shock: scheduled.name.clone() });
+                }
+            }
+            if scheduled.over || scheduled.is_running() || !scheduled.triggers(tick) {
+                continue;
+            }
+            if !scheduled.shock.start(sim) {
+                continue;
+            }
+            scheduled.ends = Some(match scheduled.duration {
+                0 => u64::MAX,
+                duration => tick + duration,
+            });
+            if sim.events.is_active() {
+                sim.events.emit(tick, SimEvent::ShockStarted { shock: scheduled.name.clone() });
+            }
+        }
+    }
+}
+
+// A quantity of a target changed by the shocks
+pub fn shocked(quantity: Quantity, change: f64) -> Quantity {
+    if change == 0. {
+        return quantity;
+    }
+    (quantity as f64 * (1. + change).max(0.)) as Quantity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::goods::GoodRegistry;
+    use crate::region::TradeRoute;
+
+    #[test]
+    fn shocks_start_at_their_tick_and_are_undone_after_their_duration() {
+        let mut sim = Simulation::new(GoodRegistry::default(), 0);
+        let from = sim.add_region("North");
+        let to = sim.add_region("South");
+        sim.add_route(TradeRoute::new("grain", 0, from, to, 100, 1., 1000.));
+        let mut shocks = ShockSchedule::new(vec![
+            ScheduledShock::new("embargo", ShockTrigger::At(2), 3, Box::new(Embargo { route: 0 }), None),
+            ScheduledShock::new("tax", ShockTrigger::At(3), 0, Box::new(MarketTax { region: to, good_uid: 0, rate: 0.1 }), None),
+        ]);
+        let mut embargoed = vec![];
+        for tick in 0..8 {
+            sim.tick = tick;
+            shocks.apply(&mut sim);
+            embargoed.push(sim.routes[0].embargoes);
+        }
+        assert_eq!(embargoed, vec![0, 0, 1, 1, 1, 0, 0, 0]);
+        assert!(!shocks.shocks()[0].is_running() && shocks.shocks()[1].is_running());
+        assert_eq!(sim.regions[to].taxes[&0], 0.1);
+    }
+
+    #[test]
+    fn taxed_trades_pay_the_government() {
+        let scenario = crate::scenario::Scenario::from_toml(include_str!("../scenarios/shocks.toml")).unwrap();
+        let mut sim = scenario.build().unwrap();
+        while sim.tick < 14 {
+            sim.step();
+        }
+        assert_eq!(sim.recorder.series("shock/drought").unwrap()[..8], [0., 0., 0., 1., 1., 1., 1., 0.]);
+        assert!(sim.government.balances().iter().any(|x| x.amount > 0.));
+    }
+}
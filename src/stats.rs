@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use crate::{Price, Quantity, TradeReport};
+
+// What a market did in its last ticks. Every market records the tick when its state is cleared,
+// so during a tick the stats cover the ticks before it: entities can look at them to decide
+// their orders. The quantities are in base units.
+
+const DEFAULT_WINDOW: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TickStats {
+    // Price of the trade
+    pub price: Price,
+    pub traded: Quantity,
+    pub unfilled_buy: Quantity,
+    pub unfilled_sell: Quantity,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarketStats {
+    // Ticks of the averages
+    window: usize,
+    // The last `window` ticks, the oldest first
+    history: VecDeque<TickStats>,
+}
+
+impl Default for MarketStats {
+    fn default() -> MarketStats {
+        MarketStats::new(DEFAULT_WINDOW)
+    }
+}
+
+impl MarketStats {
+    pub fn new(window: usize) -> MarketStats {
+        MarketStats { window: window.max(1), history: VecDeque::new() }
+    }
+
+    pub fn record(&mut self, price: Price, report: &TradeReport) {
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(TickStats {
+            price,
+            traded: report.traded,
+            unfilled_buy: report.unfilled_buy,
+            unfilled_sell: report.unfilled_sell,
+        });
+    }
+
+    fn mean(&self, value: impl Fn(&TickStats) -> f64) -> Option<f64> {
+        match self.history.is_empty() {
+            true => None,
+            false => Some(self.history.iter().map(value).sum::<f64>() / self.history.len() as f64),
+        }
+    }
+
+    pub fn mean_price(&self) -> Option<Price> {
+        self.mean(|x| x.price)
+    }
+
+    pub fn mean_traded(&self) -> Option<f64> {
+        self.mean(|x| x.traded as f64)
+    }
+
+    // Unfilled buy quantity less the unfilled sell quantity, the pressure on the price
+    pub fn mean_excess_demand(&self) -> Option<f64> {
+        self.mean(|x| x.unfilled_buy as f64 - x.unfilled_sell as f64)
+    }
+
+    // Standard deviation of the relative price changes between the ticks of the window, None
+    // with less than two ticks
+    pub fn volatility(&self) -> Option<f64> {
+        let changes: Vec<f64> = self.history.iter().zip(self.history.iter().skip(1))
+            .filter(|(a, _)| a.price > 0.)
+            .map(|(a, b)| b.price / a.price - 1.)
+            .collect();
+        if changes.is_empty() {
+            return None;
+        }
+        let mean = changes.iter().sum::<f64>() / changes.len() as f64;
+        let variance = changes.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / changes.len() as f64;
+        Some(variance.sqrt())
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::goods::GoodRegistry;
+use crate::waste::{WasteKind, WasteRecord};
+use crate::{GoodUid, Quantity};
+
+// Goods in stock don't keep forever. Every tick a perishable good loses the fraction `decay` of
+// its stock, see Good. An entity has room for `capacity` units of all its goods together: what
+// doesn't fit is dumped, every good in proportion to its stock, or kept paying `overflow_cost`
+// per unit every tick when the entity rents the room. The rented room the entity has no money
+// for is dumped too. Spoiled and dumped goods are waste.
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Storage {
+    // Units of all the goods together, 0 without a limit
+    capacity: f64,
+    // Per unit above the capacity per tick, 0 dumps what is above the capacity
+    overflow_cost: f64,
+    // Base units rotten but not whole yet, per good
+    rotting: BTreeMap<GoodUid, f64>,
+    records: Vec<WasteRecord>,
+}
+
+impl Storage {
+    pub fn new(capacity: f64, overflow_cost: f64) -> Storage {
+        Storage { capacity, overflow_cost, ..Default::default() }
+    }
+
+    // Spoil and fit the stocks in the storage. Returns the cost of the room above the capacity,
+    // never more than `money`.
+    pub fn keep(&mut self, goods: &GoodRegistry, mut stocks: Vec<(GoodUid, &mut Quantity)>, money: f64) -> f64 {
+        for (good_uid, stock) in stocks.iter_mut() {
+            let decay = goods.decay(*good_uid);
+            if decay <= 0. {
+                continue;
+            }
+            let rotting = self.rotting.entry(*good_uid).or_default();
+            *rotting += **stock as f64 * decay.min(1.);
+            let rotten = (rotting.trunc() as Quantity).min(**stock);
+            *rotting = rotting.fract();
+            self.remove(WasteKind::Spoiled, *good_uid, stock, rotten);
+        }
+        if self.capacity <= 0. {
+            return 0.;
+        }
+        let stored: f64 = stocks.iter().map(|(good_uid, stock)| goods.to_units(*good_uid, **stock)).sum();
+        if stored <= self.capacity {
+            return 0.;
+        }
+        let mut room = self.capacity;
+        let mut cost = 0.;
+        if self.overflow_cost > 0. {
+            cost = (stored - self.capacity) * self.overflow_cost;
+            if cost <= money {
+                return cost;
+            }
+            cost = money.max(0.);
+            room += cost / self.overflow_cost;
+        }
+        let ratio = (stored - room) / stored;
+        for (good_uid, stock) in stocks.iter_mut() {
+            let dumped = (**stock as f64 * ratio).ceil() as Quantity;
+            self.remove(WasteKind::Dumped, *good_uid, stock, dumped);
+        }
+        cost
+    }
+
+    fn remove(&mut self, kind: WasteKind, good_uid: GoodUid, stock: &mut Quantity, quantity: Quantity) {
+        let quantity = quantity.min(*stock);
+        if quantity > 0 {
+            *stock -= quantity;
+            self.records.push(WasteRecord { kind, good_uid, quantity });
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<WasteRecord> {
+        std::mem::take(&mut self.records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_room_without_money_is_dumped() {
+        let mut goods = GoodRegistry::default();
+        let grain = goods.register("Grain", 0, 0., 1.);
+        let mut storage = Storage::new(100., 0.5);
+        let mut stock = 140;
+        assert_eq!(storage.keep(&goods, vec![(grain, &mut stock)], 50.), 20.);
+        assert_eq!(stock, 140);
+        // 10$ rent 20 units above the capacity, the other 20 are dumped
+        assert_eq!(storage.keep(&goods, vec![(grain, &mut stock)], 10.), 10.);
+        assert_eq!(stock, 120);
+        assert_eq!(storage.keep(&goods, vec![(grain, &mut stock)], -5.), 0.);
+        assert_eq!(stock, 100);
+        assert_eq!(storage.take().iter().map(|x| x.quantity).sum::<Quantity>(), 40);
+    }
+}
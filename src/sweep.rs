@@ -0,0 +1,95 @@
+use std::fmt;
+use crate::scenario::{Scenario, ScenarioError};
+
+// Analysis of how robust a parameterization is: the same scenario is run with many seeds and
+// every run is classified in a regime from the trajectory of the total money and of the
+// average standard of living of the pops.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Regime {
+    // The economy shrinks or the pops get poorer
+    Collapse,
+    Steady,
+    // The economy grows and the pops don't get poorer
+    Boom,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunOutcome {
+    pub seed: u64,
+    // Relative change of the total money between the first and the last tick
+    pub money_growth: f64,
+    // Average change per tick of the mean standard of living, 0 without pops
+    pub sol_trend: f64,
+    pub regime: Regime,
+}
+
+impl RunOutcome {
+    fn classify(money_growth: f64, sol_trend: f64, tolerance: f64) -> Regime {
+        if money_growth < -tolerance || sol_trend < -tolerance {
+            Regime::Collapse
+        } else if money_growth > tolerance {
+            Regime::Boom
+        } else {
+            Regime::Steady
+        }
+    }
+}
+
+pub struct SweepReport {
+    pub ticks: u64,
+    pub burn_in: u64,
+    pub outcomes: Vec<RunOutcome>,
+}
+
+// Runs the scenario once for every seed. `tolerance` is the relative change of the money, and
+// the change per tick of the standard of living, still considered steady.
+pub fn sweep(scenario: &mut Scenario, seeds: impl Iterator<Item=u64>, tolerance: f64) -> Result<SweepReport, ScenarioError> {
+    let mut outcomes = vec![];
+    for seed in seeds {
+        scenario.simulation.seed = seed;
+        let mut sim = scenario.build()?;
+        // The trajectory is measured from the end of the burn-in
+        while sim.tick < sim.burn_in.min(scenario.simulation.ticks) {
+            sim.step();
+        }
+        let money_start = sim.total_money();
+        let sol_start = sim.average_standard_of_living().unwrap_or(0.);
+        while sim.tick < scenario.simulation.ticks && !sim.should_stop() {
+            sim.step();
+        }
+        let money_growth = match money_start {
+            x if x > 0. => sim.total_money() / x - 1.,
+            _ => 0.,
+        };
+        let ticks = sim.tick.saturating_sub(sim.burn_in).max(1) as f64;
+        let sol_trend = (sim.average_standard_of_living().unwrap_or(0.) - sol_start) / ticks;
+        let regime = RunOutcome::classify(money_growth, sol_trend, tolerance);
+        outcomes.push(RunOutcome { seed, money_growth, sol_trend, regime });
+    }
+    Ok(SweepReport { ticks: scenario.simulation.ticks, burn_in: scenario.simulation.burn_in, outcomes })
+}
+
+impl fmt::Display for SweepReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} runs of {} ticks, burn-in {}", self.outcomes.len(), self.ticks, self.burn_in)?;
+        writeln!(f, "{:>10} {:>6} {:>8} {:>14} {:>10}  seeds", "regime", "runs", "freq", "money growth", "SoL/tick")?;
+        for regime in [Regime::Collapse, Regime::Steady, Regime::Boom] {
+            let runs: Vec<&RunOutcome> = self.outcomes.iter().filter(|x| x.regime == regime).collect();
+            if runs.is_empty() {
+                continue;
+            }
+            let n = runs.len() as f64;
+            let seeds: Vec<String> = runs.iter().take(8).map(|x| x.seed.to_string()).collect();
+            let more = if runs.len() > 8 { ", ..." } else { "" };
+            writeln!(f, "{:>10} {:>6} {:>7.1}% {:>+13.1}% {:>+10.3}  {}{more}",
+                     format!("{regime:?}"),
+                     runs.len(),
+                     n / self.outcomes.len() as f64 * 100.,
+                     runs.iter().map(|x| x.money_growth).sum::<f64>() / n * 100.,
+                     runs.iter().map(|x| x.sol_trend).sum::<f64>() / n,
+                     seeds.join(", "))?;
+        }
+        Ok(())
+    }
+}
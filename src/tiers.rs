@@ -0,0 +1,99 @@
+use std::collections::{BTreeMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use crate::OrderInfo;
+
+// The orders of a side of TestMarket are grouped in tiers by prestige. The trade meets the top
+// tier of the buyers with the top tier of the sellers: the smaller tier is filled, the larger
+// one shares what there is and meets the next tier of the other side. When both tiers are
+// exhausted together both sides move on. The trade ends when a side has no tiers left, the
+// tiers of the other side that are left keep their orders untraded and are reported.
+
+// How the orders of a side are grouped in tiers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TierPolicy {
+    // A tier for every prestige, the most prestigious orders are filled first
+    #[default]
+    Prestige,
+    // All the orders in one tier, so every order of the larger side gets its share
+    Pooled,
+}
+
+// The tiers that are exhausted after a trade of the current ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Exhausted {
+    Buy,
+    Sell,
+    Both,
+}
+
+// The trade between the tiers of the two sides, from the top ones
+pub(crate) struct TierWalk {
+    buy_tiers: VecDeque<Vec<OrderInfo>>,
+    sell_tiers: VecDeque<Vec<OrderInfo>>,
+    // Orders of the tiers that have been met
+    buy_done: Vec<OrderInfo>,
+    sell_done: Vec<OrderInfo>,
+}
+
+impl TierPolicy {
+    fn tiers(self, orders: Vec<OrderInfo>) -> VecDeque<Vec<OrderInfo>> {
+        if orders.is_empty() {
+            return VecDeque::new();
+        }
+        match self {
+            TierPolicy::Prestige => {
+                // Ordered maps keep the runs deterministic
+                let mut tiers = BTreeMap::<i64, Vec<OrderInfo>>::new();
+                for x in orders {
+                    tiers.entry(x.prestige as i64).or_default().push(x);
+                }
+                tiers.into_values().rev().collect()
+            }
+            TierPolicy::Pooled => VecDeque::from([orders]),
+        }
+    }
+}
+
+impl TierWalk {
+    pub fn new(policy: TierPolicy, buy_orders: Vec<OrderInfo>, sell_orders: Vec<OrderInfo>) -> TierWalk {
+        TierWalk {
+            buy_tiers: policy.tiers(buy_orders),
+            sell_tiers: policy.tiers(sell_orders),
+            buy_done: vec![],
+            sell_done: vec![],
+        }
+    }
+
+    // The current tiers of the buyers and of the sellers, None when the trade is over
+    pub fn current(&mut self) -> Option<(&mut Vec<OrderInfo>, &mut Vec<OrderInfo>)> {
+        match (self.buy_tiers.front_mut(), self.sell_tiers.front_mut()) {
+            (Some(buy), Some(sell)) => Some((buy, sell)),
+            _ => None,
+        }
+    }
+
+    // Close the exhausted tiers. When both are exhausted both sides move on, even if only one
+    // of them has another tier: the other one has nothing left to meet it.
+    pub fn advance(&mut self, exhausted: Exhausted) {
+        if matches!(exhausted, Exhausted::Buy | Exhausted::Both) {
+            self.buy_done.extend(self.buy_tiers.pop_front().into_iter().flatten());
+        }
+        if matches!(exhausted, Exhausted::Sell | Exhausted::Both) {
+            self.sell_done.extend(self.sell_tiers.pop_front().into_iter().flatten());
+        }
+    }
+
+    // The buy and sell orders, the ones of the tiers met first, and the number of tiers left
+    // untraded. The current tier of the side left over may have traded a part.
+    pub fn finish(self) -> (Vec<OrderInfo>, Vec<OrderInfo>, usize) {
+        let untraded = self.buy_tiers.iter().chain(self.sell_tiers.iter())
+            .filter(|tier| tier.iter().all(|x| x.traded_quantity == 0))
+            .count();
+        let mut buy_orders = self.buy_done;
+        buy_orders.extend(self.buy_tiers.into_iter().flatten());
+        let mut sell_orders = self.sell_done;
+        sell_orders.extend(self.sell_tiers.into_iter().flatten());
+        (buy_orders, sell_orders, untraded)
+    }
+}
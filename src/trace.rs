@@ -0,0 +1,112 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use crate::goods::GoodRegistry;
+use crate::{GoodUid, Quantity};
+
+// Decision trace: what an entity looked at (prices, budgets, targets) and what it did about it.
+// Entities always log their decisions, the engine keeps only the ones of the traced entities
+// and they are written as JSON lines at the end of the run.
+
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub action: &'static str,
+    pub good_uid: Option<GoodUid>,
+    // What the entity looked at to decide, quantities in units of their good
+    pub inputs: Vec<(&'static str, f64)>,
+    // Quantity ordered or produced, in base units of the good
+    pub quantity: Quantity,
+}
+
+// Decisions of a single entity, collected by the engine at the end of the tick
+#[derive(Debug, Default)]
+pub struct DecisionLog {
+    decisions: Vec<Decision>,
+}
+
+impl DecisionLog {
+    pub fn record(&mut self, action: &'static str, good_uid: Option<GoodUid>, inputs: Vec<(&'static str, f64)>,
+                  quantity: Quantity) {
+        self.decisions.push(Decision { action, good_uid, inputs, quantity });
+    }
+
+    pub fn take(&mut self) -> Vec<Decision> {
+        std::mem::take(&mut self.decisions)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+    pub tick: u64,
+    pub entity: String,
+    pub good: Option<String>,
+    pub decision: Decision,
+    // Quantity of the decision in units
+    pub quantity: f64,
+}
+
+impl Serialize for TraceRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("tick", &self.tick)?;
+        map.serialize_entry("entity", &self.entity)?;
+        map.serialize_entry("action", self.decision.action)?;
+        if let Some(good) = &self.good {
+            map.serialize_entry("good", good)?;
+        }
+        map.serialize_entry("inputs", &Inputs(&self.decision.inputs))?;
+        map.serialize_entry("quantity", &self.quantity)?;
+        map.end()
+    }
+}
+
+// The inputs as a JSON object, in the order they were logged
+struct Inputs<'a>(&'a [(&'static str, f64)]);
+
+impl Serialize for Inputs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in self.0.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DecisionTrace {
+    // Names of the traced entities
+    pub entities: Vec<String>,
+    pub records: Vec<TraceRecord>,
+}
+
+impl DecisionTrace {
+    pub fn new(entities: Vec<String>) -> DecisionTrace {
+        DecisionTrace { entities, records: vec![] }
+    }
+
+    pub fn is_traced(&self, entity: &str) -> bool {
+        self.entities.iter().any(|x| x == entity)
+    }
+
+    pub fn add(&mut self, tick: u64, entity: &str, goods: &GoodRegistry, decisions: Vec<Decision>) {
+        for decision in decisions {
+            let (good, quantity) = match decision.good_uid {
+                Some(good_uid) => (Some(goods.get_good_name(good_uid)), goods.to_units(good_uid, decision.quantity)),
+                None => (None, decision.quantity as f64),
+            };
+            self.records.push(TraceRecord { tick, entity: entity.to_owned(), good, decision, quantity });
+        }
+    }
+
+    // One JSON object per line
+    pub fn export(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        for record in self.records.iter() {
+            serde_json::to_writer(&mut out, record)?;
+            writeln!(out)?;
+        }
+        out.flush()
+    }
+}
@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::time::Duration;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols;
+use ratatui::text::Line;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use ecosim::engine::Simulation;
+
+// Live view of a running simulation: the markets, the entities and the price history of the
+// selected market. The simulation steps on its own at the chosen speed, it can be paused and
+// stepped a tick at a time. The values come from the recorder, as they are after the last tick.
+
+const HELP: &str = "space pause/resume · n step · +/- speed · ↑/↓ market · q quit";
+
+struct App {
+    ticks: u64,
+    paused: bool,
+    // Time between two ticks when running
+    delay: Duration,
+    // Index of the market in Simulation::markets
+    selected: usize,
+}
+
+pub fn run(sim: Simulation, ticks: u64) -> Result<(), Box<dyn Error>> {
+    let mut terminal = ratatui::init();
+    let mut app = App { ticks, paused: false, delay: Duration::from_millis(200), selected: 0 };
+    let result = app.run(&mut terminal, sim);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal, mut sim: Simulation) -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame, &sim))?;
+            let running = !self.paused && !self.is_over(&sim);
+            let timeout = if running { self.delay } else { Duration::from_millis(250) };
+            if !event::poll(timeout)? {
+                if running {
+                    sim.step();
+                }
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char(' ') => self.paused = !self.paused,
+                KeyCode::Char('n') => {
+                    self.paused = true;
+                    if !self.is_over(&sim) {
+                        sim.step();
+                    }
+                }
+                KeyCode::Char('+') => self.delay = (self.delay / 2).max(Duration::from_millis(10)),
+                KeyCode::Char('-') => self.delay = (self.delay * 2).min(Duration::from_secs(2)),
+                KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down => self.selected = (self.selected + 1).min(sim.markets().count().saturating_sub(1)),
+                _ => {}
+            }
+        }
+    }
+
+    fn is_over(&self, sim: &Simulation) -> bool {
+        sim.tick >= self.ticks || sim.should_stop()
+    }
+
+    fn draw(&self, frame: &mut Frame, sim: &Simulation) {
+        let [header, body, chart, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(6),
+            Constraint::Length(12),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [markets, entities] = Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(body);
+        let state = match (self.is_over(sim), self.paused) {
+            (true, _) => "ended",
+            (false, true) => "paused",
+            (false, false) => "running",
+        };
+        frame.render_widget(Line::from(format!(
+            "tick {}/{}  {state}  {}ms/tick  money {:.2}",
+            sim.tick, self.ticks, self.delay.as_millis(), sim.total_money(),
+        )).style(Style::new().add_modifier(Modifier::BOLD)), header);
+        self.draw_markets(frame, sim, markets);
+        draw_entities(frame, sim, entities);
+        self.draw_chart(frame, sim, chart);
+        frame.render_widget(Line::from(HELP).style(Style::new().fg(Color::DarkGray)), help);
+    }
+
+    fn draw_markets(&self, frame: &mut Frame, sim: &Simulation, area: Rect) {
+        let rows = sim.markets().enumerate().map(|(i, (region, market))| {
+            let label = sim.market_label(region, market.good_uid());
+            let value = |series: &str| last(sim, &format!("market/{label}/{series}"));
+            let row = Row::new(vec![
+                label.clone(),
+                value("price"),
+                value("traded"),
+                value("unfilled_buy"),
+                value("unfilled_sell"),
+            ]);
+            match i == self.selected {
+                true => row.style(Style::new().add_modifier(Modifier::REVERSED)),
+                false => row,
+            }
+        });
+        let widths = [Constraint::Fill(2), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(vec!["market", "price", "traded", "unf. buy", "unf. sell"]).style(Style::new().fg(Color::Yellow)))
+            .block(Block::bordered().title("Markets"));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_chart(&self, frame: &mut Frame, sim: &Simulation, area: Rect) {
+        let Some((region, market)) = sim.markets().nth(self.selected) else {
+            return;
+        };
+        let label = sim.market_label(region, market.good_uid());
+        let points: Vec<(f64, f64)> = sim.recorder.series(&format!("market/{label}/price")).unwrap_or(&[]).iter()
+            .zip(sim.recorder.ticks())
+            .filter(|(x, _)| !x.is_nan())
+            .map(|(x, tick)| (*tick as f64, *x))
+            .collect();
+        let (min, max) = points.iter().fold((f64::MAX, f64::MIN), |(min, max), x| (min.min(x.1), max.max(x.1)));
+        let (min, max) = match points.is_empty() {
+            true => (0., 1.),
+            false => (min * 0.95, (max * 1.05).max(min + 1e-6)),
+        };
+        let last_tick = points.last().map_or(1., |x| x.0.max(1.));
+        let dataset = Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::new().fg(Color::Cyan))
+            .data(&points);
+        let chart = Chart::new(vec![dataset])
+            .block(Block::bordered().title(format!("{label} price")))
+            .x_axis(Axis::default().bounds([0., last_tick]).labels(["0".to_owned(), format!("{last_tick}")]))
+            .y_axis(Axis::default().bounds([min, max]).labels([format!("{min:.2}"), format!("{max:.2}")]));
+        frame.render_widget(chart, area);
+    }
+}
+
+fn draw_entities(frame: &mut Frame, sim: &Simulation, area: Rect) {
+    let rows = sim.entities.iter().map(|(_, name, entity)| {
+        let inventory: Vec<String> = entity.inventory().into_iter()
+            .map(|(good_uid, x)| format!("{} {}", sim.goods.get_good_name(good_uid), sim.goods.to_units(good_uid, x)))
+            .collect();
+        Row::new(vec![name.to_owned(), format!("{:.2}", entity.money_balance()), inventory.join(", ")])
+    });
+    let widths = [Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(3)];
+    let table = Table::new(rows, widths)
+        .header(Row::new(vec!["entity", "money", "inventory"]).style(Style::new().fg(Color::Yellow)))
+        .block(Block::bordered().title("Entities"));
+    frame.render_widget(table, area);
+}
+
+// Last recorded value of a series, `-` before the first tick
+fn last(sim: &Simulation, series: &str) -> String {
+    match sim.recorder.series(series).and_then(|x| x.last().copied()) {
+        Some(x) if !x.is_nan() => format!("{x:.2}"),
+        _ => "-".to_owned(),
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use crate::recorder::Recorder;
+use crate::{GoodUid, Quantity};
+
+// Inefficiency of a production: a fraction of the inputs is lost on the way and a byproduct,
+// usually a Waste good, comes out besides the output. The byproduct is kept by the producer.
+// The engine collects the waste of every producer each tick and records it per producer and
+// in total, see Simulation::collect_waste.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WasteKind {
+    // Inputs consumed that didn't become output
+    Lost,
+    // Byproduct produced
+    Emitted,
+    // Stock gone bad or not fitting the storage, see Storage
+    Spoiled,
+    Dumped,
+}
+
+impl WasteKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WasteKind::Lost => "lost",
+            WasteKind::Emitted => "emitted",
+            WasteKind::Spoiled => "spoiled",
+            WasteKind::Dumped => "dumped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WasteRecord {
+    pub kind: WasteKind,
+    pub good_uid: GoodUid,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Byproduct {
+    pub good_uid: GoodUid,
+    pub unit_scale: Quantity,
+    // Units of byproduct for every unit of production, see the producers
+    pub per_unit: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WasteProfile {
+    // Fraction of the inputs lost
+    loss: f64,
+    byproduct: Option<Byproduct>,
+    // Byproduct held
+    stock: Quantity,
+    records: Vec<WasteRecord>,
+}
+
+impl WasteProfile {
+    pub fn new(loss: f64, byproduct: Option<Byproduct>) -> WasteProfile {
+        WasteProfile { loss: loss.clamp(0., 1.), byproduct, stock: 0, records: vec![] }
+    }
+
+    // Fraction of the inputs that ends in the output
+    pub fn efficiency(&self) -> f64 {
+        1. - self.loss
+    }
+
+    // The quantity of the input lost out of `used` base units
+    pub fn lose(&mut self, good_uid: GoodUid, used: Quantity) {
+        let quantity = (used as f64 * self.loss) as Quantity;
+        if quantity > 0 {
+            self.records.push(WasteRecord { kind: WasteKind::Lost, good_uid, quantity });
+        }
+    }
+
+    // Emit the byproduct of `units` units of production
+    pub fn emit(&mut self, units: f64) {
+        let Some(byproduct) = self.byproduct else {
+            return;
+        };
+        let quantity = (units * byproduct.per_unit * byproduct.unit_scale as f64) as Quantity;
+        if quantity > 0 {
+            self.stock += quantity;
+            self.records.push(WasteRecord { kind: WasteKind::Emitted, good_uid: byproduct.good_uid, quantity });
+        }
+    }
+
+    // The byproduct held, for the inventory of the producer
+    pub fn inventory(&self) -> Option<(GoodUid, Quantity)> {
+        self.byproduct.map(|x| (x.good_uid, self.stock))
+    }
+
+    pub fn take(&mut self) -> Vec<WasteRecord> {
+        std::mem::take(&mut self.records)
+    }
+}
+
+// Total waste of the run after the burn-in, from the recorded series
+pub fn print_report(recorder: &Recorder, burn_in: u64) {
+    let start = recorder.ticks().iter().position(|x| *x >= burn_in).unwrap_or(recorder.ticks().len());
+    let totals: Vec<(&String, f64)> = recorder.names().iter()
+        .filter(|x| x.starts_with("waste/"))
+        .map(|x| (x, recorder.series(x).unwrap()[start..].iter().filter(|v| !v.is_nan()).sum()))
+        .collect();
+    if totals.is_empty() {
+        return;
+    }
+    println!("Waste:");
+    for (name, total) in totals {
+        println!("  {}: {total:.2}", &name["waste/".len()..]);
+    }
+}
+
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::Serialize;
+use tungstenite::{Message, WebSocket};
+use ecosim::engine::Simulation;
+
+// Live runs watched from the browser. Every run steps in a thread of its own and after every tick
+// sends the last row of its recorder to the pages connected over WebSocket, a page connecting
+// late gets first the rows sent so far. The page draws the chosen series of all the runs in the
+// same chart, to compare them side by side. The page and the socket share the port.
+
+const PAGE: &str = include_str!("web.html");
+
+#[derive(Serialize)]
+struct Row<'a> {
+    run: &'a str,
+    tick: u64,
+    values: BTreeMap<&'a str, f64>,
+}
+
+#[derive(Default)]
+struct Hub {
+    // Every row sent, in JSON
+    rows: Vec<String>,
+    clients: Vec<WebSocket<TcpStream>>,
+}
+
+impl Hub {
+    // A client that can't keep up is dropped
+    fn broadcast(&mut self, row: String) {
+        self.clients.retain_mut(|x| x.send(Message::text(row.clone())).is_ok());
+        self.rows.push(row);
+    }
+}
+
+pub fn run(runs: Vec<(String, Simulation, u64)>, port: u16, delay: Duration) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Dashboard at http://{}", listener.local_addr()?);
+    let hub = Arc::new(Mutex::new(Hub::default()));
+    for (name, sim, ticks) in runs {
+        let hub = hub.clone();
+        thread::spawn(move || step(&name, sim, ticks, delay, &hub));
+    }
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let hub = hub.clone();
+        thread::spawn(move || {
+            if let Err(e) = serve(stream, &hub) {
+                eprintln!("Connection dropped, {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn step(name: &str, mut sim: Simulation, ticks: u64, delay: Duration, hub: &Mutex<Hub>) {
+    while sim.tick < ticks && !sim.should_stop() {
+        sim.step();
+        let recorder = &sim.recorder;
+        let row = Row {
+            run: name,
+            tick: sim.tick,
+            values: recorder.names().iter()
+                .filter_map(|x| Some((x.as_str(), *recorder.series(x)?.last()?)))
+                .collect(),
+        };
+        let row = serde_json::to_string(&row).expect("A row is always serializable");
+        hub.lock().expect("A run panicked").broadcast(row);
+        thread::sleep(delay);
+    }
+    println!("{name} ended at tick {}", sim.tick);
+}
+
+fn serve(stream: TcpStream, hub: &Mutex<Hub>) -> Result<(), Box<dyn Error>> {
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+    let mut head = [0; 2048];
+    let read = stream.peek(&mut head)?;
+    if String::from_utf8_lossy(&head[..read]).to_ascii_lowercase().contains("upgrade: websocket") {
+        let mut socket = tungstenite::accept(stream)?;
+        let mut hub = hub.lock().expect("A run panicked");
+        for row in hub.rows.iter() {
+            socket.send(Message::text(row.clone()))?;
+        }
+        hub.clients.push(socket);
+        return Ok(());
+    }
+    let mut lines = BufReader::new(&stream).lines();
+    let request = lines.next().transpose()?.unwrap_or_default();
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    // The headers are ignored
+    for line in lines {
+        if line?.is_empty() {
+            break;
+        }
+    }
+    let (status, body) = match path {
+        "/" => ("200 OK", PAGE),
+        _ => ("404 Not Found", "Not found"),
+    };
+    write!(&stream, "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{body}", body.len())?;
+    Ok(())
+}